@@ -0,0 +1,148 @@
+use futures::prelude::*;
+use telebot;
+use telebot::functions::*;
+use telebot::objects::{CallbackQuery, InlineKeyboardButton, InlineKeyboardMarkup};
+
+use data::{Database, FeedID, SubscriberID, TemplateTest};
+use feed;
+use template::render;
+use utils::{truncate_message, Escape, TELEGRAM_MAX_MSG_LEN};
+
+// both renderings have to fit in one message
+const PREVIEW_MAX_LEN: usize = TELEGRAM_MAX_MSG_LEN / 2 - 200;
+
+// sends the admin `item` rendered with both templates, the test is kept
+// until one of the buttons is pressed
+pub fn preview_templates(
+    bot: &telebot::RcBot,
+    db: &Database,
+    subscriber: SubscriberID,
+    feed_id: FeedID,
+    test: &TemplateTest,
+    item: &feed::Item,
+    rss_title: &str,
+    rss_link: &str,
+) {
+    let _ = db.update_options_by_id(subscriber, feed_id, |o| {
+        if let Some(ref mut test) = o.template_test {
+            test.previewed = true;
+        }
+    });
+    let locale = db.get_locale(subscriber);
+    let preview = |template: &str| {
+        let text = render(template, item, rss_title, rss_link, locale);
+        Escape(&truncate_message(&text, PREVIEW_MAX_LEN)).to_string()
+    };
+    let lang = db.get_lang(test.admin);
+    let text = lang.template_preview(rss_title, &preview(&test.a), &preview(&test.b));
+    let markup = InlineKeyboardMarkup {
+        inline_keyboard: vec![vec![
+            button("A", &callback_data(subscriber, feed_id, "a")),
+            button("B", &callback_data(subscriber, feed_id, "b")),
+        ]],
+    };
+    let admin = test.admin;
+    let r = bot.message(admin, text)
+        .parse_mode("HTML")
+        .disable_web_page_preview(true)
+        .reply_markup(markup)
+        .send()
+        .map(|_| ())
+        .map_err(move |e| error!("failed to send template preview to {}: {:?}", admin, e));
+    bot.inner.handle.spawn(r);
+}
+
+fn button(text: &str, data: &str) -> InlineKeyboardButton {
+    InlineKeyboardButton {
+        text: text.to_owned(),
+        callback_data: Some(data.to_owned()),
+        ..InlineKeyboardButton::default()
+    }
+}
+
+fn callback_data(subscriber: SubscriberID, feed_id: FeedID, choice: &str) -> String {
+    format!("ab:{}:{}:{}", subscriber, feed_id, choice)
+}
+
+fn parse_callback_data(data: &str) -> Option<(SubscriberID, FeedID, &str)> {
+    let mut parts = data.split(':');
+    if parts.next() != Some("ab") {
+        return None;
+    }
+    let subscriber = parts.next()?.parse().ok()?;
+    let feed_id = parts.next()?.parse().ok()?;
+    match parts.next() {
+        Some(choice @ "a") | Some(choice @ "b") => Some((subscriber, feed_id, choice)),
+        _ => None,
+    }
+}
+
+// only the admin who started the test chooses, returns the reply to `user_id`
+fn choose(
+    db: &Database,
+    user_id: i64,
+    subscriber: SubscriberID,
+    feed_id: FeedID,
+    choice: &str,
+) -> String {
+    let lang = db.get_lang(user_id);
+    let test = db.get_options(subscriber, feed_id)
+        .template_test
+        .filter(|test| test.admin == user_id);
+    match test {
+        Some(test) => {
+            let template = if choice == "a" { test.a } else { test.b };
+            let r = db.update_options_by_id(subscriber, feed_id, |o| {
+                o.template = Some(template);
+                o.template_test = None;
+            });
+            match r {
+                Ok(()) => lang.template_chosen(&choice.to_uppercase()),
+                Err(e) => lang.error_reason(&e),
+            }
+        }
+        None => lang.template_test_not_found(),
+    }
+}
+
+pub fn handle_callback(bot: &telebot::RcBot, db: &Database, query: CallbackQuery) {
+    let parsed = query.data.as_ref().and_then(|data| parse_callback_data(data));
+    let (subscriber, feed_id, choice) = match parsed {
+        Some((subscriber, feed_id, choice)) => (subscriber, feed_id, choice.to_owned()),
+        None => return,
+    };
+    let reply = choose(db, query.from.id, subscriber, feed_id, &choice);
+    let r = bot.answer_callback_query(query.id)
+        .text(reply)
+        .send()
+        .map(|_| ())
+        .map_err(|e| error!("telebot: {:?}", e));
+    bot.inner.handle.spawn(r);
+}
+
+#[test]
+fn test_choose() {
+    use data::{test_database, LinkPreview};
+    let db = test_database("abtest-choose");
+    let link = "http://a.example/feed";
+    db.subscribe(-1001, link, &feed::RSS::default(), LinkPreview::Off).unwrap();
+    let feed_id = db.get_feed(link).unwrap().get_id();
+    db.update_options_by_id(-1001, feed_id, |o| {
+        o.template_test = Some(TemplateTest {
+            a: "{title}".to_owned(),
+            b: "{link}".to_owned(),
+            admin: 5,
+            previewed: true,
+        })
+    }).unwrap();
+    let lang = db.get_lang(5);
+    // a member of the channel who isn't the admin
+    assert_eq!(choose(&db, 6, -1001, feed_id, "a"), lang.template_test_not_found());
+    assert!(db.get_options(-1001, feed_id).template_test.is_some());
+    assert_eq!(choose(&db, 5, -1001, feed_id, "b"), lang.template_chosen("B"));
+    let options = db.get_options(-1001, feed_id);
+    assert_eq!(options.template, Some("{link}".to_owned()));
+    assert!(options.template_test.is_none());
+    // only once
+    assert_eq!(choose(&db, 5, -1001, feed_id, "a"), lang.template_test_not_found());
+}
@@ -0,0 +1,207 @@
+use std::collections::HashMap;
+use std::io;
+use std::net::SocketAddr;
+
+use futures::future;
+use futures::prelude::*;
+use hyper;
+use hyper::header::ContentType;
+use hyper::server::{Http, Request, Response, Service};
+use hyper::{Method, StatusCode};
+use serde_json;
+use telebot;
+use tokio_core::net::TcpListener;
+use tokio_curl::Session;
+use url::form_urlencoded;
+
+use data::{Database, Feed};
+use errors;
+use fetcher::fetch_feed_updates;
+use sender::Sender;
+
+#[derive(Serialize)]
+struct FeedInfo<'a> {
+    link: &'a str,
+    title: &'a str,
+    error_count: u32,
+    subscribers: Vec<i64>,
+}
+
+impl<'a> FeedInfo<'a> {
+    fn new(feed: &'a Feed) -> FeedInfo<'a> {
+        FeedInfo {
+            link: &feed.link,
+            title: &feed.title,
+            error_count: feed.error_count,
+            subscribers: feed.subscribers.iter().cloned().collect(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct SubscriberInfo {
+    id: i64,
+    feeds: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct Message<'a> {
+    ok: bool,
+    message: &'a str,
+}
+
+fn json_response(status: StatusCode, body: String) -> Response {
+    Response::new()
+        .with_status(status)
+        .with_header(ContentType::json())
+        .with_body(body)
+}
+
+fn message(status: StatusCode, message: &str) -> Response {
+    let body = Message {
+        ok: status.is_success(),
+        message: message,
+    };
+    json_response(status, serde_json::to_string(&body).unwrap())
+}
+
+fn error_response(e: &errors::Error) -> Response {
+    match *e.kind() {
+        errors::ErrorKind::FeedNotFound => message(StatusCode::NotFound, "feed not found"),
+        _ => message(StatusCode::InternalServerError, &e.to_string()),
+    }
+}
+
+struct AdminApi {
+    bot: telebot::RcBot,
+    db: Database,
+    sender: Sender,
+}
+
+impl AdminApi {
+    fn list_feeds(&self) -> Response {
+        let mut feeds = self.db.get_all_feeds();
+        feeds.sort_by(|a, b| a.link.cmp(&b.link));
+        let feeds: Vec<FeedInfo> = feeds.iter().map(FeedInfo::new).collect();
+        json_response(StatusCode::Ok, serde_json::to_string(&feeds).unwrap())
+    }
+
+    fn list_subscribers(&self) -> Response {
+        let mut subscribers: Vec<SubscriberInfo> = self
+            .db
+            .get_all_subscribers()
+            .into_iter()
+            .map(|id| SubscriberInfo {
+                id: id,
+                feeds: self
+                    .db
+                    .get_subscribed_feeds(id)
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|feed| feed.link)
+                    .collect(),
+            })
+            .collect();
+        subscribers.sort_by_key(|subscriber| subscriber.id);
+        json_response(StatusCode::Ok, serde_json::to_string(&subscribers).unwrap())
+    }
+
+    fn remove_feed(&self, params: &HashMap<String, String>) -> Response {
+        let link = match params.get("url") {
+            Some(link) => link,
+            None => return message(StatusCode::BadRequest, "missing `url`"),
+        };
+        match self.db.remove_feed(link) {
+            Ok(feed) => json_response(
+                StatusCode::Ok,
+                serde_json::to_string(&FeedInfo::new(&feed)).unwrap(),
+            ),
+            Err(e) => error_response(&e),
+        }
+    }
+
+    fn migrate_subscriber(&self, params: &HashMap<String, String>) -> Response {
+        let from = params.get("from").and_then(|id| id.parse().ok());
+        let to = params.get("to").and_then(|id| id.parse().ok());
+        match (from, to) {
+            (Some(from), Some(to)) => {
+                if self.db.get_subscribed_feeds(from).is_none() {
+                    return message(StatusCode::NotFound, "subscriber not found");
+                }
+                self.db.update_subscriber(from, to);
+                message(StatusCode::Ok, "migrated")
+            }
+            _ => message(StatusCode::BadRequest, "`from` and `to` must be chat IDs"),
+        }
+    }
+
+    fn fetch_feed(&self, params: &HashMap<String, String>) -> Box<Future<Item = Response, Error = hyper::Error>> {
+        let feed = match params.get("url").and_then(|link| self.db.get_feed(link)) {
+            Some(feed) => feed,
+            None => return Box::new(future::ok(message(StatusCode::NotFound, "feed not found"))),
+        };
+        let session = Session::new(self.bot.inner.handle.clone());
+        let r = fetch_feed_updates(
+            self.bot.clone(),
+            self.db.clone(),
+            session,
+            feed,
+            self.sender.clone(),
+        );
+        Box::new(r.then(|_| Ok(message(StatusCode::Ok, "fetched"))))
+    }
+}
+
+impl Service for AdminApi {
+    type Request = Request;
+    type Response = Response;
+    type Error = hyper::Error;
+    type Future = Box<Future<Item = Response, Error = hyper::Error>>;
+
+    fn call(&self, req: Request) -> Self::Future {
+        let params: HashMap<String, String> = req
+            .query()
+            .map(|query| form_urlencoded::parse(query.as_bytes()).into_owned().collect())
+            .unwrap_or_default();
+        let response = match (req.method(), req.path()) {
+            (&Method::Get, "/feeds") => self.list_feeds(),
+            (&Method::Delete, "/feeds") => self.remove_feed(&params),
+            (&Method::Post, "/feeds/fetch") => return self.fetch_feed(&params),
+            (&Method::Get, "/subscribers") => self.list_subscribers(),
+            (&Method::Post, "/subscribers/migrate") => self.migrate_subscriber(&params),
+            _ => message(StatusCode::NotFound, "not found"),
+        };
+        Box::new(future::ok(response))
+    }
+}
+
+pub fn spawn_admin_api(
+    addr: SocketAddr,
+    bot: telebot::RcBot,
+    db: Database,
+    sender: Sender,
+) -> io::Result<()> {
+    let handle = bot.inner.handle.clone();
+    let listener = TcpListener::bind(&addr, &handle)?;
+    let http = Http::<hyper::Chunk>::new();
+    let handle2 = handle.clone();
+    let server = listener
+        .incoming()
+        .for_each(move |(socket, _)| {
+            let api = AdminApi {
+                bot: bot.clone(),
+                db: db.clone(),
+                sender: sender.clone(),
+            };
+            let conn = http
+                .serve_connection(socket, api)
+                .map(|_| ())
+                .map_err(|e| warn!("admin api: {}", e));
+            handle2.spawn(conn);
+            Ok(())
+        })
+        .map_err(|e| error!("admin api: {}", e));
+    handle.spawn(server);
+    info!("admin api listening on {}", addr);
+    Ok(())
+}
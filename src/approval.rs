@@ -0,0 +1,175 @@
+use futures::prelude::*;
+use telebot;
+use telebot::functions::*;
+use telebot::objects::{CallbackQuery, InlineKeyboardButton, InlineKeyboardMarkup};
+use tokio_curl::Session;
+
+use cmdhandles::subscribe_feed;
+use data::{ApprovalRequest, Database};
+
+// queues the subscription and asks every owner to approve or reject the domain,
+// the requester is told by the caller
+pub fn request(bot: &telebot::RcBot, db: &Database, request: ApprovalRequest) {
+    let id = match db.queue_approval(request.clone()) {
+        Ok(Some(id)) => id,
+        Ok(None) => return,
+        Err(e) => {
+            warn!("{}", e);
+            return;
+        }
+    };
+    for owner in db.get_owners() {
+        let lang = db.get_lang(owner);
+        let text = lang.approval_request(&request.domain, &request.link, request.subscriber);
+        let markup = InlineKeyboardMarkup {
+            inline_keyboard: vec![vec![
+                button(&lang.approve(), &callback_data(id, true)),
+                button(&lang.reject(), &callback_data(id, false)),
+            ]],
+        };
+        let r = bot.message(owner, text)
+            .disable_web_page_preview(true)
+            .reply_markup(markup)
+            .send()
+            .map(|_| ())
+            .map_err(move |e| error!("failed to ask {} for approval: {:?}", owner, e));
+        bot.inner.handle.spawn(r);
+    }
+}
+
+fn button(text: &str, data: &str) -> InlineKeyboardButton {
+    InlineKeyboardButton {
+        text: text.to_owned(),
+        callback_data: Some(data.to_owned()),
+        ..InlineKeyboardButton::default()
+    }
+}
+
+fn callback_data(id: u64, approved: bool) -> String {
+    format!("approve:{}:{}", id, if approved { "y" } else { "n" })
+}
+
+fn parse_callback_data(data: &str) -> Option<(u64, bool)> {
+    let mut parts = data.split(':');
+    if parts.next() != Some("approve") {
+        return None;
+    }
+    let id = parts.next()?.parse().ok()?;
+    match parts.next() {
+        Some("y") => Some((id, true)),
+        Some("n") => Some((id, false)),
+        _ => None,
+    }
+}
+
+// only owners decide, returns the reply to `owner` and the requests decided
+fn decide(db: &Database, owner: i64, id: u64, approved: bool) -> (String, Vec<ApprovalRequest>) {
+    let lang = db.get_lang(owner);
+    if !db.is_owner(owner) {
+        return (lang.owner_only(), Vec::new());
+    }
+    match db.decide_approval(id, approved) {
+        Ok(ref requests) if requests.is_empty() => (lang.choice_expired(), Vec::new()),
+        Ok(requests) => (lang.approval_decided(&requests[0].domain, approved), requests),
+        Err(e) => {
+            warn!("{}", e);
+            (lang.error_reason(&e), Vec::new())
+        }
+    }
+}
+
+// approved subscriptions are fetched and subscribed to again, reporting in the
+// chats they were asked for in
+pub fn handle_callback(
+    bot: &telebot::RcBot,
+    db: &Database,
+    query: CallbackQuery,
+    self_hosts: &[String],
+) {
+    let parsed = query.data.as_ref().and_then(|data| parse_callback_data(data));
+    let (id, approved) = match parsed {
+        Some(parsed) => parsed,
+        None => return,
+    };
+    let (reply, requests) = decide(db, query.from.id, id, approved);
+    let r = bot.answer_callback_query(query.id)
+        .text(reply.clone())
+        .send()
+        .map(|_| ())
+        .map_err(|e| error!("telebot: {:?}", e));
+    bot.inner.handle.spawn(r);
+    if requests.is_empty() {
+        return;
+    }
+    if let Some(message) = query.message {
+        let r = bot.edit_message_text(message.chat.id, message.message_id, reply)
+            .send()
+            .map(|_| ())
+            .map_err(|e| error!("telebot: {:?}", e));
+        bot.inner.handle.spawn(r);
+    }
+    for request in requests {
+        let lang = db.get_lang(request.chat_id);
+        let chat_id = request.chat_id;
+        if !approved {
+            let r = bot.message(chat_id, lang.approval_rejected(&request.link))
+                .disable_web_page_preview(true)
+                .send()
+                .map(|_| ())
+                .map_err(|e| error!("telebot: {:?}", e));
+            bot.inner.handle.spawn(r);
+            continue;
+        }
+        let db = db.clone();
+        let session = Session::new(bot.inner.handle.clone());
+        let self_hosts = self_hosts.to_vec();
+        let r = bot.message(chat_id, lang.processing())
+            .send()
+            .and_then(move |(bot, msg)| {
+                subscribe_feed(
+                    bot,
+                    db,
+                    session,
+                    chat_id,
+                    msg.message_id,
+                    request.user_id,
+                    request.subscriber,
+                    request.link,
+                    request.link_preview,
+                    self_hosts,
+                    false,
+                )
+            })
+            .map_err(|e| error!("telebot: {:?}", e));
+        bot.inner.handle.spawn(r);
+    }
+}
+
+#[test]
+fn test_decide() {
+    use data::{test_database, LinkPreview};
+    let db = test_database("approval-decide");
+    db.set_owners(vec![1]);
+    db.set_approve_new_domains(true);
+    let request = |subscriber: i64| ApprovalRequest {
+        domain: "a.example".to_owned(),
+        link: format!("http://a.example/{}", subscriber),
+        subscriber: subscriber,
+        link_preview: LinkPreview::Off,
+        chat_id: subscriber,
+        user_id: subscriber,
+    };
+    let id = db.queue_approval(request(5)).unwrap().unwrap();
+    db.queue_approval(request(6)).unwrap().unwrap();
+    let lang = db.get_lang(1);
+    // someone pressing a forwarded button
+    assert_eq!(decide(&db, 5, id, true), (lang.owner_only(), Vec::new()));
+    assert!(db.check_approval("http://a.example/feed").is_err());
+    // every request for the domain is decided at once
+    let (reply, requests) = decide(&db, 1, id, true);
+    assert_eq!(reply, lang.approval_decided("a.example", true));
+    assert_eq!(requests, vec![request(5), request(6)]);
+    assert!(db.check_approval("http://a.example/feed").is_ok());
+    // another owner was faster
+    assert_eq!(decide(&db, 1, id, false), (lang.choice_expired(), Vec::new()));
+}
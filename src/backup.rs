@@ -0,0 +1,62 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use chrono::Local;
+use futures::prelude::*;
+use tokio_core::reactor::{Handle, Interval};
+
+use data::Database;
+use utils::log_error;
+
+// copies `path` to `path.backup-TIMESTAMP` and removes all but the newest `keep` copies
+pub fn backup(path: &str, keep: usize) -> io::Result<PathBuf> {
+    let target = PathBuf::from(format!(
+        "{}.backup-{}",
+        path,
+        Local::now().format("%Y%m%d%H%M%S")
+    ));
+    fs::copy(path, &target)?;
+
+    let path = Path::new(path);
+    let dir = match path.parent() {
+        Some(dir) if dir.as_os_str().is_empty() => Path::new("."),
+        Some(dir) => dir,
+        None => Path::new("."),
+    };
+    let prefix = format!(
+        "{}.backup-",
+        path.file_name().unwrap_or_default().to_string_lossy()
+    );
+    let mut backups: Vec<PathBuf> = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|p| {
+            p.file_name()
+                .map(|name| name.to_string_lossy().starts_with(&prefix))
+                .unwrap_or(false)
+        })
+        .collect();
+    // timestamps sort lexicographically
+    backups.sort();
+    let outdated = backups.len().saturating_sub(keep);
+    for old in &backups[..outdated] {
+        fs::remove_file(old)?;
+    }
+    Ok(target)
+}
+
+pub fn spawn_backup(db: Database, interval: u64, handle: Handle) {
+    let lop = Interval::new(Duration::from_secs(interval), &handle)
+        .expect("failed to start backup loop")
+        .for_each(move |_| {
+            match db.backup() {
+                Ok(target) => info!("database backed up to {}", target.display()),
+                Err(e) => log_error(&e),
+            }
+            Ok(())
+        })
+        .map_err(|e| error!("backup loop: {}", e));
+    handle.spawn(lop);
+}
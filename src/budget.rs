@@ -0,0 +1,65 @@
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+
+use chrono::Utc;
+
+// limits how many requests a host receives per time window, the counters are
+// kept in memory and only cover this instance
+pub struct HostBudget {
+    limit: Cell<u32>,
+    window: Cell<i64>,
+    used: RefCell<HashMap<String, (i64, u32)>>,
+}
+
+impl HostBudget {
+    // a `limit` of 0 means unlimited
+    pub fn new(limit: u32, window: i64) -> HostBudget {
+        HostBudget {
+            limit: Cell::new(limit),
+            window: Cell::new(window.max(1)),
+            used: RefCell::new(HashMap::new()),
+        }
+    }
+
+    // the counts are kept unless the window changes
+    pub fn set_limit(&self, limit: u32, window: i64) {
+        self.limit.set(limit);
+        self.window.set(window.max(1));
+    }
+
+    // `false` if the host has used up its budget for the current window
+    pub fn acquire(&self, host: &str) -> bool {
+        self.acquire_at(host, Utc::now().timestamp())
+    }
+
+    fn acquire_at(&self, host: &str, now: i64) -> bool {
+        let limit = self.limit.get();
+        if limit == 0 {
+            return true;
+        }
+        let window = now - now % self.window.get();
+        let mut used = self.used.borrow_mut();
+        used.retain(|_, &mut (start, _)| start == window);
+        let entry = used.entry(host.to_owned()).or_insert((window, 0));
+        if entry.1 >= limit {
+            return false;
+        }
+        entry.1 += 1;
+        true
+    }
+}
+
+#[test]
+fn test_local_budget() {
+    let budget = HostBudget::new(2, 60);
+    assert!(budget.acquire_at("a.com", 0));
+    assert!(budget.acquire_at("a.com", 1));
+    assert!(!budget.acquire_at("a.com", 2));
+    assert!(budget.acquire_at("b.com", 2));
+    assert!(budget.acquire_at("a.com", 60));
+
+    let unlimited = HostBudget::new(0, 60);
+    for _ in 0..10 {
+        assert!(unlimited.acquire_at("a.com", 0));
+    }
+}
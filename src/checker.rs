@@ -6,6 +6,7 @@ use telebot::functions::*;
 use tokio_core::reactor::{Handle, Interval};
 
 use data;
+use sender::{Job, Sender};
 use utils::chat_is_unavailable;
 
 pub fn spawn_subscriber_alive_checker(bot: telebot::RcBot, db: data::Database, handle: Handle) {
@@ -34,7 +35,7 @@ pub fn spawn_subscriber_alive_checker(bot: telebot::RcBot, db: data::Database, h
                             chat_member.status == "kicked" ||
                             chat_member.status == "member" && chat.kind == "channel"
                         {
-                            db.delete_subscriber(subscriber)
+                            db.delete_subscriber(subscriber);
                         }
                     }
                 }
@@ -63,3 +64,37 @@ pub fn spawn_subscriber_alive_checker(bot: telebot::RcBot, db: data::Database, h
     }.map_err(|e: ::std::io::Error| error!("checker loop: {}", e));
     handle2.spawn(lop);
 }
+
+pub fn spawn_expiry_checker(db: data::Database, sender: Sender, handle: Handle) {
+    let lop = Interval::new(Duration::from_secs(10 * 60), &handle)
+        .expect("failed to start expiry checker loop")
+        .for_each(move |_| {
+            for (subscriber, feed) in db.take_expired() {
+                let lang = db.get_lang(subscriber);
+                let msg = match feed {
+                    Some(feed) => lang.subscription_expired(&feed.link, &feed.title),
+                    None => lang.chat_expired(),
+                };
+                sender.send(Job::text(subscriber, msg));
+            }
+            Ok(())
+        })
+        .map_err(|e| error!("expiry checker loop: {}", e));
+    handle.spawn(lop);
+}
+
+// suggests feeds to the chats which left them once they got popular, see
+// `Database::take_popular_again`
+pub fn spawn_popularity_checker(db: data::Database, sender: Sender, handle: Handle) {
+    let lop = Interval::new(Duration::from_secs(60 * 60), &handle)
+        .expect("failed to start popularity checker loop")
+        .for_each(move |_| {
+            for (subscriber, feed) in db.take_popular_again() {
+                let msg = db.get_lang(subscriber).popular_again(&feed.link, &feed.title);
+                sender.send(Job::text(subscriber, msg));
+            }
+            Ok(())
+        })
+        .map_err(|e| error!("popularity checker loop: {}", e));
+    handle.spawn(lop);
+}
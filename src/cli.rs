@@ -0,0 +1,219 @@
+use std::fs::File;
+use std::io::{Read, Write};
+
+use regex::RegexBuilder;
+use tokio_core::reactor::Core;
+use tokio_curl::Session;
+
+use data::{gen_item_hash, Database, Feed, LinkPreview, SubscriptionOptions};
+use errors::*;
+use feed;
+use import::import_file;
+use opml::to_opml;
+
+const DB_USAGE: &str = "Usage: {} db DATAFILE ls
+       {} db DATAFILE grep PATTERN
+       {} db DATAFILE rm RSS-URL [SUBSCRIBER]
+       {} db DATAFILE subscribers
+       {} db DATAFILE export OPML-FILE [SUBSCRIBER]
+       {} db DATAFILE import SUBSCRIBER OPML-OR-JSON-FILE
+       {} db DATAFILE vacuum";
+
+const CHECK_USAGE: &str = "Usage: {} check URL [--user-agent UA] [--proxy URL]";
+
+// there's no bot to name in the User-Agent
+const USER_AGENT: &str = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
+
+fn print_db_usage(program: &str) {
+    eprintln!("{}", DB_USAGE.replace("{}", program));
+}
+
+fn check_usage(program: &str) -> ! {
+    eprintln!("{}", CHECK_USAGE.replace("{}", program));
+    ::std::process::exit(1);
+}
+
+fn print_feed(feed: &Feed) {
+    let mut subscribers: Vec<String> = feed.subscribers.iter().map(|s| s.to_string()).collect();
+    subscribers.sort();
+    println!(
+        "{}\t{}\t{}\t{}",
+        feed.link,
+        feed.title,
+        feed.error_count,
+        subscribers.join(",")
+    );
+}
+
+fn parse_subscriber(s: &str) -> i64 {
+    s.parse().unwrap_or_else(|_| {
+        eprintln!("subscriber must be an integer");
+        ::std::process::exit(1);
+    })
+}
+
+// every feed without settings, or the subscriptions of `subscriber` with theirs
+fn export(db: &Database, path: &str, subscriber: Option<i64>) -> Result<usize> {
+    let feeds: Vec<(Feed, LinkPreview, SubscriptionOptions)> = match subscriber {
+        Some(subscriber) => db
+            .get_subscribed_feeds(subscriber)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|feed| {
+                let feed_id = feed.get_id();
+                let link_preview = db.get_link_preview(subscriber, feed_id)
+                    .unwrap_or(LinkPreview::Off);
+                (feed, link_preview, db.get_options(subscriber, feed_id))
+            })
+            .collect(),
+        None => db
+            .get_all_feeds()
+            .into_iter()
+            .map(|feed| (feed, LinkPreview::Off, SubscriptionOptions::default()))
+            .collect(),
+    };
+    let count = feeds.len();
+    File::create(path)
+        .and_then(|mut f| f.write_all(to_opml(feeds).as_bytes()))
+        .chain_err(|| format!("failed to write '{}'", path))?;
+    Ok(count)
+}
+
+// fetches every feed of the file like `/import`, so its items aren't sent again
+fn import(db: &Database, subscriber: i64, path: &str) -> Result<()> {
+    let mut body = Vec::new();
+    File::open(path)
+        .and_then(|mut f| f.read_to_end(&mut body))
+        .chain_err(|| format!("failed to read '{}'", path))?;
+    let mut core = Core::new().chain_err(|| "failed to start the event loop")?;
+    let session = Session::new(core.handle());
+    for (link, link_preview, options) in import_file(db, subscriber, &body)? {
+        let r = feed::fetch_feed_with_headers(
+            session.clone(),
+            USER_AGENT.to_owned(),
+            options.headers.clone(),
+            None,
+            link.clone(),
+        );
+        let r = core.run(r).and_then(|rss| {
+            let source = rss.source.clone().unwrap();
+            db.check_domain(&source)?;
+            db.subscribe(subscriber, &source, &rss, link_preview)?;
+            db.update_options(subscriber, &source, |o| {
+                let initial_items = o.initial_items.clone();
+                *o = options;
+                o.initial_items = initial_items;
+            })?;
+            Ok(source)
+        });
+        match r {
+            Ok(source) => println!("subscribed {} to {}", subscriber, source),
+            Err(e) => eprintln!("failed to subscribe {} to {}: {}", subscriber, link, e),
+        }
+    }
+    Ok(())
+}
+
+// `args` is the full argument list, starting with the program name
+pub fn run_db(args: &[String]) -> Result<()> {
+    let program = &args[0];
+    if args.len() < 4 {
+        print_db_usage(program);
+        ::std::process::exit(1);
+    }
+    let db = Database::open(&args[2])?;
+    match (args[3].as_str(), args.len()) {
+        ("ls", 4) => {
+            let mut feeds = db.get_all_feeds();
+            feeds.sort_by(|a, b| a.link.cmp(&b.link));
+            feeds.iter().for_each(print_feed);
+        }
+        ("grep", 5) => {
+            let pattern = RegexBuilder::new(&args[4])
+                .case_insensitive(true)
+                .build()?;
+            let mut feeds: Vec<Feed> = db
+                .get_all_feeds()
+                .into_iter()
+                .filter(|feed| pattern.is_match(&feed.link) || pattern.is_match(&feed.title))
+                .collect();
+            feeds.sort_by(|a, b| a.link.cmp(&b.link));
+            feeds.iter().for_each(print_feed);
+        }
+        ("rm", 5) => {
+            let feed = db.remove_feed(&args[4])?;
+            println!("removed {} ({} subscribers)", feed.link, feed.subscribers.len());
+        }
+        ("rm", 6) => {
+            let subscriber = parse_subscriber(&args[5]);
+            let feed = db.unsubscribe(subscriber, &args[4])?;
+            println!("unsubscribed {} from {}", subscriber, feed.link);
+        }
+        ("subscribers", 4) => {
+            let mut subscribers = db.get_all_subscribers();
+            subscribers.sort();
+            for subscriber in subscribers {
+                let feeds = db.get_subscribed_feeds(subscriber).unwrap_or_default();
+                println!("{}\t{}", subscriber, feeds.len());
+            }
+        }
+        ("export", 5) | ("export", 6) => {
+            let subscriber = args.get(5).map(|s| parse_subscriber(s));
+            let count = export(&db, &args[4], subscriber)?;
+            println!("exported {} feeds to {}", count, args[4]);
+        }
+        ("import", 6) => import(&db, parse_subscriber(&args[4]), &args[5])?,
+        ("vacuum", 4) => {
+            let (feeds, settings) = db.vacuum()?;
+            println!(
+                "removed {} feeds without subscribers and {} orphaned settings",
+                feeds, settings
+            );
+        }
+        _ => {
+            print_db_usage(program);
+            ::std::process::exit(1);
+        }
+    }
+    Ok(())
+}
+
+// `rssbot check URL`, fetches and parses a feed like the bot does and explains the result
+pub fn run_check(args: &[String]) -> Result<()> {
+    let program = &args[0];
+    let mut link = None;
+    let mut ua = USER_AGENT.to_owned();
+    let mut proxy = None;
+    let mut iter = args.iter().skip(2);
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--user-agent" => ua = iter.next().cloned().unwrap_or_else(|| check_usage(program)),
+            "--proxy" => proxy = Some(iter.next().cloned().unwrap_or_else(|| check_usage(program))),
+            _ if link.is_none() && !arg.starts_with("--") => link = Some(arg.clone()),
+            _ => check_usage(program),
+        }
+    }
+    let link = link.unwrap_or_else(|| check_usage(program));
+    let mut core = Core::new().chain_err(|| "failed to start the event loop")?;
+    let session = Session::new(core.handle());
+    let diagnosis = core.run(feed::diagnose(session, ua.clone(), proxy, link))?;
+    println!("URL:          {}", diagnosis.source);
+    println!("User-Agent:   {}", ua);
+    println!("HTTP status:  {}", diagnosis.status);
+    println!("Content-Type: {}", diagnosis.content_type.unwrap_or_default());
+    println!("Format:       {}", diagnosis.format.unwrap_or_else(|| "unknown".into()));
+    let rss = diagnosis.feed?;
+    println!("Title:        {}", rss.title);
+    println!("Link:         {}", rss.link);
+    println!("Items:        {}", rss.items.len());
+    for item in &rss.items {
+        println!(
+            "{:016x}\t{}\t{}\t{}",
+            gen_item_hash(item),
+            item.pubdate.map(|date| date.to_rfc3339()).unwrap_or_default(),
+            item.title.as_ref().map(|s| s.as_str()).unwrap_or_default(),
+            item.link.as_ref().map(|s| s.as_str()).unwrap_or_default()
+        );
+    }
+    Ok(())
+}
@@ -1,491 +1,1112 @@
-use futures::future;
+use chrono::{DateTime, TimeZone, Utc};
+use chrono_tz::Tz;
 use futures::prelude::*;
+use serde_json;
 use telebot;
 use telebot::functions::File;
 use telebot::functions::*;
+use telebot::objects::Message;
 use tokio_core::reactor::Handle;
 use tokio_curl::Session;
+use url::Url;
 
-use data::{Database, LinkPreview, SubscriptionResult};
+use approval;
+use config::Config;
+use csv::to_csv;
+use data::{ApprovalRequest, Database, Feed, LinkPreview, RewriteRule, SubscriptionOptions,
+           SubscriptionResult, TemplateTest};
+use discovery;
+use erase;
 use errors::*;
 use feed;
+use fetcher::{deliver_updates, fetch_feed_updates, format_updates, send_updates};
+use html::extract_title;
+use import::import_file;
+use json::to_json;
+use messages::{Lang, LANGS};
 use opml::to_opml;
-use utils::{format_and_split_msgs, gen_ua, log_error, send_multiple_messages,
-            to_chinese_error_msg, Escape, EscapeUrl};
+use ratelimit;
+use sender::Sender;
+use shortcut;
+use utils::{base64_decode, format_and_split_msgs, gen_ua, link_in_domains, log_error,
+            normalize_url, send_multiple_messages, split_credentials, to_hashtag,
+            truncate_message, Escape, EscapeUrl};
+use watch;
+use youtube;
 
-pub fn register_commands(bot: &telebot::RcBot, db: &Database, lphandle: Handle) {
+const MAX_BACKLOG: usize = 50;
+const NOTE_MAX_LEN: usize = 200;
+const TITLE_MAX_LEN: usize = 100;
+// in characters, an emoji or a short word
+const PREFIX_MAX_LEN: usize = 10;
+const DEDUPE_MAX_HOURS: u32 = 7 * 24;
+// the sender of the messages of anonymous group administrators
+const GROUP_ANONYMOUS_BOT: i64 = 1_087_968_824;
+// seconds since the last fetch before `/checknow` fetches again
+const CHECKNOW_INTERVAL: i64 = 60;
+
+pub fn register_commands(
+    bot: &telebot::RcBot,
+    db: &Database,
+    lphandle: Handle,
+    config: &Config,
+    sender: &Sender,
+) {
+    register_start(bot, db.clone(), config.self_hosts());
     register_rss(bot, db.clone());
-    register_sub(bot, db.clone(), lphandle);
+    register_find(bot, db.clone());
+    register_feedstatus(bot, db.clone());
+    register_sub(
+        bot,
+        db.clone(),
+        lphandle.clone(),
+        config.self_hosts(),
+    );
     register_unsub(bot, db.clone());
     register_unsubthis(bot, db.clone());
     register_export(bot, db.clone());
+    register_import(
+        bot,
+        db.clone(),
+        lphandle.clone(),
+        config.self_hosts(),
+    );
+    register_toggle(
+        bot,
+        db.clone(),
+        "/media",
+        |o, v| o.media = v,
+        Lang::media_delivery,
+    );
+    register_toggle(
+        bot,
+        db.clone(),
+        "/podcast",
+        |o, v| o.podcast = v,
+        Lang::podcast_mode,
+    );
+    register_toggle(
+        bot,
+        db.clone(),
+        "/silent",
+        |o, v| o.silent = v,
+        Lang::silent_delivery,
+    );
+    register_toggle(
+        bot,
+        db.clone(),
+        "/ogimage",
+        |o, v| o.og_image = v,
+        Lang::preview_photo,
+    );
+    register_toggle(
+        bot,
+        db.clone(),
+        "/canonical",
+        |o, v| o.canonical = v,
+        Lang::canonical_links,
+    );
+    register_toggle(
+        bot,
+        db.clone(),
+        "/cleanlinks",
+        |o, v| o.strip_tracking = Some(v),
+        Lang::clean_links,
+    );
+    register_toggle(
+        bot,
+        db.clone(),
+        "/errnotify",
+        |o, v| o.error_notify = v,
+        Lang::error_notify,
+    );
+    register_tag(bot, db.clone(), "/tag", true);
+    register_tag(bot, db.clone(), "/untag", false);
+    register_template(bot, db.clone());
+    register_note(bot, db.clone());
+    register_pinrule(bot, db.clone());
+    register_rewrite(bot, db.clone());
+    register_mirror(bot, db.clone(), "/mirror", true);
+    register_copy(bot, db.clone());
+    register_erase(bot, db.clone());
+    register_mirror(bot, db.clone(), "/unmirror", false);
+    register_rename(bot, db.clone());
+    register_prefix(bot, db.clone());
+    register_header(bot, db.clone());
+    register_snooze(bot, db.clone());
+    register_summary(bot, db.clone());
+    register_display(bot, db.clone());
+    register_hashtags(bot, db.clone());
+    register_linkpreview(bot, db.clone());
+    register_abtest(bot, db.clone());
+    register_history(bot, db.clone());
+    register_backlog(bot, db.clone(), lphandle.clone());
+    register_checknow(bot, db.clone(), lphandle.clone(), sender.clone());
+    register_watch(bot, db.clone(), lphandle.clone());
+    register_unwatch(bot, db.clone());
+    register_lang(bot, db.clone());
+    register_timezone(bot, db.clone());
+    register_suggest(bot, db.clone());
+    register_groupadminonly(bot, db.clone());
+    register_dedupe(bot, db.clone());
+    register_expire(bot, db.clone());
+    register_premium(bot, db.clone());
+    register_admin(bot, db.clone(), lphandle.clone(), sender.clone());
+    register_iv(bot, db.clone());
+    register_rewriterule(bot, db.clone());
+    register_proxy(bot, db.clone());
+    register_blockdomain(bot, db.clone(), "/blockdomain", true);
+    register_blockdomain(bot, db.clone(), "/unblockdomain", false);
+    register_maintenance(bot, db.clone());
+    register_auth(bot, db.clone());
+    register_botstats(bot, db.clone(), sender.clone());
+    register_broadcast(bot, db.clone(), sender.clone());
 }
 
-fn register_rss(bot: &telebot::RcBot, db: Database) {
-    let handle = bot.new_cmd("/rss")
-        .map_err(Some)
-        .and_then(move |(bot, msg)| {
-            let text = msg.text.unwrap();
-            let args: Vec<&str> = text.split_whitespace().collect();
-            let raw: bool;
-            let subscriber: future::Either<_, _>;
-            match args.len() {
-                0 => {
-                    raw = false;
-                    subscriber = future::Either::A(future::ok(Some(msg.chat.id)));
-                }
-                1 => {
-                    if args[0] == "raw" {
-                        raw = true;
-                        subscriber = future::Either::A(future::ok(Some(msg.chat.id)));
-                    } else {
-                        raw = false;
-                        let channel = args[0];
-                        let channel_id =
-                            check_channel(&bot, channel, msg.chat.id, msg.from.unwrap().id);
-                        subscriber = future::Either::B(channel_id);
-                    }
-                }
-                2 => {
-                    raw = true;
-                    let channel = args[0];
-                    let channel_id =
-                        check_channel(&bot, channel, msg.chat.id, msg.from.unwrap().id);
-                    subscriber = future::Either::B(channel_id);
-                }
-                _ => {
-                    let r = bot.message(
-                        msg.chat.id,
-                        "Usage: /rss <Channel ID> <raw>".to_string(),
-                    ).send()
-                        .then(|result| match result {
-                            Ok(_) => Err(None),
-                            Err(e) => Err(Some(e)),
-                        });
-                    return future::Either::A(r);
-                }
-            }
-            let db = db.clone();
-            let chat_id = msg.chat.id;
-            let r = subscriber
-                .then(|result| match result {
-                    Ok(Some(ok)) => Ok(ok),
-                    Ok(None) => Err(None),
-                    Err(err) => Err(Some(err)),
-                })
-                .map(move |subscriber| (bot, db, subscriber, raw, chat_id));
-            future::Either::B(r)
-        })
-        .and_then(|(bot, db, subscriber, raw, chat_id)| {
-            match db.get_subscribed_feeds(subscriber) {
-                Some(feeds) => Ok((bot, raw, chat_id, feeds)),
-                None => Err((bot, chat_id)),
-            }.into_future()
-                .or_else(|(bot, chat_id)| {
-                    bot.message(chat_id, "Subscription list is empty".to_string())
-                        .send()
-                        .then(|r| match r {
-                            Ok(_) => Err(None),
-                            Err(e) => Err(Some(e)),
+// `/start <payload>` of the `t.me/<bot>?start=<payload>` links, the payload is
+// a base64 encoded feed URL which is subscribed to after a confirmation
+fn register_start(bot: &telebot::RcBot, db: Database, self_hosts: Vec<String>) {
+    let handle = ratelimit::new_cmd(bot, &db, "/start")
+        .and_then(move |(bot, msg)| {
+            let db = db.clone();
+            let self_hosts = self_hosts.clone();
+            async_block! {
+                let chat_id = msg.chat.id;
+                let lang = db.get_lang(chat_id);
+                let payload = msg.text.unwrap().trim().to_owned();
+                if payload.is_empty() {
+                    return Ok(());
+                }
+                let feed_link = base64_decode(&payload)
+                    .and_then(|bytes| String::from_utf8(bytes).ok())
+                    .filter(|link| {
+                        Url::parse(link).ok().map_or(false, |url| {
+                            url.scheme() == "http" || url.scheme() == "https"
                         })
-                })
-        })
-        .and_then(|(bot, raw, chat_id, mut feeds)| {
-            let text = String::from("Subscription list:");
-            if !raw {
-                feeds.sort_by_key(|feed| &feed.title);
-                let msgs = format_and_split_msgs(text, &feeds, |feed| {
-                    format!(
-                        "<a href=\"{}\">{}</a>",
-                        EscapeUrl(&feed.link),
-                        Escape(&feed.title)
-                    )
-                });
-                send_multiple_messages(&bot, chat_id, msgs, false)
-            } else {
-                feeds.sort_by(|a, b| a.link.cmp(&b.link));
-                let msgs = format_and_split_msgs(text, &feeds, |feed| {
+                    });
+                let feed_link = match feed_link {
+                    Some(feed_link) => feed_link,
+                    None => {
+                        await!(bot.message(chat_id, lang.invalid_deep_link()).send())?;
+                        return Ok(());
+                    }
+                };
+                let checked = if link_in_domains(&feed_link, &self_hosts) {
+                    Err(ErrorKind::SelfReferentialFeed.into())
+                } else {
+                    db.check_domain(&feed_link)
+                };
+                if let Err(e) = checked {
+                    let reason = lang.error_reason(&e);
+                    await!(bot.message(chat_id, lang.subscription_failed(&reason)).send())?;
+                    return Ok(());
+                }
+                let user_id = msg.from.unwrap().id;
+                if !await!(check_group_admin(bot.clone(), db.clone(), chat_id, user_id, lang))? {
+                    return Ok(());
+                }
+                let text = lang.subscribe_confirm(&feed_link);
+                let msg_id = await!(bot.message(chat_id, text.clone()).send())?.1.message_id;
+                let pending = discovery::Pending {
+                    user_id: user_id,
+                    subscriber: chat_id,
+                    link_preview: db.default_link_preview(&feed_link),
+                    candidates: vec![(feed_link, lang.subscribe_button())],
+                };
+                await!(discovery::offer(bot, chat_id, msg_id, pending, text))?;
+                Ok(())
+            }
+        })
+        .then(|result| {
+            if let Err(err) = result {
+                error!("telebot: {:?}", err);
+            }
+            Ok::<(), ()>(())
+        });
+
+    bot.register(handle);
+}
+
+fn register_rss(bot: &telebot::RcBot, db: Database) {
+    let handle = ratelimit::new_cmd(bot, &db, "/rss")
+        .and_then(move |(bot, msg)| {
+            let db = db.clone();
+            async_block! {
+                let chat_id = msg.chat.id;
+                let lang = db.get_lang(chat_id);
+                let text = msg.text.unwrap();
+                let args: Vec<String> = text.split_whitespace().map(str::to_owned).collect();
+                let is_format = |s: &str| s == "raw" || s == "json" || s == "new";
+                // anything else is a tag, see `/tag`
+                let (channel, format, tag) = match args.len() {
+                    0 => (None, String::new(), None),
+                    1 if is_format(args[0].as_str()) => (None, args[0].clone(), None),
+                    1 if is_channel_arg(&args[0]) => (Some(args[0].clone()), String::new(), None),
+                    1 => (None, String::new(), Some(args[0].clone())),
+                    2 if is_format(args[1].as_str()) => {
+                        (Some(args[0].clone()), args[1].clone(), None)
+                    }
+                    2 => (Some(args[0].clone()), String::new(), Some(args[1].clone())),
+                    _ => {
+                        let usage = lang.usage("/rss <Channel ID> <raw|json|new|Tag>");
+                        await!(bot.message(chat_id, usage).send())?;
+                        return Ok(());
+                    }
+                };
+                let forwarded = forwarded_post(&msg.reply_to_message);
+                let user_id = msg.from.unwrap().id;
+                let subscriber = match await!(resolve_subscriber(
+                    bot.clone(),
+                    db.clone(),
+                    channel,
+                    forwarded,
+                    chat_id,
+                    user_id,
+                    lang
+                ))? {
+                    Some(subscriber) => subscriber,
+                    None => return Ok(()),
+                };
+                let mut feeds = match db.get_subscribed_feeds(subscriber) {
+                    Some(feeds) => feeds,
+                    None => {
+                        await!(bot.message(chat_id, lang.subscription_list_empty()).send())?;
+                        return Ok(());
+                    }
+                };
+                let mut text = lang.subscription_list();
+                if let Some(tag) = tag {
+                    feeds.retain(|feed| {
+                        db.get_options(subscriber, feed.get_id()).tags.contains(&tag)
+                    });
+                    if feeds.is_empty() {
+                        await!(bot.message(chat_id, lang.tag_empty(&tag)).send())?;
+                        return Ok(());
+                    }
+                    text = lang.subscription_list_tagged(&tag);
+                }
+                if format == "json" {
+                    let json = export_json(&db, subscriber, feeds);
+                    let file = File::new("feeds.json".into(), json.into_bytes());
+                    await!(bot.document(chat_id, file).send())?;
+                    return Ok(());
+                }
+                if format == "new" {
+                    let since = db.get_chat_settings(subscriber).new_items_checked_at;
+                    let now = Utc::now().timestamp();
+                    let r = db.update_chat_settings(subscriber, |s| s.new_items_checked_at = now);
+                    if let Err(e) = r {
+                        log_error(&e);
+                    }
+                    feeds.retain(|feed| feed.last_item_at.map_or(false, |t| t > since));
+                    if feeds.is_empty() {
+                        await!(bot.message(chat_id, lang.no_new_items()).send())?;
+                        return Ok(());
+                    }
+                    text = lang.subscription_list_new();
+                }
+                for feed in &mut feeds {
+                    if let Some(title) = db.get_options(subscriber, feed.get_id()).title {
+                        feed.title = title;
+                    }
+                }
+                let msgs = if format != "raw" {
+                    feeds.sort_by(|a, b| a.title.cmp(&b.title));
+                    format_and_split_msgs(text, &feeds, |feed| {
+                        format!(
+                            "<a href=\"{}\">{}</a>",
+                            EscapeUrl(&feed.link),
+                            Escape(&feed.title)
+                        )
+                    })
+                } else {
+                    feeds.sort_by(|a, b| a.link.cmp(&b.link));
+                    format_and_split_msgs(text, &feeds, |feed| {
+                        let line = format!("{}: {}", Escape(&feed.title), Escape(&feed.link));
+                        match db.get_options(subscriber, feed.get_id()).note {
+                            Some(note) => format!("{} ({})", line, Escape(&note)),
+                            None => line,
+                        }
+                    })
+                };
+                await!(send_multiple_messages(&bot, chat_id, msgs, false))?;
+                Ok(())
+            }
+        })
+        .then(|result| {
+            if let Err(err) = result {
+                error!("telebot: {:?}", err);
+            }
+            Ok::<(), ()>(())
+        });
+
+    bot.register(handle);
+}
+
+// `/find [Channel ID] <Text>`, case-insensitive substring of the title or link
+fn register_find(bot: &telebot::RcBot, db: Database) {
+    let handle = ratelimit::new_cmd(bot, &db, "/find")
+        .and_then(move |(bot, msg)| {
+            let db = db.clone();
+            async_block! {
+                let chat_id = msg.chat.id;
+                let lang = db.get_lang(chat_id);
+                let text = msg.text.unwrap();
+                let args: Vec<String> = text.split_whitespace().map(str::to_owned).collect();
+                let (channel, query) = match args.len() {
+                    0 => {
+                        let usage = lang.usage("/find [Channel ID] <Text>");
+                        await!(bot.message(chat_id, usage).send())?;
+                        return Ok(());
+                    }
+                    n if n > 1 && is_channel_arg(&args[0]) => {
+                        (Some(args[0].clone()), args[1..].join(" "))
+                    }
+                    _ => (None, args.join(" ")),
+                };
+                let forwarded = forwarded_post(&msg.reply_to_message);
+                let user_id = msg.from.unwrap().id;
+                let subscriber = match await!(resolve_subscriber(
+                    bot.clone(),
+                    db.clone(),
+                    channel,
+                    forwarded,
+                    chat_id,
+                    user_id,
+                    lang
+                ))? {
+                    Some(subscriber) => subscriber,
+                    None => return Ok(()),
+                };
+                let query = query.to_lowercase();
+                let mut feeds: Vec<Feed> = db.get_subscribed_feeds(subscriber)
+                    .unwrap_or_default()
+                    .into_iter()
+                    .filter(|feed| {
+                        feed.title.to_lowercase().contains(&query)
+                            || feed.link.to_lowercase().contains(&query)
+                    })
+                    .collect();
+                if feeds.is_empty() {
+                    await!(bot.message(chat_id, lang.find_nothing()).send())?;
+                    return Ok(());
+                }
+                feeds.sort_by(|a, b| a.title.cmp(&b.title));
+                let msgs = format_and_split_msgs(lang.find_results(feeds.len()), &feeds, |feed| {
                     format!("{}: {}", Escape(&feed.title), Escape(&feed.link))
                 });
-                send_multiple_messages(&bot, chat_id, msgs, false)
-            }.map_err(Some)
-        })
-        .then(|result| match result {
-            Err(Some(err)) => {
-                error!("telebot: {:?}", err);
-                Ok::<(), ()>(())
-            }
-            _ => Ok(()),
-        });
-
-    bot.register(handle);
-}
-
-fn register_sub(bot: &telebot::RcBot, db: Database, lphandle: Handle) {
-    let handle = bot.new_cmd("/sub")
-        .map_err(Some)
-        .and_then(move |(bot, msg)| {
-            let text = msg.text.unwrap();
-            let args: Vec<&str> = text.split_whitespace().collect();
-            let feed_link: &str;
-            let mut link_preview = LinkPreview::Off;
-            let subscriber: future::Either<_, _>;
-            match args.len() {
-                1 => {
-                    feed_link = args[0];
-                    subscriber = future::Either::A(future::ok(Some(msg.chat.id)));
-                }
-                2 => {
-                    if args[1].to_ascii_lowercase() == "on" {
-                        // Not channel and LP on.
-                        link_preview = LinkPreview::On;
-                        feed_link = args[0];
-                        subscriber = future::Either::A(future::ok(Some(msg.chat.id)));
-                    }
-                    else {
-                        let channel = args[0];
-                        let channel_id =
-                            check_channel(&bot, channel, msg.chat.id, msg.from.unwrap().id);
-                        subscriber = future::Either::B(channel_id);
-                        feed_link = args[1];
-                    }
-                }
-                3 => {
-                    let channel = args[0];
-                    let channel_id =
-                        check_channel(&bot, channel, msg.chat.id, msg.from.unwrap().id);
-                    subscriber = future::Either::B(channel_id);
-                    feed_link = args[1];
-                    link_preview = LinkPreview::from_iv_rhash(
-                        u64::from_str_radix(args[2], 16).unwrap_or(u64::max_value()),
-                    );
-                }
-                _ => {
-                    let r = bot.message(
-                        msg.chat.id,
-                        "Usage: /sub [Channel ID] <RSS URL> [InstantView RHASH]"
-                            .to_string(),
-                    ).send()
-                        .then(|result| match result {
-                            Ok(_) => Err(None),
-                            Err(e) => Err(Some(e)),
-                        });
-                    return future::Either::A(r);
-                }
+                await!(send_multiple_messages(&bot, chat_id, msgs, false))?;
+                Ok(())
+            }
+        })
+        .then(|result| {
+            if let Err(err) = result {
+                error!("telebot: {:?}", err);
             }
+            Ok::<(), ()>(())
+        });
+
+    bot.register(handle);
+}
+
+// `/feedstatus [Channel ID]`, failing feeds first
+fn register_feedstatus(bot: &telebot::RcBot, db: Database) {
+    let handle = ratelimit::new_cmd(bot, &db, "/feedstatus")
+        .and_then(move |(bot, msg)| {
             let db = db.clone();
-            let feed_link = feed_link.to_owned();
-            let chat_id = msg.chat.id;
-            let lphandle = lphandle.clone();
-            let r = subscriber
-                .then(|result| match result {
-                    Ok(Some(ok)) => Ok(ok),
-                    Ok(None) => Err(None),
-                    Err(err) => Err(Some(err)),
-                })
-                .map(move |subscriber| {
-                    (
-                        bot,
-                        db,
-                        subscriber,
-                        feed_link,
-                        link_preview,
-                        chat_id,
-                        lphandle,
+            async_block! {
+                let chat_id = msg.chat.id;
+                let lang = db.get_lang(chat_id);
+                let text = msg.text.unwrap();
+                let args: Vec<String> = text.split_whitespace().map(str::to_owned).collect();
+                let channel = match args.len() {
+                    0 => None,
+                    1 => Some(args[0].clone()),
+                    _ => {
+                        let usage = lang.usage("/feedstatus <Channel ID>");
+                        await!(bot.message(chat_id, usage).send())?;
+                        return Ok(());
+                    }
+                };
+                let forwarded = forwarded_post(&msg.reply_to_message);
+                let user_id = msg.from.unwrap().id;
+                let subscriber = match await!(resolve_subscriber(
+                    bot.clone(),
+                    db.clone(),
+                    channel,
+                    forwarded,
+                    chat_id,
+                    user_id,
+                    lang
+                ))? {
+                    Some(subscriber) => subscriber,
+                    None => return Ok(()),
+                };
+                let mut feeds = match db.get_subscribed_feeds(subscriber) {
+                    Some(feeds) => feeds,
+                    None => {
+                        await!(bot.message(chat_id, lang.subscription_list_empty()).send())?;
+                        return Ok(());
+                    }
+                };
+                feeds.sort_by(|a, b| {
+                    b.error_count.cmp(&a.error_count).then_with(|| a.title.cmp(&b.title))
+                });
+                let locale = db.get_locale(chat_id);
+                let msgs = format_and_split_msgs(lang.feed_status_head(), &feeds, |feed| {
+                    let last_fetched = feed.last_fetched_at
+                        .map(|t| locale.format_date(&Utc.timestamp(t, 0)));
+                    let note = db.get_options(subscriber, feed.get_id()).note;
+                    lang.feed_status(
+                        &feed.link,
+                        &feed.title,
+                        feed.error_count,
+                        last_fetched,
+                        feed.last_error.as_ref().map(|s| s.as_str()),
+                        note.as_ref().map(|s| s.as_str()),
                     )
                 });
-            future::Either::B(r)
+                await!(send_multiple_messages(&bot, chat_id, msgs, false))?;
+                Ok(())
+            }
+        })
+        .then(|result| {
+            if let Err(err) = result {
+                error!("telebot: {:?}", err);
+            }
+            Ok::<(), ()>(())
+        });
+
+    bot.register(handle);
+}
+
+fn export_json(db: &Database, subscriber: i64, feeds: Vec<Feed>) -> String {
+    let subscriptions: Vec<_> = feeds
+        .into_iter()
+        .map(|feed| {
+            let feed_id = feed.get_id();
+            let link_preview = db.get_link_preview(subscriber, feed_id)
+                .unwrap_or(LinkPreview::Off);
+            (feed, link_preview, db.get_options(subscriber, feed_id))
         })
-        /*.and_then(
-            |(bot, db, subscriber, feed_link, link_preview, chat_id, lphandle)| {
-                if db.is_subscribed(subscriber, &feed_link) {
-                    Err((bot, chat_id))
+        .collect();
+    let settings = db.get_chat_settings(subscriber);
+    to_json(subscriber, &settings, &subscriptions)
+}
+
+fn register_sub(
+    bot: &telebot::RcBot,
+    db: Database,
+    lphandle: Handle,
+    self_hosts: Vec<String>,
+) {
+    let handle = ratelimit::new_cmd(bot, &db, "/sub")
+        .and_then(move |(bot, msg)| {
+            let db = db.clone();
+            let lphandle = lphandle.clone();
+            let self_hosts = self_hosts.clone();
+            async_block! {
+                let chat_id = msg.chat.id;
+                let lang = db.get_lang(chat_id);
+                if let Some(reply) = read_only_reply(&db, lang) {
+                    await!(bot.message(chat_id, reply).send())?;
+                    return Ok(());
+                }
+                let text = msg.text.unwrap();
+                // `github:owner/repo` and the like, see `shortcut::expand`
+                let mut args: Vec<String> = text.split_whitespace()
+                    .map(|arg| shortcut::expand(arg).unwrap_or_else(|| arg.to_owned()))
+                    .collect();
+                // like `/unsubthis`, the link is taken from the replied to message
+                if args.is_empty() {
+                    args.extend(reply_link(&msg.reply_to_message));
+                }
+                let links = if !args.is_empty() && is_channel_arg(&args[0]) {
+                    &args[1..]
                 } else {
-                    Ok((
-                        bot,
-                        db,
-                        subscriber,
-                        feed_link,
-                        link_preview,
-                        chat_id,
-                        lphandle,
-                    ))
-                }.into_future()
-                    .or_else(|(bot, chat_id)| {
-                        bot.message(chat_id, "Subscribed RSS".to_string())
-                            .send()
-                            .then(|result| match result {
-                                Ok(_) => Err(None),
-                                Err(e) => Err(Some(e)),
-                            })
-                    })
-            },
-        )*/
-        .and_then(
-            |(bot, db, subscriber, feed_link, link_preview, chat_id, lphandle)| {
-                bot.message(chat_id, "Please wait while processing".to_owned())
-                    .send()
-                    .map_err(Some)
-                    .map(move |(bot, msg)| {
-                        (
-                            bot,
-                            db,
-                            subscriber,
-                            feed_link,
-                            link_preview,
-                            chat_id,
-                            msg.message_id,
-                            lphandle,
-                        )
-                    })
-            },
-        )
-        .and_then(
-            |(bot, db, subscriber, feed_link, link_preview, chat_id, msg_id, lphandle)| {
-                let session = Session::new(lphandle);
-                let bot2 = bot.clone();
-                feed::fetch_feed(session, gen_ua(&bot), feed_link)
-                    .map(move |feed| (bot2, db, subscriber, link_preview, chat_id, msg_id, feed))
-                    .or_else(move |e| {
-                        bot.edit_message_text(
-                            chat_id,
-                            msg_id,
-                            format!("Subscription failed: {}", to_chinese_error_msg(e)),
-                        ).send()
-                            .then(|result| match result {
-                                Ok(_) => Err(None),
-                                Err(e) => Err(Some(e)),
-                            })
-                    })
-            },
-        )
-        .and_then(
-            |(bot, db, subscriber, link_preview, chat_id, msg_id, feed)| {
-                let source = feed.source.as_ref().unwrap();
-                match db.subscribe(subscriber, source, &feed, link_preview) {
-                    Ok(result) => bot.edit_message_text(
+                    &args[..]
+                };
+                if links.len() > 1 && links.iter().all(|link| looks_like_link(link)) {
+                    let channel = if links.len() < args.len() {
+                        Some(args[0].clone())
+                    } else {
+                        None
+                    };
+                    let links = links
+                        .iter()
+                        .map(|link| {
+                            let link_preview = db.default_link_preview(link);
+                            (link.clone(), link_preview, SubscriptionOptions::default())
+                        })
+                        .collect();
+                    let forwarded = forwarded_post(&msg.reply_to_message);
+                    let user_id = msg.from.unwrap().id;
+                    let subscriber = match await!(resolve_manager(
+                        bot.clone(),
+                        db.clone(),
+                        channel,
+                        forwarded,
                         chat_id,
-                        msg_id,
-                        format!(
-                            "「<a href=\"{source}\">{title}</a>」{action}success{lp_status}",
-                            source = EscapeUrl(source),
-                            title  = Escape(&feed.title),
-                            action = match result {
-                                SubscriptionResult::NewlySubscribed => "订阅",
-                                SubscriptionResult::LinkPreviewUpdated => "订阅更新",
-                            },
-                            lp_status = match link_preview {
-                                LinkPreview::Off => "\nLink Preview deactivated".to_string(),
-                                LinkPreview::On => "\nLink Preview activated".to_string(),
-                                LinkPreview::InstantView(rhash) => {
-                                    format!("\n Instant View activated, rhash: {:x}", rhash)
-                                }
-                            },
-                        ),
-                    ).parse_mode("HTML")
-                        .disable_web_page_preview(true)
-                        .send(),
-                    Err(Error(ErrorKind::AlreadySubscribed, _)) => {
-                        bot.edit_message_text(chat_id, msg_id, "Subscribed RSS".to_string())
-                            .send()
+                        user_id,
+                        lang
+                    ))? {
+                        Some(subscriber) => subscriber,
+                        None => return Ok(()),
+                    };
+                    let session = Session::new(lphandle);
+                    await!(subscribe_many(bot, db, session, chat_id, user_id, subscriber,
+                                          links, self_hosts))?;
+                    return Ok(());
+                }
+                // without one, the rhash known for the domain is used, see `/iv`
+                let (channel, feed_link, link_preview) = match args.len() {
+                    1 => (None, args[0].clone(), None),
+                    // not a channel, link preview on or off
+                    2 if args[1].to_ascii_lowercase() == "on" => {
+                        (None, args[0].clone(), Some(LinkPreview::On))
                     }
-                    Err(e) => {
-                        log_error(&e);
-                        bot.edit_message_text(chat_id, msg_id, format!("error: {}", e))
-                            .send()
+                    2 if args[1].to_ascii_lowercase() == "off" => {
+                        (None, args[0].clone(), Some(LinkPreview::Off))
+                    }
+                    2 => (Some(args[0].clone()), args[1].clone(), None),
+                    3 => (
+                        Some(args[0].clone()),
+                        args[1].clone(),
+                        Some(LinkPreview::from_iv_rhash(
+                            u64::from_str_radix(&args[2], 16).unwrap_or(u64::max_value()),
+                        )),
+                    ),
+                    _ => {
+                        let usage = lang.usage(
+                            "/sub [Channel ID] <RSS URL> [InstantView RHASH]\n\
+                             /sub [Channel ID] <RSS URL> <RSS URL>...\n\
+                             /sub (as a reply to a message with a link)",
+                        );
+                        await!(bot.message(chat_id, usage).send())?;
+                        return Ok(());
+                    }
+                };
+                if link_in_domains(&feed_link, &self_hosts) {
+                    let reason = lang.error_reason(&ErrorKind::SelfReferentialFeed.into());
+                    await!(bot.message(chat_id, lang.subscription_failed(&reason)).send())?;
+                    return Ok(());
+                }
+                if let Err(e) = db.check_domain(&feed_link) {
+                    let reason = lang.error_reason(&e);
+                    await!(bot.message(chat_id, lang.subscription_failed(&reason)).send())?;
+                    return Ok(());
+                }
+                let forwarded = forwarded_post(&msg.reply_to_message);
+                let user_id = msg.from.unwrap().id;
+                let subscriber = match await!(resolve_manager(
+                    bot.clone(),
+                    db.clone(),
+                    channel,
+                    forwarded,
+                    chat_id,
+                    user_id,
+                    lang
+                ))? {
+                    Some(subscriber) => subscriber,
+                    None => return Ok(()),
+                };
+                let subscribed = db.get_subscribed_feeds(subscriber).unwrap_or_default();
+                let resubscribe = subscribed
+                    .iter()
+                    .any(|feed| normalize_url(&feed.link) == normalize_url(&feed_link));
+                // checked before fetching, `Database::subscribe` enforces it anyway
+                if let Some(limit) = db.subscription_limit(subscriber) {
+                    if !resubscribe && subscribed.len() >= limit {
+                        await!(bot.message(chat_id, lang.quota_exceeded(limit)).send())?;
+                        return Ok(());
                     }
-                }.map_err(Some)
-            },
-        )
-        .then(|result| match result {
-            Err(Some(err)) => {
+                }
+                let link_preview =
+                    link_preview.unwrap_or_else(|| db.default_link_preview(&feed_link));
+                let msg_id = await!(bot.message(chat_id, lang.processing()).send())?.1.message_id;
+                await!(subscribe_feed(
+                    bot,
+                    db,
+                    Session::new(lphandle),
+                    chat_id,
+                    msg_id,
+                    user_id,
+                    subscriber,
+                    feed_link,
+                    link_preview,
+                    self_hosts,
+                    true
+                ))?;
+                Ok(())
+            }
+        })
+        .then(|result| {
+            if let Err(err) = result {
                 error!("telebot: {:?}", err);
-                Ok::<(), ()>(())
             }
-            _ => Ok(()),
+            Ok::<(), ()>(())
         });
 
     bot.register(handle);
 }
 
-fn register_unsub(bot: &telebot::RcBot, db: Database) {
-    let handle = bot.new_cmd("/unsub")
-        .map_err(Some)
-        .and_then(move |(bot, msg)| {
-            let text = msg.text.unwrap();
-            let args: Vec<&str> = text.split_whitespace().collect();
-            let feed_link: &str;
-            let subscriber: future::Either<_, _>;
-            match args.len() {
-                1 => {
-                    feed_link = args[0];
-                    subscriber = future::Either::A(future::ok(Some(msg.chat.id)));
-                }
-                2 => {
-                    let channel = args[0];
-                    let channel_id =
-                        check_channel(&bot, channel, msg.chat.id, msg.from.unwrap().id);
-                    subscriber = future::Either::B(channel_id);
-                    feed_link = args[1];
-                }
-                _ => {
-                    let r = bot.message(
-                        msg.chat.id,
-                        "Usage: /unsub [Channel ID] <RSS URL>".to_string(),
-                    ).send()
-                        .then(|result| match result {
-                            Ok(_) => Err(None),
-                            Err(e) => Err(Some(e)),
-                        });
-                    return future::Either::A(r);
+// the result is reported by editing `msg_id`, a web page is searched for the feeds
+// it links to when `discover` is set, see `discovery::offer`
+#[async]
+pub fn subscribe_feed(
+    bot: telebot::RcBot,
+    db: Database,
+    session: Session,
+    chat_id: i64,
+    msg_id: i64,
+    user_id: i64,
+    subscriber: i64,
+    feed_link: String,
+    link_preview: LinkPreview,
+    self_hosts: Vec<String>,
+    discover: bool,
+) -> ::std::result::Result<(), telebot::Error> {
+    let lang = db.get_lang(chat_id);
+    let ua = gen_ua(&bot);
+    // YouTube channels and playlists don't link to their feeds
+    let r = youtube::feed_link(session.clone(), ua.clone(), feed_link.clone());
+    let feed_link = match await!(r) {
+        Ok(Some(link)) => link,
+        _ => feed_link,
+    };
+    let mut headers = Vec::new();
+    let feed_link = move_credentials(&feed_link, &mut headers);
+    let r = feed::fetch_feed_with_headers(
+        session.clone(),
+        ua.clone(),
+        headers.clone(),
+        None,
+        feed_link.clone(),
+    );
+    let mut r = await!(r);
+    let maybe_page = match r {
+        Ok(_) | Err(Error(ErrorKind::Http(_), _)) => false,
+        Err(_) => true,
+    };
+    if maybe_page && discover {
+        let candidates: Vec<(String, String)> =
+            await!(feed::discover_feeds(session.clone(), ua.clone(), feed_link))
+                .unwrap_or_default()
+                .into_iter()
+                .filter(|&(ref link, _)| !link_in_domains(link, &self_hosts))
+                .collect();
+        if candidates.len() == 1 {
+            let link = candidates[0].0.clone();
+            r = await!(feed::fetch_feed_with_headers(session, ua, headers.clone(), None, link));
+        } else if candidates.len() > 1 {
+            let pending = discovery::Pending {
+                user_id: user_id,
+                subscriber: subscriber,
+                link_preview: link_preview,
+                candidates: candidates,
+            };
+            let text = lang.feeds_discovered();
+            await!(discovery::offer(bot, chat_id, msg_id, pending, text))?;
+            return Ok(());
+        }
+    }
+    let feed = match r {
+        Ok(feed) => feed,
+        Err(e) => {
+            let msg = lang.subscription_failed(&lang.error_reason(&e));
+            await!(bot.edit_message_text(chat_id, msg_id, msg).send())?;
+            return Ok(());
+        }
+    };
+    let r = subscribe_fetched(&db, subscriber, &feed, link_preview, &self_hosts).and_then(
+        |(source, result)| {
+            if !headers.is_empty() {
+                let merge = |o: &mut SubscriptionOptions| merge_headers(&mut o.headers, headers);
+                db.update_options(subscriber, &source, merge)?;
+            }
+            Ok((source, result))
+        },
+    );
+    let r = match r {
+        Ok((source, result)) => {
+            let updated = match result {
+                SubscriptionResult::NewlySubscribed => false,
+                SubscriptionResult::LinkPreviewUpdated => true,
+            };
+            let msg = lang.subscribed(&source, &feed.title, updated, link_preview);
+            bot.edit_message_text(chat_id, msg_id, msg)
+                .parse_mode("HTML")
+                .disable_web_page_preview(true)
+                .send()
+        }
+        Err(Error(ErrorKind::AlreadySubscribed, _)) => {
+            bot.edit_message_text(chat_id, msg_id, lang.already_subscribed())
+                .send()
+        }
+        Err(Error(ErrorKind::ApprovalRequired(domain), _)) => {
+            let text = lang.approval_pending(&domain);
+            let request = ApprovalRequest {
+                domain: domain,
+                link: feed.source.clone().unwrap(),
+                subscriber: subscriber,
+                link_preview: link_preview,
+                chat_id: chat_id,
+                user_id: user_id,
+            };
+            approval::request(&bot, &db, request);
+            bot.edit_message_text(chat_id, msg_id, text).send()
+        }
+        Err(e @ Error(ErrorKind::SelfReferentialFeed, _))
+        | Err(e @ Error(ErrorKind::DomainBlocked(_), _))
+        | Err(e @ Error(ErrorKind::QuotaExceeded(_), _)) => {
+            let reason = lang.error_reason(&e);
+            bot.edit_message_text(chat_id, msg_id, lang.subscription_failed(&reason))
+                .send()
+        }
+        Err(e) => {
+            log_error(&e);
+            bot.edit_message_text(chat_id, msg_id, lang.unexpected_error(&e))
+                .send()
+        }
+    };
+    await!(r)?;
+    Ok(())
+}
+
+// returns the link the feed is subscribed under
+fn subscribe_fetched(
+    db: &Database,
+    subscriber: i64,
+    feed: &feed::RSS,
+    link_preview: LinkPreview,
+    self_hosts: &[String],
+) -> Result<(String, SubscriptionResult)> {
+    let source = feed.source.clone().unwrap();
+    // the original link may redirect to us
+    if link_in_domains(&source, self_hosts) {
+        return Err(ErrorKind::SelfReferentialFeed.into());
+    }
+    db.check_domain(&source)?;
+    db.check_approval(&source)?;
+    let result = db.subscribe(subscriber, &source, feed, link_preview)?;
+    Ok((source, result))
+}
+
+// used by `/sub` with several links and `/import`, replies with a single summary
+#[async]
+fn subscribe_many(
+    bot: telebot::RcBot,
+    db: Database,
+    session: Session,
+    chat_id: i64,
+    user_id: i64,
+    subscriber: i64,
+    links: Vec<(String, LinkPreview, SubscriptionOptions)>,
+    self_hosts: Vec<String>,
+) -> ::std::result::Result<(), telebot::Error> {
+    let lang = db.get_lang(chat_id);
+    await!(bot.message(chat_id, lang.processing()).send())?;
+    let limit = db.subscription_limit(subscriber);
+    let total = links.len();
+    let mut lines = Vec::with_capacity(total);
+    let mut succeeded = 0;
+    for (link, link_preview, mut options) in links {
+        let link = move_credentials(&link, &mut options.headers);
+        let subscribed = db.get_subscribed_feeds(subscriber).unwrap_or_default();
+        if let Some(limit) = limit {
+            if subscribed.len() >= limit {
+                lines.push(lang.summary_failed(&link, &lang.quota_exceeded(limit)));
+                continue;
+            }
+        }
+        let r = if link_in_domains(&link, &self_hosts) {
+            Err(ErrorKind::SelfReferentialFeed.into())
+        } else if let Err(e) = db.check_domain(&link) {
+            Err(e)
+        } else {
+            let ua = gen_ua(&bot);
+            let r = youtube::feed_link(session.clone(), ua.clone(), link.clone());
+            let link = await!(r).ok().and_then(|link| link).unwrap_or_else(|| link.clone());
+            let headers = options.headers.clone();
+            let proxy = db.get_feed(&link).and_then(|feed| feed.proxy);
+            let r =
+                feed::fetch_feed_with_headers(session.clone(), ua, headers, proxy, link.clone());
+            await!(r).and_then(|feed| {
+                let (source, _) =
+                    subscribe_fetched(&db, subscriber, &feed, link_preview, &self_hosts)?;
+                if options != SubscriptionOptions::default() {
+                    db.update_options(subscriber, &source, |o| {
+                        let initial_items = o.initial_items.clone();
+                        *o = options;
+                        o.initial_items = initial_items;
+                    })?;
                 }
+                Ok((source, feed.title))
+            })
+        };
+        let line = match r {
+            Ok((source, title)) => {
+                succeeded += 1;
+                lang.summary_succeeded(&source, &title)
+            }
+            Err(Error(ErrorKind::AlreadySubscribed, _)) => {
+                lang.summary_failed(&link, &lang.already_subscribed())
             }
+            Err(Error(ErrorKind::ApprovalRequired(domain), _)) => {
+                let line = lang.summary_failed(&link, &lang.approval_pending(&domain));
+                let request = ApprovalRequest {
+                    domain: domain,
+                    link: link,
+                    subscriber: subscriber,
+                    link_preview: link_preview,
+                    chat_id: chat_id,
+                    user_id: user_id,
+                };
+                approval::request(&bot, &db, request);
+                line
+            }
+            Err(e) => lang.summary_failed(&link, &lang.error_reason(&e)),
+        };
+        lines.push(line);
+    }
+    let msgs = format_and_split_msgs(lang.summary(succeeded, total), &lines, |line| {
+        line.clone()
+    });
+    await!(send_multiple_messages(&bot, chat_id, msgs, false))?;
+    Ok(())
+}
+
+// credentials in `link` are sent in an `Authorization` header instead, so they
+// aren't shown or exported as part of the link
+fn move_credentials(link: &str, headers: &mut feed::Headers) -> String {
+    let (link, authorization) = split_credentials(link);
+    if let Some(authorization) = authorization {
+        merge_headers(headers, vec![("Authorization".to_owned(), authorization)]);
+    }
+    link
+}
+
+// replaces the headers of the same names
+fn merge_headers(headers: &mut feed::Headers, new_headers: feed::Headers) {
+    headers.retain(|&(ref name, _)| {
+        !new_headers.iter().any(|&(ref new_name, _)| new_name.eq_ignore_ascii_case(name))
+    });
+    headers.extend(new_headers);
+}
+
+// channels are given as a numeric ID or `@username`
+fn is_channel_arg(s: &str) -> bool {
+    s.starts_with('@') || s.parse::<i64>().is_ok()
+}
+
+fn looks_like_link(s: &str) -> bool {
+    s.contains("://") || s.contains('.')
+}
+
+fn register_unsub(bot: &telebot::RcBot, db: Database) {
+    let handle = ratelimit::new_cmd(bot, &db, "/unsub")
+        .and_then(move |(bot, msg)| {
             let db = db.clone();
-            let feed_link = feed_link.to_owned();
-            let chat_id = msg.chat.id;
-            let r = subscriber
-                .then(|result| match result {
-                    Ok(Some(ok)) => Ok(ok),
-                    Ok(None) => Err(None),
-                    Err(err) => Err(Some(err)),
-                })
-                .map(move |subscriber| (bot, db, subscriber, feed_link, chat_id));
-            future::Either::B(r)
-        })
-        .and_then(|(bot, db, subscriber, feed_link, chat_id)| {
-            match db.unsubscribe(subscriber, &feed_link) {
-                Ok(feed) => {
-                    bot.message(
-                        chat_id,
-                        format!(
-                            "「<a href=\"{}\">{}</a>」退订成功",
-                            EscapeUrl(&feed.link),
-                            Escape(&feed.title)
-                        ),
-                    ).parse_mode("HTML")
-                        .disable_web_page_preview(true)
-                        .send()
+            async_block! {
+                let chat_id = msg.chat.id;
+                let lang = db.get_lang(chat_id);
+                if let Some(reply) = read_only_reply(&db, lang) {
+                    await!(bot.message(chat_id, reply).send())?;
+                    return Ok(());
                 }
-                Err(Error(ErrorKind::NotSubscribed, _)) => {
-                    bot.message(chat_id, "Unsubscribed RSS".to_string())
-                        .send()
+                let text = msg.text.unwrap();
+                let args: Vec<String> = text.split_whitespace().map(str::to_owned).collect();
+                let (channel, links) = match args.len() {
+                    0 => (None, Vec::new()),
+                    _ if is_channel_arg(&args[0]) => (Some(args[0].clone()), args[1..].to_vec()),
+                    _ => (None, args.clone()),
+                };
+                if links.is_empty() {
+                    let usage = lang.usage("/unsub [Channel ID] <RSS URL> [RSS URL]...");
+                    await!(bot.message(chat_id, usage).send())?;
+                    return Ok(());
                 }
-                Err(e) => {
-                    log_error(&e);
-                    bot.message(chat_id, format!("error: {}", e)).send()
+                let forwarded = forwarded_post(&msg.reply_to_message);
+                let user_id = msg.from.unwrap().id;
+                let subscriber = match await!(resolve_manager(
+                    bot.clone(),
+                    db.clone(),
+                    channel,
+                    forwarded,
+                    chat_id,
+                    user_id,
+                    lang
+                ))? {
+                    Some(subscriber) => subscriber,
+                    None => return Ok(()),
+                };
+                if links.len() == 1 {
+                    let feed_link = links[0].clone();
+                    await!(unsubscribe(bot, db, subscriber, feed_link, chat_id, lang))?;
+                    return Ok(());
                 }
-            }.map_err(Some)
+                let mut succeeded = 0;
+                let lines: Vec<String> = links
+                    .iter()
+                    .map(|link| match db.unsubscribe(subscriber, link) {
+                        Ok(feed) => {
+                            db.remember_left_feed(subscriber, &feed);
+                            succeeded += 1;
+                            lang.summary_succeeded(&feed.link, &feed.title)
+                        }
+                        Err(Error(ErrorKind::NotSubscribed, _)) => {
+                            lang.summary_failed(link, &lang.not_subscribed())
+                        }
+                        Err(e) => {
+                            log_error(&e);
+                            lang.summary_failed(link, &lang.unexpected_error(&e))
+                        }
+                    })
+                    .collect();
+                let head = lang.summary(succeeded, links.len());
+                let msgs = format_and_split_msgs(head, &lines, |line| line.clone());
+                await!(send_multiple_messages(&bot, chat_id, msgs, false))?;
+                Ok(())
+            }
         })
-        .then(|result| match result {
-            Err(Some(err)) => {
+        .then(|result| {
+            if let Err(err) = result {
                 error!("telebot: {:?}", err);
-                Ok::<(), ()>(())
             }
-            _ => Ok(()),
+            Ok::<(), ()>(())
         });
 
     bot.register(handle);
 }
 
 fn register_unsubthis(bot: &telebot::RcBot, db: Database) {
-    let handle = bot.new_cmd("/unsubthis")
-        .map_err(Some)
+    let handle = ratelimit::new_cmd(bot, &db, "/unsubthis")
         .and_then(move |(bot, msg)| {
-            if let Some(reply_msg) = msg.reply_to_message {
-                Ok((bot, db.clone(), msg.chat.id, reply_msg))
-            } else {
-                Err((bot, msg.chat.id))
-            }.into_future()
-                .or_else(|(bot, chat_id)| {
-                    bot.message(
-                        chat_id,
-                        "Usage: \
-                         Use this command as a reply to RSS Feed messages you want to unsubscribe,\
-                         doesn't work on channels"
-                            .to_string(),
-                    ).send()
-                        .then(|result| match result {
-                            Ok(_) => Err(None),
-                            Err(e) => Err(Some(e)),
-                        })
-                })
-        })
-        .and_then(|(bot, db, chat_id, reply_msg)| {
-            if let Some(m) = reply_msg.text {
-                if let Some(title) = m.lines().next() {
-                    Ok((bot, db, chat_id, title.to_string()))
-                } else {
-                    Err((bot, chat_id))
-                }
-            } else {
-                Err((bot, chat_id))
-            }.into_future()
-                .or_else(|(bot, chat_id)| {
-                    bot.message(chat_id, "Message unrecognized".to_string())
-                        .send()
-                        .then(|result| match result {
-                            Ok(_) => Err(None),
-                            Err(e) => Err(Some(e)),
-                        })
-                })
-        })
-        .and_then(|(bot, db, chat_id, title)| {
-            if let Some(feed_link) = db.get_subscribed_feeds(chat_id)
-                .unwrap_or_default()
-                .iter()
-                .filter(|feed| feed.title == title)
-                .map(|feed| feed.link.clone())
-                .next()
-            {
-                Ok((bot, db, chat_id, feed_link))
-            } else {
-                Err((bot, chat_id))
-            }.into_future()
-                .or_else(|(bot, chat_id)| {
-                    bot.message(chat_id, "Unable to find this subscription".to_string())
-                        .send()
-                        .then(|result| match result {
-                            Ok(_) => Err(None),
-                            Err(e) => Err(Some(e)),
-                        })
-                })
-        })
-        .and_then(|(bot, db, chat_id, feed_link)| {
-            match db.unsubscribe(chat_id, &feed_link) {
-                Ok(feed) => {
-                    bot.message(
-                        chat_id,
-                        format!(
-                            "「<a href=\"{}\">{}</a>」Unsubscribe successfully",
-                            EscapeUrl(&feed.link),
-                            Escape(&feed.title)
-                        ),
-                    ).parse_mode("HTML")
-                        .disable_web_page_preview(true)
-                        .send()
+            let db = db.clone();
+            async_block! {
+                let chat_id = msg.chat.id;
+                let lang = db.get_lang(chat_id);
+                if let Some(reply) = read_only_reply(&db, lang) {
+                    await!(bot.message(chat_id, reply).send())?;
+                    return Ok(());
                 }
-                Err(e) => {
-                    log_error(&e);
-                    bot.message(chat_id, format!("error: {}", e)).send()
+                let reply_msg = match msg.reply_to_message {
+                    Some(reply_msg) => reply_msg,
+                    None => {
+                        await!(bot.message(chat_id, lang.unsubthis_usage()).send())?;
+                        return Ok(());
+                    }
+                };
+                let title = reply_msg
+                    .text
+                    .as_ref()
+                    .and_then(|text| text.lines().next().map(str::to_owned));
+                let title = match title {
+                    Some(title) => title,
+                    None => {
+                        await!(bot.message(chat_id, lang.message_unrecognized()).send())?;
+                        return Ok(());
+                    }
+                };
+                let user_id = msg.from.unwrap().id;
+                if !await!(check_group_admin(bot.clone(), db.clone(), chat_id, user_id, lang))? {
+                    return Ok(());
                 }
-            }.map_err(Some)
+                let feed_link = db.get_subscribed_feeds(chat_id)
+                    .unwrap_or_default()
+                    .into_iter()
+                    .find(|feed| feed.title == title)
+                    .map(|feed| feed.link);
+                let feed_link = match feed_link {
+                    Some(feed_link) => feed_link,
+                    None => {
+                        await!(bot.message(chat_id, lang.subscription_not_found()).send())?;
+                        return Ok(());
+                    }
+                };
+                await!(unsubscribe(bot.clone(), db.clone(), chat_id, feed_link, chat_id, lang))?;
+                Ok(())
+            }
         })
-        .then(|result| match result {
-            Err(Some(err)) => {
+        .then(|result| {
+            if let Err(err) = result {
                 error!("telebot: {:?}", err);
-                Ok::<(), ()>(())
             }
-            _ => Ok(()),
+            Ok::<(), ()>(())
         });
 
     bot.register(handle);
 }
 
+#[async]
+fn unsubscribe(
+    bot: telebot::RcBot,
+    db: Database,
+    subscriber: i64,
+    feed_link: String,
+    chat_id: i64,
+    lang: Lang,
+) -> ::std::result::Result<(), telebot::Error> {
+    let r = match db.unsubscribe(subscriber, &feed_link) {
+        Ok(feed) => {
+            db.remember_left_feed(subscriber, &feed);
+            bot.message(chat_id, lang.unsubscribed(&feed.link, &feed.title))
+                .parse_mode("HTML")
+                .disable_web_page_preview(true)
+                .send()
+        }
+        Err(Error(ErrorKind::NotSubscribed, _)) => {
+            bot.message(chat_id, lang.not_subscribed()).send()
+        }
+        Err(e) => {
+            log_error(&e);
+            bot.message(chat_id, lang.unexpected_error(&e)).send()
+        }
+    };
+    await!(r)?;
+    Ok(())
+}
+
+// the channel and text of the message being replied to, if it was forwarded from a channel
+fn forwarded_post(reply: &Option<Box<Message>>) -> Option<(i64, String)> {
+    reply.as_ref().and_then(|reply| {
+        match (reply.forward_from_chat.as_ref(), reply.text.as_ref()) {
+            (Some(chat), Some(text)) => Some((chat.id, text.clone())),
+            _ => None,
+        }
+    })
+}
+
+// the first link in the text or the caption, or else the first hidden one
+fn reply_link(reply: &Option<Box<Message>>) -> Option<String> {
+    let reply = reply.as_ref()?;
+    let text = reply.text.as_ref().or_else(|| reply.caption.as_ref());
+    text.and_then(|text| {
+        text.split_whitespace()
+            .find(|word| word.starts_with("http://") || word.starts_with("https://"))
+            .map(str::to_owned)
+    }).or_else(|| {
+        reply
+            .entities
+            .iter()
+            .flat_map(|entities| entities.iter())
+            .filter(|entity| entity.kind == "text_link")
+            .filter_map(|entity| entity.url.clone())
+            .next()
+    })
+}
+
+// only channel administrators can post a code, so a forwarded post containing the
+// one given to the user in this chat proves ownership when the bot isn't allowed
+// to list the administrators, see `Database::ownership_code`
 fn check_channel<'a>(
     bot: &telebot::RcBot,
+    db: &Database,
     channel: &str,
+    forwarded: Option<(i64, String)>,
     chat_id: i64,
     user_id: i64,
+    lang: Lang,
 ) -> impl Future<Item = Option<i64>, Error = telebot::Error> + 'a {
     let channel = channel
         .parse::<i64>()
@@ -500,24 +1121,28 @@ fn check_channel<'a>(
             channel.to_owned()
         });
     let bot = bot.clone();
+    let db = db.clone();
     async_block! {
-        let msg = await!(bot.message(chat_id, "Verifying Channel".to_string()).send())?.1;
+        let msg = await!(bot.message(chat_id, lang.verifying_channel()).send())?.1;
         let msg_id = msg.message_id;
         let channel = match await!(bot.get_chat(channel).send()) {
             Ok((_, channel)) => channel,
             Err(telebot::Error::Telegram(_, err_msg, _)) => {
-                let msg = format!("Unable to find Channel: {}", err_msg);
+                let msg = lang.channel_not_found(&err_msg);
                 await!(bot.edit_message_text(chat_id, msg_id, msg).send())?;
                 return Ok(None);
             }
             Err(e) => return Err(e),
         };
         if channel.kind != "channel" {
-            let msg = "Target needs to be a Channel".to_string();
+            let msg = lang.not_a_channel();
             await!(bot.edit_message_text(chat_id, msg_id, msg).send())?;
             return Ok(None);
         }
         let channel_id = channel.id;
+        let verified = forwarded
+            .and_then(|(from, text)| if from == channel_id { Some(text) } else { None })
+            .map_or(false, |text| db.use_ownership_code(channel_id, chat_id, user_id, &text));
 
         let admins_list = match await!(bot.get_chat_administrators(channel_id).send()) {
             Ok((_, admins)) => admins
@@ -525,7 +1150,12 @@ fn check_channel<'a>(
                 .map(|member| member.user.id)
                 .collect::<Vec<i64>>(),
             Err(telebot::Error::Telegram(_, err_msg, _)) => {
-                let msg = format!("Please add the Bot to the target channel and give it administrator permissions: {}", err_msg);
+                if verified {
+                    await!(bot.delete_message(chat_id, msg_id).send())?;
+                    return Ok(Some(channel_id));
+                }
+                let code = db.ownership_code(channel_id, chat_id, user_id);
+                let msg = lang.ownership_challenge(&err_msg, &code);
                 await!(bot.edit_message_text(chat_id, msg_id, msg).send())?;
                 return Ok(None);
             }
@@ -533,13 +1163,20 @@ fn check_channel<'a>(
         };
 
         if !admins_list.contains(&bot.inner.id) {
-            let msg = "Please give administrator permissions to the bot".to_string();
+            let msg = lang.bot_not_admin();
             await!(bot.edit_message_text(chat_id, msg_id, msg).send())?;
             return Ok(None);
         }
 
-        if !admins_list.contains(&user_id) {
-            let msg = "This command can only be used by channel administrators".to_string();
+        // anonymous administrators of a group all show up as `GROUP_ANONYMOUS_BOT`,
+        // they prove it by posting the code like when the list is hidden
+        if !admins_list.contains(&user_id) && !verified {
+            let msg = if user_id == GROUP_ANONYMOUS_BOT {
+                let code = db.ownership_code(channel_id, chat_id, user_id);
+                lang.anonymous_admin_challenge(&code)
+            } else {
+                lang.user_not_admin()
+            };
             await!(bot.edit_message_text(chat_id, msg_id, msg).send())?;
             return Ok(None);
         }
@@ -551,73 +1188,3021 @@ fn check_channel<'a>(
 }
 
 fn register_export(bot: &telebot::RcBot, db: Database) {
-    let handle = bot.new_cmd("/export")
-        .map_err(Some)
-        .and_then(move |(bot, msg)| {
-            let text = msg.text.unwrap();
-            let args: Vec<&str> = text.split_whitespace().collect();
-            let subscriber: future::Either<_, _>;
-            match args.len() {
-                0 => {
-                    subscriber = future::Either::A(future::ok(Some(msg.chat.id)));
-                }
-                1 => {
-                    let channel = args[0];
-                    let channel_id =
-                        check_channel(&bot, channel, msg.chat.id, msg.from.unwrap().id);
-                    subscriber = future::Either::B(channel_id);
-                }
-                _ => {
-                    let r = bot.message(
-                        msg.chat.id,
-                        "Usage: /export <Channel ID>".to_string(),
-                    ).send()
-                        .then(|result| match result {
-                            Ok(_) => Err(None),
-                            Err(e) => Err(Some(e)),
-                        });
-                    return future::Either::A(r);
-                }
-            }
-            let db = db.clone();
-            let chat_id = msg.chat.id;
-            let r = subscriber
-                .then(|result| match result {
-                    Ok(Some(ok)) => Ok(ok),
-                    Ok(None) => Err(None),
-                    Err(err) => Err(Some(err)),
-                })
-                .map(move |subscriber| (bot, db, subscriber, chat_id));
-            future::Either::B(r)
-        })
-        .and_then(|(bot, db, subscriber, chat_id)| {
-            match db.get_subscribed_feeds(subscriber) {
-                Some(feeds) => Ok((bot, chat_id, feeds)),
-                None => Err((bot, chat_id)),
-            }.into_future()
-                .or_else(|(bot, chat_id)| {
-                    bot.message(chat_id, "Subscription list is empty".to_string())
-                        .send()
-                        .then(|r| match r {
-                            Ok(_) => Err(None),
-                            Err(e) => Err(Some(e)),
-                        })
-                })
+    let handle = ratelimit::new_cmd(bot, &db, "/export")
+        .and_then(move |(bot, msg)| {
+            let db = db.clone();
+            async_block! {
+                let chat_id = msg.chat.id;
+                let lang = db.get_lang(chat_id);
+                let text = msg.text.unwrap();
+                let args: Vec<String> = text.split_whitespace().map(str::to_owned).collect();
+                // `settings` is OPML plus the JSON, for moving to another bot
+                let is_format = |s: &str| ["opml", "json", "csv", "settings"].contains(&s);
+                let (channel, format) = match args.len() {
+                    0 => (None, "opml".to_owned()),
+                    1 if is_format(args[0].as_str()) => (None, args[0].clone()),
+                    1 => (Some(args[0].clone()), "opml".to_owned()),
+                    2 if is_format(args[1].as_str()) => (Some(args[0].clone()), args[1].clone()),
+                    _ => {
+                        let usage = lang.usage("/export <Channel ID> [opml|json|csv|settings]");
+                        await!(bot.message(chat_id, usage).send())?;
+                        return Ok(());
+                    }
+                };
+                let forwarded = forwarded_post(&msg.reply_to_message);
+                let user_id = msg.from.unwrap().id;
+                let subscriber = match await!(resolve_subscriber(
+                    bot.clone(),
+                    db.clone(),
+                    channel,
+                    forwarded,
+                    chat_id,
+                    user_id,
+                    lang
+                ))? {
+                    Some(subscriber) => subscriber,
+                    None => return Ok(()),
+                };
+                let feeds = match db.get_subscribed_feeds(subscriber) {
+                    Some(feeds) => feeds,
+                    None => {
+                        await!(bot.message(chat_id, lang.subscription_list_empty()).send())?;
+                        return Ok(());
+                    }
+                };
+                if format == "json" {
+                    let json = export_json(&db, subscriber, feeds);
+                    let file = File::new("feeds.json".into(), json.into_bytes());
+                    await!(bot.document(chat_id, file).send())?;
+                    return Ok(());
+                }
+                if format == "csv" {
+                    let csv = to_csv(&["title", "link"], &feeds, |feed| {
+                        vec![feed.title.clone(), feed.link.clone()]
+                    });
+                    let file = File::new("feeds.csv".into(), csv.into_bytes());
+                    await!(bot.document(chat_id, file).send())?;
+                    return Ok(());
+                }
+                let settings = if format == "settings" {
+                    Some(export_json(&db, subscriber, feeds.clone()))
+                } else {
+                    None
+                };
+                let feeds = feeds
+                    .into_iter()
+                    .map(|feed| {
+                        let feed_id = feed.get_id();
+                        let link_preview = db.get_link_preview(subscriber, feed_id)
+                            .unwrap_or(LinkPreview::Off);
+                        (feed, link_preview, db.get_options(subscriber, feed_id))
+                    })
+                    .collect();
+                let file = File::new("feeds.opml".into(), to_opml(feeds).into_bytes());
+                await!(bot.document(chat_id, file).send())?;
+                if let Some(settings) = settings {
+                    let file = File::new("settings.json".into(), settings.into_bytes());
+                    await!(bot.document(chat_id, file).send())?;
+                }
+                Ok(())
+            }
+        })
+        .then(|result| {
+            if let Err(err) = result {
+                error!("telebot: {:?}", err);
+            }
+            Ok::<(), ()>(())
+        });
+
+    bot.register(handle);
+}
+
+// `/import [Channel ID]`, as a reply to an OPML or JSON file from `/export`
+fn register_import(
+    bot: &telebot::RcBot,
+    db: Database,
+    lphandle: Handle,
+    self_hosts: Vec<String>,
+) {
+    let handle = ratelimit::new_cmd(bot, &db, "/import")
+        .and_then(move |(bot, msg)| {
+            let db = db.clone();
+            let lphandle = lphandle.clone();
+            let self_hosts = self_hosts.clone();
+            async_block! {
+                let chat_id = msg.chat.id;
+                let lang = db.get_lang(chat_id);
+                if let Some(reply) = read_only_reply(&db, lang) {
+                    await!(bot.message(chat_id, reply).send())?;
+                    return Ok(());
+                }
+                let text = msg.text.unwrap();
+                let args: Vec<String> = text.split_whitespace().map(str::to_owned).collect();
+                let channel = match args.len() {
+                    0 => None,
+                    1 => Some(args[0].clone()),
+                    _ => {
+                        let usage = lang.usage("/import <Channel ID>");
+                        await!(bot.message(chat_id, usage).send())?;
+                        return Ok(());
+                    }
+                };
+                let file_id = msg.reply_to_message
+                    .as_ref()
+                    .and_then(|reply| reply.document.as_ref())
+                    .map(|document| document.file_id.clone());
+                let file_id = match file_id {
+                    Some(file_id) => file_id,
+                    None => {
+                        await!(bot.message(chat_id, lang.import_file_missing()).send())?;
+                        return Ok(());
+                    }
+                };
+                let user_id = msg.from.unwrap().id;
+                // the reply is taken by the file, channels are checked by their admin list
+                let subscriber = match await!(resolve_manager(
+                    bot.clone(),
+                    db.clone(),
+                    channel,
+                    None,
+                    chat_id,
+                    user_id,
+                    lang
+                ))? {
+                    Some(subscriber) => subscriber,
+                    None => return Ok(()),
+                };
+                let (bot, file) = await!(bot.get_file(file_id).send())?;
+                let url = format!(
+                    "https://api.telegram.org/file/bot{}/{}",
+                    bot.inner.key,
+                    file.file_path.unwrap_or_default()
+                );
+                let session = Session::new(lphandle);
+                let r = await!(feed::fetch_page(session.clone(), gen_ua(&bot), url))
+                    .and_then(|body| import_file(&db, subscriber, &body));
+                let links = match r {
+                    Ok(ref links) if links.is_empty() => {
+                        await!(bot.message(chat_id, lang.import_empty()).send())?;
+                        return Ok(());
+                    }
+                    Ok(links) => links,
+                    Err(e) => {
+                        await!(bot.message(chat_id, lang.error_reason(&e)).send())?;
+                        return Ok(());
+                    }
+                };
+                await!(subscribe_many(bot, db, session, chat_id, user_id, subscriber, links,
+                                      self_hosts))?;
+                Ok(())
+            }
         })
-        .and_then(|(bot, chat_id, feeds)| {
-            bot.document(
-                chat_id,
-                File::new("feeds.opml".into(), to_opml(feeds).into_bytes()),
-            ).send()
-                .map_err(Some)
+        .then(|result| {
+            if let Err(err) = result {
+                error!("telebot: {:?}", err);
+            }
+            Ok::<(), ()>(())
+        });
+
+    bot.register(handle);
+}
+
+// commands of the form `/command [Channel ID] <RSS URL> <on|off>`
+fn register_toggle(
+    bot: &telebot::RcBot,
+    db: Database,
+    command: &'static str,
+    set: fn(&mut SubscriptionOptions, bool),
+    reply: fn(Lang, bool) -> String,
+) {
+    let handle = ratelimit::new_cmd(bot, &db, command)
+        .and_then(move |(bot, msg)| {
+            let db = db.clone();
+            async_block! {
+                let chat_id = msg.chat.id;
+                let lang = db.get_lang(chat_id);
+                let text = msg.text.unwrap();
+                let args: Vec<String> = text.split_whitespace().map(str::to_owned).collect();
+                let (channel, feed_link, state) = match args.len() {
+                    2 => (None, args[0].clone(), args[1].to_ascii_lowercase()),
+                    3 => (Some(args[0].clone()), args[1].clone(), args[2].to_ascii_lowercase()),
+                    _ => (None, String::new(), String::new()),
+                };
+                let enable = match state.as_str() {
+                    "on" => true,
+                    "off" => false,
+                    _ => {
+                        let syntax = format!("{} [Channel ID] <RSS URL> <on|off>", command);
+                        let usage = lang.usage(&syntax);
+                        await!(bot.message(chat_id, usage).send())?;
+                        return Ok(());
+                    }
+                };
+                let forwarded = forwarded_post(&msg.reply_to_message);
+                let user_id = msg.from.unwrap().id;
+                let subscriber = match await!(resolve_manager(
+                    bot.clone(),
+                    db.clone(),
+                    channel,
+                    forwarded,
+                    chat_id,
+                    user_id,
+                    lang
+                ))? {
+                    Some(subscriber) => subscriber,
+                    None => return Ok(()),
+                };
+                let reply = match db.update_options(subscriber, &feed_link, |o| set(o, enable)) {
+                    Ok(()) => reply(lang, enable),
+                    Err(Error(ErrorKind::NotSubscribed, _)) => lang.not_subscribed(),
+                    Err(e) => {
+                        log_error(&e);
+                        lang.unexpected_error(&e)
+                    }
+                };
+                await!(bot.message(chat_id, reply).send())?;
+                Ok(())
+            }
         })
-        .then(|result| match result {
-            Err(Some(err)) => {
+        .then(|result| {
+            if let Err(err) = result {
                 error!("telebot: {:?}", err);
-                Ok::<(), ()>(())
             }
-            _ => Ok(()),
+            Ok::<(), ()>(())
         });
 
     bot.register(handle);
 }
+
+// `/template [Channel ID] <RSS URL> <template|off>`, the template is the rest of the text
+// `/tag [Channel ID] <RSS URL> <Tag>` and `/untag` with the same arguments
+fn register_tag(bot: &telebot::RcBot, db: Database, cmd: &'static str, add: bool) {
+    let handle = ratelimit::new_cmd(bot, &db, cmd)
+        .and_then(move |(bot, msg)| {
+            let db = db.clone();
+            async_block! {
+                let chat_id = msg.chat.id;
+                let lang = db.get_lang(chat_id);
+                let text = msg.text.unwrap();
+                let args: Vec<String> = text.split_whitespace().map(str::to_owned).collect();
+                let (channel, feed_link, tag) = match args.len() {
+                    2 => (None, args[0].clone(), args[1].clone()),
+                    3 => (Some(args[0].clone()), args[1].clone(), args[2].clone()),
+                    _ => {
+                        let usage = lang.usage(&format!("{} [Channel ID] <RSS URL> <Tag>", cmd));
+                        await!(bot.message(chat_id, usage).send())?;
+                        return Ok(());
+                    }
+                };
+                let forwarded = forwarded_post(&msg.reply_to_message);
+                let user_id = msg.from.unwrap().id;
+                let subscriber = match await!(resolve_manager(
+                    bot.clone(),
+                    db.clone(),
+                    channel,
+                    forwarded,
+                    chat_id,
+                    user_id,
+                    lang
+                ))? {
+                    Some(subscriber) => subscriber,
+                    None => return Ok(()),
+                };
+                let r = db.update_options(subscriber, &feed_link, |o| {
+                    o.tags.retain(|t| *t != tag);
+                    if add {
+                        o.tags.push(tag.clone());
+                        o.tags.sort();
+                    }
+                });
+                let reply = match r {
+                    Ok(()) if add => lang.tag_added(&tag),
+                    Ok(()) => lang.tag_removed(&tag),
+                    Err(Error(ErrorKind::NotSubscribed, _)) => lang.not_subscribed(),
+                    Err(e) => {
+                        log_error(&e);
+                        lang.unexpected_error(&e)
+                    }
+                };
+                await!(bot.message(chat_id, reply).send())?;
+                Ok(())
+            }
+        })
+        .then(|result| {
+            if let Err(err) = result {
+                error!("telebot: {:?}", err);
+            }
+            Ok::<(), ()>(())
+        });
+
+    bot.register(handle);
+}
+
+// `/mirror [Channel ID] <RSS URL> <Target Channel ID>` also posts what the subscription
+// delivers to a channel the user administers, `/unmirror` with the same arguments stops it
+fn register_mirror(bot: &telebot::RcBot, db: Database, cmd: &'static str, add: bool) {
+    let handle = ratelimit::new_cmd(bot, &db, cmd)
+        .and_then(move |(bot, msg)| {
+            let db = db.clone();
+            async_block! {
+                let chat_id = msg.chat.id;
+                let lang = db.get_lang(chat_id);
+                let text = msg.text.unwrap();
+                let args: Vec<String> = text.split_whitespace().map(str::to_owned).collect();
+                let (channel, feed_link, target) = match args.len() {
+                    2 => (None, args[0].clone(), args[1].clone()),
+                    3 => (Some(args[0].clone()), args[1].clone(), args[2].clone()),
+                    _ => {
+                        let syntax = format!("{} [Channel ID] <RSS URL> <Target Channel ID>", cmd);
+                        await!(bot.message(chat_id, lang.usage(&syntax)).send())?;
+                        return Ok(());
+                    }
+                };
+                let forwarded = forwarded_post(&msg.reply_to_message);
+                let user_id = msg.from.unwrap().id;
+                let subscriber = match await!(resolve_manager(
+                    bot.clone(),
+                    db.clone(),
+                    channel,
+                    forwarded.clone(),
+                    chat_id,
+                    user_id,
+                    lang
+                ))? {
+                    Some(subscriber) => subscriber,
+                    None => return Ok(()),
+                };
+                let target_id = match await!(
+                    check_channel(&bot, &db, &target, forwarded, chat_id, user_id, lang)
+                )? {
+                    Some(target_id) => target_id,
+                    None => return Ok(()),
+                };
+                if target_id == subscriber {
+                    await!(bot.message(chat_id, lang.mirror_to_itself()).send())?;
+                    return Ok(());
+                }
+                let reply = match db.set_mirror(subscriber, &feed_link, target_id, add) {
+                    Ok(true) if add => lang.mirror_added(&target),
+                    Ok(true) => lang.mirror_removed(&target),
+                    Ok(false) if add => lang.already_mirrored(&target),
+                    Ok(false) => lang.not_mirrored(&target),
+                    Err(Error(ErrorKind::NotSubscribed, _)) => lang.not_subscribed(),
+                    Err(e) => {
+                        log_error(&e);
+                        lang.unexpected_error(&e)
+                    }
+                };
+                await!(bot.message(chat_id, reply).send())?;
+                Ok(())
+            }
+        })
+        .then(|result| {
+            if let Err(err) = result {
+                error!("telebot: {:?}", err);
+            }
+            Ok::<(), ()>(())
+        });
+
+    bot.register(handle);
+}
+
+// `/copy <From Channel ID> <To Channel ID>` subscribes the second channel to every feed
+// of the first, with the same link preview, the user has to administer both
+fn register_copy(bot: &telebot::RcBot, db: Database) {
+    let handle = ratelimit::new_cmd(bot, &db, "/copy")
+        .and_then(move |(bot, msg)| {
+            let db = db.clone();
+            async_block! {
+                let chat_id = msg.chat.id;
+                let lang = db.get_lang(chat_id);
+                let text = msg.text.unwrap();
+                let args: Vec<String> = text.split_whitespace().map(str::to_owned).collect();
+                if args.len() != 2 {
+                    let usage = lang.usage("/copy <From Channel ID> <To Channel ID>");
+                    await!(bot.message(chat_id, usage).send())?;
+                    return Ok(());
+                }
+                let forwarded = forwarded_post(&msg.reply_to_message);
+                let user_id = msg.from.unwrap().id;
+                let from = match await!(
+                    check_channel(&bot, &db, &args[0], forwarded.clone(), chat_id, user_id, lang)
+                )? {
+                    Some(from) => from,
+                    None => return Ok(()),
+                };
+                let to = match await!(
+                    check_channel(&bot, &db, &args[1], forwarded, chat_id, user_id, lang)
+                )? {
+                    Some(to) => to,
+                    None => return Ok(()),
+                };
+                let reply = match db.copy_subscriptions(from, to) {
+                    Ok((added, existing)) => lang.subscriptions_copied(added, existing),
+                    Err(e @ Error(ErrorKind::QuotaExceeded(_), _)) => lang.error_reason(&e),
+                    Err(e) => {
+                        log_error(&e);
+                        lang.unexpected_error(&e)
+                    }
+                };
+                await!(bot.message(chat_id, reply).send())?;
+                Ok(())
+            }
+        })
+        .then(|result| {
+            if let Err(err) = result {
+                error!("telebot: {:?}", err);
+            }
+            Ok::<(), ()>(())
+        });
+
+    bot.register(handle);
+}
+
+// `/erase [Channel ID]`, removes everything kept about the chat once confirmed
+fn register_erase(bot: &telebot::RcBot, db: Database) {
+    let handle = ratelimit::new_cmd(bot, &db, "/erase")
+        .and_then(move |(bot, msg)| {
+            let db = db.clone();
+            async_block! {
+                let chat_id = msg.chat.id;
+                let lang = db.get_lang(chat_id);
+                if let Some(reply) = read_only_reply(&db, lang) {
+                    await!(bot.message(chat_id, reply).send())?;
+                    return Ok(());
+                }
+                let text = msg.text.unwrap();
+                let args: Vec<String> = text.split_whitespace().map(str::to_owned).collect();
+                let channel = match args.len() {
+                    0 => None,
+                    1 => Some(args[0].clone()),
+                    _ => {
+                        await!(bot.message(chat_id, lang.usage("/erase [Channel ID]")).send())?;
+                        return Ok(());
+                    }
+                };
+                let forwarded = forwarded_post(&msg.reply_to_message);
+                let user_id = msg.from.unwrap().id;
+                let subscriber = match await!(resolve_manager(
+                    bot.clone(),
+                    db.clone(),
+                    channel,
+                    forwarded,
+                    chat_id,
+                    user_id,
+                    lang
+                ))? {
+                    Some(subscriber) => subscriber,
+                    None => return Ok(()),
+                };
+                await!(erase::confirm(&bot, &db, chat_id, subscriber, user_id))?;
+                Ok(())
+            }
+        })
+        .then(|result| {
+            if let Err(err) = result {
+                error!("telebot: {:?}", err);
+            }
+            Ok::<(), ()>(())
+        });
+
+    bot.register(handle);
+}
+
+fn register_template(bot: &telebot::RcBot, db: Database) {
+    let handle = ratelimit::new_cmd(bot, &db, "/template")
+        .and_then(move |(bot, msg)| {
+            let db = db.clone();
+            async_block! {
+                let chat_id = msg.chat.id;
+                let lang = db.get_lang(chat_id);
+                let text = msg.text.unwrap();
+                let (channel, feed_link, template) = match template_args(&text) {
+                    Some(args) => args,
+                    None => {
+                        let syntax = "/template [Channel ID] <RSS URL> <template|off>\n\
+                                      {title} {link} {feed} {feed_link} {pubdate} {author}";
+                        await!(bot.message(chat_id, lang.usage(syntax)).send())?;
+                        return Ok(());
+                    }
+                };
+                let forwarded = forwarded_post(&msg.reply_to_message);
+                let user_id = msg.from.unwrap().id;
+                let subscriber = match await!(resolve_manager(
+                    bot.clone(),
+                    db.clone(),
+                    channel,
+                    forwarded,
+                    chat_id,
+                    user_id,
+                    lang
+                ))? {
+                    Some(subscriber) => subscriber,
+                    None => return Ok(()),
+                };
+                let template = if template == "off" { None } else { Some(template) };
+                let removed = template.is_none();
+                let r = db.update_options(subscriber, &feed_link, |o| {
+                    o.template = template;
+                    o.template_test = None;
+                });
+                let reply = match r {
+                    Ok(()) if removed => lang.template_removed(),
+                    Ok(()) => lang.template_set(),
+                    Err(Error(ErrorKind::NotSubscribed, _)) => lang.not_subscribed(),
+                    Err(e) => {
+                        log_error(&e);
+                        lang.unexpected_error(&e)
+                    }
+                };
+                await!(bot.message(chat_id, reply).send())?;
+                Ok(())
+            }
+        })
+        .then(|result| {
+            if let Err(err) = result {
+                error!("telebot: {:?}", err);
+            }
+            Ok::<(), ()>(())
+        });
+
+    bot.register(handle);
+}
+
+// `/note [Channel ID] <RSS URL> <text|off>`
+fn register_note(bot: &telebot::RcBot, db: Database) {
+    let handle = ratelimit::new_cmd(bot, &db, "/note")
+        .and_then(move |(bot, msg)| {
+            let db = db.clone();
+            async_block! {
+                let chat_id = msg.chat.id;
+                let lang = db.get_lang(chat_id);
+                let text = msg.text.unwrap();
+                let (channel, feed_link, note) = match template_args(&text) {
+                    Some(args) => args,
+                    None => {
+                        let usage = lang.usage("/note [Channel ID] <RSS URL> <text|off>");
+                        await!(bot.message(chat_id, usage).send())?;
+                        return Ok(());
+                    }
+                };
+                let forwarded = forwarded_post(&msg.reply_to_message);
+                let user_id = msg.from.unwrap().id;
+                let subscriber = match await!(resolve_manager(
+                    bot.clone(),
+                    db.clone(),
+                    channel,
+                    forwarded,
+                    chat_id,
+                    user_id,
+                    lang
+                ))? {
+                    Some(subscriber) => subscriber,
+                    None => return Ok(()),
+                };
+                let note = if note == "off" {
+                    None
+                } else {
+                    Some(truncate_message(&note, NOTE_MAX_LEN))
+                };
+                let removed = note.is_none();
+                let r = db.update_options(subscriber, &feed_link, |o| o.note = note);
+                let reply = match r {
+                    Ok(()) if removed => lang.note_removed(),
+                    Ok(()) => lang.note_set(),
+                    Err(Error(ErrorKind::NotSubscribed, _)) => lang.not_subscribed(),
+                    Err(e) => {
+                        log_error(&e);
+                        lang.unexpected_error(&e)
+                    }
+                };
+                await!(bot.message(chat_id, reply).send())?;
+                Ok(())
+            }
+        })
+        .then(|result| {
+            if let Err(err) = result {
+                error!("telebot: {:?}", err);
+            }
+            Ok::<(), ()>(())
+        });
+
+    bot.register(handle);
+}
+
+// `/pinrule [Channel ID] <RSS URL> <keyword|off>`, the bot has to be allowed to pin
+fn register_pinrule(bot: &telebot::RcBot, db: Database) {
+    let handle = ratelimit::new_cmd(bot, &db, "/pinrule")
+        .and_then(move |(bot, msg)| {
+            let db = db.clone();
+            async_block! {
+                let chat_id = msg.chat.id;
+                let lang = db.get_lang(chat_id);
+                let text = msg.text.unwrap();
+                let (channel, feed_link, keyword) = match template_args(&text) {
+                    Some(args) => args,
+                    None => {
+                        let usage = lang.usage("/pinrule [Channel ID] <RSS URL> <keyword|off>");
+                        await!(bot.message(chat_id, usage).send())?;
+                        return Ok(());
+                    }
+                };
+                let forwarded = forwarded_post(&msg.reply_to_message);
+                let user_id = msg.from.unwrap().id;
+                let subscriber = match await!(resolve_manager(
+                    bot.clone(),
+                    db.clone(),
+                    channel,
+                    forwarded,
+                    chat_id,
+                    user_id,
+                    lang
+                ))? {
+                    Some(subscriber) => subscriber,
+                    None => return Ok(()),
+                };
+                let keyword = Some(keyword.to_lowercase()).filter(|keyword| keyword != "off");
+                let reply = match keyword {
+                    Some(ref keyword) => lang.pin_rule_set(keyword),
+                    None => lang.pin_rule_removed(),
+                };
+                let r = db.update_options(subscriber, &feed_link, |o| o.pin_keyword = keyword);
+                let reply = match r {
+                    Ok(()) => reply,
+                    Err(Error(ErrorKind::NotSubscribed, _)) => lang.not_subscribed(),
+                    Err(e) => {
+                        log_error(&e);
+                        lang.unexpected_error(&e)
+                    }
+                };
+                await!(bot.message(chat_id, reply).send())?;
+                Ok(())
+            }
+        })
+        .then(|result| {
+            if let Err(err) = result {
+                error!("telebot: {:?}", err);
+            }
+            Ok::<(), ()>(())
+        });
+
+    bot.register(handle);
+}
+
+// `/rewrite [Channel ID] <RSS URL> <rule...|off>`, the rules are listed by `/rewriterule`
+fn register_rewrite(bot: &telebot::RcBot, db: Database) {
+    let handle = ratelimit::new_cmd(bot, &db, "/rewrite")
+        .and_then(move |(bot, msg)| {
+            let db = db.clone();
+            async_block! {
+                let chat_id = msg.chat.id;
+                let lang = db.get_lang(chat_id);
+                let text = msg.text.unwrap();
+                let (channel, feed_link, rules) = match template_args(&text) {
+                    Some(args) => args,
+                    None => {
+                        let usage = lang.usage("/rewrite [Channel ID] <RSS URL> <rule...|off>");
+                        await!(bot.message(chat_id, usage).send())?;
+                        return Ok(());
+                    }
+                };
+                let rules: Vec<String> = rules
+                    .split_whitespace()
+                    .map(str::to_lowercase)
+                    .filter(|rule| rule != "off")
+                    .collect();
+                let unknown = rules.iter().find(|rule| db.rewrite_rule(rule).is_none()).cloned();
+                if let Some(rule) = unknown {
+                    await!(bot.message(chat_id, lang.unknown_rewrite_rule(&rule)).send())?;
+                    return Ok(());
+                }
+                let forwarded = forwarded_post(&msg.reply_to_message);
+                let user_id = msg.from.unwrap().id;
+                let subscriber = match await!(resolve_manager(
+                    bot.clone(),
+                    db.clone(),
+                    channel,
+                    forwarded,
+                    chat_id,
+                    user_id,
+                    lang
+                ))? {
+                    Some(subscriber) => subscriber,
+                    None => return Ok(()),
+                };
+                let reply = if rules.is_empty() {
+                    lang.rewrite_removed()
+                } else {
+                    lang.rewrite_set(&rules.join(", "))
+                };
+                let r = db.update_options(subscriber, &feed_link, |o| o.rewrite = rules);
+                let reply = match r {
+                    Ok(()) => reply,
+                    Err(Error(ErrorKind::NotSubscribed, _)) => lang.not_subscribed(),
+                    Err(e) => {
+                        log_error(&e);
+                        lang.unexpected_error(&e)
+                    }
+                };
+                await!(bot.message(chat_id, reply).send())?;
+                Ok(())
+            }
+        })
+        .then(|result| {
+            if let Err(err) = result {
+                error!("telebot: {:?}", err);
+            }
+            Ok::<(), ()>(())
+        });
+
+    bot.register(handle);
+}
+
+// `/rename [Channel ID] <RSS URL> <title|off>`, only for this subscription
+// `/prefix [Channel ID] <RSS URL> <prefix|off>`, e.g. an emoji before the messages
+fn register_prefix(bot: &telebot::RcBot, db: Database) {
+    let handle = ratelimit::new_cmd(bot, &db, "/prefix")
+        .and_then(move |(bot, msg)| {
+            let db = db.clone();
+            async_block! {
+                let chat_id = msg.chat.id;
+                let lang = db.get_lang(chat_id);
+                let text = msg.text.unwrap();
+                let (channel, feed_link, prefix) = match template_args(&text) {
+                    Some(args) => args,
+                    None => {
+                        let usage = lang.usage("/prefix [Channel ID] <RSS URL> <prefix|off>");
+                        await!(bot.message(chat_id, usage).send())?;
+                        return Ok(());
+                    }
+                };
+                if prefix.chars().count() > PREFIX_MAX_LEN {
+                    await!(bot.message(chat_id, lang.prefix_too_long(PREFIX_MAX_LEN)).send())?;
+                    return Ok(());
+                }
+                let forwarded = forwarded_post(&msg.reply_to_message);
+                let user_id = msg.from.unwrap().id;
+                let subscriber = match await!(resolve_manager(
+                    bot.clone(),
+                    db.clone(),
+                    channel,
+                    forwarded,
+                    chat_id,
+                    user_id,
+                    lang
+                ))? {
+                    Some(subscriber) => subscriber,
+                    None => return Ok(()),
+                };
+                let prefix = Some(prefix).filter(|prefix| prefix != "off");
+                let reply = match prefix {
+                    Some(ref prefix) => lang.prefix_set(prefix),
+                    None => lang.prefix_removed(),
+                };
+                let r = db.update_options(subscriber, &feed_link, |o| o.prefix = prefix);
+                let reply = match r {
+                    Ok(()) => reply,
+                    Err(Error(ErrorKind::NotSubscribed, _)) => lang.not_subscribed(),
+                    Err(e) => {
+                        log_error(&e);
+                        lang.unexpected_error(&e)
+                    }
+                };
+                await!(bot.message(chat_id, reply).send())?;
+                Ok(())
+            }
+        })
+        .then(|result| {
+            if let Err(err) = result {
+                error!("telebot: {:?}", err);
+            }
+            Ok::<(), ()>(())
+        });
+
+    bot.register(handle);
+}
+
+fn register_rename(bot: &telebot::RcBot, db: Database) {
+    let handle = ratelimit::new_cmd(bot, &db, "/rename")
+        .and_then(move |(bot, msg)| {
+            let db = db.clone();
+            async_block! {
+                let chat_id = msg.chat.id;
+                let lang = db.get_lang(chat_id);
+                let text = msg.text.unwrap();
+                let (channel, feed_link, title) = match template_args(&text) {
+                    Some(args) => args,
+                    None => {
+                        let usage = lang.usage("/rename [Channel ID] <RSS URL> <title|off>");
+                        await!(bot.message(chat_id, usage).send())?;
+                        return Ok(());
+                    }
+                };
+                let forwarded = forwarded_post(&msg.reply_to_message);
+                let user_id = msg.from.unwrap().id;
+                let subscriber = match await!(resolve_manager(
+                    bot.clone(),
+                    db.clone(),
+                    channel,
+                    forwarded,
+                    chat_id,
+                    user_id,
+                    lang
+                ))? {
+                    Some(subscriber) => subscriber,
+                    None => return Ok(()),
+                };
+                let title = if title == "off" {
+                    None
+                } else {
+                    Some(truncate_message(&title, TITLE_MAX_LEN))
+                };
+                let reply_title = title.clone();
+                let r = db.update_options(subscriber, &feed_link, |o| o.title = title);
+                let reply = match r {
+                    Ok(()) => {
+                        let title = reply_title.unwrap_or_else(|| {
+                            db.get_feed(&feed_link).map(|feed| feed.title).unwrap_or_default()
+                        });
+                        lang.renamed(&feed_link, &title)
+                    }
+                    Err(Error(ErrorKind::NotSubscribed, _)) => lang.not_subscribed(),
+                    Err(e) => {
+                        log_error(&e);
+                        lang.unexpected_error(&e)
+                    }
+                };
+                let r = bot.message(chat_id, reply)
+                    .parse_mode("HTML")
+                    .disable_web_page_preview(true)
+                    .send();
+                await!(r)?;
+                Ok(())
+            }
+        })
+        .then(|result| {
+            if let Err(err) = result {
+                error!("telebot: {:?}", err);
+            }
+            Ok::<(), ()>(())
+        });
+
+    bot.register(handle);
+}
+
+// `/header [Channel ID] <RSS URL> <Name: value|Name off>`, e.g. API keys of private feeds,
+// the values aren't shown again
+fn register_header(bot: &telebot::RcBot, db: Database) {
+    let handle = ratelimit::new_cmd(bot, &db, "/header")
+        .and_then(move |(bot, msg)| {
+            let db = db.clone();
+            async_block! {
+                let chat_id = msg.chat.id;
+                let lang = db.get_lang(chat_id);
+                let text = msg.text.unwrap();
+                let parsed = template_args(&text).and_then(|(channel, feed_link, header)| {
+                    parse_header(&header).map(|(name, value)| (channel, feed_link, name, value))
+                });
+                let (channel, feed_link, name, value) = match parsed {
+                    Some(args) => args,
+                    None => {
+                        let usage =
+                            lang.usage("/header [Channel ID] <RSS URL> <Name: value|Name off>");
+                        await!(bot.message(chat_id, usage).send())?;
+                        return Ok(());
+                    }
+                };
+                let forwarded = forwarded_post(&msg.reply_to_message);
+                let user_id = msg.from.unwrap().id;
+                let subscriber = match await!(resolve_manager(
+                    bot.clone(),
+                    db.clone(),
+                    channel,
+                    forwarded,
+                    chat_id,
+                    user_id,
+                    lang
+                ))? {
+                    Some(subscriber) => subscriber,
+                    None => return Ok(()),
+                };
+                let r = db.update_options(subscriber, &feed_link, |o| {
+                    o.headers.retain(|&(ref n, _)| !n.eq_ignore_ascii_case(&name));
+                    if let Some(value) = value {
+                        o.headers.push((name, value));
+                    }
+                });
+                let reply = match r {
+                    Ok(()) => {
+                        let feed = db.get_feed(&feed_link).unwrap_or_default();
+                        let headers = db.get_options(subscriber, feed.get_id()).headers;
+                        let names: Vec<&str> = headers.iter().map(|h| h.0.as_str()).collect();
+                        lang.headers_updated(&feed_link, &feed.title, &names)
+                    }
+                    Err(Error(ErrorKind::NotSubscribed, _)) => lang.not_subscribed(),
+                    Err(e @ Error(ErrorKind::HeadersTaken, _)) => lang.error_reason(&e),
+                    Err(e) => {
+                        log_error(&e);
+                        lang.unexpected_error(&e)
+                    }
+                };
+                let r = bot.message(chat_id, reply)
+                    .parse_mode("HTML")
+                    .disable_web_page_preview(true)
+                    .send();
+                await!(r)?;
+                Ok(())
+            }
+        })
+        .then(|result| {
+            if let Err(err) = result {
+                error!("telebot: {:?}", err);
+            }
+            Ok::<(), ()>(())
+        });
+
+    bot.register(handle);
+}
+
+// `Name: value` sets a header, `Name off` removes it
+fn parse_header(s: &str) -> Option<(String, Option<String>)> {
+    fn is_token(s: &str) -> bool {
+        !s.is_empty()
+            && s.chars()
+                .all(|c| c.is_ascii_alphanumeric() || "!#$%&'*+-.^_`|~".contains(c))
+    }
+    let (name, value) = match s.find(':') {
+        Some(i) => (s[..i].trim(), Some(s[i + 1..].trim())),
+        None => {
+            let mut parts = s.split_whitespace();
+            match (parts.next(), parts.next(), parts.next()) {
+                (Some(name), Some(off), None) if off.eq_ignore_ascii_case("off") => (name, None),
+                _ => return None,
+            }
+        }
+    };
+    if !is_token(name) {
+        return None;
+    }
+    match value {
+        Some(value) if value.is_empty() || value.contains(|c| c == '\r' || c == '\n') => None,
+        value => Some((name.to_owned(), value.map(str::to_owned))),
+    }
+}
+
+#[test]
+fn test_parse_header() {
+    assert_eq!(
+        parse_header("X-Api-Key: a b"),
+        Some(("X-Api-Key".to_owned(), Some("a b".to_owned())))
+    );
+    assert_eq!(parse_header("User-Agent OFF"), Some(("User-Agent".to_owned(), None)));
+    assert_eq!(parse_header("X-Api-Key:"), None);
+    assert_eq!(parse_header("Bad Name: value"), None);
+    assert_eq!(parse_header("X-Api-Key: a\nb"), None);
+}
+
+// `/snooze [Channel ID] <RSS URL> <until:Nitems|off>`
+fn register_snooze(bot: &telebot::RcBot, db: Database) {
+    let handle = ratelimit::new_cmd(bot, &db, "/snooze")
+        .and_then(move |(bot, msg)| {
+            let db = db.clone();
+            async_block! {
+                let chat_id = msg.chat.id;
+                let lang = db.get_lang(chat_id);
+                let text = msg.text.unwrap();
+                let args: Vec<String> = text.split_whitespace().map(str::to_owned).collect();
+                let parsed = match args.len() {
+                    2 => parse_snooze(&args[1]).map(|n| (None, args[0].clone(), n)),
+                    3 => parse_snooze(&args[2])
+                        .map(|n| (Some(args[0].clone()), args[1].clone(), n)),
+                    _ => None,
+                };
+                let (channel, feed_link, snooze_items) = match parsed {
+                    Some(parsed) => parsed,
+                    None => {
+                        let usage = lang.usage("/snooze [Channel ID] <RSS URL> <until:Nitems|off>");
+                        await!(bot.message(chat_id, usage).send())?;
+                        return Ok(());
+                    }
+                };
+                let forwarded = forwarded_post(&msg.reply_to_message);
+                let user_id = msg.from.unwrap().id;
+                let subscriber = match await!(resolve_manager(
+                    bot.clone(),
+                    db.clone(),
+                    channel,
+                    forwarded,
+                    chat_id,
+                    user_id,
+                    lang
+                ))? {
+                    Some(subscriber) => subscriber,
+                    None => return Ok(()),
+                };
+                let r = db.update_options(subscriber, &feed_link, |o| {
+                    o.snooze_items = snooze_items;
+                    if snooze_items.is_none() {
+                        o.snoozed.clear();
+                    }
+                });
+                let reply = match r {
+                    Ok(()) => match snooze_items {
+                        Some(n) => lang.snoozed(n),
+                        None => lang.snooze_removed(),
+                    },
+                    Err(Error(ErrorKind::NotSubscribed, _)) => lang.not_subscribed(),
+                    Err(e) => {
+                        log_error(&e);
+                        lang.unexpected_error(&e)
+                    }
+                };
+                await!(bot.message(chat_id, reply).send())?;
+                Ok(())
+            }
+        })
+        .then(|result| {
+            if let Err(err) = result {
+                error!("telebot: {:?}", err);
+            }
+            Ok::<(), ()>(())
+        });
+
+    bot.register(handle);
+}
+
+// `until:5items`, or just `5`, `Some(None)` for "off"
+fn parse_snooze(s: &str) -> Option<Option<usize>> {
+    let s = s.to_ascii_lowercase();
+    if s == "off" {
+        return Some(None);
+    }
+    let n = s.trim_left_matches("until:")
+        .trim_right_matches("items")
+        .trim_right_matches("item");
+    n.parse::<usize>().ok().filter(|n| *n > 1).map(Some)
+}
+
+const SUMMARY_DEFAULT_CHARS: usize = 200;
+const SUMMARY_MAX_CHARS: usize = 1000;
+
+// `/summary [Channel ID] <RSS URL> <on|off|chars>`
+fn register_summary(bot: &telebot::RcBot, db: Database) {
+    let handle = ratelimit::new_cmd(bot, &db, "/summary")
+        .and_then(move |(bot, msg)| {
+            let db = db.clone();
+            async_block! {
+                let chat_id = msg.chat.id;
+                let lang = db.get_lang(chat_id);
+                let text = msg.text.unwrap();
+                let args: Vec<String> = text.split_whitespace().map(str::to_owned).collect();
+                let parsed = match args.len() {
+                    2 => parse_summary(&args[1]).map(|n| (None, args[0].clone(), n)),
+                    3 => parse_summary(&args[2])
+                        .map(|n| (Some(args[0].clone()), args[1].clone(), n)),
+                    _ => None,
+                };
+                let (channel, feed_link, summary) = match parsed {
+                    Some(parsed) => parsed,
+                    None => {
+                        let usage = lang.usage("/summary [Channel ID] <RSS URL> <on|off|chars>");
+                        await!(bot.message(chat_id, usage).send())?;
+                        return Ok(());
+                    }
+                };
+                let forwarded = forwarded_post(&msg.reply_to_message);
+                let user_id = msg.from.unwrap().id;
+                let subscriber = match await!(resolve_manager(
+                    bot.clone(),
+                    db.clone(),
+                    channel,
+                    forwarded,
+                    chat_id,
+                    user_id,
+                    lang
+                ))? {
+                    Some(subscriber) => subscriber,
+                    None => return Ok(()),
+                };
+                let r = db.update_options(subscriber, &feed_link, |o| o.summary = summary);
+                let reply = match r {
+                    Ok(()) => match summary {
+                        Some(n) => lang.summary_set(n),
+                        None => lang.summary_off(),
+                    },
+                    Err(Error(ErrorKind::NotSubscribed, _)) => lang.not_subscribed(),
+                    Err(e) => {
+                        log_error(&e);
+                        lang.unexpected_error(&e)
+                    }
+                };
+                await!(bot.message(chat_id, reply).send())?;
+                Ok(())
+            }
+        })
+        .then(|result| {
+            if let Err(err) = result {
+                error!("telebot: {:?}", err);
+            }
+            Ok::<(), ()>(())
+        });
+
+    bot.register(handle);
+}
+
+// `on`, `off` or a length in characters, `Some(None)` for "off"
+fn parse_summary(s: &str) -> Option<Option<usize>> {
+    match s.to_ascii_lowercase().as_str() {
+        "on" => Some(Some(SUMMARY_DEFAULT_CHARS)),
+        "off" => Some(None),
+        s => s.parse::<usize>()
+            .ok()
+            .filter(|n| *n > 0 && *n <= SUMMARY_MAX_CHARS)
+            .map(Some),
+    }
+}
+
+// `/display [Channel ID] <RSS URL> <author,date|off>`
+fn register_display(bot: &telebot::RcBot, db: Database) {
+    let handle = ratelimit::new_cmd(bot, &db, "/display")
+        .and_then(move |(bot, msg)| {
+            let db = db.clone();
+            async_block! {
+                let chat_id = msg.chat.id;
+                let lang = db.get_lang(chat_id);
+                let text = msg.text.unwrap();
+                let args: Vec<String> = text.split_whitespace().map(str::to_owned).collect();
+                let parsed = match args.len() {
+                    2 => parse_display(&args[1]).map(|d| (None, args[0].clone(), d)),
+                    3 => parse_display(&args[2])
+                        .map(|d| (Some(args[0].clone()), args[1].clone(), d)),
+                    _ => None,
+                };
+                let (channel, feed_link, (author, date)) = match parsed {
+                    Some(parsed) => parsed,
+                    None => {
+                        let usage = lang.usage("/display [Channel ID] <RSS URL> <author,date|off>");
+                        await!(bot.message(chat_id, usage).send())?;
+                        return Ok(());
+                    }
+                };
+                let forwarded = forwarded_post(&msg.reply_to_message);
+                let user_id = msg.from.unwrap().id;
+                let subscriber = match await!(resolve_manager(
+                    bot.clone(),
+                    db.clone(),
+                    channel,
+                    forwarded,
+                    chat_id,
+                    user_id,
+                    lang
+                ))? {
+                    Some(subscriber) => subscriber,
+                    None => return Ok(()),
+                };
+                let r = db.update_options(subscriber, &feed_link, |o| {
+                    o.show_author = author;
+                    o.show_date = date;
+                });
+                let reply = match r {
+                    Ok(()) => lang.display_updated(author, date),
+                    Err(Error(ErrorKind::NotSubscribed, _)) => lang.not_subscribed(),
+                    Err(e) => {
+                        log_error(&e);
+                        lang.unexpected_error(&e)
+                    }
+                };
+                await!(bot.message(chat_id, reply).send())?;
+                Ok(())
+            }
+        })
+        .then(|result| {
+            if let Err(err) = result {
+                error!("telebot: {:?}", err);
+            }
+            Ok::<(), ()>(())
+        });
+
+    bot.register(handle);
+}
+
+// `author`, `date` or both separated by a comma, `off` for neither
+fn parse_display(s: &str) -> Option<(bool, bool)> {
+    let s = s.to_ascii_lowercase();
+    if s == "off" {
+        return Some((false, false));
+    }
+    let (mut author, mut date) = (false, false);
+    for part in s.split(',') {
+        match part {
+            "author" => author = true,
+            "date" => date = true,
+            _ => return None,
+        }
+    }
+    Some((author, date))
+}
+
+// `/hashtags [Channel ID] <RSS URL> <categories|#tag ...|off>`
+fn register_hashtags(bot: &telebot::RcBot, db: Database) {
+    let handle = ratelimit::new_cmd(bot, &db, "/hashtags")
+        .and_then(move |(bot, msg)| {
+            let db = db.clone();
+            async_block! {
+                let chat_id = msg.chat.id;
+                let lang = db.get_lang(chat_id);
+                let text = msg.text.unwrap();
+                let parsed = template_args(&text).and_then(|(channel, feed_link, rest)| {
+                    parse_hashtags(&rest).map(|hashtags| (channel, feed_link, hashtags))
+                });
+                let (channel, feed_link, (categories, hashtags)) = match parsed {
+                    Some(parsed) => parsed,
+                    None => {
+                        let syntax = "/hashtags [Channel ID] <RSS URL> <categories|#tag ...|off>";
+                        await!(bot.message(chat_id, lang.usage(syntax)).send())?;
+                        return Ok(());
+                    }
+                };
+                let forwarded = forwarded_post(&msg.reply_to_message);
+                let user_id = msg.from.unwrap().id;
+                let subscriber = match await!(resolve_manager(
+                    bot.clone(),
+                    db.clone(),
+                    channel,
+                    forwarded,
+                    chat_id,
+                    user_id,
+                    lang
+                ))? {
+                    Some(subscriber) => subscriber,
+                    None => return Ok(()),
+                };
+                let reply = lang.hashtags_updated(categories, &hashtags);
+                let r = db.update_options(subscriber, &feed_link, |o| {
+                    o.category_hashtags = categories;
+                    o.hashtags = hashtags;
+                });
+                let reply = match r {
+                    Ok(()) => reply,
+                    Err(Error(ErrorKind::NotSubscribed, _)) => lang.not_subscribed(),
+                    Err(e) => {
+                        log_error(&e);
+                        lang.unexpected_error(&e)
+                    }
+                };
+                await!(bot.message(chat_id, reply).send())?;
+                Ok(())
+            }
+        })
+        .then(|result| {
+            if let Err(err) = result {
+                error!("telebot: {:?}", err);
+            }
+            Ok::<(), ()>(())
+        });
+
+    bot.register(handle);
+}
+
+// `categories` for the categories of items, and fixed tags, `off` for none
+fn parse_hashtags(s: &str) -> Option<(bool, Vec<String>)> {
+    if s.eq_ignore_ascii_case("off") {
+        return Some((false, Vec::new()));
+    }
+    let mut categories = false;
+    let mut hashtags = Vec::new();
+    for word in s.split_whitespace() {
+        if word.eq_ignore_ascii_case("categories") {
+            categories = true;
+            continue;
+        }
+        let hashtag = to_hashtag(word)?;
+        if !hashtags.contains(&hashtag) {
+            hashtags.push(hashtag);
+        }
+    }
+    Some((categories, hashtags))
+}
+
+// `/linkpreview [Channel ID] <RSS URL> <off|on|InstantView RHASH>`
+fn register_linkpreview(bot: &telebot::RcBot, db: Database) {
+    let handle = ratelimit::new_cmd(bot, &db, "/linkpreview")
+        .and_then(move |(bot, msg)| {
+            let db = db.clone();
+            async_block! {
+                let chat_id = msg.chat.id;
+                let lang = db.get_lang(chat_id);
+                let text = msg.text.unwrap();
+                let args: Vec<String> = text.split_whitespace().map(str::to_owned).collect();
+                let parsed = match args.len() {
+                    2 => parse_link_preview(&args[1]).map(|lp| (None, args[0].clone(), lp)),
+                    3 => parse_link_preview(&args[2])
+                        .map(|lp| (Some(args[0].clone()), args[1].clone(), lp)),
+                    _ => None,
+                };
+                let (channel, feed_link, link_preview) = match parsed {
+                    Some(parsed) => parsed,
+                    None => {
+                        let usage = lang.usage(
+                            "/linkpreview [Channel ID] <RSS URL> <off|on|InstantView RHASH>",
+                        );
+                        await!(bot.message(chat_id, usage).send())?;
+                        return Ok(());
+                    }
+                };
+                let forwarded = forwarded_post(&msg.reply_to_message);
+                let user_id = msg.from.unwrap().id;
+                let subscriber = match await!(resolve_manager(
+                    bot.clone(),
+                    db.clone(),
+                    channel,
+                    forwarded,
+                    chat_id,
+                    user_id,
+                    lang
+                ))? {
+                    Some(subscriber) => subscriber,
+                    None => return Ok(()),
+                };
+                let reply = match db.set_link_preview(subscriber, &feed_link, link_preview) {
+                    Ok(()) => {
+                        let title = db.get_feed(&feed_link)
+                            .map(|feed| feed.title)
+                            .unwrap_or_default();
+                        lang.link_preview_set(&feed_link, &title, link_preview)
+                    }
+                    Err(Error(ErrorKind::NotSubscribed, _)) => lang.not_subscribed(),
+                    Err(e) => {
+                        log_error(&e);
+                        lang.unexpected_error(&e)
+                    }
+                };
+                let r = bot.message(chat_id, reply)
+                    .parse_mode("HTML")
+                    .disable_web_page_preview(true)
+                    .send();
+                await!(r)?;
+                Ok(())
+            }
+        })
+        .then(|result| {
+            if let Err(err) = result {
+                error!("telebot: {:?}", err);
+            }
+            Ok::<(), ()>(())
+        });
+
+    bot.register(handle);
+}
+
+fn parse_link_preview(s: &str) -> Option<LinkPreview> {
+    match s.to_ascii_lowercase().as_str() {
+        "off" => Some(LinkPreview::Off),
+        "on" => Some(LinkPreview::On),
+        rhash => u64::from_str_radix(rhash, 16)
+            .ok()
+            .map(LinkPreview::from_iv_rhash),
+    }
+}
+
+// `/abtest [Channel ID] <RSS URL> <template A> || <template B>`, the next update is
+// previewed to the user with both templates, see `abtest::preview_templates`
+fn register_abtest(bot: &telebot::RcBot, db: Database) {
+    let handle = ratelimit::new_cmd(bot, &db, "/abtest")
+        .and_then(move |(bot, msg)| {
+            let db = db.clone();
+            async_block! {
+                let chat_id = msg.chat.id;
+                let lang = db.get_lang(chat_id);
+                let text = msg.text.unwrap();
+                let args = template_args(&text).and_then(|(channel, feed_link, templates)| {
+                    let mut templates = templates.splitn(2, "||").map(str::trim);
+                    let a = templates.next().filter(|s| !s.is_empty())?.to_owned();
+                    let b = templates.next().filter(|s| !s.is_empty())?.to_owned();
+                    Some((channel, feed_link, a, b))
+                });
+                let (channel, feed_link, a, b) = match args {
+                    Some(args) => args,
+                    None => {
+                        let syntax = "/abtest [Channel ID] <RSS URL> <template A> || <template B>";
+                        await!(bot.message(chat_id, lang.usage(syntax)).send())?;
+                        return Ok(());
+                    }
+                };
+                let forwarded = forwarded_post(&msg.reply_to_message);
+                let user_id = msg.from.unwrap().id;
+                let subscriber = match await!(resolve_manager(
+                    bot.clone(),
+                    db.clone(),
+                    channel,
+                    forwarded,
+                    chat_id,
+                    user_id,
+                    lang
+                ))? {
+                    Some(subscriber) => subscriber,
+                    None => return Ok(()),
+                };
+                let test = TemplateTest {
+                    a: a,
+                    b: b,
+                    admin: user_id,
+                    previewed: false,
+                };
+                let r = db.update_options(subscriber, &feed_link, |o| o.template_test = Some(test));
+                let reply = match r {
+                    Ok(()) => lang.template_test_started(),
+                    Err(Error(ErrorKind::NotSubscribed, _)) => lang.not_subscribed(),
+                    Err(e) => {
+                        log_error(&e);
+                        lang.unexpected_error(&e)
+                    }
+                };
+                await!(bot.message(chat_id, reply).send())?;
+                Ok(())
+            }
+        })
+        .then(|result| {
+            if let Err(err) = result {
+                error!("telebot: {:?}", err);
+            }
+            Ok::<(), ()>(())
+        });
+
+    bot.register(handle);
+}
+
+// `[Channel ID] <RSS URL> <rest>`, the rest keeps its line breaks
+fn template_args(text: &str) -> Option<(Option<String>, String, String)> {
+    let (first, rest) = split_first_word(text)?;
+    let (channel, feed_link, rest) = if is_channel_arg(first) {
+        let (feed_link, rest) = split_first_word(rest)?;
+        (Some(first.to_owned()), feed_link, rest)
+    } else {
+        (None, first, rest)
+    };
+    let rest = rest.trim();
+    if rest.is_empty() {
+        return None;
+    }
+    Some((channel, feed_link.to_owned(), rest.to_owned()))
+}
+
+#[test]
+fn test_parse_snooze() {
+    assert_eq!(parse_snooze("until:5items"), Some(Some(5)));
+    assert_eq!(parse_snooze("10"), Some(Some(10)));
+    assert_eq!(parse_snooze("OFF"), Some(None));
+    assert_eq!(parse_snooze("until:1item"), None);
+    assert_eq!(parse_snooze("until:items"), None);
+}
+
+#[test]
+fn test_parse_summary() {
+    assert_eq!(parse_summary("on"), Some(Some(SUMMARY_DEFAULT_CHARS)));
+    assert_eq!(parse_summary("Off"), Some(None));
+    assert_eq!(parse_summary("300"), Some(Some(300)));
+    assert_eq!(parse_summary("0"), None);
+    assert_eq!(parse_summary("5000"), None);
+}
+
+#[test]
+fn test_parse_display() {
+    assert_eq!(parse_display("author,date"), Some((true, true)));
+    assert_eq!(parse_display("Date"), Some((false, true)));
+    assert_eq!(parse_display("off"), Some((false, false)));
+    assert_eq!(parse_display("author,"), None);
+}
+
+#[test]
+fn test_parse_hashtags() {
+    assert_eq!(parse_hashtags("OFF"), Some((false, Vec::new())));
+    assert_eq!(
+        parse_hashtags("categories #news tech #news"),
+        Some((true, vec!["#news".to_owned(), "#tech".to_owned()]))
+    );
+    assert_eq!(parse_hashtags("#2019"), None);
+}
+
+// `pubdate` is RFC 3339, `enclosure` is a media URL with its MIME type
+#[derive(Deserialize)]
+struct InjectedItem {
+    title: Option<String>,
+    link: Option<String>,
+    id: Option<String>,
+    pubdate: Option<String>,
+    enclosure: Option<String>,
+    enclosure_type: Option<String>,
+    description: Option<String>,
+    author: Option<String>,
+}
+
+fn parse_injected_item(json: &str) -> ::std::result::Result<feed::Item, String> {
+    let item: InjectedItem = serde_json::from_str(json).map_err(|e| e.to_string())?;
+    let pubdate = match item.pubdate {
+        Some(ref s) => Some(
+            DateTime::parse_from_rfc3339(s)
+                .map_err(|e| format!("pubdate: {}", e))?
+                .with_timezone(&Utc),
+        ),
+        None => None,
+    };
+    if item.title.is_none() && item.link.is_none() {
+        return Err("title or link is required".to_string());
+    }
+    Ok(feed::Item {
+        title: item.title,
+        link: item.link,
+        id: item.id,
+        enclosures: item.enclosure
+            .map(|url| feed::Enclosure {
+                url: url,
+                mime_type: item.enclosure_type,
+                length: None,
+            })
+            .into_iter()
+            .collect(),
+        pubdate: pubdate,
+        description: item.description,
+        author: item.author,
+    })
+}
+
+#[test]
+fn test_parse_injected_item() {
+    let item = parse_injected_item(
+        r#"{"title": "t", "pubdate": "2018-01-02T03:04:05+08:00", "enclosure": "http://a/b.mp3"}"#,
+    ).unwrap();
+    assert_eq!(item.title, Some("t".to_string()));
+    assert_eq!(item.pubdate, Some(Utc.ymd(2018, 1, 1).and_hms(19, 4, 5)));
+    assert_eq!(item.enclosures.len(), 1);
+    assert!(parse_injected_item(r#"{"id": "x"}"#).is_err());
+    assert!(parse_injected_item("title").is_err());
+}
+
+fn split_first_word(s: &str) -> Option<(&str, &str)> {
+    let s = s.trim_left();
+    if s.is_empty() {
+        return None;
+    }
+    Some(match s.find(char::is_whitespace) {
+        Some(i) => (&s[..i], &s[i..]),
+        None => (s, ""),
+    })
+}
+
+fn register_history(bot: &telebot::RcBot, db: Database) {
+    let handle = ratelimit::new_cmd(bot, &db, "/history")
+        .and_then(move |(bot, msg)| {
+            let db = db.clone();
+            async_block! {
+                let chat_id = msg.chat.id;
+                let lang = db.get_lang(chat_id);
+                let text = msg.text.unwrap();
+                let mut args: Vec<String> = text.split_whitespace().map(str::to_owned).collect();
+                let channel = if args.len() > 1 && args[0] != "export" {
+                    Some(args.remove(0))
+                } else {
+                    None
+                };
+                let days: Option<i64> = match args.len() {
+                    1 if args[0] == "export" => Some(30),
+                    2 if args[0] == "export" => args[1].parse().ok().filter(|days| *days > 0),
+                    _ => None,
+                };
+                let days = match days {
+                    Some(days) => days,
+                    None => {
+                        let usage = lang.usage("/history [Channel ID] export [days]");
+                        await!(bot.message(chat_id, usage).send())?;
+                        return Ok(());
+                    }
+                };
+                let forwarded = forwarded_post(&msg.reply_to_message);
+                let user_id = msg.from.unwrap().id;
+                let subscriber = match await!(resolve_subscriber(
+                    bot.clone(),
+                    db.clone(),
+                    channel,
+                    forwarded,
+                    chat_id,
+                    user_id,
+                    lang
+                ))? {
+                    Some(subscriber) => subscriber,
+                    None => return Ok(()),
+                };
+                let since = Utc::now().timestamp() - days * 24 * 60 * 60;
+                let history = db.get_history(subscriber, since);
+                if history.is_empty() {
+                    let msg = lang.history_empty();
+                    await!(bot.message(chat_id, msg).send())?;
+                    return Ok(());
+                }
+                let csv = to_csv(&["time", "feed", "title", "link"], &history, |item| {
+                    vec![
+                        Utc.timestamp(item.time, 0).to_rfc3339(),
+                        item.feed.clone(),
+                        item.title.clone(),
+                        item.link.clone(),
+                    ]
+                });
+                let file = File::new("history.csv".into(), csv.into_bytes());
+                await!(bot.document(chat_id, file).send())?;
+                Ok(())
+            }
+        })
+        .then(|result| {
+            if let Err(err) = result {
+                error!("telebot: {:?}", err);
+            }
+            Ok::<(), ()>(())
+        });
+
+    bot.register(handle);
+}
+
+fn register_backlog(bot: &telebot::RcBot, db: Database, lphandle: Handle) {
+    let handle = ratelimit::new_cmd(bot, &db, "/backlog")
+        .and_then(move |(bot, msg)| {
+            let db = db.clone();
+            let lphandle = lphandle.clone();
+            async_block! {
+                let chat_id = msg.chat.id;
+                let lang = db.get_lang(chat_id);
+                let text = msg.text.unwrap();
+                let args: Vec<String> = text.split_whitespace().map(str::to_owned).collect();
+                let (channel, feed_link, count) = match args.len() {
+                    2 => (None, args[0].clone(), args[1].parse::<usize>().ok()),
+                    3 => (Some(args[0].clone()), args[1].clone(), args[2].parse().ok()),
+                    _ => (None, String::new(), None),
+                };
+                let count = match count.filter(|count| *count > 0 && *count <= MAX_BACKLOG) {
+                    Some(count) => count,
+                    None => {
+                        let usage = lang.usage(&format!(
+                            "/backlog [Channel ID] <RSS URL> <1-{}>",
+                            MAX_BACKLOG
+                        ));
+                        await!(bot.message(chat_id, usage).send())?;
+                        return Ok(());
+                    }
+                };
+                let forwarded = forwarded_post(&msg.reply_to_message);
+                let user_id = msg.from.unwrap().id;
+                let subscriber = match await!(resolve_subscriber(
+                    bot.clone(),
+                    db.clone(),
+                    channel,
+                    forwarded,
+                    chat_id,
+                    user_id,
+                    lang
+                ))? {
+                    Some(subscriber) => subscriber,
+                    None => return Ok(()),
+                };
+                let feed = db.get_subscribed_feeds(subscriber)
+                    .unwrap_or_default()
+                    .into_iter()
+                    .find(|feed| normalize_url(&feed.link) == normalize_url(&feed_link));
+                let feed = match feed {
+                    Some(feed) => feed,
+                    None => {
+                        await!(bot.message(chat_id, lang.not_subscribed()).send())?;
+                        return Ok(());
+                    }
+                };
+                let session = Session::new(lphandle);
+                let r = feed::fetch_feed_pages(
+                    session,
+                    gen_ua(&bot),
+                    db.get_fetch_headers(&feed.link),
+                    feed.proxy.clone(),
+                    feed.link.clone(),
+                    count,
+                    10,
+                );
+                let rss = match await!(r) {
+                    Ok(rss) => rss,
+                    Err(e) => {
+                        let msg = lang.fetch_failed(&lang.error_reason(&e));
+                        await!(bot.message(chat_id, msg).send())?;
+                        return Ok(());
+                    }
+                };
+                let link_preview = db.get_link_preview(subscriber, feed.get_id())
+                    .unwrap_or(LinkPreview::Off);
+                let options = db.get_options(subscriber, feed.get_id());
+                let locale = db.get_locale(subscriber);
+                let msgs = format_updates(
+                    link_preview,
+                    &options,
+                    locale,
+                    &rss.title,
+                    &rss.link,
+                    &rss.items,
+                );
+                if msgs.is_empty() {
+                    await!(bot.message(chat_id, lang.feed_empty()).send())?;
+                    return Ok(());
+                }
+                let enable_lp = link_preview != LinkPreview::Off;
+                await!(send_updates(bot.clone(), subscriber, Vec::new(), msgs, enable_lp))?;
+                Ok(())
+            }
+        })
+        .then(|result| {
+            if let Err(err) = result {
+                error!("telebot: {:?}", err);
+            }
+            Ok::<(), ()>(())
+        });
+
+    bot.register(handle);
+}
+
+// fetches a subscribed feed right away, the new items go to all of its subscribers
+fn register_checknow(bot: &telebot::RcBot, db: Database, lphandle: Handle, sender: Sender) {
+    let handle = ratelimit::new_cmd(bot, &db, "/checknow")
+        .and_then(move |(bot, msg)| {
+            let db = db.clone();
+            let lphandle = lphandle.clone();
+            let sender = sender.clone();
+            async_block! {
+                let chat_id = msg.chat.id;
+                let lang = db.get_lang(chat_id);
+                let text = msg.text.unwrap();
+                let args: Vec<String> = text.split_whitespace().map(str::to_owned).collect();
+                let (channel, feed_link) = match args.len() {
+                    1 => (None, args[0].clone()),
+                    2 => (Some(args[0].clone()), args[1].clone()),
+                    _ => {
+                        let usage = lang.usage("/checknow [Channel ID] <RSS URL>");
+                        await!(bot.message(chat_id, usage).send())?;
+                        return Ok(());
+                    }
+                };
+                let forwarded = forwarded_post(&msg.reply_to_message);
+                let user_id = msg.from.unwrap().id;
+                let subscriber = match await!(resolve_subscriber(
+                    bot.clone(),
+                    db.clone(),
+                    channel,
+                    forwarded,
+                    chat_id,
+                    user_id,
+                    lang
+                ))? {
+                    Some(subscriber) => subscriber,
+                    None => return Ok(()),
+                };
+                let feed = db.get_subscribed_feeds(subscriber)
+                    .unwrap_or_default()
+                    .into_iter()
+                    .find(|feed| normalize_url(&feed.link) == normalize_url(&feed_link));
+                let feed = match feed {
+                    Some(feed) => feed,
+                    None => {
+                        await!(bot.message(chat_id, lang.not_subscribed()).send())?;
+                        return Ok(());
+                    }
+                };
+                let elapsed = feed.last_fetched_at.map(|t| Utc::now().timestamp() - t);
+                if let Some(elapsed) = elapsed.filter(|elapsed| *elapsed < CHECKNOW_INTERVAL) {
+                    let msg = lang.checknow_too_soon(CHECKNOW_INTERVAL - elapsed);
+                    await!(bot.message(chat_id, msg).send())?;
+                    return Ok(());
+                }
+                let link = feed.link.clone();
+                let session = Session::new(lphandle);
+                let r = fetch_feed_updates(bot.clone(), db.clone(), session, feed, sender);
+                let count = await!(r).unwrap_or(0);
+                let last_error = db.get_feed(&link).and_then(|feed| feed.last_error);
+                let reply = match last_error {
+                    Some(e) => lang.fetch_failed(&e),
+                    None => lang.checknow_done(count),
+                };
+                await!(bot.message(chat_id, reply).send())?;
+                Ok(())
+            }
+        })
+        .then(|result| {
+            if let Err(err) = result {
+                error!("telebot: {:?}", err);
+            }
+            Ok::<(), ()>(())
+        });
+
+    bot.register(handle);
+}
+
+// `/watch [Channel ID] [URL [CSS selector]]`, lists the watched pages without a URL
+fn register_watch(bot: &telebot::RcBot, db: Database, lphandle: Handle) {
+    let handle = ratelimit::new_cmd(bot, &db, "/watch")
+        .and_then(move |(bot, msg)| {
+            let db = db.clone();
+            let lphandle = lphandle.clone();
+            async_block! {
+                let chat_id = msg.chat.id;
+                let lang = db.get_lang(chat_id);
+                let text = msg.text.unwrap();
+                let (channel, link, selector) = watch_args(&text);
+                let forwarded = forwarded_post(&msg.reply_to_message);
+                let user_id = msg.from.unwrap().id;
+                let subscriber = match await!(resolve_manager(
+                    bot.clone(),
+                    db.clone(),
+                    channel,
+                    forwarded,
+                    chat_id,
+                    user_id,
+                    lang
+                ))? {
+                    Some(subscriber) => subscriber,
+                    None => return Ok(()),
+                };
+                let link = match link {
+                    Some(link) => link,
+                    None => {
+                        let pages = db.get_watched_pages(subscriber);
+                        if pages.is_empty() {
+                            await!(bot.message(chat_id, lang.watch_list_empty()).send())?;
+                            return Ok(());
+                        }
+                        let msgs = format_and_split_msgs(lang.watch_list(), &pages, |page| {
+                            lang.watched_page(&page.link, &page.title, page.selector.as_ref())
+                        });
+                        await!(send_multiple_messages(&bot, chat_id, msgs, false))?;
+                        return Ok(());
+                    }
+                };
+                let session = Session::new(lphandle);
+                let body = match await!(feed::fetch_page(session, gen_ua(&bot), link.clone())) {
+                    Ok(body) => body,
+                    Err(e) => {
+                        let msg = lang.fetch_failed(&lang.error_reason(&e));
+                        await!(bot.message(chat_id, msg).send())?;
+                        return Ok(());
+                    }
+                };
+                let html = String::from_utf8_lossy(&body).into_owned();
+                let hash = watch::region(&html, selector.as_ref().map(|s| s.as_str()))
+                    .map(watch::region_hash);
+                let hash = match hash {
+                    Some(hash) => hash,
+                    None => {
+                        await!(bot.message(chat_id, lang.selector_not_found()).send())?;
+                        return Ok(());
+                    }
+                };
+                let title = extract_title(&html).unwrap_or_else(|| link.clone());
+                let reply = match db.watch(subscriber, &link, selector.clone(), &title, hash) {
+                    Ok(()) => lang.watching(&link, &title, selector.as_ref()),
+                    Err(Error(ErrorKind::AlreadySubscribed, _)) => lang.already_watching(),
+                    Err(e) => {
+                        log_error(&e);
+                        lang.unexpected_error(&e)
+                    }
+                };
+                let r = bot.message(chat_id, reply)
+                    .parse_mode("HTML")
+                    .disable_web_page_preview(true)
+                    .send();
+                await!(r)?;
+                Ok(())
+            }
+        })
+        .then(|result| {
+            if let Err(err) = result {
+                error!("telebot: {:?}", err);
+            }
+            Ok::<(), ()>(())
+        });
+
+    bot.register(handle);
+}
+
+// `[Channel ID] [URL [CSS selector]]`
+fn watch_args(text: &str) -> (Option<String>, Option<String>, Option<String>) {
+    let (channel, rest) = match split_first_word(text) {
+        Some((first, rest)) if is_channel_arg(first) => (Some(first.to_owned()), rest),
+        _ => (None, text),
+    };
+    match split_first_word(rest) {
+        Some((link, selector)) => {
+            let selector = selector.trim();
+            let selector = if selector.is_empty() {
+                None
+            } else {
+                Some(selector.to_owned())
+            };
+            (channel, Some(link.to_owned()), selector)
+        }
+        None => (channel, None, None),
+    }
+}
+
+// `/unwatch [Channel ID] <URL>`, stops watching every selector of the page
+fn register_unwatch(bot: &telebot::RcBot, db: Database) {
+    let handle = ratelimit::new_cmd(bot, &db, "/unwatch")
+        .and_then(move |(bot, msg)| {
+            let db = db.clone();
+            async_block! {
+                let chat_id = msg.chat.id;
+                let lang = db.get_lang(chat_id);
+                let text = msg.text.unwrap();
+                let args: Vec<String> = text.split_whitespace().map(str::to_owned).collect();
+                let (channel, link) = match args.len() {
+                    1 => (None, args[0].clone()),
+                    2 => (Some(args[0].clone()), args[1].clone()),
+                    _ => {
+                        let usage = lang.usage("/unwatch [Channel ID] <URL>");
+                        await!(bot.message(chat_id, usage).send())?;
+                        return Ok(());
+                    }
+                };
+                let forwarded = forwarded_post(&msg.reply_to_message);
+                let user_id = msg.from.unwrap().id;
+                let subscriber = match await!(resolve_manager(
+                    bot.clone(),
+                    db.clone(),
+                    channel,
+                    forwarded,
+                    chat_id,
+                    user_id,
+                    lang
+                ))? {
+                    Some(subscriber) => subscriber,
+                    None => return Ok(()),
+                };
+                let reply = match db.unwatch(subscriber, &link) {
+                    Ok(pages) => lang.unwatched(&pages[0].link, &pages[0].title),
+                    Err(Error(ErrorKind::NotSubscribed, _)) => lang.not_watching(),
+                    Err(e) => {
+                        log_error(&e);
+                        lang.unexpected_error(&e)
+                    }
+                };
+                let r = bot.message(chat_id, reply)
+                    .parse_mode("HTML")
+                    .disable_web_page_preview(true)
+                    .send();
+                await!(r)?;
+                Ok(())
+            }
+        })
+        .then(|result| {
+            if let Err(err) = result {
+                error!("telebot: {:?}", err);
+            }
+            Ok::<(), ()>(())
+        });
+
+    bot.register(handle);
+}
+
+// `/lang [Channel ID] [code]`, shows the current language without a code
+fn register_lang(bot: &telebot::RcBot, db: Database) {
+    let handle = ratelimit::new_cmd(bot, &db, "/lang")
+        .and_then(move |(bot, msg)| {
+            let db = db.clone();
+            async_block! {
+                let chat_id = msg.chat.id;
+                let lang = db.get_lang(chat_id);
+                let text = msg.text.unwrap();
+                let args: Vec<String> = text.split_whitespace().map(str::to_owned).collect();
+                let (channel, code) = match args.len() {
+                    0 => (None, None),
+                    1 if Lang::from_code(&args[0]).is_some() => (None, Some(args[0].clone())),
+                    1 => (Some(args[0].clone()), None),
+                    2 => (Some(args[0].clone()), Some(args[1].clone())),
+                    _ => (None, Some(String::new())),
+                };
+                let new_lang = match code.map(|code| Lang::from_code(&code)) {
+                    Some(Some(new_lang)) => Some(new_lang),
+                    Some(None) => {
+                        let codes: Vec<&str> = LANGS.iter().map(|lang| lang.code()).collect();
+                        let syntax = format!("/lang [Channel ID] [{}]", codes.join("|"));
+                        let usage = lang.usage(&syntax);
+                        await!(bot.message(chat_id, usage).send())?;
+                        return Ok(());
+                    }
+                    None => None,
+                };
+                let forwarded = forwarded_post(&msg.reply_to_message);
+                let user_id = msg.from.unwrap().id;
+                let target = match await!(resolve_manager(
+                    bot.clone(),
+                    db.clone(),
+                    channel,
+                    forwarded,
+                    chat_id,
+                    user_id,
+                    lang
+                ))? {
+                    Some(target) => target,
+                    None => return Ok(()),
+                };
+                let reply = match new_lang {
+                    Some(new_lang) => match db.update_chat_settings(target, |s| s.lang = new_lang) {
+                        Ok(()) => new_lang.lang_updated(),
+                        Err(e) => {
+                            log_error(&e);
+                            lang.unexpected_error(&e)
+                        }
+                    },
+                    None => db.get_lang(target).current_lang(),
+                };
+                await!(bot.message(chat_id, reply).send())?;
+                Ok(())
+            }
+        })
+        .then(|result| {
+            if let Err(err) = result {
+                error!("telebot: {:?}", err);
+            }
+            Ok::<(), ()>(())
+        });
+
+    bot.register(handle);
+}
+
+// `/timezone [Channel ID] [offset|IANA zone]`, used to render dates in templates
+fn register_timezone(bot: &telebot::RcBot, db: Database) {
+    let handle = ratelimit::new_cmd(bot, &db, "/timezone")
+        .and_then(move |(bot, msg)| {
+            let db = db.clone();
+            async_block! {
+                let chat_id = msg.chat.id;
+                let lang = db.get_lang(chat_id);
+                let text = msg.text.unwrap();
+                let args: Vec<String> = text.split_whitespace().map(str::to_owned).collect();
+                let (channel, timezone) = match args.len() {
+                    0 => (None, None),
+                    1 => match parse_timezone(&args[0]) {
+                        Some(timezone) => (None, Some(Some(timezone))),
+                        None => (Some(args[0].clone()), None),
+                    },
+                    2 => (Some(args[0].clone()), Some(parse_timezone(&args[1]))),
+                    _ => (None, Some(None)),
+                };
+                let timezone = match timezone {
+                    Some(Some(timezone)) => Some(timezone),
+                    Some(None) => {
+                        let usage = lang.usage(
+                            "/timezone [Channel ID] [UTC|+08:00|-05:30|Asia/Shanghai]"
+                        );
+                        await!(bot.message(chat_id, usage).send())?;
+                        return Ok(());
+                    }
+                    None => None,
+                };
+                let forwarded = forwarded_post(&msg.reply_to_message);
+                let user_id = msg.from.unwrap().id;
+                let target = match await!(resolve_manager(
+                    bot.clone(),
+                    db.clone(),
+                    channel,
+                    forwarded,
+                    chat_id,
+                    user_id,
+                    lang
+                ))? {
+                    Some(target) => target,
+                    None => return Ok(()),
+                };
+                let r = timezone.map(|timezone| {
+                    db.update_chat_settings(target, |s| match timezone {
+                        Timezone::Offset(offset) => {
+                            s.utc_offset = offset;
+                            s.timezone = None;
+                        }
+                        Timezone::Zone(zone) => s.timezone = Some(zone.name().to_owned()),
+                    })
+                });
+                let reply = match r {
+                    Some(Ok(())) => {
+                        lang.timezone_updated(&db.get_chat_settings(target).timezone_name())
+                    }
+                    Some(Err(e)) => {
+                        log_error(&e);
+                        lang.unexpected_error(&e)
+                    }
+                    None => lang.current_timezone(&db.get_chat_settings(target).timezone_name()),
+                };
+                await!(bot.message(chat_id, reply).send())?;
+                Ok(())
+            }
+        })
+        .then(|result| {
+            if let Err(err) = result {
+                error!("telebot: {:?}", err);
+            }
+            Ok::<(), ()>(())
+        });
+
+    bot.register(handle);
+}
+
+// `/suggest [Channel ID] <on|off>`, opts in to hearing about unsubscribed feeds
+// which got popular since
+fn register_suggest(bot: &telebot::RcBot, db: Database) {
+    let handle = ratelimit::new_cmd(bot, &db, "/suggest")
+        .and_then(move |(bot, msg)| {
+            let db = db.clone();
+            async_block! {
+                let chat_id = msg.chat.id;
+                let lang = db.get_lang(chat_id);
+                let text = msg.text.unwrap();
+                let args: Vec<String> = text.split_whitespace().map(str::to_owned).collect();
+                let (channel, state) = match args.len() {
+                    1 => (None, args[0].to_ascii_lowercase()),
+                    2 => (Some(args[0].clone()), args[1].to_ascii_lowercase()),
+                    _ => (None, String::new()),
+                };
+                let enable = match state.as_str() {
+                    "on" => true,
+                    "off" => false,
+                    _ => {
+                        let usage = lang.usage("/suggest [Channel ID] <on|off>");
+                        await!(bot.message(chat_id, usage).send())?;
+                        return Ok(());
+                    }
+                };
+                let forwarded = forwarded_post(&msg.reply_to_message);
+                let user_id = msg.from.unwrap().id;
+                let target = match await!(resolve_manager(
+                    bot.clone(),
+                    db.clone(),
+                    channel,
+                    forwarded,
+                    chat_id,
+                    user_id,
+                    lang
+                ))? {
+                    Some(target) => target,
+                    None => return Ok(()),
+                };
+                let r = db.update_chat_settings(target, |s| s.resubscribe_suggestions = enable);
+                let reply = match r {
+                    Ok(()) => lang.resubscribe_suggestions(enable),
+                    Err(e) => {
+                        log_error(&e);
+                        lang.unexpected_error(&e)
+                    }
+                };
+                await!(bot.message(chat_id, reply).send())?;
+                Ok(())
+            }
+        })
+        .then(|result| {
+            if let Err(err) = result {
+                error!("telebot: {:?}", err);
+            }
+            Ok::<(), ()>(())
+        });
+
+    bot.register(handle);
+}
+
+// `/groupadminonly <on|off>`, always for administrators only, or anyone could turn it off
+fn register_groupadminonly(bot: &telebot::RcBot, db: Database) {
+    let handle = ratelimit::new_cmd(bot, &db, "/groupadminonly")
+        .and_then(move |(bot, msg)| {
+            let db = db.clone();
+            async_block! {
+                let chat_id = msg.chat.id;
+                let lang = db.get_lang(chat_id);
+                let state = msg.text.unwrap().trim().to_ascii_lowercase();
+                let enable = match state.as_str() {
+                    "on" => true,
+                    "off" => false,
+                    _ => {
+                        let usage = lang.usage("/groupadminonly <on|off>");
+                        await!(bot.message(chat_id, usage).send())?;
+                        return Ok(());
+                    }
+                };
+                if msg.chat.kind != "group" && msg.chat.kind != "supergroup" {
+                    await!(bot.message(chat_id, lang.not_a_group()).send())?;
+                    return Ok(());
+                }
+                let user_id = msg.from.unwrap().id;
+                let (bot, admins) = await!(bot.get_chat_administrators(chat_id).send())?;
+                let is_admin = user_id == GROUP_ANONYMOUS_BOT ||
+                    admins.iter().any(|member| member.user.id == user_id);
+                if !is_admin {
+                    await!(bot.message(chat_id, lang.group_admin_only()).send())?;
+                    return Ok(());
+                }
+                let r = db.update_chat_settings(chat_id, |s| s.admin_only = Some(enable));
+                let reply = match r {
+                    Ok(()) => lang.group_admin_only_set(enable),
+                    Err(e) => {
+                        log_error(&e);
+                        lang.unexpected_error(&e)
+                    }
+                };
+                await!(bot.message(chat_id, reply).send())?;
+                Ok(())
+            }
+        })
+        .then(|result| {
+            if let Err(err) = result {
+                error!("telebot: {:?}", err);
+            }
+            Ok::<(), ()>(())
+        });
+
+    bot.register(handle);
+}
+
+// `/dedupe [Channel ID] <hours|off>`, skips items whose link was already sent to
+// the chat by another feed within the given hours
+fn register_dedupe(bot: &telebot::RcBot, db: Database) {
+    let handle = ratelimit::new_cmd(bot, &db, "/dedupe")
+        .and_then(move |(bot, msg)| {
+            let db = db.clone();
+            async_block! {
+                let chat_id = msg.chat.id;
+                let lang = db.get_lang(chat_id);
+                let text = msg.text.unwrap();
+                let args: Vec<String> = text.split_whitespace().map(str::to_owned).collect();
+                let (channel, window) = match args.len() {
+                    1 => (None, args[0].to_ascii_lowercase()),
+                    2 => (Some(args[0].clone()), args[1].to_ascii_lowercase()),
+                    _ => (None, String::new()),
+                };
+                let hours = match window.as_str() {
+                    "off" | "0" => 0,
+                    s => match s.parse::<u32>() {
+                        Ok(hours) if hours <= DEDUPE_MAX_HOURS => hours,
+                        _ => {
+                            let usage = lang.usage("/dedupe [Channel ID] <hours|off>");
+                            await!(bot.message(chat_id, usage).send())?;
+                            return Ok(());
+                        }
+                    },
+                };
+                let forwarded = forwarded_post(&msg.reply_to_message);
+                let user_id = msg.from.unwrap().id;
+                let target = match await!(resolve_manager(
+                    bot.clone(),
+                    db.clone(),
+                    channel,
+                    forwarded,
+                    chat_id,
+                    user_id,
+                    lang
+                ))? {
+                    Some(target) => target,
+                    None => return Ok(()),
+                };
+                let r = db.update_chat_settings(target, |s| s.dedupe_hours = hours);
+                let reply = match r {
+                    Ok(()) if hours == 0 => lang.dedupe_off(),
+                    Ok(()) => lang.dedupe_set(hours),
+                    Err(e) => {
+                        log_error(&e);
+                        lang.unexpected_error(&e)
+                    }
+                };
+                await!(bot.message(chat_id, reply).send())?;
+                Ok(())
+            }
+        })
+        .then(|result| {
+            if let Err(err) = result {
+                error!("telebot: {:?}", err);
+            }
+            Ok::<(), ()>(())
+        });
+
+    bot.register(handle);
+}
+
+// `/expire <Chat ID> [RSS URL] <days|off>`, pauses delivery to a chat or a single
+// subscription after the given days, only for owners
+fn register_expire(bot: &telebot::RcBot, db: Database) {
+    let handle = ratelimit::new_cmd(bot, &db, "/expire")
+        .and_then(move |(bot, msg)| {
+            let db = db.clone();
+            async_block! {
+                let chat_id = msg.chat.id;
+                let lang = db.get_lang(chat_id);
+                if !db.is_owner(msg.from.unwrap().id) {
+                    await!(bot.message(chat_id, lang.owner_only()).send())?;
+                    return Ok(());
+                }
+                let text = msg.text.unwrap();
+                let args: Vec<String> = text.split_whitespace().map(str::to_owned).collect();
+                let (target, feed_link, days) = match args.len() {
+                    2 => (args[0].parse::<i64>().ok(), None, args[1].to_ascii_lowercase()),
+                    3 => (
+                        args[0].parse().ok(),
+                        Some(args[1].clone()),
+                        args[2].to_ascii_lowercase(),
+                    ),
+                    _ => (None, None, String::new()),
+                };
+                let (target, days) = match (target, parse_days(&days)) {
+                    (Some(target), Some(days)) => (target, days),
+                    _ => {
+                        let usage = lang.usage("/expire <Chat ID> [RSS URL] <days|off>");
+                        await!(bot.message(chat_id, usage).send())?;
+                        return Ok(());
+                    }
+                };
+                let now = Utc::now().timestamp();
+                let expires_at = days.map(|days| now + i64::from(days) * 24 * 60 * 60);
+                let r = match feed_link {
+                    Some(feed_link) => db.update_options(target, &feed_link, |o| {
+                        o.expires_at = expires_at;
+                        o.expiry_notified = false;
+                    }),
+                    None => db.update_chat_settings(target, |s| {
+                        s.expires_at = expires_at;
+                        s.expiry_notified = false;
+                    }),
+                };
+                let reply = match r {
+                    Ok(()) => match days {
+                        Some(days) => lang.expiry_set(days),
+                        None => lang.expiry_removed(),
+                    },
+                    Err(Error(ErrorKind::NotSubscribed, _)) => lang.not_subscribed(),
+                    Err(e) => {
+                        log_error(&e);
+                        lang.unexpected_error(&e)
+                    }
+                };
+                await!(bot.message(chat_id, reply).send())?;
+                Ok(())
+            }
+        })
+        .then(|result| {
+            if let Err(err) = result {
+                error!("telebot: {:?}", err);
+            }
+            Ok::<(), ()>(())
+        });
+
+    bot.register(handle);
+}
+
+// `/premium <Chat ID> <days|off>`, grants a chat the premium quota, only for owners
+fn register_premium(bot: &telebot::RcBot, db: Database) {
+    let handle = ratelimit::new_cmd(bot, &db, "/premium")
+        .and_then(move |(bot, msg)| {
+            let db = db.clone();
+            async_block! {
+                let chat_id = msg.chat.id;
+                let lang = db.get_lang(chat_id);
+                if !db.is_owner(msg.from.unwrap().id) {
+                    await!(bot.message(chat_id, lang.owner_only()).send())?;
+                    return Ok(());
+                }
+                let text = msg.text.unwrap();
+                let args: Vec<String> = text.split_whitespace().map(str::to_owned).collect();
+                let parsed = if args.len() == 2 {
+                    args[0].parse::<i64>().ok().and_then(|target| {
+                        parse_days(&args[1].to_ascii_lowercase()).map(|days| (target, days))
+                    })
+                } else {
+                    None
+                };
+                let (target, days) = match parsed {
+                    Some(parsed) => parsed,
+                    None => {
+                        let usage = lang.usage("/premium <Chat ID> <days|off>");
+                        await!(bot.message(chat_id, usage).send())?;
+                        return Ok(());
+                    }
+                };
+                let now = Utc::now().timestamp();
+                let premium_until = days.map(|days| now + i64::from(days) * 24 * 60 * 60);
+                let r = db.update_chat_settings(target, |s| s.premium_until = premium_until);
+                let reply = match r {
+                    Ok(()) => match days {
+                        Some(days) => lang.premium_set(days),
+                        None => lang.premium_removed(),
+                    },
+                    Err(e) => {
+                        log_error(&e);
+                        lang.unexpected_error(&e)
+                    }
+                };
+                await!(bot.message(chat_id, reply).send())?;
+                Ok(())
+            }
+        })
+        .then(|result| {
+            if let Err(err) = result {
+                error!("telebot: {:?}", err);
+            }
+            Ok::<(), ()>(())
+        });
+
+    bot.register(handle);
+}
+
+// `/iv <domain>` shows the Instant View rhash used for new subscriptions on it,
+// `/iv <domain> <RHASH|off>` changes it, only for owners
+fn register_iv(bot: &telebot::RcBot, db: Database) {
+    let handle = ratelimit::new_cmd(bot, &db, "/iv")
+        .and_then(move |(bot, msg)| {
+            let db = db.clone();
+            async_block! {
+                let chat_id = msg.chat.id;
+                let lang = db.get_lang(chat_id);
+                let user_id = msg.from.unwrap().id;
+                let text = msg.text.unwrap();
+                let args: Vec<String> = text.split_whitespace().map(str::to_owned).collect();
+                let reply = match args.len() {
+                    1 => {
+                        let domain = args[0].to_lowercase();
+                        lang.iv_rhash(&domain, db.iv_rhash(&domain))
+                    }
+                    2 if !db.is_owner(user_id) => lang.owner_only(),
+                    2 => {
+                        let domain = args[0].to_lowercase();
+                        let rhash = if args[1].to_ascii_lowercase() == "off" {
+                            Some(None)
+                        } else {
+                            u64::from_str_radix(&args[1], 16).ok().map(Some)
+                        };
+                        match rhash.map(|rhash| (db.set_iv_rhash(&domain, rhash), rhash)) {
+                            Some((Ok(()), Some(rhash))) => lang.iv_rhash_set(&domain, rhash),
+                            Some((Ok(()), None)) => lang.iv_rhash_removed(&domain),
+                            Some((Err(e), _)) => {
+                                log_error(&e);
+                                lang.unexpected_error(&e)
+                            }
+                            None => lang.usage("/iv <domain> [RHASH|off]"),
+                        }
+                    }
+                    _ => lang.usage("/iv <domain> [RHASH|off]"),
+                };
+                await!(bot.message(chat_id, reply).send())?;
+                Ok(())
+            }
+        })
+        .then(|result| {
+            if let Err(err) = result {
+                error!("telebot: {:?}", err);
+            }
+            Ok::<(), ()>(())
+        });
+
+    bot.register(handle);
+}
+
+// `/rewriterule` lists the rules for `/rewrite`,
+// `/rewriterule <name> <DOMAIN=HOST|DOMAIN=LINK|off>` changes one, only for owners
+fn register_rewriterule(bot: &telebot::RcBot, db: Database) {
+    let handle = ratelimit::new_cmd(bot, &db, "/rewriterule")
+        .and_then(move |(bot, msg)| {
+            let db = db.clone();
+            async_block! {
+                let chat_id = msg.chat.id;
+                let lang = db.get_lang(chat_id);
+                let user_id = msg.from.unwrap().id;
+                let text = msg.text.unwrap();
+                let args: Vec<String> = text.split_whitespace().map(str::to_owned).collect();
+                let usage = lang.usage("/rewriterule [name DOMAIN=HOST|DOMAIN=LINK|off]");
+                let reply = match args.len() {
+                    0 => lang.rewrite_rules(&db.rewrite_rules()),
+                    2 if !db.is_owner(user_id) => lang.owner_only(),
+                    2 if !RewriteRule::is_valid_name(&args[0]) => usage,
+                    2 => {
+                        let name = args[0].to_lowercase();
+                        let rule = if args[1].to_ascii_lowercase() == "off" {
+                            Some(None)
+                        } else {
+                            RewriteRule::parse(&args[1]).map(Some)
+                        };
+                        match rule.map(|rule| (db.set_rewrite_rule(&name, rule.clone()), rule)) {
+                            Some((Ok(()), Some(_))) => lang.rewrite_rule_set(&name),
+                            Some((Ok(()), None)) => lang.rewrite_rule_removed(&name),
+                            Some((Err(e), _)) => {
+                                log_error(&e);
+                                lang.unexpected_error(&e)
+                            }
+                            None => usage,
+                        }
+                    }
+                    _ => usage,
+                };
+                await!(bot.message(chat_id, reply).send())?;
+                Ok(())
+            }
+        })
+        .then(|result| {
+            if let Err(err) = result {
+                error!("telebot: {:?}", err);
+            }
+            Ok::<(), ()>(())
+        });
+
+    bot.register(handle);
+}
+
+// `/proxy <RSS URL> [proxy|direct|off]`, for feeds only reachable through a specific
+// proxy, only for owners
+fn register_proxy(bot: &telebot::RcBot, db: Database) {
+    let handle = ratelimit::new_cmd(bot, &db, "/proxy")
+        .and_then(move |(bot, msg)| {
+            let db = db.clone();
+            async_block! {
+                let chat_id = msg.chat.id;
+                let lang = db.get_lang(chat_id);
+                if !db.is_owner(msg.from.unwrap().id) {
+                    await!(bot.message(chat_id, lang.owner_only()).send())?;
+                    return Ok(());
+                }
+                let text = msg.text.unwrap();
+                let args: Vec<String> = text.split_whitespace().map(str::to_owned).collect();
+                let valid = match args.len() {
+                    1 => true,
+                    2 => args[1] == "off" || feed::is_valid_proxy(&args[1]),
+                    _ => false,
+                };
+                if !valid {
+                    let usage = lang.usage("/proxy <RSS URL> [socks5://HOST:PORT|direct|off]");
+                    await!(bot.message(chat_id, usage).send())?;
+                    return Ok(());
+                }
+                let feed = match db.get_feed(&args[0]) {
+                    Some(feed) => feed,
+                    None => {
+                        await!(bot.message(chat_id, lang.unknown_feed()).send())?;
+                        return Ok(());
+                    }
+                };
+                let reply = match args.get(1).map(String::as_str) {
+                    None => {
+                        let proxy = feed.proxy.as_ref().map(String::as_str);
+                        lang.proxy_status(&feed.link, &feed.title, proxy)
+                    }
+                    Some(proxy) => {
+                        let proxy = if proxy == "off" { None } else { Some(proxy) };
+                        match db.set_proxy(&feed.link, proxy.map(str::to_owned)) {
+                            Ok(()) => lang.proxy_status(&feed.link, &feed.title, proxy),
+                            Err(e) => {
+                                log_error(&e);
+                                lang.unexpected_error(&e)
+                            }
+                        }
+                    }
+                };
+                let r = bot.message(chat_id, reply)
+                    .parse_mode("HTML")
+                    .disable_web_page_preview(true)
+                    .send();
+                await!(r)?;
+                Ok(())
+            }
+        })
+        .then(|result| {
+            if let Err(err) = result {
+                error!("telebot: {:?}", err);
+            }
+            Ok::<(), ()>(())
+        });
+
+    bot.register(handle);
+}
+
+// `/blockdomain <domain>` and `/unblockdomain <domain>`, only for owners,
+// subdomains are blocked too, existing subscriptions aren't touched
+fn register_blockdomain(bot: &telebot::RcBot, db: Database, cmd: &'static str, block: bool) {
+    let handle = ratelimit::new_cmd(bot, &db, cmd)
+        .and_then(move |(bot, msg)| {
+            let db = db.clone();
+            async_block! {
+                let chat_id = msg.chat.id;
+                let lang = db.get_lang(chat_id);
+                if !db.is_owner(msg.from.unwrap().id) {
+                    await!(bot.message(chat_id, lang.owner_only()).send())?;
+                    return Ok(());
+                }
+                let text = msg.text.unwrap();
+                let args: Vec<String> = text.split_whitespace().map(str::to_owned).collect();
+                if args.len() != 1 {
+                    await!(bot.message(chat_id, lang.usage(&format!("{} <domain>", cmd))).send())?;
+                    return Ok(());
+                }
+                let domain = args[0].to_lowercase();
+                let r = if block {
+                    db.block_domain(&domain)
+                } else {
+                    db.unblock_domain(&domain)
+                };
+                let reply = match (r, block) {
+                    (Ok(true), true) => lang.domain_blocked(&domain),
+                    (Ok(false), true) => lang.domain_already_blocked(&domain),
+                    (Ok(true), false) => lang.domain_unblocked(&domain),
+                    (Ok(false), false) => lang.domain_not_blocked(&domain),
+                    (Err(e), _) => {
+                        log_error(&e);
+                        lang.unexpected_error(&e)
+                    }
+                };
+                await!(bot.message(chat_id, reply).send())?;
+                Ok(())
+            }
+        })
+        .then(|result| {
+            if let Err(err) = result {
+                error!("telebot: {:?}", err);
+            }
+            Ok::<(), ()>(())
+        });
+
+    bot.register(handle);
+}
+
+// `/auth <passphrase>`, lets the chat use a private instance, see `--passphrase`
+fn register_auth(bot: &telebot::RcBot, db: Database) {
+    let handle = ratelimit::new_cmd(bot, &db, "/auth")
+        .and_then(move |(bot, msg)| {
+            let db = db.clone();
+            async_block! {
+                let chat_id = msg.chat.id;
+                let lang = db.get_lang(chat_id);
+                let text = msg.text.unwrap();
+                let passphrase = text.trim().to_owned();
+                if passphrase.is_empty() {
+                    await!(bot.message(chat_id, lang.usage("/auth <passphrase>")).send())?;
+                    return Ok(());
+                }
+                let reply = match db.authorize(chat_id, &passphrase) {
+                    Ok(true) => lang.authorized(),
+                    Ok(false) => lang.wrong_passphrase(),
+                    Err(e) => {
+                        log_error(&e);
+                        lang.unexpected_error(&e)
+                    }
+                };
+                await!(bot.message(chat_id, reply).send())?;
+                Ok(())
+            }
+        })
+        .then(|result| {
+            if let Err(err) = result {
+                error!("telebot: {:?}", err);
+            }
+            Ok::<(), ()>(())
+        });
+
+    bot.register(handle);
+}
+
+// `/maintenance on [message]` or `/maintenance off`, only for owners, lasts until the
+// restart or until the read-only settings of the config change
+fn register_maintenance(bot: &telebot::RcBot, db: Database) {
+    let handle = ratelimit::new_cmd(bot, &db, "/maintenance")
+        .and_then(move |(bot, msg)| {
+            let db = db.clone();
+            async_block! {
+                let chat_id = msg.chat.id;
+                let lang = db.get_lang(chat_id);
+                if !db.is_owner(msg.from.unwrap().id) {
+                    await!(bot.message(chat_id, lang.owner_only()).send())?;
+                    return Ok(());
+                }
+                let text = msg.text.unwrap();
+                let (mode, message) = match split_first_word(&text) {
+                    Some((mode, message)) => (mode.to_ascii_lowercase(), message.trim().to_owned()),
+                    None => (String::new(), String::new()),
+                };
+                let reply = match (mode.as_str(), message.is_empty()) {
+                    ("on", _) => {
+                        db.set_read_only(true);
+                        if !message.is_empty() {
+                            db.set_maintenance_message(Some(message));
+                        }
+                        lang.maintenance_on()
+                    }
+                    ("off", true) => {
+                        db.set_read_only(false);
+                        lang.maintenance_off()
+                    }
+                    _ => lang.usage("/maintenance <on [message]|off>"),
+                };
+                await!(bot.message(chat_id, reply).send())?;
+                Ok(())
+            }
+        })
+        .then(|result| {
+            if let Err(err) = result {
+                error!("telebot: {:?}", err);
+            }
+            Ok::<(), ()>(())
+        });
+
+    bot.register(handle);
+}
+
+// `/admin transport [polling|webhook]`, `/admin inject <RSS URL> <item JSON>`,
+// only for owners
+//
+// telebot only receives updates by long polling and has no way to dispatch
+// updates delivered elsewhere, so switching to webhooks is refused
+//
+// an injected item goes through the pipeline of every subscription of the feed,
+// but the results are sent to the owner, once for each distinct rendering
+fn register_admin(bot: &telebot::RcBot, db: Database, lphandle: Handle, sender: Sender) {
+    let handle = ratelimit::new_cmd(bot, &db, "/admin")
+        .and_then(move |(bot, msg)| {
+            let db = db.clone();
+            let lphandle = lphandle.clone();
+            let sender = sender.clone();
+            async_block! {
+                let chat_id = msg.chat.id;
+                let lang = db.get_lang(chat_id);
+                if !db.is_owner(msg.from.unwrap().id) {
+                    await!(bot.message(chat_id, lang.owner_only()).send())?;
+                    return Ok(());
+                }
+                let text = msg.text.unwrap();
+                let inject = split_first_word(&text)
+                    .filter(|&(cmd, _)| cmd == "inject")
+                    .and_then(|(_, rest)| split_first_word(rest))
+                    .map(|(link, json)| (link.to_owned(), json.trim().to_owned()));
+                if let Some((feed_link, json)) = inject {
+                    let reply = match (db.get_feed(&feed_link), parse_injected_item(&json)) {
+                        (None, _) => lang.unknown_feed(),
+                        (_, Err(e)) => lang.inject_invalid_item(&e.to_string()),
+                        (Some(ref feed), Ok(ref item)) if feed.has_item(item) => {
+                            lang.inject_duplicate()
+                        }
+                        (Some(feed), Ok(item)) => {
+                            let rss = feed::RSS {
+                                title: feed.title.clone(),
+                                link: feed.link.clone(),
+                                source: Some(feed.link.clone()),
+                                ..feed::RSS::default()
+                            };
+                            let queued = await!(deliver_updates(
+                                bot.clone(),
+                                db.clone(),
+                                Session::new(lphandle),
+                                feed,
+                                rss,
+                                vec![item],
+                                sender,
+                                Some(chat_id)
+                            )).unwrap_or_default();
+                            lang.inject_queued(queued)
+                        }
+                    };
+                    await!(bot.message(chat_id, reply).send())?;
+                    return Ok(());
+                }
+                let args: Vec<&str> = text.split_whitespace().collect();
+                let reply = match args.as_slice() {
+                    ["transport"] | ["transport", "polling"] => lang.transport_polling(),
+                    ["transport", "webhook"] => lang.transport_webhook_unsupported(),
+                    _ => lang.usage(
+                        "/admin transport [polling|webhook]\n\
+                         /admin inject <RSS URL> <item JSON>",
+                    ),
+                };
+                await!(bot.message(chat_id, reply).send())?;
+                Ok(())
+            }
+        })
+        .then(|result| {
+            if let Err(err) = result {
+                error!("telebot: {:?}", err);
+            }
+            Ok::<(), ()>(())
+        });
+
+    bot.register(handle);
+}
+
+// `/botstats`, only for owners
+fn register_botstats(bot: &telebot::RcBot, db: Database, sender: Sender) {
+    let handle = ratelimit::new_cmd(bot, &db, "/botstats")
+        .and_then(move |(bot, msg)| {
+            let db = db.clone();
+            let sender = sender.clone();
+            async_block! {
+                let chat_id = msg.chat.id;
+                let lang = db.get_lang(chat_id);
+                if !db.is_owner(msg.from.unwrap().id) {
+                    await!(bot.message(chat_id, lang.owner_only()).send())?;
+                    return Ok(());
+                }
+                let reply = lang.bot_stats(&db.stats(), sender.queued());
+                await!(bot.message(chat_id, reply).send())?;
+                Ok(())
+            }
+        })
+        .then(|result| {
+            if let Err(err) = result {
+                error!("telebot: {:?}", err);
+            }
+            Ok::<(), ()>(())
+        });
+
+    bot.register(handle);
+}
+
+// `/broadcast <text>`, sent to every subscriber, only for owners
+fn register_broadcast(bot: &telebot::RcBot, db: Database, sender: Sender) {
+    let handle = ratelimit::new_cmd(bot, &db, "/broadcast")
+        .and_then(move |(bot, msg)| {
+            let db = db.clone();
+            let sender = sender.clone();
+            async_block! {
+                let chat_id = msg.chat.id;
+                let lang = db.get_lang(chat_id);
+                if !db.is_owner(msg.from.unwrap().id) {
+                    await!(bot.message(chat_id, lang.owner_only()).send())?;
+                    return Ok(());
+                }
+                let text = msg.text.unwrap().trim().to_owned();
+                if text.is_empty() {
+                    await!(bot.message(chat_id, lang.usage("/broadcast <text>")).send())?;
+                    return Ok(());
+                }
+                let targets = db.get_all_subscribers();
+                let reply = lang.broadcast_started(targets.len());
+                await!(bot.message(chat_id, reply).send())?;
+                // can't fail, errors are counted
+                let (delivered, failed) = await!(sender.broadcast(targets, text))
+                    .unwrap_or_default();
+                await!(bot.message(chat_id, lang.broadcast_done(delivered, failed)).send())?;
+                Ok(())
+            }
+        })
+        .then(|result| {
+            if let Err(err) = result {
+                error!("telebot: {:?}", err);
+            }
+            Ok::<(), ()>(())
+        });
+
+    bot.register(handle);
+}
+
+// `Some(None)` for "off"
+fn parse_days(s: &str) -> Option<Option<u32>> {
+    match s {
+        "off" => Some(None),
+        days => days.parse::<u32>().ok().filter(|days| *days > 0).map(Some),
+    }
+}
+
+// `UTC`, `+8`, `UTC-05:30`..., in seconds east of UTC
+fn parse_utc_offset(s: &str) -> Option<i32> {
+    let s = s.to_uppercase();
+    let s = s.trim_left_matches("UTC").trim_left_matches("GMT");
+    if s.is_empty() {
+        return Some(0);
+    }
+    let sign = match s.chars().next()? {
+        '+' => 1,
+        '-' => -1,
+        _ => return None,
+    };
+    let mut parts = s[1..].splitn(2, ':');
+    let hours: i32 = parts.next()?.parse().ok()?;
+    let minutes: i32 = match parts.next() {
+        Some(minutes) => minutes.parse().ok()?,
+        None => 0,
+    };
+    if hours > 14 || minutes >= 60 {
+        return None;
+    }
+    Some(sign * (hours * 3600 + minutes * 60))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Timezone {
+    Offset(i32),
+    Zone(Tz),
+}
+
+// a fixed offset, or else an IANA zone name, which is case sensitive
+fn parse_timezone(s: &str) -> Option<Timezone> {
+    match parse_utc_offset(s) {
+        Some(offset) => Some(Timezone::Offset(offset)),
+        None => s.parse().ok().map(Timezone::Zone),
+    }
+}
+
+// the reply to `/sub`, `/unsub`, `/unsubthis`, `/import` and `/erase` in read-only mode
+fn read_only_reply(db: &Database, lang: Lang) -> Option<String> {
+    if !db.is_read_only() {
+        return None;
+    }
+    Some(db.get_maintenance_message().unwrap_or_else(|| lang.read_only()))
+}
+
+#[test]
+fn test_read_only_reply() {
+    use data::test_database;
+    let db = test_database("read-only-reply");
+    assert_eq!(read_only_reply(&db, Lang::En), None);
+    db.set_read_only(true);
+    assert_eq!(read_only_reply(&db, Lang::En), Some(Lang::En.read_only()));
+    db.set_maintenance_message(Some("moving to a new server".to_owned()));
+    assert_eq!(read_only_reply(&db, Lang::En), Some("moving to a new server".to_owned()));
+}
+
+// for the commands changing subscriptions, a group with `/groupadminonly` on
+// only lets its administrators use them
+#[async]
+fn resolve_manager(
+    bot: telebot::RcBot,
+    db: Database,
+    channel: Option<String>,
+    forwarded: Option<(i64, String)>,
+    chat_id: i64,
+    user_id: i64,
+    lang: Lang,
+) -> ::std::result::Result<Option<i64>, telebot::Error> {
+    if channel.is_none()
+        && !await!(check_group_admin(bot.clone(), db.clone(), chat_id, user_id, lang))?
+    {
+        return Ok(None);
+    }
+    await!(resolve_subscriber(bot, db, channel, forwarded, chat_id, user_id, lang))
+}
+
+// tells the user when it's false
+#[async]
+fn check_group_admin(
+    bot: telebot::RcBot,
+    db: Database,
+    chat_id: i64,
+    user_id: i64,
+    lang: Lang,
+) -> ::std::result::Result<bool, telebot::Error> {
+    // only administrators can post anonymously
+    if chat_id > 0 || user_id == GROUP_ANONYMOUS_BOT || !db.is_admin_only(chat_id) {
+        return Ok(true);
+    }
+    let (bot, admins) = await!(bot.get_chat_administrators(chat_id).send())?;
+    if admins.iter().any(|member| member.user.id == user_id) {
+        return Ok(true);
+    }
+    await!(bot.message(chat_id, lang.group_admin_only()).send())?;
+    Ok(false)
+}
+
+// `None` means check_channel has already told the user why the channel can't be used
+#[async]
+fn resolve_subscriber(
+    bot: telebot::RcBot,
+    db: Database,
+    channel: Option<String>,
+    forwarded: Option<(i64, String)>,
+    chat_id: i64,
+    user_id: i64,
+    lang: Lang,
+) -> ::std::result::Result<Option<i64>, telebot::Error> {
+    match channel {
+        Some(channel) => {
+            await!(check_channel(&bot, &db, &channel, forwarded, chat_id, user_id, lang))
+        }
+        None => Ok(Some(chat_id)),
+    }
+}
+
+#[test]
+fn test_template_args() {
+    assert_eq!(
+        template_args("https://example.com/feed {title}\n{link}"),
+        Some((None, "https://example.com/feed".to_owned(), "{title}\n{link}".to_owned()))
+    );
+    assert_eq!(
+        template_args("@channel https://example.com/feed off"),
+        Some((
+            Some("@channel".to_owned()),
+            "https://example.com/feed".to_owned(),
+            "off".to_owned()
+        ))
+    );
+    assert_eq!(template_args("https://example.com/feed"), None);
+}
+
+#[test]
+fn test_parse_utc_offset() {
+    assert_eq!(parse_utc_offset("utc"), Some(0));
+    assert_eq!(parse_utc_offset("+8"), Some(8 * 3600));
+    assert_eq!(parse_utc_offset("UTC-05:30"), Some(-(5 * 3600 + 1800)));
+    assert_eq!(parse_utc_offset("-1001234567890"), None);
+    assert_eq!(parse_utc_offset("@channel"), None);
+    assert_eq!(parse_timezone("+8"), Some(Timezone::Offset(8 * 3600)));
+    assert_eq!(
+        parse_timezone("Asia/Shanghai"),
+        Some(Timezone::Zone(Tz::Asia__Shanghai))
+    );
+    assert_eq!(parse_timezone("@channel"), None);
+}
@@ -0,0 +1,371 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::net::SocketAddr;
+
+use serde_json;
+
+use data::RewriteRule;
+use errors::*;
+use feed;
+use logging;
+use shortcut;
+
+// how many feeds a chat may subscribe to, 0 means unlimited
+#[derive(Debug, Clone, Default)]
+pub struct Quota {
+    pub free: usize,
+    pub premium: usize,
+    // chats without a limit, set by owners
+    pub exempt: Vec<i64>,
+}
+
+impl Quota {
+    pub fn limit(&self, chat: i64, premium: bool) -> Option<usize> {
+        if self.exempt.contains(&chat) {
+            return None;
+        }
+        let limit = if premium { self.premium } else { self.free };
+        if limit == 0 {
+            None
+        } else {
+            Some(limit)
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub datafile: String,
+    pub token: String,
+    pub period: u64,
+    pub send_workers: usize,
+    pub host_budget: u32,
+    pub max_concurrent_fetches: usize,
+    pub backup_interval: u64,
+    pub backup_keep: usize,
+    pub max_item_age: u32,
+    pub dead_feed_days: u32,
+    pub seen_items_days: u32,
+    pub seen_items_max: usize,
+    // keep the seen items in `DATAFILE.seen`
+    pub seen_file: bool,
+    pub admin_api: Option<String>,
+    // for fetching feeds, e.g. `socks5h://127.0.0.1:1080`
+    pub proxy: Option<String>,
+    pub user_agent: Option<String>,
+    pub self_domains: Vec<String>,
+    pub allowed_domains: Vec<String>,
+    pub blocked_domains: Vec<String>,
+    pub owners: Vec<i64>,
+    // only these users and chats, and those using `/auth` with the passphrase, may
+    // use the bot when either is set
+    pub allowed_chats: Vec<i64>,
+    pub passphrase: Option<String>,
+    // subscribing to a domain no feed is on waits for an owner to approve it
+    pub approve_new_domains: bool,
+    pub max_subscriptions: usize,
+    pub premium_max_subscriptions: usize,
+    pub quota_exempt: Vec<i64>,
+    // the default of `/groupadminonly`
+    pub group_admin_only: bool,
+    // of each user except the owners, 0 is unlimited
+    pub commands_per_minute: u32,
+    // the default of `/maintenance`
+    pub read_only: bool,
+    // replied in read-only mode, `Lang::read_only` when not set
+    pub maintenance_message: Option<String>,
+    // the default of `/cleanlinks`
+    pub strip_tracking: bool,
+    // removed by `/cleanlinks`, e.g. `utm_*`, `pipeline::DEFAULT_TRACKING_PARAMS` when empty
+    pub tracking_params: Vec<String>,
+    // domain -> Instant View rhash in hex
+    pub instant_view: HashMap<String, String>,
+    // name -> `DOMAIN=HOST` or `DOMAIN=LINK` with `{}`, see `/rewrite`
+    pub rewrite_rules: HashMap<String, String>,
+    // base URL of the RSSHub instance for `rsshub:route`
+    pub rsshub: String,
+    // prefix -> feed link with `{}` for the rest, e.g. `/sub github:owner/repo`
+    pub shortcuts: HashMap<String, String>,
+    // `plain` or `json`
+    pub log_format: String,
+    // like `RUST_LOG`, e.g. `info,rssbot::fetcher=debug`
+    pub log_level: Option<String>,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            datafile: String::new(),
+            token: String::new(),
+            period: 300,
+            send_workers: 4,
+            host_budget: 0,
+            max_concurrent_fetches: 0,
+            backup_interval: 24 * 60 * 60,
+            backup_keep: 0,
+            max_item_age: 30,
+            dead_feed_days: 0,
+            seen_items_days: 30,
+            seen_items_max: 1000,
+            seen_file: false,
+            admin_api: None,
+            proxy: None,
+            user_agent: None,
+            self_domains: Vec::new(),
+            allowed_domains: Vec::new(),
+            blocked_domains: Vec::new(),
+            owners: Vec::new(),
+            allowed_chats: Vec::new(),
+            passphrase: None,
+            approve_new_domains: false,
+            max_subscriptions: 0,
+            premium_max_subscriptions: 0,
+            quota_exempt: Vec::new(),
+            group_admin_only: false,
+            commands_per_minute: 20,
+            read_only: false,
+            maintenance_message: None,
+            strip_tracking: false,
+            tracking_params: Vec::new(),
+            instant_view: HashMap::new(),
+            rewrite_rules: HashMap::new(),
+            rsshub: shortcut::DEFAULT_RSSHUB.to_owned(),
+            shortcuts: HashMap::new(),
+            log_format: "plain".to_owned(),
+            log_level: None,
+        }
+    }
+}
+
+fn parse_value<T: ::std::str::FromStr>(name: &str, value: &str) -> Result<T> {
+    value
+        .parse()
+        .map_err(|_| ErrorKind::InvalidConfig(format!("invalid value for {}: {}", name, value)).into())
+}
+
+// `PORT` or `HOST:PORT`, a bare port only listens on localhost
+fn parse_listen_addr(value: &str) -> Result<SocketAddr> {
+    match value.parse::<u16>() {
+        Ok(port) => Ok(SocketAddr::from(([127, 0, 0, 1], port))),
+        Err(_) => parse_value("--admin-api", value),
+    }
+}
+
+impl Config {
+    pub fn load(path: &str) -> Result<Config> {
+        let f = File::open(path).chain_err(|| ErrorKind::ConfigOpen(path.to_owned()))?;
+        serde_json::from_reader(&f).chain_err(|| ErrorKind::ConfigOpen(path.to_owned()))
+    }
+
+    // DATAFILE TELEGRAM-BOT-TOKEN [PERIOD] [--option value]...
+    // options given on the command line override the ones in `--config FILE`
+    pub fn from_args(args: &[String]) -> Result<Config> {
+        let mut positional = Vec::new();
+        let mut options = Vec::new();
+        let mut iter = args.iter().skip(1);
+        while let Some(arg) = iter.next() {
+            if arg.starts_with("--") {
+                let value = iter.next().ok_or_else(|| {
+                    Error::from(ErrorKind::InvalidConfig(format!("missing value for {}", arg)))
+                })?;
+                options.push((arg.as_str(), value.as_str()));
+            } else {
+                positional.push(arg.as_str());
+            }
+        }
+
+        let mut config = match options.iter().find(|option| option.0 == "--config") {
+            Some(option) => Config::load(option.1)?,
+            None => Config::default(),
+        };
+        if let Some(datafile) = positional.get(0) {
+            config.datafile = datafile.to_string();
+        }
+        if let Some(token) = positional.get(1) {
+            config.token = token.to_string();
+        }
+        if let Some(period) = positional.get(2) {
+            config.period = parse_value("PERIOD", period)?;
+        }
+        for &(name, value) in &options {
+            match name {
+                "--config" => (),
+                "--send-workers" => config.send_workers = parse_value(name, value)?,
+                "--host-budget" => config.host_budget = parse_value(name, value)?,
+                "--max-concurrent-fetches" => {
+                    config.max_concurrent_fetches = parse_value(name, value)?
+                }
+                "--backup-interval" => config.backup_interval = parse_value(name, value)?,
+                "--backup-keep" => config.backup_keep = parse_value(name, value)?,
+                "--max-item-age" => config.max_item_age = parse_value(name, value)?,
+                "--dead-feed-days" => config.dead_feed_days = parse_value(name, value)?,
+                "--seen-items-days" => config.seen_items_days = parse_value(name, value)?,
+                "--seen-items-max" => config.seen_items_max = parse_value(name, value)?,
+                "--seen-file" => config.seen_file = parse_value(name, value)?,
+                "--admin-api" => config.admin_api = Some(value.to_owned()),
+                "--proxy" => config.proxy = Some(value.to_owned()),
+                "--user-agent" => config.user_agent = Some(value.to_owned()),
+                "--self-domain" => config.self_domains.push(value.to_owned()),
+                "--allow-domain" => config.allowed_domains.push(value.to_owned()),
+                "--block-domain" => config.blocked_domains.push(value.to_owned()),
+                "--owner" => config.owners.push(parse_value(name, value)?),
+                "--allow-chat" => config.allowed_chats.push(parse_value(name, value)?),
+                "--passphrase" => config.passphrase = Some(value.to_owned()),
+                "--approve-new-domains" => config.approve_new_domains = parse_value(name, value)?,
+                "--max-subscriptions" | "--max-subs-per-chat" => {
+                    config.max_subscriptions = parse_value(name, value)?
+                }
+                "--instant-view" => {
+                    let mut parts = value.splitn(2, '=');
+                    match (parts.next(), parts.next()) {
+                        (Some(domain), Some(rhash)) => {
+                            config.instant_view.insert(domain.to_owned(), rhash.to_owned());
+                        }
+                        _ => return Err(ErrorKind::InvalidConfig(format!(
+                            "invalid value for {}: {}, expected DOMAIN=RHASH",
+                            name, value
+                        )).into()),
+                    }
+                }
+                "--rewrite-rule" => {
+                    let mut parts = value.splitn(2, '=');
+                    match (parts.next(), parts.next()) {
+                        (Some(name), Some(rule)) => {
+                            config.rewrite_rules.insert(name.to_owned(), rule.to_owned());
+                        }
+                        _ => return Err(ErrorKind::InvalidConfig(format!(
+                            "invalid value for {}: {}, expected NAME=DOMAIN=TARGET",
+                            name, value
+                        )).into()),
+                    }
+                }
+                "--rsshub" => config.rsshub = value.to_owned(),
+                "--shortcut" => {
+                    let mut parts = value.splitn(2, '=');
+                    match (parts.next(), parts.next()) {
+                        (Some(prefix), Some(link)) => {
+                            config.shortcuts.insert(prefix.to_owned(), link.to_owned());
+                        }
+                        _ => return Err(ErrorKind::InvalidConfig(format!(
+                            "invalid value for {}: {}, expected PREFIX=URL",
+                            name, value
+                        )).into()),
+                    }
+                }
+                "--log-format" => config.log_format = value.to_owned(),
+                "--log-level" => config.log_level = Some(value.to_owned()),
+                "--quota-exempt" => config.quota_exempt.push(parse_value(name, value)?),
+                "--group-admin-only" => config.group_admin_only = parse_value(name, value)?,
+                "--commands-per-minute" => config.commands_per_minute = parse_value(name, value)?,
+                "--read-only" => config.read_only = parse_value(name, value)?,
+                "--maintenance-message" => config.maintenance_message = Some(value.to_owned()),
+                "--strip-tracking" => config.strip_tracking = parse_value(name, value)?,
+                "--tracking-param" => config.tracking_params.push(value.to_owned()),
+                "--premium-max-subscriptions" => {
+                    config.premium_max_subscriptions = parse_value(name, value)?
+                }
+                _ => return Err(ErrorKind::InvalidConfig(format!("unknown option {}", name)).into()),
+            }
+        }
+
+        if config.datafile.is_empty() || config.token.is_empty() {
+            return Err(ErrorKind::InvalidConfig("DATAFILE and TELEGRAM-BOT-TOKEN are required".into()).into());
+        }
+        if config.backup_keep > 0 && config.backup_interval == 0 {
+            return Err(ErrorKind::InvalidConfig("--backup-interval must be positive".into()).into());
+        }
+        if config.send_workers == 0 {
+            return Err(ErrorKind::InvalidConfig("--send-workers must be positive".into()).into());
+        }
+        if let Some(ref proxy) = config.proxy {
+            if proxy == feed::NO_PROXY || !feed::is_valid_proxy(proxy) {
+                return Err(ErrorKind::InvalidConfig(format!("invalid proxy: {}", proxy)).into());
+            }
+        }
+        config.admin_api_addr()?;
+        config.iv_rhashes()?;
+        config.rewrite_rules()?;
+        config.shortcuts()?;
+        config.log_format()?;
+        Ok(config)
+    }
+
+    pub fn log_format(&self) -> Result<logging::Format> {
+        logging::Format::from_name(&self.log_format).ok_or_else(|| {
+            ErrorKind::InvalidConfig(format!("invalid log format: {}", self.log_format)).into()
+        })
+    }
+
+    // hosts serving the bot's own output, feeds on them would loop
+    pub fn self_hosts(&self) -> Vec<String> {
+        let mut hosts = self.self_domains.clone();
+        if let Ok(Some(addr)) = self.admin_api_addr() {
+            hosts.push(addr.ip().to_string());
+            if addr.ip().is_loopback() {
+                hosts.push("localhost".to_string());
+            }
+        }
+        hosts
+    }
+
+    pub fn iv_rhashes(&self) -> Result<HashMap<String, u64>> {
+        self.instant_view
+            .iter()
+            .map(|(domain, rhash)| {
+                let rhash = u64::from_str_radix(rhash, 16).map_err(|_| {
+                    ErrorKind::InvalidConfig(format!("invalid rhash for {}: {}", domain, rhash))
+                })?;
+                Ok((domain.to_lowercase(), rhash))
+            })
+            .collect()
+    }
+
+    pub fn rewrite_rules(&self) -> Result<HashMap<String, RewriteRule>> {
+        self.rewrite_rules
+            .iter()
+            .map(|(name, rule)| {
+                let name = name.to_lowercase();
+                match RewriteRule::parse(rule) {
+                    Some(rule) if RewriteRule::is_valid_name(&name) => Ok((name, rule)),
+                    _ => Err(ErrorKind::InvalidConfig(format!(
+                        "invalid rewrite rule {}: {}, expected DOMAIN=TARGET",
+                        name, rule
+                    )).into()),
+                }
+            })
+            .collect()
+    }
+
+    // the builtin ones, overridden by the configured ones
+    pub fn shortcuts(&self) -> Result<HashMap<String, String>> {
+        let mut shortcuts = shortcut::builtin(&self.rsshub);
+        for (prefix, link) in &self.shortcuts {
+            let valid_prefix =
+                !prefix.is_empty() && prefix.chars().all(|c| c.is_ascii_alphanumeric());
+            if !valid_prefix || !link.contains("{}") {
+                return Err(ErrorKind::InvalidConfig(format!(
+                    "invalid shortcut {}: {}, expected a link with {{}}",
+                    prefix, link
+                )).into());
+            }
+            shortcuts.insert(prefix.to_ascii_lowercase(), link.clone());
+        }
+        Ok(shortcuts)
+    }
+
+    pub fn quota(&self) -> Quota {
+        Quota {
+            free: self.max_subscriptions,
+            premium: self.premium_max_subscriptions,
+            exempt: self.quota_exempt.clone(),
+        }
+    }
+
+    pub fn admin_api_addr(&self) -> Result<Option<SocketAddr>> {
+        match self.admin_api {
+            Some(ref addr) => parse_listen_addr(addr).map(Some),
+            None => Ok(None),
+        }
+    }
+}
@@ -0,0 +1,32 @@
+// RFC 4180
+fn escape_field(field: &str) -> String {
+    if field.contains(|c| c == ',' || c == '"' || c == '\n' || c == '\r') {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_owned()
+    }
+}
+
+pub fn to_csv<T, F>(header: &[&str], data: &[T], row_fn: F) -> String
+where
+    F: Fn(&T) -> Vec<String>,
+{
+    let mut csv = header.join(",");
+    csv.push_str("\r\n");
+    for item in data {
+        let row: Vec<String> = row_fn(item).iter().map(|s| escape_field(s)).collect();
+        csv.push_str(&row.join(","));
+        csv.push_str("\r\n");
+    }
+    csv
+}
+
+#[test]
+fn test_to_csv() {
+    let data = vec![("a", "plain"), ("b", "with, \"quotes\"")];
+    let r = to_csv(&["key", "value"], &data, |&(k, v)| vec![k.to_owned(), v.to_owned()]);
+    assert_eq!(
+        r,
+        "key,value\r\na,plain\r\nb,\"with, \"\"quotes\"\"\"\r\n"
+    );
+}
@@ -1,15 +1,24 @@
 use std;
-use std::cell::RefCell;
-use std::collections::{HashMap, HashSet};
-use std::fs::File;
-use std::hash::{Hash, Hasher};
-use std::path::Path;
+use std::cell::{Cell, RefCell};
+use std::cmp::max;
+use std::collections::hash_map::{Entry, RandomState};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::fs::{self, File};
+use std::hash::{BuildHasher, Hash, Hasher};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
 
+use chrono::Utc;
 use serde_json;
+use url::Url;
 
+use backup;
+use config::Quota;
 use errors::*;
 use feed;
+use messages::{format_utc_offset, Lang, Locale};
+use utils::{legacy_normalize_url, link_host, link_in_domains, normalize_url, Media};
 
 pub enum SubscriptionResult {
     NewlySubscribed,
@@ -22,8 +31,37 @@ fn get_hash<T: Hash>(t: &T) -> u64 {
     hasher.finish()
 }
 
-type FeedID = u64;
-type SubscriberID = i64;
+// links that only differ in letter case of host, default port and so on are the same feed
+fn feed_id(rss_link: &str) -> FeedID {
+    get_hash(&normalize_url(rss_link))
+}
+
+pub type FeedID = u64;
+pub type SubscriberID = i64;
+
+const HISTORY_MAX_AGE: i64 = 90 * 24 * 60 * 60;
+const HISTORY_MAX_LEN: usize = 1000;
+// destructive operations in a row share one backup
+const BACKUP_MIN_INTERVAL: i64 = 10 * 60;
+// a left feed is popular again once its subscribers doubled and grew by at least this
+const POPULAR_MIN_GROWTH: usize = 10;
+const LEFT_FEEDS_MAX_AGE: i64 = 180 * 24 * 60 * 60;
+const LEFT_FEEDS_MAX_LEN: usize = 20;
+const RECENT_MAX_LEN: usize = 1000;
+// per feed, searched by inline queries
+const RECENT_ITEMS_MAX_LEN: usize = 20;
+// in seconds, `last_seen` of seen items is only bumped once it's this old, so
+// fetching the same items again doesn't rewrite `DATAFILE.seen` every time
+const SEEN_RESOLUTION: i64 = 24 * 60 * 60;
+// in seconds, for posting the code in the channel and forwarding it back
+const OWNERSHIP_CODE_MAX_AGE: i64 = 60 * 60;
+
+thread_local! {
+    // whether the seen items are kept in `DATAFILE.seen`, see `set_seen_file`
+    static SEEN_FILE: Cell<bool> = Cell::new(false);
+}
+// subscribers of a dead feed have this long to keep it
+const DEAD_FEED_GRACE: i64 = 24 * 60 * 60;
 
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct Feed {
@@ -31,15 +69,116 @@ pub struct Feed {
     pub title: String,
     pub error_count: u32,
     pub subscribers: HashSet<SubscriberID>,
+    // from older versions, moved into `seen` on load
+    #[serde(default, skip_serializing)]
     hash_list: Vec<u64>,
+    #[serde(default, skip_serializing_if = "in_seen_file")]
+    seen: Vec<SeenItem>,
+    // the newest pubDate seen, new items published before it are skipped
+    #[serde(default)]
+    newest_pubdate: Option<i64>,
+    // when the last new item was found, see `/rss new`
+    #[serde(default)]
+    pub last_item_at: Option<i64>,
+    // see `/feedstatus`
+    #[serde(default)]
+    pub last_fetched_at: Option<i64>,
+    // cleared by the next successful fetch
+    #[serde(default)]
+    pub last_error: Option<String>,
+    #[serde(default)]
+    pub failing_since: Option<i64>,
+    // when the subscribers were told it's going to be removed
+    #[serde(default)]
+    pub dead_warned_at: Option<i64>,
+    // overrides the default proxy, see `/proxy`
+    #[serde(default)]
+    pub proxy: Option<String>,
+    // of the last fetch, newest first
+    #[serde(default)]
+    pub recent_items: Vec<RecentItem>,
 }
 
 impl Feed {
     pub fn get_id(&self) -> u64 {
-        get_hash(&self.link)
+        feed_id(&self.link)
+    }
+
+    // whether `item` was seen already, so it wouldn't be delivered
+    pub fn has_item(&self, item: &feed::Item) -> bool {
+        let hash = gen_item_hash(item);
+        self.seen.iter().any(|seen| seen.hash == hash)
+    }
+
+    fn merge_seen(&mut self, seen: Vec<SeenItem>) {
+        for seen in seen {
+            if !self.seen.iter().any(|s| s.hash == seen.hash) {
+                self.seen.push(seen);
+            }
+        }
+    }
+
+    fn migrate_hash_list(&mut self, now: i64) {
+        for hash in self.hash_list.drain(..) {
+            self.seen.push(SeenItem {
+                hash: hash,
+                first_seen: now,
+                last_seen: now,
+            });
+        }
+    }
+
+    fn merge(&mut self, other: Feed) {
+        self.subscribers.extend(other.subscribers);
+        self.merge_seen(other.seen);
+        self.error_count = self.error_count.min(other.error_count);
+        self.last_item_at = self.last_item_at.max(other.last_item_at);
+        self.newest_pubdate = self.newest_pubdate.max(other.newest_pubdate);
+        self.proxy = self.proxy.take().or(other.proxy);
+        if self.recent_items.is_empty() {
+            self.recent_items = other.recent_items;
+        }
     }
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecentItem {
+    pub title: String,
+    pub link: String,
+}
+
+// the items with a title and a link, at most `RECENT_ITEMS_MAX_LEN`
+fn recent_items(items: &[feed::Item]) -> Vec<RecentItem> {
+    items
+        .iter()
+        .filter_map(|item| match (&item.title, &item.link) {
+            (&Some(ref title), &Some(ref link)) => Some(RecentItem {
+                title: title.clone(),
+                link: link.clone(),
+            }),
+            _ => None,
+        })
+        .take(RECENT_ITEMS_MAX_LEN)
+        .collect()
+}
+
+// an item of a feed, remembered so it's only delivered once
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct SeenItem {
+    hash: u64,
+    first_seen: i64,
+    // items still in the feed never expire
+    last_seen: i64,
+}
+
+fn in_seen_file(_: &[SeenItem]) -> bool {
+    SEEN_FILE.with(|s| s.get())
+}
+
+fn seen_path(path: &str) -> String {
+    format!("{}.seen", path)
+}
+
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
 pub enum LinkPreview {
     Off,
@@ -58,16 +197,331 @@ impl LinkPreview {
     }
 }
 
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct SubscriptionOptions {
+    pub media: bool,
+    pub og_image: bool,
+    // link to the `rel=canonical` URL of the item page instead
+    pub canonical: bool,
+    pub expires_at: Option<i64>,
+    pub expiry_notified: bool,
+    pub template: Option<String>,
+    pub template_test: Option<TemplateTest>,
+    // sent when fetching, e.g. API keys of private feeds
+    pub headers: feed::Headers,
+    pub tags: Vec<String>,
+    // why it was subscribed, see `/note`
+    pub note: Option<String>,
+    // shown instead of the title of the feed, see `/rename`
+    pub title: Option<String>,
+    // before the messages, e.g. an emoji, see `/prefix`
+    pub prefix: Option<String>,
+    // characters of the description shown below the title, see `/summary`
+    pub summary: Option<usize>,
+    // shown after the title, see `/display`
+    pub show_author: bool,
+    pub show_date: bool,
+    // audio enclosures are sent as playable files, see `/podcast`
+    pub podcast: bool,
+    // delivered without a notification sound, see `/silent`
+    pub silent: bool,
+    // links without tracking parameters, see `/cleanlinks`, `None` is `--strip-tracking`
+    pub strip_tracking: Option<bool>,
+    // lowercase, items whose title contains it are pinned, see `/pinrule`
+    pub pin_keyword: Option<String>,
+    // names of the rules changing the item links, see `/rewrite`
+    pub rewrite: Vec<String>,
+    // appended to messages, see `/hashtags`
+    pub category_hashtags: bool,
+    pub hashtags: Vec<String>,
+    // deliver in batches of this many items, see `/snooze`
+    pub snooze_items: Option<usize>,
+    pub snoozed: Vec<SnoozedItem>,
+    // not removed along with a dead feed
+    pub keep_failing: bool,
+    // told when the feed starts failing and when it recovers, see `/errnotify`
+    pub error_notify: bool,
+    // items the feed had when subscribed but wasn't updated with yet, never delivered
+    pub initial_items: Vec<u64>,
+}
+
+// where item links on `domain` and its subdomains point instead, see `/rewrite`
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RewriteRule {
+    // `*` matches every link
+    pub domain: String,
+    // a host replacing the one of the link, or a link with `{}` for the whole link
+    pub to: String,
+}
+
+impl RewriteRule {
+    // `DOMAIN=HOST` or `DOMAIN=LINK`, e.g. `twitter.com=nitter.net`
+    // or `*=https://archive.today/newest/{}`
+    pub fn parse(s: &str) -> Option<RewriteRule> {
+        let mut parts = s.splitn(2, '=');
+        let domain = parts.next()?.trim_right_matches('.').to_lowercase();
+        let to = parts.next()?.trim().to_owned();
+        let valid_domain = !domain.is_empty() && !domain.contains('/');
+        let valid_to = if to.contains("{}") {
+            to.starts_with("http://") || to.starts_with("https://")
+        } else {
+            !to.is_empty() && !to.contains(|c: char| c == '/' || c == ':' || c.is_whitespace())
+        };
+        if valid_domain && valid_to {
+            Some(RewriteRule {
+                domain: domain,
+                to: to,
+            })
+        } else {
+            None
+        }
+    }
+
+    pub fn is_valid_name(name: &str) -> bool {
+        !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    }
+
+    // `None` if the link isn't on the domain
+    pub fn rewrite(&self, link: &str) -> Option<String> {
+        let host = link_host(link)?;
+        let matched = self.domain == "*"
+            || host == self.domain
+            || host.ends_with(&format!(".{}", self.domain));
+        if !matched {
+            return None;
+        }
+        if self.to.contains("{}") {
+            return Some(self.to.replace("{}", link));
+        }
+        let mut url = Url::parse(link).ok()?;
+        url.set_host(Some(&self.to)).ok()?;
+        Some(url.into_string())
+    }
+}
+
+// see `Database::check_dead_feed`
+#[derive(Debug, Clone, PartialEq)]
+pub enum DeadFeed {
+    // the subscribers who can still keep it
+    Warn(Vec<SubscriberID>),
+    // the subscribers who were unsubscribed
+    Removed(Vec<SubscriberID>),
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct SnoozedItem {
+    pub title: Option<String>,
+    pub link: Option<String>,
+}
+
+// two candidate templates, previewed to `admin` with the next update
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct TemplateTest {
+    pub a: String,
+    pub b: String,
+    pub admin: i64,
+    pub previewed: bool,
+}
+
+// settings of a chat, independent of its subscriptions
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct ChatSettings {
+    pub lang: Lang,
+    pub expires_at: Option<i64>,
+    pub expiry_notified: bool,
+    // entitlement to the premium quota, granted by an owner
+    pub premium_until: Option<i64>,
+    // seconds east of UTC
+    pub utc_offset: i32,
+    // IANA name like `Asia/Shanghai`, overrides `utc_offset`
+    pub timezone: Option<String>,
+    // opt-in, see `Database::take_popular_again`
+    pub resubscribe_suggestions: bool,
+    // last run of `/rss new`
+    pub new_items_checked_at: i64,
+    // items delivered again within this from another feed are skipped, see `/dedupe`
+    pub dedupe_hours: u32,
+    // only group administrators may change the subscriptions, see `/groupadminonly`,
+    // `None` follows `--group-admin-only`
+    pub admin_only: Option<bool>,
+}
+
+impl ChatSettings {
+    pub fn locale(&self) -> Locale {
+        Locale {
+            lang: self.lang,
+            utc_offset: self.utc_offset,
+            timezone: self.timezone.as_ref().and_then(|name| name.parse().ok()),
+        }
+    }
+
+    pub fn timezone_name(&self) -> String {
+        self.timezone
+            .clone()
+            .unwrap_or_else(|| format_utc_offset(self.utc_offset))
+    }
+}
+
+// a feed a chat unsubscribed from, remembered to suggest it again once it gets popular
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LeftFeed {
+    pub link: String,
+    pub title: String,
+    pub left_at: i64,
+    // subscriber count after the chat left
+    pub subscribers: usize,
+}
+
+// a web page without a feed, see `/watch`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct WatchedPage {
+    pub link: String,
+    pub title: String,
+    // the whole `<body>` without one, see `html::select`
+    pub selector: Option<String>,
+    // of the text of the selected region, `None` until it was fetched once
+    pub hash: Option<u64>,
+    pub error_count: u32,
+    pub subscribers: HashSet<SubscriberID>,
+}
+
+impl WatchedPage {
+    pub fn get_id(&self) -> u64 {
+        watch_id(&self.link, self.selector.as_ref().map(|s| s.as_str()))
+    }
+}
+
+// a page is watched once for every selector
+fn watch_id(link: &str, selector: Option<&str>) -> u64 {
+    get_hash(&(normalize_url(link), selector))
+}
+
+// see `/botstats`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Stats {
+    pub feeds: usize,
+    pub subscribers: usize,
+    pub failing_feeds: usize,
+    pub delivered_last_day: usize,
+    pub hashes: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeliveredItem {
+    pub time: i64,
+    pub feed: String,
+    pub title: String,
+    pub link: String,
+}
+
 #[derive(Serialize)]
 struct DataStorageOut<'a> {
     pub feeds: Vec<&'a Feed>,
     pub lp: Vec<(SubscriberID, FeedID, LinkPreview)>,
+    pub options: Vec<(SubscriberID, FeedID, &'a SubscriptionOptions)>,
+    pub history: Vec<(SubscriberID, &'a VecDeque<DeliveredItem>)>,
+    pub chats: Vec<(SubscriberID, &'a ChatSettings)>,
+    pub left: Vec<(SubscriberID, &'a Vec<LeftFeed>)>,
+    pub recent: Vec<(SubscriberID, &'a VecDeque<(i64, u64)>)>,
+    pub watched: Vec<&'a WatchedPage>,
+    pub queue: Vec<(u64, &'a QueuedJob)>,
+    pub mirrors: Vec<(SubscriberID, FeedID, &'a HashSet<SubscriberID>)>,
+    pub blocked_domains: &'a Vec<String>,
+    pub iv_rhashes: &'a HashMap<String, u64>,
+    pub rewrite_rules: &'a HashMap<String, RewriteRule>,
+    pub authorized_chats: &'a HashSet<i64>,
+    pub approvals: Vec<(u64, &'a ApprovalRequest)>,
+    pub approved_domains: &'a HashSet<String>,
+    pub ownership_codes: &'a Vec<OwnershipCode>,
 }
 
 #[derive(Deserialize)]
 struct DataStorageIn {
     pub feeds: Vec<Feed>,
     pub lp: Vec<(SubscriberID, FeedID, LinkPreview)>,
+    #[serde(default)]
+    pub options: Vec<(SubscriberID, FeedID, SubscriptionOptions)>,
+    #[serde(default)]
+    pub history: Vec<(SubscriberID, VecDeque<DeliveredItem>)>,
+    #[serde(default)]
+    pub chats: Vec<(SubscriberID, ChatSettings)>,
+    #[serde(default)]
+    pub left: Vec<(SubscriberID, Vec<LeftFeed>)>,
+    #[serde(default)]
+    pub recent: Vec<(SubscriberID, VecDeque<(i64, u64)>)>,
+    #[serde(default)]
+    pub watched: Vec<WatchedPage>,
+    #[serde(default)]
+    pub queue: Vec<(u64, QueuedJob)>,
+    #[serde(default)]
+    pub mirrors: Vec<(SubscriberID, FeedID, HashSet<SubscriberID>)>,
+    #[serde(default)]
+    pub blocked_domains: Vec<String>,
+    #[serde(default)]
+    pub iv_rhashes: HashMap<String, u64>,
+    #[serde(default)]
+    pub rewrite_rules: HashMap<String, RewriteRule>,
+    #[serde(default)]
+    pub authorized_chats: HashSet<i64>,
+    #[serde(default)]
+    pub approvals: Vec<(u64, ApprovalRequest)>,
+    #[serde(default)]
+    pub approved_domains: HashSet<String>,
+    #[serde(default)]
+    pub ownership_codes: Vec<OwnershipCode>,
+}
+
+// a job of `Sender` until it's sent, so a restart doesn't lose it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueuedJob {
+    pub target: SubscriberID,
+    pub media_msgs: Vec<Media>,
+    pub msgs: Vec<String>,
+    pub link_preview: bool,
+    pub silent: bool,
+    pub pin_keyword: Option<String>,
+    pub feed_title: String,
+    // (title, link) of the items, for the delivery history
+    pub items: Vec<(Option<String>, Option<String>)>,
+}
+
+// posted in a channel to prove `user` manages it when the bot can't see the
+// administrators, only accepted once and from the chat it was given in
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct OwnershipCode {
+    channel: i64,
+    chat: i64,
+    user: i64,
+    code: String,
+    expires_at: i64,
+}
+
+// 128 bits nobody can guess, the keys of `RandomState` come from the OS
+fn random_code() -> String {
+    let now = Utc::now().timestamp_nanos();
+    let halves: Vec<u64> = (0..2u8)
+        .map(|i| {
+            let mut hasher = RandomState::new().build_hasher();
+            (now, i).hash(&mut hasher);
+            hasher.finish()
+        })
+        .collect();
+    format!("rssbot-verify-{:016x}{:016x}", halves[0], halves[1])
+}
+
+// a subscription to a domain no feed was on before, waiting for an owner, see
+// `--approve-new-domains`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ApprovalRequest {
+    pub domain: String,
+    pub link: String,
+    pub subscriber: SubscriberID,
+    pub link_preview: LinkPreview,
+    // where the subscription was asked for and by whom, told about the decision
+    pub chat_id: i64,
+    pub user_id: i64,
 }
 
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
@@ -82,6 +536,67 @@ struct DatabaseInner {
     feeds: HashMap<FeedID, Feed>,
     subscribers: HashMap<SubscriberID, HashSet<FeedID>>,
     lp_map: HashMap<(SubscriberID, FeedID), LinkPreview>,
+    options: HashMap<(SubscriberID, FeedID), SubscriptionOptions>,
+    history: HashMap<SubscriberID, VecDeque<DeliveredItem>>,
+    chats: HashMap<SubscriberID, ChatSettings>,
+    left: HashMap<SubscriberID, Vec<LeftFeed>>,
+    // (time, item hash) queued for a subscriber, only kept with `/dedupe`
+    recent: HashMap<SubscriberID, VecDeque<(i64, u64)>>,
+    watched: HashMap<u64, WatchedPage>,
+    // ID -> job not sent yet, in the order they were queued
+    queue: BTreeMap<u64, QueuedJob>,
+    // (subscriber, feed) -> chats also receiving what it delivers, see `/mirror`
+    mirrors: HashMap<(SubscriberID, FeedID), HashSet<SubscriberID>>,
+    // by owners with `/blockdomain`
+    blocked_domains: Vec<String>,
+    // from the config, not saved, an empty allowlist allows every domain
+    config_blocked_domains: Vec<String>,
+    allowed_domains: Vec<String>,
+    // domain -> Instant View rhash, set by owners with `/iv`, they win over the config
+    iv_rhashes: HashMap<String, u64>,
+    config_iv_rhashes: HashMap<String, u64>,
+    // name -> rule, set by owners with `/rewriterule`, they win over the config
+    rewrite_rules: HashMap<String, RewriteRule>,
+    config_rewrite_rules: HashMap<String, RewriteRule>,
+    quota: Quota,
+    // Telegram user IDs, see `--owner`
+    owners: Vec<i64>,
+    // user or chat IDs from the config, with `passphrase` they make the bot private
+    allowed_chats: Vec<i64>,
+    passphrase: Option<String>,
+    // by `/auth` with the passphrase
+    authorized_chats: HashSet<i64>,
+    // subscribing to a domain no feed is on waits for an owner
+    approve_new_domains: bool,
+    // ID -> request, the IDs are in the callback data of the buttons
+    approvals: BTreeMap<u64, ApprovalRequest>,
+    approved_domains: HashSet<String>,
+    ownership_codes: Vec<OwnershipCode>,
+    // the new items of fetches whose jobs aren't queued yet, not saved until
+    // `mark_seen` so the items and their jobs are persisted together
+    unsaved_seen: HashMap<FeedID, Vec<SeenItem>>,
+    // 0 disables backups
+    backup_keep: usize,
+    last_backup: i64,
+    // in seconds, 0 delivers items of any age
+    max_item_age: i64,
+    // in seconds, 0 never removes failing feeds
+    dead_feed_age: i64,
+    // in seconds since an item was last in its feed, 0 keeps seen items until the cap
+    seen_item_age: i64,
+    // seen items kept per feed, never fewer than it has now, 0 is unlimited
+    seen_items_max: usize,
+    // the default of `ChatSettings::admin_only`
+    group_admin_only: bool,
+    // subscriptions can't be changed, see `/maintenance`
+    read_only: bool,
+    maintenance_message: Option<String>,
+    // what the config said last time, `/maintenance` lasts until it changes
+    config_read_only: Option<(bool, Option<String>)>,
+    // the seen items changed since `DATAFILE.seen` was last written
+    seen_dirty: Cell<bool>,
+    // the queued jobs changed since the last save, see `save_queue`
+    queue_dirty: Cell<bool>,
 }
 
 impl DatabaseInner {
@@ -93,6 +608,24 @@ impl DatabaseInner {
         self.subscribers.iter().map(|(k, _)| *k).collect()
     }
 
+    fn get_feed(&self, rss_link: &str) -> Option<Feed> {
+        self.feeds.get(&feed_id(rss_link)).cloned()
+    }
+
+    fn stats(&self) -> Stats {
+        let since = Utc::now().timestamp() - 24 * 60 * 60;
+        Stats {
+            feeds: self.feeds.len(),
+            subscribers: self.subscribers.len(),
+            failing_feeds: self.feeds.values().filter(|feed| feed.error_count > 0).count(),
+            delivered_last_day: self.history
+                .values()
+                .map(|history| history.iter().filter(|item| item.time >= since).count())
+                .sum(),
+            hashes: self.feeds.values().map(|feed| feed.seen.len()).sum(),
+        }
+    }
+
     fn get_subscribed_feeds(&self, subscriber: SubscriberID) -> Option<Vec<Feed>> {
         self.subscribers.get(&subscriber).map(|feeds| {
             feeds
@@ -103,19 +636,23 @@ impl DatabaseInner {
         })
     }
 
-    fn inc_error_count(&mut self, rss_link: &str) -> u32 {
-        let feed_id = get_hash(&rss_link);
+    fn inc_error_count(&mut self, rss_link: &str, error: &str) -> u32 {
+        let feed_id = feed_id(rss_link);
         self.feeds
             .get_mut(&feed_id)
             .map(|feed| {
                 feed.error_count += 1;
+                feed.last_error = Some(error.to_owned());
+                if feed.failing_since.is_none() {
+                    feed.failing_since = Some(Utc::now().timestamp());
+                }
                 feed.error_count
             })
             .unwrap_or_default()
     }
 
     fn reset_error_count(&mut self, rss_link: &str) {
-        let feed_id = get_hash(&rss_link);
+        let feed_id = feed_id(rss_link);
         self.feeds
             .get_mut(&feed_id)
             .map(|feed| feed.error_count = 0)
@@ -125,7 +662,7 @@ impl DatabaseInner {
     /*fn is_subscribed(&self, subscriber: SubscriberID, rss_link: &str) -> bool {
         self.subscribers
             .get(&subscriber)
-            .map(|feeds| feeds.contains(&get_hash(&rss_link)))
+            .map(|feeds| feeds.contains(&feed_id(rss_link)))
             .unwrap_or(false)
     }*/
 
@@ -136,28 +673,74 @@ impl DatabaseInner {
         rss: &feed::RSS,
         link_preview: LinkPreview,
     ) -> Result<SubscriptionResult> {
-        let feed_id = get_hash(&rss_link);
+        let feed_id = feed_id(rss_link);
+        let limit = self.subscription_limit(subscriber);
+        let newly_subscribed;
         {
             let subscribed_feeds = self
                 .subscribers
                 .entry(subscriber)
                 .or_insert_with(HashSet::new);
-            if !subscribed_feeds.insert(feed_id)
+            if let Some(limit) = limit {
+                if !subscribed_feeds.contains(&feed_id) && subscribed_feeds.len() >= limit {
+                    return Err(ErrorKind::QuotaExceeded(limit).into());
+                }
+            }
+            newly_subscribed = subscribed_feeds.insert(feed_id);
+            if !newly_subscribed
                 && self.lp_map.get(&(subscriber, feed_id)).map(|lp| *lp) == Some(link_preview)
             {
                 return Err(ErrorKind::AlreadySubscribed.into());
             }
         }
+        // the next update of an existing feed may still bring these as new
+        if newly_subscribed {
+            if let Some(feed) = self.feeds.get(&feed_id) {
+                let initial_items: Vec<u64> = rss.items
+                    .iter()
+                    .map(gen_item_hash)
+                    .filter(|hash| !feed.seen.iter().any(|seen| seen.hash == *hash))
+                    .collect();
+                if !initial_items.is_empty() {
+                    self.options
+                        .entry((subscriber, feed_id))
+                        .or_insert_with(SubscriptionOptions::default)
+                        .initial_items = initial_items;
+                }
+            }
+        }
         {
+            let now = Utc::now().timestamp();
             let feed = self.feeds.entry(feed_id).or_insert_with(|| Feed {
                 link: rss_link.to_owned(),
                 title: rss.title.to_owned(),
                 error_count: 0,
-                hash_list: rss.items.iter().map(gen_item_hash).collect(),
+                hash_list: Vec::new(),
+                seen: rss.items
+                    .iter()
+                    .map(|item| SeenItem {
+                        hash: gen_item_hash(item),
+                        first_seen: now,
+                        last_seen: now,
+                    })
+                    .collect(),
+                newest_pubdate: rss.items
+                    .iter()
+                    .filter_map(|item| item.pubdate.map(|date| date.timestamp()))
+                    .max(),
                 subscribers: HashSet::new(),
+                last_item_at: None,
+                last_fetched_at: None,
+                last_error: None,
+                failing_since: None,
+                dead_warned_at: None,
+                proxy: None,
+                recent_items: recent_items(&rss.items),
             });
             feed.subscribers.insert(subscriber);
         }
+        self.seen_dirty.set(true);
+        self.forget_left_feed(subscriber, feed_id);
         let result = match self.update_link_preview(subscriber, feed_id, link_preview) {
             None => SubscriptionResult::NewlySubscribed,
             _ => SubscriptionResult::LinkPreviewUpdated,
@@ -166,8 +749,46 @@ impl DatabaseInner {
         Ok(result)
     }
 
+    // the feeds are known already, nothing is fetched, returns (added, already subscribed)
+    fn copy_subscriptions(
+        &mut self,
+        from: SubscriberID,
+        to: SubscriberID,
+    ) -> Result<(usize, usize)> {
+        let feed_ids: Vec<FeedID> = self.subscribers
+            .get(&from)
+            .map(|feeds| feeds.iter().cloned().collect())
+            .unwrap_or_default();
+        let subscribed = self.subscribers.get(&to).cloned().unwrap_or_default();
+        let (existing, new): (Vec<FeedID>, Vec<FeedID>) =
+            feed_ids.into_iter().partition(|feed_id| subscribed.contains(feed_id));
+        if let Some(limit) = self.subscription_limit(to) {
+            if subscribed.len() + new.len() > limit {
+                return Err(ErrorKind::QuotaExceeded(limit).into());
+            }
+        }
+        if new.is_empty() {
+            return Ok((0, existing.len()));
+        }
+        for feed_id in &new {
+            self.subscribers
+                .entry(to)
+                .or_insert_with(HashSet::new)
+                .insert(*feed_id);
+            if let Some(feed) = self.feeds.get_mut(feed_id) {
+                feed.subscribers.insert(to);
+            }
+            if let Some(lp) = self.lp_map.get(&(from, *feed_id)).cloned() {
+                self.lp_map.insert((to, *feed_id), lp);
+            }
+            self.forget_left_feed(to, *feed_id);
+        }
+        self.save()?;
+        Ok((new.len(), existing.len()))
+    }
+
     fn unsubscribe(&mut self, subscriber: SubscriberID, rss_link: &str) -> Result<Feed> {
-        let feed_id = get_hash(&rss_link);
+        let feed_id = feed_id(rss_link);
 
         let clear_subscriber;
         if let Some(subscribed_feeds) = self.subscribers.get_mut(&subscriber) {
@@ -199,22 +820,132 @@ impl DatabaseInner {
             self.feeds.remove(&feed_id);
         }
         self.lp_map.remove(&(subscriber, feed_id));
+        self.options.remove(&(subscriber, feed_id));
+        self.mirrors.remove(&(subscriber, feed_id));
         self.save()?;
         Ok(result)
     }
 
-    fn delete_subscriber(&mut self, subscriber: SubscriberID) {
-        self.get_subscribed_feeds(subscriber)
-            .map(|feeds| {
-                for feed in feeds {
-                    let _ = self.unsubscribe(subscriber, &feed.link);
-                }
-            })
-            .unwrap_or_default();
+    fn remove_feed(&mut self, rss_link: &str) -> Result<Feed> {
+        let feed_id = feed_id(rss_link);
+        if self.feeds.contains_key(&feed_id) {
+            self.backup_before_destructive();
+        }
+        let feed = self
+            .feeds
+            .remove(&feed_id)
+            .ok_or(ErrorKind::FeedNotFound)?;
+        for subscriber in &feed.subscribers {
+            let clear_subscriber = self
+                .subscribers
+                .get_mut(subscriber)
+                .map(|feeds| {
+                    feeds.remove(&feed_id);
+                    feeds.is_empty()
+                })
+                .unwrap_or(false);
+            if clear_subscriber {
+                self.subscribers.remove(subscriber);
+            }
+            self.lp_map.remove(&(*subscriber, feed_id));
+            self.options.remove(&(*subscriber, feed_id));
+            self.mirrors.remove(&(*subscriber, feed_id));
+        }
+        self.save()?;
+        Ok(feed)
+    }
+
+    // drops feeds without subscribers and the settings of gone subscriptions,
+    // returns how many of both were removed
+    fn vacuum(&mut self) -> Result<(usize, usize)> {
+        self.backup_before_destructive();
+        let feed_count = self.feeds.len();
+        self.feeds.retain(|_, feed| !feed.subscribers.is_empty());
+        let feeds = &self.feeds;
+        for subscribed in self.subscribers.values_mut() {
+            subscribed.retain(|feed_id| feeds.contains_key(feed_id));
+        }
+        self.subscribers.retain(|_, subscribed| !subscribed.is_empty());
+        let settings_count = self.lp_map.len() + self.options.len() + self.mirrors.len();
+        let subscribers = &self.subscribers;
+        let is_subscribed = |&(subscriber, feed_id): &(SubscriberID, FeedID)| {
+            subscribers
+                .get(&subscriber)
+                .map_or(false, |subscribed| subscribed.contains(&feed_id))
+        };
+        self.lp_map.retain(|key, _| is_subscribed(key));
+        self.options.retain(|key, _| is_subscribed(key));
+        self.mirrors.retain(|key, _| is_subscribed(key));
+        let removed = (
+            feed_count - self.feeds.len(),
+            settings_count - self.lp_map.len() - self.options.len() - self.mirrors.len(),
+        );
+        self.save()?;
+        Ok(removed)
+    }
+
+    // returns the feeds it was subscribed to
+    fn delete_subscriber(&mut self, subscriber: SubscriberID) -> Vec<Feed> {
+        if self.subscribers.contains_key(&subscriber) {
+            self.backup_before_destructive();
+        }
+        self.forget_subscriber(subscriber)
+    }
+
+    // `delete_subscriber` without the backup
+    fn forget_subscriber(&mut self, subscriber: SubscriberID) -> Vec<Feed> {
+        self.history.remove(&subscriber);
+        self.chats.remove(&subscriber);
+        self.left.remove(&subscriber);
+        self.recent.remove(&subscriber);
+        self.remove_watcher(subscriber, |_| true);
+        for targets in self.mirrors.values_mut() {
+            targets.remove(&subscriber);
+        }
+        self.mirrors.retain(|_, targets| !targets.is_empty());
+        let feeds = self.get_subscribed_feeds(subscriber).unwrap_or_default();
+        for feed in &feeds {
+            let _ = self.unsubscribe(subscriber, &feed.link);
+        }
+        feeds
+    }
+
+    // everything kept about `subscriber`, for `/erase`, returns the number of
+    // subscriptions removed and whether copies are left in the timestamped backups
+    // until they rotate out, no new backup is made and `DATAFILE.bak` is removed
+    fn erase_subscriber(&mut self, subscriber: SubscriberID) -> Result<(usize, bool)> {
+        let feeds = self.forget_subscriber(subscriber);
+        self.queue.retain(|_, job| job.target != subscriber);
+        self.authorized_chats.remove(&subscriber);
+        self.approvals
+            .retain(|_, request| request.subscriber != subscriber && request.chat_id != subscriber);
+        self.ownership_codes
+            .retain(|code| code.channel != subscriber && code.chat != subscriber);
+        self.save()?;
+        let bak = format!("{}.bak", self.path);
+        if Path::new(&bak).exists() {
+            fs::remove_file(&bak).chain_err(|| ErrorKind::DatabaseSave(bak))?;
+        }
+        Ok((feeds.len(), self.backup_keep > 0))
     }
 
     fn update_subscriber(&mut self, from: SubscriberID, to: SubscriberID) {
-        let feeds = self.subscribers.remove(&from).unwrap();
+        if self.authorized_chats.remove(&from) {
+            self.authorized_chats.insert(to);
+        }
+        for job in self.queue.values_mut().filter(|job| job.target == from) {
+            job.target = to;
+        }
+        for request in self.approvals.values_mut() {
+            if request.subscriber == from {
+                request.subscriber = to;
+            }
+            if request.chat_id == from {
+                request.chat_id = to;
+            }
+        }
+        // a chat without subscriptions still has its settings, history and pages
+        let feeds = self.subscribers.remove(&from).unwrap_or_default();
         for feed_id in &feeds {
             {
                 let feed = self.feeds.get_mut(&feed_id).unwrap();
@@ -224,48 +955,294 @@ impl DatabaseInner {
             self.lp_map
                 .remove(&(from, *feed_id))
                 .and_then(|lp| self.lp_map.insert((to, *feed_id), lp));
+            self.options
+                .remove(&(from, *feed_id))
+                .and_then(|options| self.options.insert((to, *feed_id), options));
+            self.mirrors
+                .remove(&(from, *feed_id))
+                .and_then(|targets| self.mirrors.insert((to, *feed_id), targets));
+        }
+        for targets in self.mirrors.values_mut() {
+            if targets.remove(&from) {
+                targets.insert(to);
+            }
+        }
+        if !feeds.is_empty() {
+            self.subscribers
+                .entry(to)
+                .or_insert_with(HashSet::new)
+                .extend(feeds);
+        }
+        if let Some(history) = self.history.remove(&from) {
+            self.history.insert(to, history);
+        }
+        if let Some(settings) = self.chats.remove(&from) {
+            self.chats.insert(to, settings);
+        }
+        if let Some(left) = self.left.remove(&from) {
+            self.left.insert(to, left);
+        }
+        if let Some(recent) = self.recent.remove(&from) {
+            self.recent.insert(to, recent);
+        }
+        for page in self.watched.values_mut() {
+            if page.subscribers.remove(&from) {
+                page.subscribers.insert(to);
+            }
+        }
+        self.save().unwrap_or_default();
+    }
+
+    fn watch(
+        &mut self,
+        subscriber: SubscriberID,
+        link: &str,
+        selector: Option<String>,
+        title: &str,
+        hash: u64,
+    ) -> Result<()> {
+        let id = watch_id(link, selector.as_ref().map(|s| s.as_str()));
+        let page = self.watched.entry(id).or_insert_with(|| WatchedPage {
+            link: link.to_owned(),
+            title: title.to_owned(),
+            selector: selector,
+            hash: Some(hash),
+            ..WatchedPage::default()
+        });
+        if !page.subscribers.insert(subscriber) {
+            return Err(ErrorKind::AlreadySubscribed.into());
+        }
+        self.save()
+    }
+
+    // returns the pages `subscriber` no longer watches, pages without watchers are removed
+    fn remove_watcher<F>(&mut self, subscriber: SubscriberID, filter: F) -> Vec<WatchedPage>
+    where
+        F: Fn(&WatchedPage) -> bool,
+    {
+        let ids: Vec<u64> = self.watched
+            .iter()
+            .filter(|&(_, page)| page.subscribers.contains(&subscriber) && filter(page))
+            .map(|(id, _)| *id)
+            .collect();
+        let mut removed = Vec::with_capacity(ids.len());
+        for id in ids {
+            let empty = {
+                let page = self.watched.get_mut(&id).unwrap();
+                page.subscribers.remove(&subscriber);
+                removed.push(page.clone());
+                page.subscribers.is_empty()
+            };
+            if empty {
+                self.watched.remove(&id);
+            }
+        }
+        removed
+    }
+
+    // every selector watched on the page
+    fn unwatch(&mut self, subscriber: SubscriberID, link: &str) -> Result<Vec<WatchedPage>> {
+        let link = normalize_url(link);
+        let removed = self.remove_watcher(subscriber, |page| normalize_url(&page.link) == link);
+        if removed.is_empty() {
+            return Err(ErrorKind::NotSubscribed.into());
+        }
+        self.save()?;
+        Ok(removed)
+    }
+
+    fn get_watched_pages(&self, subscriber: SubscriberID) -> Vec<WatchedPage> {
+        let mut pages: Vec<WatchedPage> = self.watched
+            .values()
+            .filter(|page| page.subscribers.contains(&subscriber))
+            .cloned()
+            .collect();
+        pages.sort_by(|a, b| a.link.cmp(&b.link));
+        pages
+    }
+
+    // `true` if the selected region changed since the last check
+    fn update_watched_page(&mut self, id: u64, hash: u64) -> bool {
+        let changed = match self.watched.get_mut(&id) {
+            Some(page) => {
+                let changed = page.hash.map_or(false, |old| old != hash);
+                let modified = page.hash != Some(hash) || page.error_count > 0;
+                page.hash = Some(hash);
+                page.error_count = 0;
+                if !modified {
+                    return false;
+                }
+                changed
+            }
+            None => return false,
+        };
+        self.save().unwrap_or_default();
+        changed
+    }
+
+    fn inc_watch_error_count(&mut self, id: u64) -> u32 {
+        self.watched
+            .get_mut(&id)
+            .map(|page| {
+                page.error_count += 1;
+                page.error_count
+            })
+            .unwrap_or_default()
+    }
+
+    fn set_proxy(&mut self, rss_link: &str, proxy: Option<String>) -> Result<()> {
+        match self.feeds.get_mut(&feed_id(rss_link)) {
+            Some(feed) => feed.proxy = proxy,
+            None => return Err(ErrorKind::FeedNotFound.into()),
+        }
+        self.save()
+    }
+
+    // the feed keeps its subscribers and their settings under the new link
+    fn move_feed(&mut self, from: &str, to: &str) -> Result<Feed> {
+        let old_id = feed_id(from);
+        let new_id = feed_id(to);
+        let mut feed = self.feeds.remove(&old_id).ok_or(ErrorKind::FeedNotFound)?;
+        if old_id == new_id {
+            feed.link = to.to_owned();
+            self.feeds.insert(new_id, feed.clone());
+            self.save()?;
+            return Ok(feed);
+        }
+        feed.link = to.to_owned();
+        for subscriber in &feed.subscribers {
+            if let Some(feeds) = self.subscribers.get_mut(subscriber) {
+                feeds.remove(&old_id);
+                feeds.insert(new_id);
+            }
+            if let Some(lp) = self.lp_map.remove(&(*subscriber, old_id)) {
+                self.lp_map.entry((*subscriber, new_id)).or_insert(lp);
+            }
+            if let Some(options) = self.options.remove(&(*subscriber, old_id)) {
+                self.options.entry((*subscriber, new_id)).or_insert(options);
+            }
+            if let Some(targets) = self.mirrors.remove(&(*subscriber, old_id)) {
+                self.mirrors
+                    .entry((*subscriber, new_id))
+                    .or_insert_with(HashSet::new)
+                    .extend(targets);
+            }
         }
-        self.subscribers.insert(to, feeds);
+        let feed = match self.feeds.entry(new_id) {
+            Entry::Occupied(mut entry) => {
+                entry.get_mut().merge(feed);
+                entry.get().clone()
+            }
+            Entry::Vacant(entry) => entry.insert(feed).clone(),
+        };
+        self.save()?;
+        Ok(feed)
     }
 
     fn update(&mut self, rss_link: &str, items: Vec<feed::Item>) -> Vec<feed::Item> {
-        let feed_id = get_hash(&rss_link);
+        let feed_id = feed_id(rss_link);
         if self.feeds.get(&feed_id).is_none() {
             return Vec::new();
         }
 
         self.reset_error_count(rss_link);
+        {
+            let feed = self.feeds.get_mut(&feed_id).unwrap();
+            feed.last_fetched_at = Some(Utc::now().timestamp());
+            feed.last_error = None;
+            feed.failing_since = None;
+            feed.dead_warned_at = None;
+            feed.recent_items = recent_items(&items);
+        }
 
+        let now = Utc::now().timestamp();
         let mut result = Vec::new();
-        let mut new_hash_list = Vec::new();
-        let items_len = items.len();
-        for item in items {
-            let hash = gen_item_hash(&item);
-            if !self.feeds[&feed_id].hash_list.contains(&hash) {
-                new_hash_list.push(hash);
-                result.push(item);
+        let mut seen_changed = false;
+        {
+            let feed = self.feeds.get_mut(&feed_id).unwrap();
+            let unsaved = self.unsaved_seen.entry(feed_id).or_insert_with(Vec::new);
+            let items_len = items.len();
+            let newest_pubdate = feed.newest_pubdate;
+            for item in items {
+                let pubdate = item.pubdate.map(|date| date.timestamp());
+                feed.newest_pubdate = feed.newest_pubdate.max(pubdate);
+                let hash = gen_item_hash(&item);
+                if let Some(seen) = feed.seen.iter_mut().find(|seen| seen.hash == hash) {
+                    if now - seen.last_seen >= SEEN_RESOLUTION {
+                        seen.last_seen = now;
+                        seen_changed = true;
+                    }
+                    continue;
+                }
+                if unsaved.iter().any(|seen| seen.hash == hash) {
+                    continue;
+                }
+                unsaved.push(SeenItem {
+                    hash: hash,
+                    first_seen: now,
+                    last_seen: now,
+                });
+                // a rotated out item coming back after it expired
+                let stale = match (pubdate, newest_pubdate) {
+                    (Some(pubdate), Some(newest)) => pubdate < newest,
+                    _ => false,
+                };
+                if !stale {
+                    result.push(item);
+                }
             }
+            let seen_len = feed.seen.len();
+            if self.seen_item_age > 0 {
+                let since = now - self.seen_item_age;
+                feed.seen.retain(|seen| seen.last_seen >= since);
+            }
+            let max_len = self.seen_items_max.max(items_len);
+            if self.seen_items_max > 0 && feed.seen.len() > max_len {
+                feed.seen.sort_by_key(|seen| seen.last_seen);
+                let excess = feed.seen.len() - max_len;
+                feed.seen.drain(..excess);
+            }
+            seen_changed |= feed.seen.len() != seen_len;
+        }
+        // persisted along with the next save, the new items by `mark_seen`
+        if seen_changed {
+            self.seen_dirty.set(true);
+        }
+        // a feed regenerating its archive shouldn't flood the chats
+        if self.max_item_age > 0 {
+            let now = Utc::now().timestamp();
+            let max_item_age = self.max_item_age;
+            result.retain(|item| {
+                item.pubdate
+                    .map_or(true, |date| now - date.timestamp() <= max_item_age)
+            });
         }
+        // like history, persisted along with the next save
         if !result.is_empty() {
-            {
-                let max_size = items_len * 2;
-                let feed = self.feeds.get_mut(&feed_id).unwrap();
-                let mut append: Vec<u64> = feed
-                    .hash_list
-                    .iter()
-                    .take(max_size - new_hash_list.len())
-                    .cloned()
-                    .collect();
-                new_hash_list.append(&mut append);
-                feed.hash_list = new_hash_list;
-            }
-            self.save().unwrap_or_default();
+            self.feeds.get_mut(&feed_id).unwrap().last_item_at = Some(Utc::now().timestamp());
         }
         result
     }
 
+    // called once the jobs of the new items `update` returned are queued, they're
+    // saved together; without `queued` the items are fetched as new again
+    fn mark_seen(&mut self, rss_link: &str, queued: bool) {
+        let feed_id = feed_id(rss_link);
+        let unsaved = self.unsaved_seen.remove(&feed_id).unwrap_or_default();
+        if unsaved.is_empty() || !queued {
+            return;
+        }
+        if let Some(feed) = self.feeds.get_mut(&feed_id) {
+            feed.seen.extend(unsaved);
+        }
+        self.seen_dirty.set(true);
+        if let Err(e) = self.save() {
+            warn!("{}", e);
+        }
+    }
+
     fn update_title(&mut self, rss_link: &str, new_title: &str) {
-        let feed_id = get_hash(&rss_link);
+        let feed_id = feed_id(rss_link);
         self.feeds
             .get_mut(&feed_id)
             .map(|feed| feed.title = new_title.to_owned())
@@ -276,6 +1253,25 @@ impl DatabaseInner {
         self.lp_map.insert((subscriber_id, feed_id), link_preview)
     }
 
+    fn set_link_preview(
+        &mut self,
+        subscriber: SubscriberID,
+        rss_link: &str,
+        link_preview: LinkPreview,
+    ) -> Result<()> {
+        let feed_id = feed_id(rss_link);
+        let subscribed = self
+            .subscribers
+            .get(&subscriber)
+            .map(|feeds| feeds.contains(&feed_id))
+            .unwrap_or(false);
+        if !subscribed {
+            return Err(ErrorKind::NotSubscribed.into());
+        }
+        self.update_link_preview(subscriber, feed_id, link_preview);
+        self.save()
+    }
+
     fn get_link_preview(
         &self,
         subscriber_id: SubscriberID,
@@ -284,24 +1280,676 @@ impl DatabaseInner {
         self.lp_map.get(&(subscriber_id, feed_id))
     }
 
-    fn save(&self) -> Result<()> {
-        let feeds: Vec<&Feed> = self.feeds.iter().map(|(_id, feed)| feed).collect();
-        let lp: Vec<(SubscriberID, FeedID, LinkPreview)> = self
-            .lp_map
-            .iter()
-            .map(|((subscriber_id, feed_id), link_preview)| {
-                (*subscriber_id, *feed_id, *link_preview)
-            })
-            .collect();
-        let data = DataStorageOut {
-            feeds: feeds,
-            lp: lp,
-        };
-        let mut file =
-            File::create(&self.path).chain_err(|| ErrorKind::DatabaseSave(self.path.to_owned()))?;
-        serde_json::to_writer(&mut file, &data)
-            .chain_err(|| ErrorKind::DatabaseSave(self.path.to_owned()))
+    // not saved immediately, history is persisted along with the next save
+    fn record_delivery(&mut self, subscriber: SubscriberID, feed_title: &str, items: &[feed::Item]) {
+        let now = Utc::now().timestamp();
+        let history = self
+            .history
+            .entry(subscriber)
+            .or_insert_with(VecDeque::new);
+        for item in items {
+            history.push_back(DeliveredItem {
+                time: now,
+                feed: feed_title.to_owned(),
+                title: item.title.clone().unwrap_or_default(),
+                link: item.link.clone().unwrap_or_default(),
+            });
+        }
+        while history.len() > HISTORY_MAX_LEN
+            || history
+                .front()
+                .map(|item| now - item.time > HISTORY_MAX_AGE)
+                .unwrap_or(false)
+        {
+            history.pop_front();
+        }
     }
+
+    fn get_history(&self, subscriber: SubscriberID, since: i64) -> Vec<DeliveredItem> {
+        self.history
+            .get(&subscriber)
+            .map(|history| {
+                history
+                    .iter()
+                    .filter(|item| item.time >= since)
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    fn get_options(&self, subscriber_id: SubscriberID, feed_id: FeedID) -> SubscriptionOptions {
+        self.options
+            .get(&(subscriber_id, feed_id))
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    // drops the items `subscriber` saw when subscribing, only the first update
+    // after it can have them
+    fn skip_initial_items(
+        &mut self,
+        subscriber: SubscriberID,
+        feed_id: FeedID,
+        items: Vec<feed::Item>,
+    ) -> Vec<feed::Item> {
+        let initial_items = match self.options.get_mut(&(subscriber, feed_id)) {
+            Some(options) if !options.initial_items.is_empty() => {
+                std::mem::replace(&mut options.initial_items, Vec::new())
+            }
+            _ => return items,
+        };
+        items
+            .into_iter()
+            .filter(|item| !initial_items.contains(&gen_item_hash(item)))
+            .collect()
+    }
+
+    // drops the items queued for `subscriber` already within its dedupe window,
+    // like history, persisted along with the next save
+    fn dedupe(
+        &mut self,
+        subscriber: SubscriberID,
+        items: &[feed::Item],
+        now: i64,
+    ) -> Vec<feed::Item> {
+        let window = i64::from(self.get_chat_settings(subscriber).dedupe_hours) * 60 * 60;
+        if window == 0 {
+            self.recent.remove(&subscriber);
+            return items.to_vec();
+        }
+        let recent = self.recent.entry(subscriber).or_insert_with(VecDeque::new);
+        while recent.front().map_or(false, |&(time, _)| now - time > window) {
+            recent.pop_front();
+        }
+        let mut result = Vec::with_capacity(items.len());
+        for item in items {
+            let hash = gen_link_hash(item);
+            if recent.iter().any(|&(_, h)| h == hash) {
+                continue;
+            }
+            recent.push_back((now, hash));
+            result.push(item.clone());
+        }
+        while recent.len() > RECENT_MAX_LEN {
+            recent.pop_front();
+        }
+        result
+    }
+
+    // keeps `items` until there are `snooze_items` of them, then returns them all,
+    // like history, persisted along with the next save
+    fn snooze(
+        &mut self,
+        subscriber: SubscriberID,
+        feed_id: FeedID,
+        items: &[feed::Item],
+    ) -> Option<Vec<feed::Item>> {
+        let options = self.options.get_mut(&(subscriber, feed_id))?;
+        let threshold = options.snooze_items?;
+        options.snoozed.extend(items.iter().map(|item| SnoozedItem {
+            title: item.title.clone(),
+            link: item.link.clone(),
+        }));
+        if options.snoozed.len() < threshold {
+            return None;
+        }
+        let items = options
+            .snoozed
+            .drain(..)
+            .map(|item| feed::Item {
+                title: item.title,
+                link: item.link,
+                ..feed::Item::default()
+            })
+            .collect();
+        Some(items)
+    }
+
+    // a feed is fetched once for all subscribers, so only one of them may set
+    // headers, see `update_options`; the lowest ID wins in older databases
+    fn get_fetch_headers(&self, rss_link: &str) -> feed::Headers {
+        let feed_id = feed_id(rss_link);
+        let mut subscribers: Vec<SubscriberID> = match self.feeds.get(&feed_id) {
+            Some(feed) => feed.subscribers.iter().cloned().collect(),
+            None => return Vec::new(),
+        };
+        subscribers.sort();
+        subscribers
+            .into_iter()
+            .filter_map(|subscriber| self.options.get(&(subscriber, feed_id)))
+            .find(|options| !options.headers.is_empty())
+            .map(|options| options.headers.clone())
+            .unwrap_or_default()
+    }
+
+    fn update_options<F>(&mut self, subscriber: SubscriberID, feed_id: FeedID, f: F) -> Result<()>
+    where
+        F: FnOnce(&mut SubscriptionOptions),
+    {
+        let subscribed = self
+            .subscribers
+            .get(&subscriber)
+            .map(|feeds| feeds.contains(&feed_id))
+            .unwrap_or(false);
+        if !subscribed {
+            return Err(ErrorKind::NotSubscribed.into());
+        }
+        let mut options = self.get_options(subscriber, feed_id);
+        let old_headers = options.headers.clone();
+        f(&mut options);
+        if !options.headers.is_empty()
+            && options.headers != old_headers
+            && self.headers_taken(subscriber, feed_id)
+        {
+            return Err(ErrorKind::HeadersTaken.into());
+        }
+        if options == SubscriptionOptions::default() {
+            self.options.remove(&(subscriber, feed_id));
+        } else {
+            self.options.insert((subscriber, feed_id), options);
+        }
+        self.save()
+    }
+
+    // whether another subscriber decides the headers the feed is fetched with
+    fn headers_taken(&self, subscriber: SubscriberID, feed_id: FeedID) -> bool {
+        self.feeds.get(&feed_id).map_or(false, |feed| {
+            feed.subscribers.iter().any(|other| {
+                *other != subscriber
+                    && self.options
+                        .get(&(*other, feed_id))
+                        .map_or(false, |options| !options.headers.is_empty())
+            })
+        })
+    }
+
+    // delivery pauses once the chat or the subscription has expired
+    fn is_expired(&self, subscriber: SubscriberID, feed_id: FeedID, now: i64) -> bool {
+        let expired = |expires_at: Option<i64>| expires_at.map_or(false, |t| t <= now);
+        expired(self.get_chat_settings(subscriber).expires_at)
+            || expired(self.get_options(subscriber, feed_id).expires_at)
+    }
+
+    // expired chats (without a feed) and subscriptions nobody has been told about yet
+    fn take_expired(&mut self, now: i64) -> Vec<(SubscriberID, Option<Feed>)> {
+        let mut expired = Vec::new();
+        for (chat, settings) in self.chats.iter_mut() {
+            if settings.expires_at.map_or(false, |t| t <= now) && !settings.expiry_notified {
+                settings.expiry_notified = true;
+                expired.push((*chat, None));
+            }
+        }
+        for (&(subscriber, feed_id), options) in self.options.iter_mut() {
+            if options.expires_at.map_or(false, |t| t <= now) && !options.expiry_notified {
+                options.expiry_notified = true;
+                if let Some(feed) = self.feeds.get(&feed_id) {
+                    expired.push((subscriber, Some(feed.clone())));
+                }
+            }
+        }
+        if !expired.is_empty() {
+            if let Err(e) = self.save() {
+                warn!("{}", e);
+            }
+        }
+        expired
+    }
+
+    fn get_chat_settings(&self, chat: SubscriberID) -> ChatSettings {
+        self.chats.get(&chat).cloned().unwrap_or_default()
+    }
+
+    // a feed failing for `dead_feed_age` is announced to its subscribers first,
+    // a day later the ones who didn't keep it are unsubscribed
+    fn check_dead_feed(&mut self, rss_link: &str, now: i64) -> Option<DeadFeed> {
+        let feed_id = feed_id(rss_link);
+        let (failing_since, dead_warned_at, subscribers) = {
+            let feed = self.feeds.get(&feed_id)?;
+            (feed.failing_since?, feed.dead_warned_at, feed.subscribers.clone())
+        };
+        if self.dead_feed_age == 0 || now - failing_since < self.dead_feed_age {
+            return None;
+        }
+        let at_risk: Vec<SubscriberID> = subscribers
+            .into_iter()
+            .filter(|subscriber| !self.get_options(*subscriber, feed_id).keep_failing)
+            .collect();
+        if at_risk.is_empty() {
+            return None;
+        }
+        match dead_warned_at {
+            None => {
+                self.feeds.get_mut(&feed_id).unwrap().dead_warned_at = Some(now);
+                self.save().unwrap_or_default();
+                Some(DeadFeed::Warn(at_risk))
+            }
+            Some(t) if now - t >= DEAD_FEED_GRACE => {
+                self.backup_before_destructive();
+                for subscriber in &at_risk {
+                    let _ = self.unsubscribe(*subscriber, rss_link);
+                }
+                // the ones who kept it stay subscribed
+                if let Some(feed) = self.feeds.get_mut(&feed_id) {
+                    feed.dead_warned_at = None;
+                }
+                self.save().unwrap_or_default();
+                Some(DeadFeed::Removed(at_risk))
+            }
+            Some(_) => None,
+        }
+    }
+
+    fn is_premium(&self, chat: SubscriberID) -> bool {
+        self.get_chat_settings(chat)
+            .premium_until
+            .map_or(false, |t| t > Utc::now().timestamp())
+    }
+
+    fn subscription_limit(&self, subscriber: SubscriberID) -> Option<usize> {
+        self.quota.limit(subscriber, self.is_premium(subscriber))
+    }
+
+    // the most specific domain the link is on wins, `None` if it has no known rhash
+    fn iv_rhash(&self, link: &str) -> Option<(String, u64)> {
+        let host = link_host(link).or_else(|| Some(link.trim_right_matches('.').to_lowercase()))?;
+        self.config_iv_rhashes
+            .iter()
+            .chain(self.iv_rhashes.iter())
+            .filter(|&(domain, _)| host == *domain || host.ends_with(&format!(".{}", domain)))
+            // the last of equal ones, so owners win over the config
+            .max_by_key(|&(domain, _)| domain.len())
+            .map(|(domain, rhash)| (domain.clone(), *rhash))
+    }
+
+    fn set_iv_rhash(&mut self, domain: &str, rhash: Option<u64>) -> Result<()> {
+        let domain = domain.trim_right_matches('.').to_lowercase();
+        match rhash {
+            Some(rhash) => self.iv_rhashes.insert(domain, rhash),
+            None => self.iv_rhashes.remove(&domain),
+        };
+        self.save()
+    }
+
+    fn check_domain(&self, link: &str) -> Result<()> {
+        let blocked = link_in_domains(link, &self.blocked_domains)
+            || link_in_domains(link, &self.config_blocked_domains)
+            || (!self.allowed_domains.is_empty() && !link_in_domains(link, &self.allowed_domains));
+        if blocked {
+            let host = link_host(link).unwrap_or_else(|| link.to_owned());
+            return Err(ErrorKind::DomainBlocked(host).into());
+        }
+        Ok(())
+    }
+
+    // everyone is when neither is set in the config
+    fn is_authorized(&self, chat: i64, user: Option<i64>) -> bool {
+        if self.allowed_chats.is_empty() && self.passphrase.is_none() {
+            return true;
+        }
+        let allowed = |id: i64| {
+            self.allowed_chats.contains(&id)
+                || self.authorized_chats.contains(&id)
+                || self.owners.contains(&id)
+        };
+        allowed(chat) || user.map_or(false, allowed)
+    }
+
+    // returns false if the passphrase is wrong or there is none
+    fn authorize(&mut self, chat: i64, passphrase: &str) -> Result<bool> {
+        if self.passphrase.as_ref().map(|p| p.as_str()) != Some(passphrase) {
+            return Ok(false);
+        }
+        if self.authorized_chats.insert(chat) {
+            self.save()?;
+        }
+        Ok(true)
+    }
+
+    // a domain is new when no feed is on it and no owner approved it yet, there
+    // is nobody to ask without owners
+    fn check_approval(&self, link: &str) -> Result<()> {
+        if !self.approve_new_domains || self.owners.is_empty() {
+            return Ok(());
+        }
+        let domain = match link_host(link) {
+            Some(domain) => domain,
+            None => return Ok(()),
+        };
+        let known = self.approved_domains.contains(&domain)
+            || self.feeds
+                .values()
+                .any(|feed| link_host(&feed.link).as_ref() == Some(&domain));
+        if known {
+            Ok(())
+        } else {
+            Err(ErrorKind::ApprovalRequired(domain).into())
+        }
+    }
+
+    // `None` if the same subscription is waiting already
+    fn queue_approval(&mut self, request: ApprovalRequest) -> Result<Option<u64>> {
+        let waiting = self.approvals
+            .values()
+            .any(|r| r.subscriber == request.subscriber && r.link == request.link);
+        if waiting {
+            return Ok(None);
+        }
+        let id = self.approvals.keys().next_back().map_or(0, |id| id + 1);
+        self.approvals.insert(id, request);
+        self.save()?;
+        Ok(Some(id))
+    }
+
+    // the decision covers every request for the domain, they are returned to be
+    // told about it, none if another owner decided first
+    fn decide_approval(&mut self, id: u64, approved: bool) -> Result<Vec<ApprovalRequest>> {
+        let domain = match self.approvals.get(&id) {
+            Some(request) => request.domain.clone(),
+            None => return Ok(Vec::new()),
+        };
+        let ids: Vec<u64> = self.approvals
+            .iter()
+            .filter(|&(_, request)| request.domain == domain)
+            .map(|(id, _)| *id)
+            .collect();
+        let requests: Vec<ApprovalRequest> = ids.iter()
+            .filter_map(|id| self.approvals.remove(id))
+            .collect();
+        if approved {
+            self.approved_domains.insert(domain);
+        }
+        self.save()?;
+        Ok(requests)
+    }
+
+    // the code `user` is asked to post in `channel`, the same one until it expires
+    fn ownership_code(&mut self, channel: i64, chat: i64, user: i64) -> String {
+        let now = Utc::now().timestamp();
+        self.ownership_codes.retain(|code| code.expires_at > now);
+        let existing = self.ownership_codes
+            .iter()
+            .find(|code| code.channel == channel && code.chat == chat && code.user == user)
+            .map(|code| code.code.clone());
+        if let Some(code) = existing {
+            return code;
+        }
+        let code = random_code();
+        self.ownership_codes.push(OwnershipCode {
+            channel: channel,
+            chat: chat,
+            user: user,
+            code: code.clone(),
+            expires_at: now + OWNERSHIP_CODE_MAX_AGE,
+        });
+        if let Err(e) = self.save() {
+            warn!("{}", e);
+        }
+        code
+    }
+
+    // whether `post`, forwarded from `channel`, has the code given to `user` in
+    // `chat`, which can't be used again
+    fn use_ownership_code(&mut self, channel: i64, chat: i64, user: i64, post: &str) -> bool {
+        let now = Utc::now().timestamp();
+        self.ownership_codes.retain(|code| code.expires_at > now);
+        let index = self.ownership_codes.iter().position(|code| {
+            code.channel == channel
+                && code.chat == chat
+                && code.user == user
+                && post.contains(&code.code)
+        });
+        let index = match index {
+            Some(index) => index,
+            None => return false,
+        };
+        self.ownership_codes.remove(index);
+        if let Err(e) = self.save() {
+            warn!("{}", e);
+        }
+        true
+    }
+
+    // returns false if it was already blocked
+    fn block_domain(&mut self, domain: &str) -> Result<bool> {
+        let domain = domain.trim_right_matches('.').to_lowercase();
+        if self.blocked_domains.contains(&domain) {
+            return Ok(false);
+        }
+        self.blocked_domains.push(domain);
+        self.save()?;
+        Ok(true)
+    }
+
+    // returns false if it wasn't blocked, domains blocked in the config stay blocked
+    fn unblock_domain(&mut self, domain: &str) -> Result<bool> {
+        let domain = domain.trim_right_matches('.').to_lowercase();
+        let len = self.blocked_domains.len();
+        self.blocked_domains.retain(|d| *d != domain);
+        if self.blocked_domains.len() == len {
+            return Ok(false);
+        }
+        self.save()?;
+        Ok(true)
+    }
+
+    fn update_chat_settings<F>(&mut self, chat: SubscriberID, f: F) -> Result<()>
+    where
+        F: FnOnce(&mut ChatSettings),
+    {
+        let is_default = {
+            let settings = self.chats.entry(chat).or_insert_with(ChatSettings::default);
+            f(settings);
+            *settings == ChatSettings::default()
+        };
+        if is_default {
+            self.chats.remove(&chat);
+        }
+        self.save()
+    }
+
+    fn remember_left_feed(&mut self, subscriber: SubscriberID, feed: &Feed, now: i64) {
+        if !self.get_chat_settings(subscriber).resubscribe_suggestions {
+            return;
+        }
+        let feed_id = feed.get_id();
+        self.forget_left_feed(subscriber, feed_id);
+        let left = self.left.entry(subscriber).or_insert_with(Vec::new);
+        left.push(LeftFeed {
+            link: feed.link.clone(),
+            title: feed.title.clone(),
+            left_at: now,
+            subscribers: feed.subscribers.len(),
+        });
+        if left.len() > LEFT_FEEDS_MAX_LEN {
+            left.remove(0);
+        }
+        if let Err(e) = self.save() {
+            warn!("{}", e);
+        }
+    }
+
+    fn forget_left_feed(&mut self, subscriber: SubscriberID, id: FeedID) {
+        let clear = match self.left.get_mut(&subscriber) {
+            Some(left) => {
+                left.retain(|left| feed_id(&left.link) != id);
+                left.is_empty()
+            }
+            None => false,
+        };
+        if clear {
+            self.left.remove(&subscriber);
+        }
+    }
+
+    // left feeds which got popular since, each is only returned once
+    fn take_popular_again(&mut self, now: i64) -> Vec<(SubscriberID, LeftFeed)> {
+        let mut popular = Vec::new();
+        let mut changed = false;
+        for (subscriber, left) in &mut self.left {
+            let opted_in = self.chats
+                .get(subscriber)
+                .map_or(false, |settings| settings.resubscribe_suggestions);
+            let len = left.len();
+            for feed in left.drain(..).collect::<Vec<_>>() {
+                let count = self.feeds
+                    .get(&feed_id(&feed.link))
+                    .map_or(0, |f| f.subscribers.len());
+                let threshold = max(feed.subscribers * 2, feed.subscribers + POPULAR_MIN_GROWTH);
+                if opted_in && count >= threshold {
+                    popular.push((*subscriber, feed));
+                } else if opted_in && now - feed.left_at < LEFT_FEEDS_MAX_AGE {
+                    left.push(feed);
+                }
+            }
+            changed |= left.len() != len;
+        }
+        self.left.retain(|_, left| !left.is_empty());
+        if changed {
+            if let Err(e) = self.save() {
+                warn!("{}", e);
+            }
+        }
+        popular
+    }
+
+    fn backup(&mut self) -> Result<PathBuf> {
+        let target = backup::backup(&self.path, self.backup_keep)
+            .chain_err(|| ErrorKind::DatabaseBackup(self.path.to_owned()))?;
+        let seen_path = seen_path(&self.path);
+        if Path::new(&seen_path).exists() {
+            backup::backup(&seen_path, self.backup_keep)
+                .chain_err(|| ErrorKind::DatabaseBackup(seen_path.clone()))?;
+        }
+        self.last_backup = Utc::now().timestamp();
+        Ok(target)
+    }
+
+    fn backup_before_destructive(&mut self) {
+        if self.backup_keep == 0 || Utc::now().timestamp() - self.last_backup < BACKUP_MIN_INTERVAL {
+            return;
+        }
+        match self.backup() {
+            Ok(target) => info!("database backed up to {}", target.display()),
+            Err(e) => warn!("{}", e),
+        }
+    }
+
+    fn save(&self) -> Result<()> {
+        // written first, the main file without them is useless otherwise
+        let seen_path = seen_path(&self.path);
+        if SEEN_FILE.with(|s| s.get()) {
+            if self.seen_dirty.get() {
+                let seen: Vec<(FeedID, &Vec<SeenItem>)> = self
+                    .feeds
+                    .iter()
+                    .map(|(feed_id, feed)| (*feed_id, &feed.seen))
+                    .collect();
+                write_atomically(&seen_path, |file| {
+                    serde_json::to_writer(file, &seen).map_err(io::Error::from)
+                }).chain_err(|| ErrorKind::DatabaseSave(seen_path.clone()))?;
+                self.seen_dirty.set(false);
+            }
+        } else if Path::new(&seen_path).exists() {
+            // moved back into the main file by this save
+            self.save_main()?;
+            self.queue_dirty.set(false);
+            return fs::remove_file(&seen_path)
+                .chain_err(|| ErrorKind::DatabaseSave(seen_path.clone()));
+        }
+        self.save_main()?;
+        self.queue_dirty.set(false);
+        Ok(())
+    }
+
+    fn save_main(&self) -> Result<()> {
+        let feeds: Vec<&Feed> = self.feeds.iter().map(|(_id, feed)| feed).collect();
+        let lp: Vec<(SubscriberID, FeedID, LinkPreview)> = self
+            .lp_map
+            .iter()
+            .map(|((subscriber_id, feed_id), link_preview)| {
+                (*subscriber_id, *feed_id, *link_preview)
+            })
+            .collect();
+        let options: Vec<(SubscriberID, FeedID, &SubscriptionOptions)> = self
+            .options
+            .iter()
+            .map(|((subscriber_id, feed_id), options)| (*subscriber_id, *feed_id, options))
+            .collect();
+        let history: Vec<(SubscriberID, &VecDeque<DeliveredItem>)> = self
+            .history
+            .iter()
+            .map(|(subscriber_id, history)| (*subscriber_id, history))
+            .collect();
+        let chats: Vec<(SubscriberID, &ChatSettings)> = self
+            .chats
+            .iter()
+            .map(|(chat, settings)| (*chat, settings))
+            .collect();
+        let left: Vec<(SubscriberID, &Vec<LeftFeed>)> = self
+            .left
+            .iter()
+            .map(|(chat, left)| (*chat, left))
+            .collect();
+        let recent: Vec<(SubscriberID, &VecDeque<(i64, u64)>)> = self
+            .recent
+            .iter()
+            .map(|(chat, recent)| (*chat, recent))
+            .collect();
+        let watched: Vec<&WatchedPage> = self.watched.values().collect();
+        let queue: Vec<(u64, &QueuedJob)> = self.queue.iter().map(|(id, job)| (*id, job)).collect();
+        let approvals: Vec<(u64, &ApprovalRequest)> = self.approvals
+            .iter()
+            .map(|(id, request)| (*id, request))
+            .collect();
+        let mirrors: Vec<(SubscriberID, FeedID, &HashSet<SubscriberID>)> = self.mirrors
+            .iter()
+            .map(|(&(subscriber, feed_id), targets)| (subscriber, feed_id, targets))
+            .collect();
+        let data = DataStorageOut {
+            feeds: feeds,
+            lp: lp,
+            options: options,
+            history: history,
+            chats: chats,
+            left: left,
+            recent: recent,
+            watched: watched,
+            queue: queue,
+            mirrors: mirrors,
+            blocked_domains: &self.blocked_domains,
+            iv_rhashes: &self.iv_rhashes,
+            rewrite_rules: &self.rewrite_rules,
+            authorized_chats: &self.authorized_chats,
+            approvals: approvals,
+            approved_domains: &self.approved_domains,
+            ownership_codes: &self.ownership_codes,
+        };
+        write_atomically(&self.path, |file| {
+            serde_json::to_writer(file, &data).map_err(io::Error::from)
+        }).chain_err(|| ErrorKind::DatabaseSave(self.path.to_owned()))
+    }
+}
+
+// writes to a temporary file next to `path` and renames it over the old one,
+// so a crash never leaves a half written file behind; the old one is kept as `.bak`
+fn write_atomically<F>(path: &str, write: F) -> io::Result<()>
+where
+    F: FnOnce(&mut BufWriter<&File>) -> io::Result<()>,
+{
+    let tmp_path = format!("{}.tmp", path);
+    let file = File::create(&tmp_path)?;
+    {
+        let mut writer = BufWriter::new(&file);
+        write(&mut writer)?;
+        writer.flush()?;
+    }
+    file.sync_all()?;
+    if Path::new(path).exists() {
+        fs::copy(path, format!("{}.bak", path))?;
+    }
+    fs::rename(&tmp_path, path)?;
+    Ok(())
 }
 
 #[derive(Debug)]
@@ -317,7 +1965,17 @@ impl Clone for Database {
     }
 }
 
-fn gen_item_hash(item: &feed::Item) -> u64 {
+// the same article in different feeds, which may differ in title or ID
+fn gen_link_hash(item: &feed::Item) -> u64 {
+    match (item.link.as_ref(), item.id.as_ref()) {
+        (Some(link), _) => get_hash(&normalize_url(link)),
+        (None, Some(id)) => get_hash(id),
+        (None, None) => gen_item_hash(item),
+    }
+}
+
+// what sent items are remembered by, see `rssbot check`
+pub fn gen_item_hash(item: &feed::Item) -> u64 {
     item.id.as_ref().map(|id| get_hash(&id)).unwrap_or_else(|| {
         let title = item.title.as_ref().map(|s| s.as_str()).unwrap_or_default();
         let link = item.link.as_ref().map(|s| s.as_str()).unwrap_or_default();
@@ -335,6 +1993,43 @@ impl Database {
                 feeds: feeds,
                 subscribers: subscribers,
                 lp_map: HashMap::new(),
+                options: HashMap::new(),
+                history: HashMap::new(),
+                chats: HashMap::new(),
+                left: HashMap::new(),
+                recent: HashMap::new(),
+                watched: HashMap::new(),
+                queue: BTreeMap::new(),
+                mirrors: HashMap::new(),
+                blocked_domains: Vec::new(),
+                config_blocked_domains: Vec::new(),
+                allowed_domains: Vec::new(),
+                iv_rhashes: HashMap::new(),
+                config_iv_rhashes: HashMap::new(),
+                rewrite_rules: HashMap::new(),
+                config_rewrite_rules: HashMap::new(),
+                quota: Quota::default(),
+                owners: Vec::new(),
+                allowed_chats: Vec::new(),
+                passphrase: None,
+                authorized_chats: HashSet::new(),
+                approve_new_domains: false,
+                approvals: BTreeMap::new(),
+                approved_domains: HashSet::new(),
+                ownership_codes: Vec::new(),
+                unsaved_seen: HashMap::new(),
+                backup_keep: 0,
+                last_backup: 0,
+                max_item_age: 0,
+                dead_feed_age: 0,
+                group_admin_only: false,
+                read_only: false,
+                maintenance_message: None,
+                config_read_only: None,
+                seen_item_age: 0,
+                seen_items_max: 0,
+                seen_dirty: Cell::new(true),
+                queue_dirty: Cell::new(false),
             })),
         };
 
@@ -353,30 +2048,127 @@ impl Database {
             let mut feeds: HashMap<FeedID, Feed> = HashMap::with_capacity(data.feeds.len());
             let mut subscribers: HashMap<SubscriberID, HashSet<FeedID>> = HashMap::new();
             let mut lp_map: HashMap<(SubscriberID, FeedID), LinkPreview> = HashMap::new();
+            let mut options: HashMap<(SubscriberID, FeedID), SubscriptionOptions> = HashMap::new();
+            // IDs stored in the file may come from links that weren't normalized
+            let mut id_map: HashMap<FeedID, FeedID> = HashMap::new();
+            let mut merged = 0;
+            let now = Utc::now().timestamp();
 
-            for feed in data.feeds {
-                let feed_id = get_hash(&feed.link);
+            for mut feed in data.feeds {
+                feed.migrate_hash_list(now);
+                let feed_id = feed.get_id();
+                id_map.insert(get_hash(&feed.link), feed_id);
+                id_map.insert(get_hash(&legacy_normalize_url(&feed.link)), feed_id);
                 for subscriber in &feed.subscribers {
                     let subscribed_feeds = subscribers
                         .entry(subscriber.to_owned())
                         .or_insert_with(HashSet::new);
                     subscribed_feeds.insert(feed_id);
                 }
-                feeds.insert(feed_id, feed);
+                match feeds.entry(feed_id) {
+                    Entry::Occupied(mut entry) => {
+                        warn!(
+                            "merging duplicate feed {} into {} ({} subscribers)",
+                            feed.link,
+                            entry.get().link,
+                            feed.subscribers.len()
+                        );
+                        entry.get_mut().merge(feed);
+                        merged += 1;
+                    }
+                    Entry::Vacant(entry) => {
+                        entry.insert(feed);
+                    }
+                }
+            }
+
+            let seen_path = seen_path(path);
+            if Path::new(&seen_path).exists() {
+                let f = File::open(&seen_path)
+                    .chain_err(|| ErrorKind::DatabaseOpen(seen_path.clone()))?;
+                let seen: Vec<(FeedID, Vec<SeenItem>)> =
+                    serde_json::from_reader(&f).chain_err(|| ErrorKind::DatabaseFormat)?;
+                for (feed_id, seen) in seen {
+                    let feed_id = id_map.get(&feed_id).cloned().unwrap_or(feed_id);
+                    if let Some(feed) = feeds.get_mut(&feed_id) {
+                        feed.merge_seen(seen);
+                    }
+                }
+            }
+
+            for (subscriber_id, feed_id, lp) in data.lp {
+                let feed_id = id_map.get(&feed_id).cloned().unwrap_or(feed_id);
+                lp_map.entry((subscriber_id, feed_id)).or_insert(lp);
+            }
+
+            for (subscriber_id, feed_id, o) in data.options {
+                let feed_id = id_map.get(&feed_id).cloned().unwrap_or(feed_id);
+                options.entry((subscriber_id, feed_id)).or_insert(o);
             }
 
-            for entry in data.lp {
-                lp_map.insert((entry.0, entry.1), entry.2);
+            let mut mirrors: HashMap<(SubscriberID, FeedID), HashSet<SubscriberID>> =
+                HashMap::new();
+            for (subscriber_id, feed_id, targets) in data.mirrors {
+                let feed_id = id_map.get(&feed_id).cloned().unwrap_or(feed_id);
+                mirrors
+                    .entry((subscriber_id, feed_id))
+                    .or_insert_with(HashSet::new)
+                    .extend(targets);
             }
 
-            Ok(Database {
+            let db = Database {
                 inner: Rc::new(RefCell::new(DatabaseInner {
                     path: path.to_owned(),
                     feeds: feeds,
                     subscribers: subscribers,
                     lp_map: lp_map,
+                    options: options,
+                    history: data.history.into_iter().collect(),
+                    chats: data.chats.into_iter().collect(),
+                    left: data.left.into_iter().collect(),
+                    recent: data.recent.into_iter().collect(),
+                    watched: data.watched
+                        .into_iter()
+                        .map(|page| (page.get_id(), page))
+                        .collect(),
+                    queue: data.queue.into_iter().collect(),
+                    mirrors: mirrors,
+                    blocked_domains: data.blocked_domains,
+                    config_blocked_domains: Vec::new(),
+                    allowed_domains: Vec::new(),
+                    iv_rhashes: data.iv_rhashes,
+                    config_iv_rhashes: HashMap::new(),
+                    rewrite_rules: data.rewrite_rules,
+                    config_rewrite_rules: HashMap::new(),
+                    quota: Quota::default(),
+                    owners: Vec::new(),
+                    allowed_chats: Vec::new(),
+                    passphrase: None,
+                    authorized_chats: data.authorized_chats,
+                    approve_new_domains: false,
+                    approvals: data.approvals.into_iter().collect(),
+                    approved_domains: data.approved_domains,
+                    ownership_codes: data.ownership_codes,
+                    unsaved_seen: HashMap::new(),
+                    backup_keep: 0,
+                    last_backup: 0,
+                    max_item_age: 0,
+                    dead_feed_age: 0,
+                    group_admin_only: false,
+                    read_only: false,
+                    maintenance_message: None,
+                    config_read_only: None,
+                    seen_item_age: 0,
+                    seen_items_max: 0,
+                    seen_dirty: Cell::new(true),
+                    queue_dirty: Cell::new(false),
                 })),
-            })
+            };
+            if merged > 0 {
+                info!("merged {} duplicate feeds", merged);
+                db.save()?;
+            }
+            Ok(db)
         } else {
             Database::create(path)
         }
@@ -390,12 +2182,99 @@ impl Database {
         self.inner.borrow().get_all_subscribers()
     }
 
+    pub fn get_feed(&self, rss_link: &str) -> Option<Feed> {
+        self.inner.borrow().get_feed(rss_link)
+    }
+
+    pub fn get_all_watched_pages(&self) -> Vec<WatchedPage> {
+        self.inner.borrow().watched.values().cloned().collect()
+    }
+
+    pub fn get_watched_pages(&self, subscriber: SubscriberID) -> Vec<WatchedPage> {
+        self.inner.borrow().get_watched_pages(subscriber)
+    }
+
+    pub fn watch(
+        &self,
+        subscriber: SubscriberID,
+        link: &str,
+        selector: Option<String>,
+        title: &str,
+        hash: u64,
+    ) -> Result<()> {
+        self.inner
+            .borrow_mut()
+            .watch(subscriber, link, selector, title, hash)
+    }
+
+    pub fn unwatch(&self, subscriber: SubscriberID, link: &str) -> Result<Vec<WatchedPage>> {
+        self.inner.borrow_mut().unwatch(subscriber, link)
+    }
+
+    pub fn update_watched_page(&self, id: u64, hash: u64) -> bool {
+        self.inner.borrow_mut().update_watched_page(id, hash)
+    }
+
+    pub fn inc_watch_error_count(&self, id: u64) -> u32 {
+        self.inner.borrow_mut().inc_watch_error_count(id)
+    }
+
+    pub fn stats(&self) -> Stats {
+        self.inner.borrow().stats()
+    }
+
     pub fn get_subscribed_feeds(&self, subscriber: SubscriberID) -> Option<Vec<Feed>> {
         self.inner.borrow().get_subscribed_feeds(subscriber)
     }
 
-    pub fn inc_error_count(&self, rss_link: &str) -> u32 {
-        self.inner.borrow_mut().inc_error_count(rss_link)
+    // the chats also receiving what the subscription delivers, see `/mirror`
+    pub fn get_mirrors(&self, subscriber: SubscriberID, feed_id: FeedID) -> Vec<SubscriberID> {
+        self.inner
+            .borrow()
+            .mirrors
+            .get(&(subscriber, feed_id))
+            .map(|targets| targets.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    // `false` if it's already mirrored there, or not when removing
+    pub fn set_mirror(
+        &self,
+        subscriber: SubscriberID,
+        rss_link: &str,
+        target: SubscriberID,
+        mirrored: bool,
+    ) -> Result<bool> {
+        let mut inner = self.inner.borrow_mut();
+        let feed_id = feed_id(rss_link);
+        let subscribed = inner
+            .subscribers
+            .get(&subscriber)
+            .map(|feeds| feeds.contains(&feed_id))
+            .unwrap_or(false);
+        if !subscribed {
+            return Err(ErrorKind::NotSubscribed.into());
+        }
+        let changed = {
+            let targets = inner
+                .mirrors
+                .entry((subscriber, feed_id))
+                .or_insert_with(HashSet::new);
+            if mirrored {
+                targets.insert(target)
+            } else {
+                targets.remove(&target)
+            }
+        };
+        inner.mirrors.retain(|_, targets| !targets.is_empty());
+        if changed {
+            inner.save()?;
+        }
+        Ok(changed)
+    }
+
+    pub fn inc_error_count(&self, rss_link: &str, error: &str) -> u32 {
+        self.inner.borrow_mut().inc_error_count(rss_link, error)
     }
 
     pub fn reset_error_count(&self, rss_link: &str) {
@@ -422,22 +2301,65 @@ impl Database {
         self.inner.borrow_mut().unsubscribe(subscriber, rss_link)
     }
 
-    pub fn delete_subscriber(&self, subscriber: SubscriberID) {
-        self.inner.borrow_mut().delete_subscriber(subscriber);
+    pub fn remove_feed(&self, rss_link: &str) -> Result<Feed> {
+        self.inner.borrow_mut().remove_feed(rss_link)
+    }
+
+    pub fn vacuum(&self) -> Result<(usize, usize)> {
+        self.inner.borrow_mut().vacuum()
+    }
+
+    pub fn delete_subscriber(&self, subscriber: SubscriberID) -> Vec<Feed> {
+        self.inner.borrow_mut().delete_subscriber(subscriber)
+    }
+
+    pub fn erase_subscriber(&self, subscriber: SubscriberID) -> Result<(usize, bool)> {
+        self.inner.borrow_mut().erase_subscriber(subscriber)
+    }
+
+    pub fn copy_subscriptions(
+        &self,
+        from: SubscriberID,
+        to: SubscriberID,
+    ) -> Result<(usize, usize)> {
+        self.inner.borrow_mut().copy_subscriptions(from, to)
     }
 
     pub fn update_subscriber(&self, from: SubscriberID, to: SubscriberID) {
         self.inner.borrow_mut().update_subscriber(from, to);
     }
 
+    pub fn set_proxy(&self, rss_link: &str, proxy: Option<String>) -> Result<()> {
+        self.inner.borrow_mut().set_proxy(rss_link, proxy)
+    }
+
+    pub fn move_feed(&self, from: &str, to: &str) -> Result<Feed> {
+        self.inner.borrow_mut().move_feed(from, to)
+    }
+
     pub fn update(&self, rss_link: &str, items: Vec<feed::Item>) -> Vec<feed::Item> {
         self.inner.borrow_mut().update(rss_link, items)
     }
 
+    pub fn mark_seen(&self, rss_link: &str, queued: bool) {
+        self.inner.borrow_mut().mark_seen(rss_link, queued)
+    }
+
     pub fn update_title(&self, rss_link: &str, new_title: &str) {
         self.inner.borrow_mut().update_title(rss_link, new_title)
     }
 
+    pub fn set_link_preview(
+        &self,
+        subscriber: SubscriberID,
+        rss_link: &str,
+        link_preview: LinkPreview,
+    ) -> Result<()> {
+        self.inner
+            .borrow_mut()
+            .set_link_preview(subscriber, rss_link, link_preview)
+    }
+
     pub fn get_link_preview(
         &self,
         subscriber_id: SubscriberID,
@@ -449,7 +2371,668 @@ impl Database {
             .map(|lp| *lp)
     }
 
+    pub fn record_delivery(&self, subscriber: SubscriberID, feed_title: &str, items: &[feed::Item]) {
+        self.inner
+            .borrow_mut()
+            .record_delivery(subscriber, feed_title, items)
+    }
+
+    pub fn get_history(&self, subscriber: SubscriberID, since: i64) -> Vec<DeliveredItem> {
+        self.inner.borrow().get_history(subscriber, since)
+    }
+
+    pub fn get_options(&self, subscriber_id: SubscriberID, feed_id: FeedID) -> SubscriptionOptions {
+        self.inner.borrow().get_options(subscriber_id, feed_id)
+    }
+
+    pub fn skip_initial_items(
+        &self,
+        subscriber: SubscriberID,
+        feed_id: FeedID,
+        items: Vec<feed::Item>,
+    ) -> Vec<feed::Item> {
+        self.inner
+            .borrow_mut()
+            .skip_initial_items(subscriber, feed_id, items)
+    }
+
+    pub fn dedupe(&self, subscriber: SubscriberID, items: &[feed::Item]) -> Vec<feed::Item> {
+        self.inner
+            .borrow_mut()
+            .dedupe(subscriber, items, Utc::now().timestamp())
+    }
+
+    pub fn snooze(
+        &self,
+        subscriber: SubscriberID,
+        feed_id: FeedID,
+        items: &[feed::Item],
+    ) -> Option<Vec<feed::Item>> {
+        self.inner.borrow_mut().snooze(subscriber, feed_id, items)
+    }
+
+    pub fn get_fetch_headers(&self, rss_link: &str) -> feed::Headers {
+        self.inner.borrow().get_fetch_headers(rss_link)
+    }
+
+    pub fn update_options<F>(&self, subscriber: SubscriberID, rss_link: &str, f: F) -> Result<()>
+    where
+        F: FnOnce(&mut SubscriptionOptions),
+    {
+        self.update_options_by_id(subscriber, feed_id(rss_link), f)
+    }
+
+    pub fn update_options_by_id<F>(&self, subscriber: SubscriberID, feed_id: FeedID, f: F) -> Result<()>
+    where
+        F: FnOnce(&mut SubscriptionOptions),
+    {
+        self.inner
+            .borrow_mut()
+            .update_options(subscriber, feed_id, f)
+    }
+
+    pub fn is_expired(&self, subscriber: SubscriberID, feed_id: FeedID) -> bool {
+        self.inner
+            .borrow()
+            .is_expired(subscriber, feed_id, Utc::now().timestamp())
+    }
+
+    pub fn take_expired(&self) -> Vec<(SubscriberID, Option<Feed>)> {
+        self.inner.borrow_mut().take_expired(Utc::now().timestamp())
+    }
+
+    pub fn get_chat_settings(&self, chat: SubscriberID) -> ChatSettings {
+        self.inner.borrow().get_chat_settings(chat)
+    }
+
+    pub fn remember_left_feed(&self, subscriber: SubscriberID, feed: &Feed) {
+        self.inner
+            .borrow_mut()
+            .remember_left_feed(subscriber, feed, Utc::now().timestamp())
+    }
+
+    pub fn take_popular_again(&self) -> Vec<(SubscriberID, LeftFeed)> {
+        self.inner
+            .borrow_mut()
+            .take_popular_again(Utc::now().timestamp())
+    }
+
+    pub fn is_admin_only(&self, chat: SubscriberID) -> bool {
+        let default = self.inner.borrow().group_admin_only;
+        self.get_chat_settings(chat).admin_only.unwrap_or(default)
+    }
+
+    pub fn get_lang(&self, chat: SubscriberID) -> Lang {
+        self.get_chat_settings(chat).lang
+    }
+
+    pub fn get_locale(&self, chat: SubscriberID) -> Locale {
+        self.get_chat_settings(chat).locale()
+    }
+
+    // `None` if the chat may subscribe to any number of feeds
+    pub fn subscription_limit(&self, subscriber: SubscriberID) -> Option<usize> {
+        self.inner.borrow().subscription_limit(subscriber)
+    }
+
+    pub fn set_quota(&self, quota: Quota) {
+        self.inner.borrow_mut().quota = quota;
+    }
+
+    pub fn set_owners(&self, owners: Vec<i64>) {
+        self.inner.borrow_mut().owners = owners;
+    }
+
+    pub fn is_owner(&self, user: i64) -> bool {
+        self.inner.borrow().owners.contains(&user)
+    }
+
+    pub fn get_owners(&self) -> Vec<i64> {
+        self.inner.borrow().owners.clone()
+    }
+
+    // an empty list and no passphrase let everyone use the bot
+    pub fn set_private(&self, allowed_chats: Vec<i64>, passphrase: Option<String>) {
+        let mut inner = self.inner.borrow_mut();
+        inner.allowed_chats = allowed_chats;
+        inner.passphrase = passphrase;
+    }
+
+    pub fn is_authorized(&self, chat: i64, user: Option<i64>) -> bool {
+        self.inner.borrow().is_authorized(chat, user)
+    }
+
+    pub fn authorize(&self, chat: i64, passphrase: &str) -> Result<bool> {
+        self.inner.borrow_mut().authorize(chat, passphrase)
+    }
+
+    pub fn check_dead_feed(&self, rss_link: &str) -> Option<DeadFeed> {
+        self.inner
+            .borrow_mut()
+            .check_dead_feed(rss_link, Utc::now().timestamp())
+    }
+
+    pub fn is_premium(&self, chat: SubscriberID) -> bool {
+        self.inner.borrow().is_premium(chat)
+    }
+
+    pub fn update_chat_settings<F>(&self, chat: SubscriberID, f: F) -> Result<()>
+    where
+        F: FnOnce(&mut ChatSettings),
+    {
+        self.inner.borrow_mut().update_chat_settings(chat, f)
+    }
+
+    // rejects links on a blocked domain, or not on an allowed one
+    pub fn check_domain(&self, link: &str) -> Result<()> {
+        self.inner.borrow().check_domain(link)
+    }
+
+    // a link or a domain
+    pub fn iv_rhash(&self, link: &str) -> Option<(String, u64)> {
+        self.inner.borrow().iv_rhash(link)
+    }
+
+    // `None` removes it, domains in the config keep their rhash
+    pub fn set_iv_rhash(&self, domain: &str, rhash: Option<u64>) -> Result<()> {
+        self.inner.borrow_mut().set_iv_rhash(domain, rhash)
+    }
+
+    // the default link preview of new subscriptions to `link`
+    pub fn default_link_preview(&self, link: &str) -> LinkPreview {
+        self.iv_rhash(link)
+            .map_or(LinkPreview::Off, |(_, rhash)| LinkPreview::InstantView(rhash))
+    }
+
+    pub fn set_config_iv_rhashes(&self, rhashes: HashMap<String, u64>) {
+        self.inner.borrow_mut().config_iv_rhashes = rhashes;
+    }
+
+    pub fn rewrite_rule(&self, name: &str) -> Option<RewriteRule> {
+        let inner = self.inner.borrow();
+        inner
+            .rewrite_rules
+            .get(name)
+            .or_else(|| inner.config_rewrite_rules.get(name))
+            .cloned()
+    }
+
+    // by name, the ones of owners replace the configured ones
+    pub fn rewrite_rules(&self) -> Vec<(String, RewriteRule)> {
+        let inner = self.inner.borrow();
+        let mut rules: BTreeMap<&String, &RewriteRule> =
+            inner.config_rewrite_rules.iter().collect();
+        rules.extend(inner.rewrite_rules.iter());
+        rules
+            .into_iter()
+            .map(|(name, rule)| (name.clone(), rule.clone()))
+            .collect()
+    }
+
+    // `None` removes it, rules in the config are kept
+    pub fn set_rewrite_rule(&self, name: &str, rule: Option<RewriteRule>) -> Result<()> {
+        let mut inner = self.inner.borrow_mut();
+        match rule {
+            Some(rule) => inner.rewrite_rules.insert(name.to_owned(), rule),
+            None => inner.rewrite_rules.remove(name),
+        };
+        inner.save()
+    }
+
+    pub fn set_config_rewrite_rules(&self, rules: HashMap<String, RewriteRule>) {
+        self.inner.borrow_mut().config_rewrite_rules = rules;
+    }
+
+    pub fn set_approve_new_domains(&self, enabled: bool) {
+        self.inner.borrow_mut().approve_new_domains = enabled;
+    }
+
+    pub fn check_approval(&self, link: &str) -> Result<()> {
+        self.inner.borrow().check_approval(link)
+    }
+
+    pub fn queue_approval(&self, request: ApprovalRequest) -> Result<Option<u64>> {
+        self.inner.borrow_mut().queue_approval(request)
+    }
+
+    pub fn decide_approval(&self, id: u64, approved: bool) -> Result<Vec<ApprovalRequest>> {
+        self.inner.borrow_mut().decide_approval(id, approved)
+    }
+
+    pub fn ownership_code(&self, channel: i64, chat: i64, user: i64) -> String {
+        self.inner.borrow_mut().ownership_code(channel, chat, user)
+    }
+
+    pub fn use_ownership_code(&self, channel: i64, chat: i64, user: i64, post: &str) -> bool {
+        self.inner
+            .borrow_mut()
+            .use_ownership_code(channel, chat, user, post)
+    }
+
+    pub fn block_domain(&self, domain: &str) -> Result<bool> {
+        self.inner.borrow_mut().block_domain(domain)
+    }
+
+    pub fn unblock_domain(&self, domain: &str) -> Result<bool> {
+        self.inner.borrow_mut().unblock_domain(domain)
+    }
+
+    pub fn set_domain_lists(&self, allowed: Vec<String>, blocked: Vec<String>) {
+        let mut inner = self.inner.borrow_mut();
+        inner.allowed_domains = allowed;
+        inner.config_blocked_domains = blocked;
+    }
+
+    // keep the newest `keep` backups, 0 disables them
+    pub fn set_backup_keep(&self, keep: usize) {
+        self.inner.borrow_mut().backup_keep = keep;
+    }
+
+    // new items published more than `days` ago aren't returned by `update`, 0 disables it
+    pub fn set_max_item_age(&self, days: u32) {
+        self.inner.borrow_mut().max_item_age = i64::from(days) * 24 * 60 * 60;
+    }
+
+    pub fn set_group_admin_only(&self, enabled: bool) {
+        self.inner.borrow_mut().group_admin_only = enabled;
+    }
+
+    // fetching and delivery go on, only the commands changing subscriptions are refused
+    pub fn set_read_only(&self, enabled: bool) {
+        self.inner.borrow_mut().read_only = enabled;
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.inner.borrow().read_only
+    }
+
+    pub fn set_maintenance_message(&self, message: Option<String>) {
+        self.inner.borrow_mut().maintenance_message = message;
+    }
+
+    pub fn get_maintenance_message(&self) -> Option<String> {
+        self.inner.borrow().maintenance_message.clone()
+    }
+
+    // applied only when the config differs from the last one, so reloading it
+    // doesn't undo `/maintenance`
+    pub fn set_config_read_only(&self, enabled: bool, message: Option<String>) {
+        let mut inner = self.inner.borrow_mut();
+        let config = Some((enabled, message.clone()));
+        if inner.config_read_only != config {
+            inner.read_only = enabled;
+            inner.maintenance_message = message;
+            inner.config_read_only = config;
+        }
+    }
+
+    // feeds failing for `days` are removed, see `check_dead_feed`, 0 disables it
+    pub fn set_dead_feed_days(&self, days: u32) {
+        self.inner.borrow_mut().dead_feed_age = i64::from(days) * 24 * 60 * 60;
+    }
+
+    // items gone from their feed for `days` are forgotten, and each feed remembers
+    // at most `max` items or as many as it has now, 0 disables either limit
+    pub fn set_seen_items(&self, days: u32, max: usize) {
+        let mut inner = self.inner.borrow_mut();
+        inner.seen_item_age = i64::from(days) * 24 * 60 * 60;
+        inner.seen_items_max = max;
+    }
+
+    // keeps the seen items of the feeds in `DATAFILE.seen`, only written when they
+    // changed, instead of with every save of `DATAFILE`
+    pub fn set_seen_file(&self, enabled: bool) {
+        SEEN_FILE.with(|s| s.set(enabled));
+        self.inner.borrow().seen_dirty.set(true);
+    }
+
+    pub fn backup(&self) -> Result<PathBuf> {
+        self.inner.borrow_mut().backup()
+    }
+
     fn save(&self) -> Result<()> {
         self.inner.borrow().save()
     }
+
+    // saved along with the next change or by `save_queue`
+    pub fn queue_job(&self, job: QueuedJob) -> u64 {
+        let mut inner = self.inner.borrow_mut();
+        let id = inner.queue.keys().next_back().map_or(0, |id| id + 1);
+        inner.queue.insert(id, job);
+        inner.queue_dirty.set(true);
+        id
+    }
+
+    pub fn is_job_queued(&self, id: u64) -> bool {
+        self.inner.borrow().queue.contains_key(&id)
+    }
+
+    pub fn get_queued_job(&self, id: u64) -> Option<QueuedJob> {
+        self.inner.borrow().queue.get(&id).cloned()
+    }
+
+    // the first message of the job is sent, a restart resumes after it
+    pub fn job_message_sent(&self, id: u64, pinned: bool) {
+        let mut inner = self.inner.borrow_mut();
+        {
+            let job = match inner.queue.get_mut(&id) {
+                Some(job) => job,
+                None => return,
+            };
+            if !job.media_msgs.is_empty() {
+                job.media_msgs.remove(0);
+            } else if !job.msgs.is_empty() {
+                job.msgs.remove(0);
+            }
+            if pinned {
+                job.pin_keyword = None;
+            }
+        }
+        inner.queue_dirty.set(true);
+    }
+
+    pub fn unqueue_job(&self, id: u64) {
+        let mut inner = self.inner.borrow_mut();
+        if inner.queue.remove(&id).is_some() {
+            inner.queue_dirty.set(true);
+        }
+    }
+
+    // the progress of the queued jobs, saved in batches instead of after every
+    // message, a restart resends at most what was sent since
+    pub fn save_queue(&self) {
+        if !self.inner.borrow().queue_dirty.get() {
+            return;
+        }
+        if let Err(e) = self.save() {
+            warn!("{}", e);
+        }
+    }
+
+    pub fn get_queued_jobs(&self) -> Vec<(u64, QueuedJob)> {
+        self.inner
+            .borrow()
+            .queue
+            .iter()
+            .map(|(id, job)| (*id, job.clone()))
+            .collect()
+    }
+
+    // most changes are saved right away, this covers the rest before exiting
+    pub fn flush(&self) -> Result<()> {
+        self.save()
+    }
+}
+
+// an empty database in the temporary directory
+#[cfg(test)]
+pub fn test_database(name: &str) -> Database {
+    let path = std::env::temp_dir().join(format!("rssbot-test-{}-{}", name, std::process::id()));
+    let _ = fs::remove_file(&path);
+    Database::create(path.to_str().unwrap()).unwrap()
+}
+
+#[test]
+fn test_ownership_code() {
+    let db = test_database("ownership-code");
+    let code = db.ownership_code(-1001, 5, 6);
+    assert_eq!(db.ownership_code(-1001, 5, 6), code);
+    // bound to the channel, the chat it was given in and the user
+    assert_ne!(db.ownership_code(-1001, 7, 6), code);
+    let post = format!("verify {}", code);
+    assert!(!db.use_ownership_code(-1002, 5, 6, &post));
+    assert!(!db.use_ownership_code(-1001, 7, 6, &post));
+    assert!(!db.use_ownership_code(-1001, 5, 8, &post));
+    assert!(db.use_ownership_code(-1001, 5, 6, &post));
+    // only once
+    assert!(!db.use_ownership_code(-1001, 5, 6, &post));
+    assert_ne!(db.ownership_code(-1001, 5, 6), code);
+}
+
+#[test]
+fn test_move_feed_mirrors() {
+    let db = test_database("move-feed-mirrors");
+    db.subscribe(1, "http://a.example/feed", &feed::RSS::default(), LinkPreview::Off)
+        .unwrap();
+    db.set_mirror(1, "http://a.example/feed", -1001, true).unwrap();
+    db.move_feed("http://a.example/feed", "http://c.example/feed").unwrap();
+    assert!(db.get_mirrors(1, feed_id("http://a.example/feed")).is_empty());
+    assert_eq!(db.get_mirrors(1, feed_id("http://c.example/feed")), vec![-1001]);
+    db.remove_feed("http://c.example/feed").unwrap();
+    assert!(db.get_mirrors(1, feed_id("http://c.example/feed")).is_empty());
+}
+
+#[test]
+fn test_update_mark_seen() {
+    let db = test_database("update-mark-seen");
+    let link = "http://a.example/feed";
+    db.subscribe(1, link, &feed::RSS::default(), LinkPreview::Off).unwrap();
+    let items = vec![feed::Item {
+        title: Some("a".to_owned()),
+        link: Some("http://a.example/a".to_owned()),
+        ..Default::default()
+    }];
+    assert_eq!(db.update(link, items.clone()).len(), 1);
+    // not delivered twice while its jobs are being queued
+    assert!(db.update(link, items.clone()).is_empty());
+    // nor lost when they couldn't be
+    db.mark_seen(link, false);
+    assert_eq!(db.update(link, items.clone()).len(), 1);
+    db.mark_seen(link, true);
+    assert!(db.update(link, items.clone()).is_empty());
+    db.mark_seen(link, true);
+    assert!(db.get_feed(link).unwrap().has_item(&items[0]));
+}
+
+#[test]
+fn test_update_subscriber_queue_approvals() {
+    let db = test_database("update-subscriber");
+    let job = QueuedJob {
+        target: -1,
+        media_msgs: Vec::new(),
+        msgs: vec!["a".to_owned()],
+        link_preview: false,
+        silent: false,
+        pin_keyword: None,
+        feed_title: String::new(),
+        items: Vec::new(),
+    };
+    let id = db.queue_job(job);
+    let request = ApprovalRequest {
+        domain: "a.example".to_owned(),
+        link: "http://a.example/feed".to_owned(),
+        subscriber: -1,
+        link_preview: LinkPreview::Off,
+        chat_id: -1,
+        user_id: 5,
+    };
+    let approval = db.queue_approval(request).unwrap().unwrap();
+    db.update_subscriber(-1, -1002);
+    assert_eq!(db.get_queued_job(id).unwrap().target, -1002);
+    let decided = db.decide_approval(approval, true).unwrap();
+    assert_eq!(decided[0].subscriber, -1002);
+    assert_eq!(decided[0].chat_id, -1002);
+}
+
+#[test]
+fn test_save_queue() {
+    let db = test_database("save-queue");
+    let id = db.queue_job(QueuedJob {
+        target: 5,
+        media_msgs: Vec::new(),
+        msgs: vec!["a".to_owned(), "b".to_owned()],
+        link_preview: false,
+        silent: false,
+        pin_keyword: None,
+        feed_title: String::new(),
+        items: Vec::new(),
+    });
+    db.job_message_sent(id, false);
+    db.save_queue();
+    assert!(!db.inner.borrow().queue_dirty.get());
+    let path = db.inner.borrow().path.clone();
+    let reopened = Database::open(&path).unwrap();
+    assert_eq!(reopened.get_queued_job(id).unwrap().msgs, vec!["b".to_owned()]);
+    db.unqueue_job(id);
+    db.save_queue();
+    assert!(!Database::open(&path).unwrap().is_job_queued(id));
+}
+
+#[test]
+fn test_erase_subscriber() {
+    let db = test_database("erase-subscriber");
+    let link = "http://a.example/feed";
+    let items = vec![feed::Item {
+        title: Some("a".to_owned()),
+        link: Some("http://a.example/a".to_owned()),
+        ..Default::default()
+    }];
+    db.set_private(Vec::new(), Some("secret".to_owned()));
+    for &chat in &[5, 6] {
+        db.subscribe(chat, link, &feed::RSS::default(), LinkPreview::Off).unwrap();
+        db.record_delivery(chat, "a", &items);
+        db.queue_job(QueuedJob {
+            target: chat,
+            media_msgs: Vec::new(),
+            msgs: vec!["a".to_owned()],
+            link_preview: false,
+            silent: false,
+            pin_keyword: None,
+            feed_title: String::new(),
+            items: Vec::new(),
+        });
+        db.authorize(chat, "secret").unwrap();
+    }
+    db.queue_approval(ApprovalRequest {
+        domain: "b.example".to_owned(),
+        link: "http://b.example/feed".to_owned(),
+        subscriber: -1001,
+        link_preview: LinkPreview::Off,
+        chat_id: 5,
+        user_id: 5,
+    }).unwrap();
+    let code = db.ownership_code(-1001, 5, 5);
+    assert_eq!(db.erase_subscriber(5).unwrap(), (1, false));
+    assert!(db.get_subscribed_feeds(5).is_none());
+    assert!(db.get_history(5, 0).is_empty());
+    assert!(!db.is_authorized(5, Some(5)));
+    assert!(!db.use_ownership_code(-1001, 5, 5, &code));
+    let jobs = db.get_queued_jobs();
+    assert_eq!(jobs.len(), 1);
+    assert_eq!(jobs[0].1.target, 6);
+    assert!(db.inner.borrow().approvals.is_empty());
+    let path = db.inner.borrow().path.clone();
+    assert!(!Path::new(&format!("{}.bak", path)).exists());
+    // the other subscriber of the feed is left alone
+    assert_eq!(db.get_subscribed_feeds(6).unwrap().len(), 1);
+    assert_eq!(db.get_history(6, 0).len(), 1);
+    assert!(db.is_authorized(6, Some(6)));
+}
+
+#[test]
+fn test_is_authorized() {
+    let db = test_database("is-authorized");
+    // a public instance
+    assert!(db.is_authorized(5, Some(5)));
+    db.set_owners(vec![1]);
+    db.set_private(vec![-1001, 2], Some("secret".to_owned()));
+    assert!(db.is_authorized(-1001, None));
+    // a listed user in any chat, e.g. adding the bot to a group
+    assert!(db.is_authorized(-1002, Some(2)));
+    assert!(db.is_authorized(-1002, Some(1)));
+    assert!(!db.is_authorized(-1002, Some(5)));
+    assert!(!db.is_authorized(5, Some(5)));
+    assert!(!db.authorize(5, "guess").unwrap());
+    assert!(!db.is_authorized(5, Some(5)));
+    assert!(db.authorize(5, "secret").unwrap());
+    assert!(db.is_authorized(5, Some(5)));
+}
+
+#[test]
+fn test_check_dead_feed() {
+    let db = test_database("check-dead-feed");
+    let link = "http://a.example/feed";
+    for &chat in &[5, 6] {
+        db.subscribe(chat, link, &feed::RSS::default(), LinkPreview::Off).unwrap();
+    }
+    let feed_id = db.get_feed(link).unwrap().get_id();
+    db.update_options_by_id(6, feed_id, |o| o.keep_failing = true).unwrap();
+    // off by default
+    db.inc_error_count(link, "timeout");
+    assert!(db.check_dead_feed(link).is_none());
+    db.set_dead_feed_days(1);
+    let day = 24 * 60 * 60;
+    let since = db.get_feed(link).unwrap().failing_since.unwrap();
+    let mut inner = db.inner.borrow_mut();
+    assert!(inner.check_dead_feed(link, since + day - 1).is_none());
+    assert_eq!(inner.check_dead_feed(link, since + day), Some(DeadFeed::Warn(vec![5])));
+    assert!(inner.check_dead_feed(link, since + day + 1).is_none());
+    let removed = inner.check_dead_feed(link, since + day + DEAD_FEED_GRACE);
+    assert_eq!(removed, Some(DeadFeed::Removed(vec![5])));
+    assert!(inner.get_subscribed_feeds(5).is_none());
+    assert_eq!(inner.get_subscribed_feeds(6).unwrap().len(), 1);
+}
+
+#[test]
+fn test_update_subscriber_without_feeds() {
+    let db = test_database("update-subscriber-without-feeds");
+    db.update_chat_settings(-1, |settings| {
+        settings.lang = Lang::Zh;
+        settings.timezone = Some("Asia/Shanghai".to_owned());
+    }).unwrap();
+    let items = vec![feed::Item {
+        title: Some("a".to_owned()),
+        link: Some("http://a.example/a".to_owned()),
+        ..Default::default()
+    }];
+    db.record_delivery(-1, "a", &items);
+    db.update_subscriber(-1, -1002);
+    let settings = db.get_chat_settings(-1002);
+    assert_eq!(settings.lang, Lang::Zh);
+    assert_eq!(settings.timezone, Some("Asia/Shanghai".to_owned()));
+    assert_eq!(db.get_chat_settings(-1), ChatSettings::default());
+    assert_eq!(db.get_history(-1002, 0).len(), 1);
+    assert!(db.get_history(-1, 0).is_empty());
+    assert!(db.get_subscribed_feeds(-1002).is_none());
+}
+
+#[test]
+fn test_headers_taken() {
+    let db = test_database("headers-taken");
+    let link = "http://a.example/feed";
+    for &chat in &[5, 6] {
+        db.subscribe(chat, link, &feed::RSS::default(), LinkPreview::Off).unwrap();
+    }
+    let feed_id = db.get_feed(link).unwrap().get_id();
+    let key = ("X-Api-Key".to_owned(), "a".to_owned());
+    db.update_options_by_id(6, feed_id, |o| o.headers = vec![key.clone()]).unwrap();
+    // a lower ID can't replace what the feed is fetched with
+    let r = db.update_options_by_id(5, feed_id, |o| {
+        o.headers = vec![("Authorization".to_owned(), "Bearer b".to_owned())]
+    });
+    match r {
+        Err(Error(ErrorKind::HeadersTaken, _)) => (),
+        r => panic!("{:?}", r),
+    }
+    // other options are fine
+    db.update_options_by_id(5, feed_id, |o| o.silent = true).unwrap();
+    assert_eq!(db.get_fetch_headers(link), vec![key.clone()]);
+    // free again once 6 removes its own
+    db.update_options_by_id(6, feed_id, |o| o.headers.clear()).unwrap();
+    db.update_options_by_id(5, feed_id, |o| o.headers = vec![key.clone()]).unwrap();
+    assert_eq!(db.get_fetch_headers(link), vec![key]);
+}
+
+#[test]
+fn test_set_config_read_only() {
+    let db = test_database("config-read-only");
+    db.set_config_read_only(false, None);
+    // `/maintenance on`
+    db.set_read_only(true);
+    // a reload with the same config
+    db.set_config_read_only(false, None);
+    assert!(db.is_read_only());
+    db.set_config_read_only(false, Some("back soon".to_owned()));
+    assert!(!db.is_read_only());
+    assert_eq!(db.get_maintenance_message(), Some("back soon".to_owned()));
 }
@@ -0,0 +1,147 @@
+use chrono::Utc;
+use futures::prelude::*;
+use telebot;
+use telebot::functions::*;
+use telebot::objects::{CallbackQuery, InlineKeyboardButton, InlineKeyboardMarkup};
+
+use data::{Database, Feed, FeedID, SubscriberID};
+use errors::{Error, ErrorKind};
+
+// tells `subscribers` the feed is going to be removed, with a button to keep it,
+// see `Database::check_dead_feed`
+pub fn warn(
+    bot: &telebot::RcBot,
+    db: &Database,
+    feed: &Feed,
+    error: &Error,
+    subscribers: Vec<SubscriberID>,
+) {
+    let failing_since = db.get_feed(&feed.link)
+        .and_then(|feed| feed.failing_since)
+        .unwrap_or_else(|| Utc::now().timestamp());
+    let days = (Utc::now().timestamp() - failing_since) / (24 * 60 * 60);
+    let feed_id = feed.get_id();
+    for subscriber in subscribers {
+        let lang = db.get_lang(subscriber);
+        let text = lang.dead_feed_warning(&feed.link, &feed.title, days, &lang.error_reason(error));
+        let markup = InlineKeyboardMarkup {
+            inline_keyboard: vec![vec![InlineKeyboardButton {
+                text: lang.dead_feed_keep(),
+                callback_data: Some(callback_data(subscriber, feed_id)),
+                ..InlineKeyboardButton::default()
+            }]],
+        };
+        let r = bot.message(subscriber, text)
+            .parse_mode("HTML")
+            .disable_web_page_preview(true)
+            .reply_markup(markup)
+            .send()
+            .map(|_| ())
+            .map_err(move |e| error!("failed to warn {} of a dead feed: {:?}", subscriber, e));
+        bot.inner.handle.spawn(r);
+    }
+}
+
+fn callback_data(subscriber: SubscriberID, feed_id: FeedID) -> String {
+    format!("dead:{}:{}", subscriber, feed_id)
+}
+
+fn parse_callback_data(data: &str) -> Option<(SubscriberID, FeedID)> {
+    let mut parts = data.split(':');
+    if parts.next() != Some("dead") {
+        return None;
+    }
+    let subscriber = parts.next()?.parse().ok()?;
+    let feed_id = parts.next()?.parse().ok()?;
+    Some((subscriber, feed_id))
+}
+
+// the warning was sent to `subscriber` itself, a button pressed anywhere else
+// has forged callback data
+fn keep(db: &Database, chat_id: Option<i64>, subscriber: SubscriberID, feed_id: FeedID) -> String {
+    let lang = db.get_lang(subscriber);
+    if chat_id != Some(subscriber) {
+        return lang.not_subscribed();
+    }
+    let r = db.update_options_by_id(subscriber, feed_id, |o| o.keep_failing = true);
+    match r {
+        Ok(()) => lang.dead_feed_kept(),
+        Err(Error(ErrorKind::NotSubscribed, _)) => lang.not_subscribed(),
+        Err(e) => lang.error_reason(&e),
+    }
+}
+
+// only the administrators of a channel, or of a group where `admin_only` is on,
+// can keep its feeds like they can change its subscriptions
+fn needs_admin(db: &Database, chat_id: i64, kind: &str) -> bool {
+    chat_id < 0 && (kind == "channel" || db.is_admin_only(chat_id))
+}
+
+pub fn handle_callback(bot: &telebot::RcBot, db: &Database, query: CallbackQuery) {
+    let parsed = query.data.as_ref().and_then(|data| parse_callback_data(data));
+    let (subscriber, feed_id) = match parsed {
+        Some(parsed) => parsed,
+        None => return,
+    };
+    let chat_id = query.message.as_ref().map(|message| message.chat.id);
+    let admin_chat = query
+        .message
+        .as_ref()
+        .map(|message| &message.chat)
+        .and_then(|chat| if needs_admin(db, chat.id, &chat.kind) {
+            Some((chat.id, chat.kind == "channel"))
+        } else {
+            None
+        });
+    let user_id = query.from.id;
+    let query_id = query.id;
+    let bot2 = bot.clone();
+    let db = db.clone();
+    let r = async_block! {
+        let reply = match admin_chat {
+            Some((admin_chat, is_channel)) => {
+                let (_, admins) = await!(bot2.get_chat_administrators(admin_chat).send())?;
+                let lang = db.get_lang(subscriber);
+                if admins.iter().any(|member| member.user.id == user_id) {
+                    keep(&db, chat_id, subscriber, feed_id)
+                } else if is_channel {
+                    lang.user_not_admin()
+                } else {
+                    lang.group_admin_only()
+                }
+            }
+            None => keep(&db, chat_id, subscriber, feed_id),
+        };
+        await!(bot2.answer_callback_query(query_id).text(reply).send())?;
+        Ok(())
+    }.map_err(|e: telebot::Error| error!("telebot: {:?}", e));
+    bot.inner.handle.spawn(r);
+}
+
+#[test]
+fn test_keep() {
+    use data::{test_database, LinkPreview};
+    use feed;
+    let db = test_database("deadfeed-keep");
+    let link = "http://a.example/feed";
+    db.subscribe(-1001, link, &feed::RSS::default(), LinkPreview::Off).unwrap();
+    let feed_id = db.get_feed(link).unwrap().get_id();
+    let lang = db.get_lang(-1001);
+    // the callback data of another chat
+    assert_eq!(keep(&db, Some(5), -1001, feed_id), lang.not_subscribed());
+    assert!(!db.get_options(-1001, feed_id).keep_failing);
+    assert_eq!(keep(&db, Some(-1001), -1001, feed_id), lang.dead_feed_kept());
+    assert!(db.get_options(-1001, feed_id).keep_failing);
+    assert_eq!(keep(&db, Some(-1002), -1002, feed_id), lang.not_subscribed());
+}
+
+#[test]
+fn test_needs_admin() {
+    use data::test_database;
+    let db = test_database("deadfeed-needs-admin");
+    assert!(!needs_admin(&db, 5, "private"));
+    assert!(needs_admin(&db, -1001, "channel"));
+    assert!(!needs_admin(&db, -1002, "supergroup"));
+    db.set_group_admin_only(true);
+    assert!(needs_admin(&db, -1002, "supergroup"));
+}
@@ -0,0 +1,196 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use futures::prelude::*;
+use telebot;
+use telebot::functions::*;
+use telebot::objects::{CallbackQuery, InlineKeyboardButton, InlineKeyboardMarkup, Message};
+use tokio_curl::Session;
+
+use cmdhandles::subscribe_feed;
+use data::{Database, LinkPreview};
+use feed;
+use utils::{gen_ua, link_in_domains, truncate_message};
+use youtube;
+
+const MAX_CANDIDATES: usize = 10;
+
+thread_local! {
+    // (chat, message with the buttons) -> feeds found on a web page given to /sub
+    static PENDING: RefCell<HashMap<(i64, i64), Pending>> = RefCell::new(HashMap::new());
+}
+
+pub struct Pending {
+    // only the user who sent /sub may choose
+    pub user_id: i64,
+    pub subscriber: i64,
+    pub link_preview: LinkPreview,
+    pub candidates: Vec<(String, String)>,
+}
+
+// asks the user which of the candidates to subscribe to, `text` replaces `msg_id`
+pub fn offer(
+    bot: telebot::RcBot,
+    chat_id: i64,
+    msg_id: i64,
+    mut pending: Pending,
+    text: String,
+) -> impl Future<Item = (), Error = telebot::Error> {
+    pending.candidates.truncate(MAX_CANDIDATES);
+    let buttons = pending
+        .candidates
+        .iter()
+        .enumerate()
+        .map(|(i, &(ref link, ref title))| {
+            let text = if title.is_empty() { link } else { title };
+            vec![InlineKeyboardButton {
+                text: truncate_message(text, 64),
+                callback_data: Some(format!("sub:{}", i)),
+                ..InlineKeyboardButton::default()
+            }]
+        })
+        .collect();
+    PENDING.with(|p| p.borrow_mut().insert((chat_id, msg_id), pending));
+    bot.edit_message_text(chat_id, msg_id, text)
+        .reply_markup(InlineKeyboardMarkup {
+            inline_keyboard: buttons,
+        })
+        .send()
+        .map(|_| ())
+}
+
+// a link sent in a private chat outside of a command is probed for feeds,
+// which are offered like the ones found by /sub
+pub fn handle_message(bot: &telebot::RcBot, db: &Database, msg: &Message, self_hosts: &[String]) {
+    if msg.chat.kind != "private" || msg.forward_from_chat.is_some() {
+        return;
+    }
+    let link = msg.text
+        .as_ref()
+        .filter(|text| !text.starts_with('/'))
+        .and_then(|text| {
+            text.split_whitespace()
+                .find(|word| word.starts_with("http://") || word.starts_with("https://"))
+        })
+        .map(str::to_owned);
+    let (link, user_id) = match (link, msg.from.as_ref()) {
+        (Some(link), Some(user)) => (link, user.id),
+        _ => return,
+    };
+    if link_in_domains(&link, self_hosts) || db.check_domain(&link).is_err() {
+        return;
+    }
+    let r = suggest(
+        bot.clone(),
+        db.clone(),
+        Session::new(bot.inner.handle.clone()),
+        msg.chat.id,
+        user_id,
+        link,
+        self_hosts.to_vec(),
+    ).map_err(|e| error!("telebot: {:?}", e));
+    bot.inner.handle.spawn(r);
+}
+
+#[async]
+fn suggest(
+    bot: telebot::RcBot,
+    db: Database,
+    session: Session,
+    chat_id: i64,
+    user_id: i64,
+    link: String,
+    self_hosts: Vec<String>,
+) -> Result<(), telebot::Error> {
+    let lang = db.get_lang(chat_id);
+    let ua = gen_ua(&bot);
+    let msg_id = await!(bot.message(chat_id, lang.processing()).send())?.1.message_id;
+    let link = match await!(youtube::feed_link(session.clone(), ua.clone(), link.clone())) {
+        Ok(Some(feed_link)) => feed_link,
+        _ => link,
+    };
+    let headers = Vec::new();
+    let r = feed::fetch_feed_with_headers(session.clone(), ua.clone(), headers, None, link.clone());
+    let candidates: Vec<(String, String)> = match await!(r) {
+        Ok(rss) => vec![(link.clone(), rss.title)],
+        Err(_) => await!(feed::discover_feeds(session, ua, link.clone()))
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|&(ref link, _)| !link_in_domains(link, &self_hosts))
+            .collect(),
+    };
+    let text = match candidates.len() {
+        0 => {
+            await!(bot.edit_message_text(chat_id, msg_id, lang.no_feed_found()).send())?;
+            return Ok(());
+        }
+        1 => lang.subscribe_confirm(&candidates[0].0),
+        _ => lang.feeds_discovered(),
+    };
+    let pending = Pending {
+        user_id: user_id,
+        subscriber: chat_id,
+        link_preview: db.default_link_preview(&candidates[0].0),
+        candidates: candidates,
+    };
+    await!(offer(bot, chat_id, msg_id, pending, text))
+}
+
+pub fn handle_callback(
+    bot: &telebot::RcBot,
+    db: &Database,
+    query: CallbackQuery,
+    self_hosts: &[String],
+) {
+    let index: Option<usize> = query
+        .data
+        .as_ref()
+        .and_then(|data| data.trim_left_matches("sub:").parse().ok());
+    let (index, message) = match (index, query.message) {
+        (Some(index), Some(message)) => (index, message),
+        _ => return,
+    };
+    let user_id = query.from.id;
+    let key = (message.chat.id, message.message_id);
+    let pending = PENDING.with(|p| {
+        let mut p = p.borrow_mut();
+        let chosen = p.get(&key).map_or(false, |pending| {
+            pending.user_id == user_id && index < pending.candidates.len()
+        });
+        if chosen {
+            p.remove(&key)
+        } else {
+            None
+        }
+    });
+    let lang = db.get_lang(message.chat.id);
+    let reply = if pending.is_some() {
+        String::new()
+    } else {
+        lang.choice_expired()
+    };
+    let r = bot.answer_callback_query(query.id)
+        .text(reply)
+        .send()
+        .map(|_| ())
+        .map_err(|e| error!("telebot: {:?}", e));
+    bot.inner.handle.spawn(r);
+
+    if let Some(pending) = pending {
+        let link = pending.candidates[index].0.clone();
+        let r = subscribe_feed(
+            bot.clone(),
+            db.clone(),
+            Session::new(bot.inner.handle.clone()),
+            message.chat.id,
+            message.message_id,
+            user_id,
+            pending.subscriber,
+            link,
+            pending.link_preview,
+            self_hosts.to_vec(),
+            false,
+        ).map_err(|e| error!("telebot: {:?}", e));
+        bot.inner.handle.spawn(r);
+    }
+}
@@ -0,0 +1,124 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use futures::prelude::*;
+use telebot;
+use telebot::functions::*;
+use telebot::objects::{CallbackQuery, InlineKeyboardButton, InlineKeyboardMarkup};
+
+use data::{Database, SubscriberID};
+
+thread_local! {
+    // (chat, message with the button) -> what `/erase` was checked for, the callback
+    // data only says which button it is and can't be trusted
+    static PENDING: RefCell<HashMap<(i64, i64), Pending>> = RefCell::new(HashMap::new());
+}
+
+struct Pending {
+    // only the user who sent `/erase` may confirm
+    user_id: i64,
+    subscriber: SubscriberID,
+}
+
+// asks `user_id` to confirm `/erase`, nothing is removed until the button is pressed,
+// `subscriber` has to be checked to be managed by `user_id` already
+pub fn confirm(
+    bot: &telebot::RcBot,
+    db: &Database,
+    chat_id: i64,
+    subscriber: SubscriberID,
+    user_id: i64,
+) -> impl Future<Item = (), Error = telebot::Error> {
+    let lang = db.get_lang(chat_id);
+    let markup = InlineKeyboardMarkup {
+        inline_keyboard: vec![vec![InlineKeyboardButton {
+            text: lang.erase_button(),
+            callback_data: Some("erase".to_owned()),
+            ..InlineKeyboardButton::default()
+        }]],
+    };
+    bot.message(chat_id, lang.erase_confirm())
+        .reply_markup(markup)
+        .send()
+        .map(move |(_, msg)| {
+            let pending = Pending {
+                user_id: user_id,
+                subscriber: subscriber,
+            };
+            PENDING.with(|p| p.borrow_mut().insert((chat_id, msg.message_id), pending));
+        })
+}
+
+// the subscriber confirmed by pressing the button of `msg_id`, the button only
+// works once and only for the user it was shown to
+fn take_pending(chat_id: i64, msg_id: i64, user_id: i64) -> Option<SubscriberID> {
+    PENDING.with(|p| {
+        let mut p = p.borrow_mut();
+        let allowed = p.get(&(chat_id, msg_id))
+            .map_or(false, |pending| pending.user_id == user_id);
+        if allowed {
+            p.remove(&(chat_id, msg_id)).map(|pending| pending.subscriber)
+        } else {
+            None
+        }
+    })
+}
+
+// the message becomes the receipt
+pub fn handle_callback(bot: &telebot::RcBot, db: &Database, query: CallbackQuery) {
+    let message = match query.message {
+        Some(ref message) => message,
+        None => return,
+    };
+    let chat_id = message.chat.id;
+    // looked up first, the language is erased too
+    let lang = db.get_lang(chat_id);
+    let subscriber = match take_pending(chat_id, message.message_id, query.from.id) {
+        Some(subscriber) => subscriber,
+        None => {
+            let r = bot.answer_callback_query(query.id)
+                .text(lang.choice_expired())
+                .send()
+                .map(|_| ())
+                .map_err(|e| error!("telebot: {:?}", e));
+            bot.inner.handle.spawn(r);
+            return;
+        }
+    };
+    let reply = match db.erase_subscriber(subscriber) {
+        Ok((subscriptions, in_backups)) => {
+            info!("erased {} on request", subscriber);
+            lang.erased(subscriptions, in_backups)
+        }
+        Err(e) => {
+            warn!("{}", e);
+            lang.error_reason(&e)
+        }
+    };
+    let r = bot.answer_callback_query(query.id)
+        .send()
+        .map(|_| ())
+        .map_err(|e| error!("telebot: {:?}", e));
+    bot.inner.handle.spawn(r);
+    let r = bot.edit_message_text(chat_id, message.message_id, reply)
+        .send()
+        .map(|_| ())
+        .map_err(|e| error!("telebot: {:?}", e));
+    bot.inner.handle.spawn(r);
+}
+
+#[test]
+fn test_take_pending_forged() {
+    let pending = Pending {
+        user_id: 5,
+        subscriber: -1001234,
+    };
+    PENDING.with(|p| p.borrow_mut().insert((5, 10), pending));
+    // someone else pressing it, or a button of another message
+    assert_eq!(take_pending(5, 10, 6), None);
+    assert_eq!(take_pending(-1001234, 10, 5), None);
+    assert_eq!(take_pending(5, 11, 5), None);
+    assert_eq!(take_pending(5, 10, 5), Some(-1001234));
+    // only once
+    assert_eq!(take_pending(5, 10, 5), None);
+}
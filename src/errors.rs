@@ -4,6 +4,10 @@ error_chain! {
 
         NotSubscribed
 
+        FeedNotFound {
+            description("feed not found")
+        }
+
         EOF {
             description("unexpected EOF")
         }
@@ -12,10 +16,37 @@ error_chain! {
             description("too many redirects")
         }
 
+        SelfReferentialFeed {
+            description("feed points at the bot itself")
+        }
+
         EmptyFeed {
             description("feed is empty or not valid")
         }
 
+        DomainBlocked(domain: String) {
+            description("domain is blocked")
+            display("feeds on {} are not accepted by this bot", domain)
+        }
+
+        ApprovalRequired(domain: String) {
+            description("domain needs the approval of an owner")
+            display("feeds on {} need the approval of the bot owner", domain)
+        }
+
+        HeadersTaken {
+            description("another subscriber has set the headers of this feed")
+        }
+
+        QuotaExceeded(limit: usize) {
+            description("subscription limit reached")
+            display("subscription limit reached ({})", limit)
+        }
+
+        InvalidExport {
+            description("not a valid export of the bot")
+        }
+
         Http(code: u32) {
             description("unexpected HTTP response code")
             display("HTTP {} ({})", code, response_code(*code).unwrap_or("Unknown"))
@@ -31,9 +62,24 @@ error_chain! {
             display("failed to save database: '{}'", path)
         }
 
+        DatabaseBackup(path: String) {
+            description("failed to back up database")
+            display("failed to back up database: '{}'", path)
+        }
+
         DatabaseFormat {
             description("illegal database format")
         }
+
+        ConfigOpen(path: String) {
+            description("failed to load config file")
+            display("failed to load config file: '{}'", path)
+        }
+
+        InvalidConfig(msg: String) {
+            description("invalid configuration")
+            display("invalid configuration: {}", msg)
+        }
     }
     links {
         Xml(::quick_xml::errors::Error, ::quick_xml::errors::ErrorKind);
@@ -41,6 +87,7 @@ error_chain! {
     foreign_links {
         Curl(::tokio_curl::PerformError);
         Utf8(::std::str::Utf8Error);
+        Regex(::regex::Error);
     }
 }
 
@@ -1,61 +1,148 @@
+use std::cell::{Cell, RefCell};
 use std::collections::HashMap;
+use std::rc::Rc;
 use std::time::Duration;
 
+use chrono::Utc;
 use futures::prelude::*;
+use futures::stream;
 use regex::Regex;
 use telebot;
-use telebot::functions::*;
-use telebot::objects::ResponseParameters;
-use tokio_core::reactor::{Interval, Timeout};
+use tokio_core::reactor::Timeout;
 use tokio_curl::Session;
+use url::Url;
 
+use abtest::preview_templates;
+use budget::HostBudget;
 use data;
-use data::LinkPreview;
+use data::{DeadFeed, LinkPreview, RewriteRule, SubscriptionOptions};
+use deadfeed;
 use feed;
+use html::{excerpt, extract_canonical, extract_meta};
+use logging;
+use messages::Locale;
+use pipeline::{Context, Output, Pipeline};
+use sender::{Job, Sender};
+use shutdown;
+use template::render_html;
 use utils::{
-    chat_is_unavailable, construct_iv_url, format_and_split_msgs, format_msgs, gen_ua,
-    send_multiple_messages, to_chinese_error_msg, truncate_message, Escape, EscapeUrl,
-    TELEGRAM_MAX_MSG_LEN,
+    construct_iv_url, format_and_split_msgs, format_msgs, gen_ua, log_error, normalize_url,
+    send_media, send_multiple_messages, to_hashtag, truncate_message, Escape, EscapeUrl, Media,
+    TELEGRAM_MAX_CAPTION_LEN, TELEGRAM_MAX_MSG_LEN,
 };
+use watch;
 
 lazy_static!{
     // it's different from `feed::HOST`, so maybe need a better name?
     static ref HOST: Regex = Regex::new(r"^(?:https?://)?([^/]+)").unwrap();
 }
 
-pub fn spawn_fetcher(bot: telebot::RcBot, db: data::Database, period: u64) {
+const PAGE_CACHE_SIZE: usize = 4096;
+const MAX_HASHTAGS: usize = 10;
+// jobs waiting in the sender, beyond this rounds are skipped and only feeds
+// of premium chats are fetched until the queue drains
+const QUEUE_THRESHOLD: usize = 1000;
+const MAX_SKIPPED_ROUNDS: usize = 8;
+
+thread_local! {
+    // item link -> what its page says about itself
+    static PAGE_CACHE: RefCell<HashMap<String, PageMeta>> = RefCell::new(HashMap::new());
+    // seconds between rounds, see `set_period`
+    static PERIOD: Cell<u64> = Cell::new(300);
+    // host -> where its group starts in the next round, see `rotate_group`
+    static GROUP_START: RefCell<HashMap<String, usize>> = RefCell::new(HashMap::new());
+}
+
+// takes effect after the current round, e.g. when the config is reloaded
+pub fn set_period(period: u64) {
+    PERIOD.with(|p| p.set(period.max(1)));
+}
+
+pub fn spawn_fetcher(
+    bot: telebot::RcBot,
+    db: data::Database,
+    period: u64,
+    sender: Sender,
+    budget: Rc<HostBudget>,
+    max_concurrent: usize,
+) {
+    // 0 means unlimited
+    let max_concurrent = if max_concurrent == 0 {
+        usize::max_value()
+    } else {
+        max_concurrent
+    };
     let handle = bot.inner.handle.clone();
     let handle2 = handle.clone();
+    set_period(period);
+    // whether the fetches of the last round are still running
+    let running = Rc::new(Cell::new(false));
     let lop = async_block! {
-        #[async]
-        for _ in Interval::new(Duration::from_secs(period), &handle)
-            .expect("failed to start feed loop")
-            .map_err(|e| error!("feed loop error: {}", e))
-        {
-            let feeds = db.get_all_feeds();
+        let mut skip = 0;
+        loop {
+            let period = PERIOD.with(|p| p.get());
+            await!(Timeout::new(Duration::from_secs(period), &handle)
+                   .expect("failed to start feed loop"))
+                .map_err(|e| error!("feed loop error: {}", e))?;
+            if shutdown::is_shutting_down() {
+                break;
+            }
+            if skip > 0 {
+                skip -= 1;
+                continue;
+            }
+            // rounds never overlap, otherwise more than `max_concurrent` fetches could run
+            if running.get() {
+                info!("the last round is still running, skipping this one");
+                continue;
+            }
+            let mut feeds = db.get_all_feeds();
+            // Telegram is slower than the feeds, don't let the queue grow unbounded
+            let queued = sender.queued();
+            if queued > QUEUE_THRESHOLD {
+                skip = (queued / QUEUE_THRESHOLD).min(MAX_SKIPPED_ROUNDS);
+                let total = feeds.len();
+                feeds.retain(|feed| feed.subscribers.iter().any(|s| db.is_premium(*s)));
+                warn!(
+                    "{} jobs queued, deferring {} of {} feeds and skipping {} rounds",
+                    queued,
+                    total - feeds.len(),
+                    total,
+                    skip
+                );
+            }
+            // pages without feeds, see `/watch`
+            let session = Session::new(bot.inner.handle.clone());
+            handle.spawn(watch::check_pages(bot.clone(), db.clone(), session,
+                                            sender.clone(), budget.clone()));
             let grouped_feeds = grouping_by_host(feeds);
             let handle2 = handle.clone();
             let bot = bot.clone();
             let db = db.clone();
-            let fetcher = async_block! {
-                for group in grouped_feeds {
-                    let session = Session::new(handle2.clone());
-                    let bot = bot.clone();
-                    let db = db.clone();
-                    let group_fetcher = async_block! {
-                        for feed in group {
-                            await!(fetch_feed_updates(bot.clone(), db.clone(),
-                                                      session.clone(), feed))?;
-                        }
-                        Ok(())
-                    };
-                    handle2.spawn(group_fetcher);
-                    await!(Timeout::new(Duration::from_secs(1), &handle2)
-                           .expect("failed to start sleep"))
-                        .map_err(|e| error!("feed loop sleep error: {}", e))?;
-                }
-                Ok(())
-            };
+            let sender = sender.clone();
+            let budget = budget.clone();
+            let running2 = Rc::clone(&running);
+            running.set(true);
+            // feeds of the same host are fetched one by one, so at most
+            // `max_concurrent` fetches are running at any time
+            let fetcher = stream::iter_ok(grouped_feeds)
+                .and_then(move |group| {
+                    Timeout::new(Duration::from_secs(1), &handle2)
+                        .expect("failed to start sleep")
+                        .map(|_| group)
+                        .map_err(|e| error!("feed loop sleep error: {}", e))
+                })
+                .map(move |group| {
+                    let session = Session::new(bot.inner.handle.clone());
+                    fetch_group(bot.clone(), db.clone(), session, group,
+                                sender.clone(), budget.clone())
+                })
+                .buffer_unordered(max_concurrent)
+                .for_each(|()| Ok(()))
+                .then(move |r| {
+                    running2.set(false);
+                    r
+                });
             handle.spawn(fetcher);
         }
         Ok(())
@@ -63,6 +150,56 @@ pub fn spawn_fetcher(bot: telebot::RcBot, db: data::Database, period: u64) {
     handle2.spawn(lop)
 }
 
+#[async]
+fn fetch_group(
+    bot: telebot::RcBot,
+    db: data::Database,
+    session: Session,
+    group: Vec<data::Feed>,
+    sender: Sender,
+    budget: Rc<HostBudget>,
+) -> Result<(), ()> {
+    let group = rotate_group(group);
+    let len = group.len();
+    let mut fetched = 0;
+    for feed in group {
+        let host = get_host(&feed.link).to_owned();
+        // the rest of the group waits for the next round, and goes first then
+        if !budget.acquire(&host) {
+            info!("fetch budget of {} used up", host);
+            advance_group(&host, fetched, len);
+            break;
+        }
+        fetched += 1;
+        // a failed feed shouldn't stop the rest of the group
+        let _ = await!(fetch_feed_updates(bot.clone(), db.clone(),
+                                          session.clone(), feed,
+                                          sender.clone()));
+    }
+    Ok(())
+}
+
+// the feeds of a host in a fixed order, starting where the budget stopped the last round
+fn rotate_group(mut group: Vec<data::Feed>) -> Vec<data::Feed> {
+    if group.is_empty() {
+        return group;
+    }
+    group.sort_by(|a, b| a.link.cmp(&b.link));
+    let host = get_host(&group[0].link).to_owned();
+    let start = GROUP_START.with(|s| s.borrow().get(&host).cloned().unwrap_or(0));
+    let len = group.len();
+    group.rotate_left(start % len);
+    group
+}
+
+fn advance_group(host: &str, fetched: usize, len: usize) {
+    GROUP_START.with(|s| {
+        let mut s = s.borrow_mut();
+        let start = s.entry(host.to_owned()).or_insert(0);
+        *start = (*start + fetched) % len;
+    });
+}
+
 fn grouping_by_host(feeds: Vec<data::Feed>) -> Vec<Vec<data::Feed>> {
     let mut result = HashMap::new();
     for feed in feeds {
@@ -78,216 +215,602 @@ fn get_host(url: &str) -> &str {
         .map_or(url, |r| r.get(0).unwrap().as_str())
 }
 
+// returns the number of new items, a failed fetch has none
 #[async]
-fn fetch_feed_updates(
+pub fn fetch_feed_updates(
     bot: telebot::RcBot,
     db: data::Database,
     session: Session,
     feed: data::Feed,
-) -> Result<(), ()> {
-    let handle = bot.inner.handle.clone();
-    let rss = match await!(feed::fetch_feed(
-        session,
+    sender: Sender,
+) -> Result<usize, ()> {
+    let rss = match await!(feed::fetch_feed_with_headers(
+        session.clone(),
         gen_ua(&bot),
+        db.get_fetch_headers(&feed.link),
+        feed.proxy.clone(),
         feed.link.to_owned(),
     )) {
         Ok(rss) => rss,
         Err(e) => {
-            // 1440 * 5 minute = 5 days
-            if db.inc_error_count(&feed.link) > 1440 {
-                db.reset_error_count(&feed.link);
-                let err_msg = to_chinese_error_msg(e);
-                let msg = format!(
-                    "「<a href=\"{}\">{}</a>」\
-                     Failed to fetch {} for 5 days, please consider unsubscribing",
-                    EscapeUrl(&feed.link),
-                    Escape(&feed.title),
-                    Escape(&err_msg)
-                );
-                for subscriber in feed.subscribers {
-                    let m = bot
-                        .message(subscriber, msg.clone())
-                        .parse_mode("HTML")
-                        .disable_web_page_preview(true)
-                        .send();
-                    match await!(m) {
-                        Err(telebot::Error::Telegram(_, ref s, None)) if chat_is_unavailable(s) => {
-                            db.delete_subscriber(subscriber);
-                        }
-                        Err(telebot::Error::Telegram(
-                            _,
-                            _,
-                            Some(ResponseParameters {
-                                migrate_to_chat_id: Some(new_id),
-                                ..
-                            }),
-                        )) => {
-                            db.update_subscriber(subscriber, new_id);
-                            handle.spawn(
-                                bot.message(new_id, msg.clone())
-                                    .parse_mode("HTML")
-                                    .disable_web_page_preview(true)
-                                    .send()
-                                    .then(|_| Ok(())),
-                            );
-                        }
-                        Err(e) => warn!("failed to send error to {}, {:?}", subscriber, e),
-                        _ => (),
+            let kind = logging::error_kind(&e);
+            let fields = [("feed", feed.link.as_str()), ("error_kind", kind.as_str())];
+            logging::with_fields(&fields, || info!("failed to fetch: {}", e));
+            // it was healthy until now
+            if db.inc_error_count(&feed.link, &e.to_string()) == 1 {
+                for subscriber in error_notify_subscribers(&db, &feed) {
+                    let lang = db.get_lang(subscriber);
+                    let msg = lang.feed_failing(&feed.link, &feed.title, &lang.error_reason(&e));
+                    sender.send(Job::text(subscriber, msg));
+                }
+            }
+            match db.check_dead_feed(&feed.link) {
+                Some(DeadFeed::Warn(subscribers)) => {
+                    deadfeed::warn(&bot, &db, &feed, &e, subscribers);
+                }
+                Some(DeadFeed::Removed(subscribers)) => {
+                    logging::with_fields(&[("feed", feed.link.as_str())], || {
+                        info!("removed dead feed from {} chats", subscribers.len())
+                    });
+                    for subscriber in subscribers {
+                        let lang = db.get_lang(subscriber);
+                        let msg = lang.dead_feed_removed(&feed.link, &feed.title);
+                        sender.send(Job::text(subscriber, msg));
                     }
                 }
+                None => (),
             }
-            return Ok(());
+            return Ok(0);
         }
     };
-    let moved = if rss.source.as_ref().unwrap() != &feed.link {
-        Some(rss.clone())
+    let source = rss.source.clone().unwrap();
+    let feed = if normalize_url(&source) != normalize_url(&feed.link) {
+        match db.move_feed(&feed.link, &source) {
+            Ok(moved) => {
+                for &subscriber in &moved.subscribers {
+                    let msg = db.get_lang(subscriber).feed_moved(&feed.link, &source, &moved.title);
+                    sender.send(Job::text(subscriber, msg));
+                }
+                moved
+            }
+            Err(e) => {
+                logging::with_fields(&[("feed", feed.link.as_str())], || {
+                    warn!("failed to move to {}: {}", source, e)
+                });
+                feed
+            }
+        }
     } else {
-        None
+        feed
     };
+    if feed.error_count > 0 {
+        for subscriber in error_notify_subscribers(&db, &feed) {
+            let msg = db.get_lang(subscriber).feed_recovered(&feed.link, &feed.title);
+            sender.send(Job::text(subscriber, msg));
+        }
+    }
     if rss.title != feed.title {
         db.update_title(&feed.link, &rss.title);
     }
-    let feed::RSS {
-        title: rss_title,
-        link: rss_link,
-        items: rss_items,
-        ..
-    } = rss.clone();
-    let updates = db.update(&feed.link, rss_items);
+    let updates = db.update(&feed.link, rss.items.clone());
     if updates.is_empty() {
-        return Ok(());
+        // e.g. old items coming back
+        db.mark_seen(&feed.link, true);
+        return Ok(0);
     }
+    let count = updates.len();
+    let link = feed.link.clone();
+    let r = await!(deliver_updates(bot, db.clone(), session, feed, rss, updates, sender, None));
+    db.mark_seen(&link, r.is_ok());
+    r?;
+    Ok(count)
+}
+
+// the keyword of `/pinrule` when the title of an item contains it
+fn pin_keyword(options: &SubscriptionOptions, items: &[feed::Item]) -> Option<String> {
+    options.pin_keyword.clone().filter(|keyword| {
+        items.iter().any(|item| {
+            item.title
+                .as_ref()
+                .map_or(false, |title| title.to_lowercase().contains(keyword.as_str()))
+        })
+    })
+}
+
+fn error_notify_subscribers(db: &data::Database, feed: &data::Feed) -> Vec<i64> {
+    let feed_id = feed.get_id();
+    feed.subscribers
+        .iter()
+        .cloned()
+        .filter(|subscriber| db.get_options(*subscriber, feed_id).error_notify)
+        .collect()
+}
+
+// also to the chats mirroring the subscription, see `/mirror`, returns the number of jobs
+fn send_mirrored(db: &data::Database, sender: &Sender, feed_id: data::FeedID, job: Job) -> usize {
+    let mirrors = db.get_mirrors(job.target, feed_id);
+    for target in &mirrors {
+        sender.send(Job {
+            target: *target,
+            ..job.clone()
+        });
+    }
+    sender.send(job);
+    mirrors.len() + 1
+}
+
+// renders `updates` for every subscriber with its own options and queues them,
+// returns how many jobs were queued
+//
+// with `redirect` each distinct rendering is sent there instead, once, and
+// no subscription state is touched, see `/admin inject`
+#[async]
+pub fn deliver_updates(
+    bot: telebot::RcBot,
+    db: data::Database,
+    session: Session,
+    feed: data::Feed,
+    rss: feed::RSS,
+    updates: Vec<feed::Item>,
+    sender: Sender,
+    redirect: Option<i64>,
+) -> Result<usize, ()> {
+    let feed_title = rss.title.clone();
+    let rss_link = rss.link.clone();
     let feed_id = feed.get_id();
+    let mut queued = 0;
+
+    let pages = if feed.subscribers.iter().any(|subscriber| {
+        let options = db.get_options(*subscriber, feed_id);
+        options.og_image || options.canonical
+    }) {
+        let links = updates.iter().filter_map(|item| item.link.clone()).collect();
+        await!(fetch_pages(session, gen_ua(&bot), links))?
+    } else {
+        HashMap::new()
+    };
 
-    let mut msgs_cache: HashMap<LinkPreview, Vec<String>> = HashMap::new();
+    // everything which changes the messages, the locale only matters with dates,
+    // the ones changing the item links are nested as tuples only go up to 12
+    let mut output_cache: HashMap<
+        (
+            LinkPreview,
+            bool,
+            bool,
+            (bool, Option<bool>, Vec<String>),
+            bool,
+            Option<String>,
+            Option<usize>,
+            bool,
+            Option<Locale>,
+            bool,
+            Vec<String>,
+            (String, Option<String>),
+        ),
+        Output,
+    > = HashMap::new();
 
     for subscriber in feed.subscribers {
         use data::LinkPreview::*;
         let link_preview = db.get_link_preview(subscriber, feed_id);
-        let (msgs, enable_lp) = match link_preview {
-            None => (
-                {
-                    let _ = db.subscribe(subscriber, &rss_link, &rss, Off);
-                    vec!(format!(
-                        "<b>Error</b>: Link Previews for {} have been disabled",
-                        Escape(&rss_title)
-                    ))
-                },
-                false,
-            ),
-            Some(link_preview) => match link_preview {
-                Off => (
-                    msgs_cache
-                        .entry(Off)
-                        .or_insert_with(|| {
-                            format_and_split_msgs(
-                                format!("<b>{}</b>", Escape(&rss_title)),
-                                &updates,
-                                |item| {
-                                    let title = item
-                                        .title
-                                        .as_ref()
-                                        .map(|s| s.as_str())
-                                        .unwrap_or_else(|| &rss_title);
-                                    let link = item
-                                        .link
-                                        .as_ref()
-                                        .map(|s| s.as_str())
-                                        .unwrap_or_else(|| &rss_link);
-                                    format!(
-                                        "<a href=\"{}\">{}</a>",
-                                        EscapeUrl(link),
-                                        Escape(&truncate_message(
-                                            title,
-                                            TELEGRAM_MAX_MSG_LEN - 500
-                                        ))
-                                    )
-                                },
-                            )
-                        })
-                        .clone(),
-                    false,
-                ),
-                On => (
-                    msgs_cache
-                        .entry(On)
-                        .or_insert_with(|| {
-                            format_msgs(&updates, |item| {
-                                let title = item
-                                    .title
-                                    .as_ref()
-                                    .map(|s| s.as_str())
-                                    .unwrap_or_else(|| &rss_title);
-                                let link = item
-                                    .link
-                                    .as_ref()
-                                    .map(|s| s.as_str())
-                                    .unwrap_or_else(|| &rss_link);
-                                format!(
-                                    "<b>{}</b> <a href=\"{}\">{}</a>",
-                                    Escape(&rss_title),
-                                    EscapeUrl(link),
-                                    Escape(&truncate_message(title, TELEGRAM_MAX_MSG_LEN - 500))
-                                )
-                            })
-                        })
-                        .clone(),
-                    true,
-                ),
-                InstantView(rhash) => (
-                    msgs_cache
-                        .entry(InstantView(rhash))
-                        .or_insert_with(|| {
-                            format_msgs(&updates, |item| {
-                                let title = item
-                                    .title
-                                    .as_ref()
-                                    .map(|s| s.as_str())
-                                    .unwrap_or_else(|| &rss_title);
-                                let link = item
-                                    .link
-                                    .as_ref()
-                                    .map(|s| s.as_str())
-                                    .unwrap_or_else(|| &rss_link);
-                                format!(
-                                    "<a href=\"{}\">🔗</a><a href=\"{}\">{}</a>",
-                                    EscapeUrl(&construct_iv_url(link, rhash)),
-                                    EscapeUrl(link),
-                                    Escape(&truncate_message(title, TELEGRAM_MAX_MSG_LEN - 500))
-                                )
-                            })
-                        })
-                        .clone(),
-                    true,
-                ),
-            },
-        };
-        let r = send_multiple_messages(&bot, subscriber, msgs.clone(), enable_lp);
-        match await!(r) {
-            Err(telebot::Error::Telegram(_, ref s, None)) if chat_is_unavailable(s) => {
-                db.delete_subscriber(subscriber);
+        let options = db.get_options(subscriber, feed_id);
+        let locale = db.get_locale(subscriber);
+        // see `/rename`
+        let rss_title = options.title.clone().unwrap_or_else(|| feed_title.clone());
+        if let Some(ref test) = options.template_test {
+            if !test.previewed && redirect.is_none() {
+                preview_templates(&bot, &db, subscriber, feed_id, test, &updates[0],
+                                  &rss_title, &rss_link);
             }
-            Err(telebot::Error::Telegram(
-                _,
-                _,
-                Some(ResponseParameters {
-                    migrate_to_chat_id: Some(new_id),
-                    ..
-                }),
-            )) => {
-                db.update_subscriber(subscriber, new_id);
-                handle.spawn(
-                    send_multiple_messages(&bot, new_id, msgs.clone(), enable_lp).then(|_| Ok(())),
+        }
+        let mut items = updates.clone();
+        if redirect.is_none() {
+            items = db.skip_initial_items(subscriber, feed_id, items);
+        }
+        // items another feed already sent, see `/dedupe`
+        let delivering =
+            redirect.is_none() && link_preview.is_some() && !db.is_expired(subscriber, feed_id);
+        if delivering {
+            items = db.dedupe(subscriber, &items);
+        }
+        if items.is_empty() {
+            continue;
+        }
+        let filtered = items.len() < updates.len();
+        if options.snooze_items.is_some() && link_preview.is_some() && redirect.is_none() {
+            // a batch is always one combined list
+            if let Some(items) = db.snooze(subscriber, feed_id, &items) {
+                if !db.is_expired(subscriber, feed_id) {
+                    let msgs = format_updates(
+                        Off,
+                        &SubscriptionOptions::default(),
+                        locale,
+                        &rss_title,
+                        &rss_link,
+                        &items,
+                    );
+                    let job = Job {
+                        target: subscriber,
+                        media_msgs: Vec::new(),
+                        msgs: msgs,
+                        link_preview: false,
+                        silent: options.silent,
+                        pin_keyword: pin_keyword(&options, &items),
+                        feed_title: rss_title.clone(),
+                        items: items,
+                    };
+                    queued += send_mirrored(&db, &sender, feed_id, job);
+                }
+            }
+            continue;
+        }
+        let (output, enable_lp) = match link_preview {
+            None if redirect.is_some() => continue,
+            None => {
+                let _ = db.subscribe(subscriber, &rss_link, &rss, Off);
+                let output = Output {
+                    media_msgs: Vec::new(),
+                    msgs: vec![db.get_lang(subscriber).link_preview_disabled(&rss_title)],
+                    items: Vec::new(),
+                };
+                (output, false)
+            }
+            Some(link_preview) => {
+                let key = (
+                    link_preview,
+                    options.media,
+                    options.og_image,
+                    (options.canonical, options.strip_tracking, options.rewrite.clone()),
+                    options.podcast,
+                    options.template.clone(),
+                    options.summary,
+                    options.show_author,
+                    Some(locale).filter(|_| options.template.is_some() || options.show_date),
+                    options.category_hashtags,
+                    options.hashtags.clone(),
+                    (rss_title.clone(), options.prefix.clone()),
                 );
+                if redirect.is_some() && output_cache.contains_key(&key) {
+                    continue;
+                }
+                let rewrite_rules: Vec<RewriteRule> =
+                    options.rewrite.iter().filter_map(|name| db.rewrite_rule(name)).collect();
+                let run = |items| {
+                    let ctx = Context {
+                        rss_title: &rss_title,
+                        rss_link: &rss_link,
+                        link_preview: link_preview,
+                        options: &options,
+                        locale: locale,
+                        pages: &pages,
+                        rewrite_rules: &rewrite_rules,
+                    };
+                    Pipeline::for_subscription(&options).run(items, &ctx)
+                };
+                // a rendering of fewer items is only good for this subscriber
+                let output = if filtered {
+                    run(items)
+                } else {
+                    output_cache
+                        .entry(key)
+                        .or_insert_with(|| run(updates.clone()))
+                        .clone()
+                };
+                (output, link_preview != Off)
             }
-            Err(e) => warn!("failed to send updates to {}, {:?}", subscriber, e),
-            _ => (),
+        };
+        if let Some(target) = redirect {
+            // not a delivery, kept out of the history
+            sender.send(Job {
+                target: target,
+                media_msgs: output.media_msgs,
+                msgs: output.msgs,
+                link_preview: enable_lp,
+                silent: false,
+                pin_keyword: None,
+                feed_title: rss_title.clone(),
+                items: Vec::new(),
+            });
+            queued += 1;
+        } else if !db.is_expired(subscriber, feed_id) {
+            let job = Job {
+                target: subscriber,
+                media_msgs: output.media_msgs,
+                msgs: output.msgs,
+                link_preview: enable_lp,
+                silent: options.silent,
+                pin_keyword: pin_keyword(&options, &output.items),
+                feed_title: rss_title.clone(),
+                items: output.items,
+            };
+            queued += send_mirrored(&db, &sender, feed_id, job);
         }
-        if let Some(ref rss) = moved {
-            // ignore error
-            let _ = db.unsubscribe(subscriber, &feed.link);
-            let _ = db.subscribe(subscriber, rss.source.as_ref().unwrap(), rss, link_preview.unwrap_or(Off));
+    }
+    // otherwise saved along with the seen items, see `Database::mark_seen`
+    if queued > 0 && redirect.is_some() {
+        if let Err(e) = db.flush() {
+            log_error(&e);
         }
     }
+    Ok(queued)
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct PageMeta {
+    pub og_image: Option<String>,
+    pub canonical: Option<String>,
+}
+
+// pages are also found by their canonical link, it may replace the item link
+#[async]
+fn fetch_pages(
+    session: Session,
+    ua: String,
+    links: Vec<String>,
+) -> Result<HashMap<String, PageMeta>, ()> {
+    let mut pages = HashMap::new();
+    for link in links {
+        let cached = PAGE_CACHE.with(|cache| cache.borrow().get(&link).cloned());
+        let page = match cached {
+            Some(page) => page,
+            None => {
+                let body = await!(feed::fetch_page(session.clone(), ua.clone(), link.clone()));
+                let page = match body {
+                    Ok(body) => {
+                        let html = String::from_utf8_lossy(&body);
+                        PageMeta {
+                            og_image: extract_meta(&html, "og:image")
+                                .and_then(|image| {
+                                    Url::parse(&link).and_then(|base| base.join(&image)).ok()
+                                })
+                                .map(|image| image.into_string()),
+                            canonical: extract_canonical(&html, &link),
+                        }
+                    }
+                    Err(e) => {
+                        info!("failed to fetch page {}: {}", link, e);
+                        PageMeta::default()
+                    }
+                };
+                PAGE_CACHE.with(|cache| {
+                    let mut cache = cache.borrow_mut();
+                    if cache.len() >= PAGE_CACHE_SIZE {
+                        cache.clear();
+                    }
+                    cache.insert(link.clone(), page.clone());
+                });
+                page
+            }
+        };
+        if let Some(canonical) = page.canonical.clone() {
+            pages.insert(canonical, page.clone());
+        }
+        pages.insert(link, page);
+    }
+    Ok(pages)
+}
+
+// only the options about the messages are used,
+// see `/template`, `/summary`, `/display`, `/hashtags` and `/prefix`
+pub fn format_updates(
+    link_preview: LinkPreview,
+    options: &SubscriptionOptions,
+    locale: Locale,
+    rss_title: &str,
+    rss_link: &str,
+    items: &[feed::Item],
+) -> Vec<String> {
+    use data::LinkPreview::*;
+    if items.is_empty() {
+        return Vec::new();
+    }
+    let prefix = format_prefix(options);
+    if let Some(ref template) = options.template {
+        return format_msgs(items, |item| {
+            let text = render_html(template, item, rss_title, rss_link, locale);
+            prefix.clone() + &text + &format_hashtags(item, options)
+        });
+    }
+    let title_and_link = |item: &feed::Item| {
+        let title = item
+            .title
+            .as_ref()
+            .map(|s| s.as_str())
+            .unwrap_or(rss_title);
+        let link = item
+            .link
+            .as_ref()
+            .map(|s| s.as_str())
+            .unwrap_or(rss_link);
+        (
+            truncate_message(title, TELEGRAM_MAX_MSG_LEN - 500),
+            link.to_owned(),
+        )
+    };
+    let now = Utc::now();
+    // `Title — Author · 2h ago`
+    let byline_of = |item: &feed::Item| {
+        let mut parts = Vec::new();
+        if let (true, Some(author)) = (options.show_author, item.author.as_ref()) {
+            parts.push(truncate_message(author, 100));
+        }
+        if let (true, Some(pubdate)) = (options.show_date, item.pubdate.as_ref()) {
+            parts.push(locale.format_age(pubdate, &now));
+        }
+        if parts.is_empty() {
+            String::new()
+        } else {
+            format!(" — {}", Escape(&parts.join(" · ")))
+        }
+    };
+    let summary_of = |item: &feed::Item| match (options.summary, item.description.as_ref()) {
+        (Some(len), Some(description)) => {
+            let excerpt = excerpt(description, len);
+            if excerpt.is_empty() {
+                excerpt
+            } else {
+                format!("\n{}", excerpt)
+            }
+        }
+        _ => String::new(),
+    };
+    // audio which couldn't be sent as a file, see `/podcast`
+    let audio_of = |item: &feed::Item| match (options.podcast, item.audio()) {
+        (true, Some(audio)) => {
+            let name = audio.url
+                .split(|c| c == '?' || c == '#')
+                .next()
+                .and_then(|path| path.rsplit('/').next())
+                .filter(|name| !name.is_empty())
+                .unwrap_or(&audio.url);
+            format!("\n🎧 <a href=\"{}\">{}</a>", EscapeUrl(&audio.url), Escape(name))
+        }
+        _ => String::new(),
+    };
+    match link_preview {
+        Off => {
+            let head = format!("{}<b>{}</b>", prefix, Escape(rss_title));
+            format_and_split_msgs(head, items, |item| {
+                let (title, link) = title_and_link(item);
+                format!(
+                    "<a href=\"{}\">{}</a>{}{}{}{}",
+                    EscapeUrl(&link),
+                    Escape(&title),
+                    byline_of(item),
+                    summary_of(item),
+                    audio_of(item),
+                    format_hashtags(item, options)
+                )
+            })
+        }
+        On => format_msgs(items, |item| {
+            let (title, link) = title_and_link(item);
+            format!(
+                "{}<b>{}</b> <a href=\"{}\">{}</a>{}{}{}{}",
+                prefix,
+                Escape(rss_title),
+                EscapeUrl(&link),
+                Escape(&title),
+                byline_of(item),
+                summary_of(item),
+                audio_of(item),
+                format_hashtags(item, options)
+            )
+        }),
+        InstantView(rhash) => format_msgs(items, |item| {
+            let (title, link) = title_and_link(item);
+            format!(
+                "{}<a href=\"{}\">🔗</a><a href=\"{}\">{}</a>{}{}{}{}",
+                prefix,
+                EscapeUrl(&construct_iv_url(&link, rhash)),
+                EscapeUrl(&link),
+                Escape(&title),
+                byline_of(item),
+                summary_of(item),
+                audio_of(item),
+                format_hashtags(item, options)
+            )
+        }),
+    }
+}
+
+// `🔥 ` before the messages, see `/prefix`
+fn format_prefix(options: &SubscriptionOptions) -> String {
+    options
+        .prefix
+        .as_ref()
+        .map(|prefix| format!("{} ", Escape(prefix)))
+        .unwrap_or_default()
+}
+
+// `\n#fixed #categories`, see `/hashtags`
+fn format_hashtags(item: &feed::Item, options: &SubscriptionOptions) -> String {
+    let mut tags = options.hashtags.clone();
+    if options.category_hashtags {
+        for tag in item.categories.iter().filter_map(|category| to_hashtag(category)) {
+            if !tags.contains(&tag) {
+                tags.push(tag);
+            }
+        }
+    }
+    tags.truncate(MAX_HASHTAGS);
+    if tags.is_empty() {
+        String::new()
+    } else {
+        format!("\n{}", Escape(&tags.join(" ")))
+    }
+}
+
+pub fn format_caption(
+    item: &feed::Item,
+    options: &SubscriptionOptions,
+    rss_title: &str,
+    rss_link: &str,
+) -> String {
+    let title = item
+        .title
+        .as_ref()
+        .map(|s| s.as_str())
+        .unwrap_or(rss_title);
+    let link = item
+        .link
+        .as_ref()
+        .map(|s| s.as_str())
+        .unwrap_or(rss_link);
+    format!(
+        "{}<b>{}</b>\n<a href=\"{}\">{}</a>{}",
+        format_prefix(options),
+        Escape(&truncate_message(rss_title, 100)),
+        EscapeUrl(link),
+        Escape(&truncate_message(title, TELEGRAM_MAX_CAPTION_LEN / 2)),
+        format_hashtags(item, options)
+    )
+}
+
+// media which can't be sent (too large, unreachable) falls back to its caption
+#[async]
+pub fn send_updates(
+    bot: telebot::RcBot,
+    target: i64,
+    media_msgs: Vec<Media>,
+    msgs: Vec<String>,
+    link_preview: bool,
+) -> Result<(), telebot::Error> {
+    for media in media_msgs {
+        if let Err(e) = await!(send_media(&bot, target, &media, false)) {
+            logging::with_fields(&[("chat", target.to_string().as_str())], || {
+                info!("failed to send {:?} {}, {:?}", media.kind, media.url, e)
+            });
+            let caption = media.caption;
+            await!(send_multiple_messages(&bot, target, vec![caption], link_preview))?;
+        }
+    }
+    await!(send_multiple_messages(&bot, target, msgs, link_preview))?;
     Ok(())
 }
+
+#[test]
+fn test_rotate_group() {
+    let group: Vec<data::Feed> = (0..5)
+        .map(|i| data::Feed {
+            link: format!("http://rotate.example/{}", i),
+            ..data::Feed::default()
+        })
+        .collect();
+    let links = |group: Vec<data::Feed>| -> Vec<String> {
+        group.into_iter().map(|feed| feed.link).collect()
+    };
+    let first = links(rotate_group(group.clone()));
+    assert_eq!(first[0], "http://rotate.example/0");
+    // a budget of 2 per round still reaches every feed
+    advance_group("http://rotate.example", 2, 5);
+    assert_eq!(links(rotate_group(group.clone()))[0], "http://rotate.example/2");
+    advance_group("http://rotate.example", 2, 5);
+    assert_eq!(links(rotate_group(group.clone()))[0], "http://rotate.example/4");
+    advance_group("http://rotate.example", 2, 5);
+    assert_eq!(links(rotate_group(group.clone()))[0], "http://rotate.example/1");
+    // the order itself doesn't depend on the database
+    let mut reversed = group.clone();
+    reversed.reverse();
+    assert_eq!(links(rotate_group(reversed)), links(rotate_group(group)));
+}
@@ -0,0 +1,486 @@
+use regex::Regex;
+use url::Url;
+
+use utils::{Escape, EscapeUrl};
+
+lazy_static! {
+    static ref META: Regex = Regex::new(r"(?is)<meta\s[^>]*>").unwrap();
+    static ref LINK: Regex = Regex::new(r"(?is)<link\s[^>]*>").unwrap();
+    static ref ATTR: Regex =
+        Regex::new(r#"(?s)([A-Za-z:-]+)\s*=\s*(?:"([^"]*)"|'([^']*)'|([^\s"'>]+))"#).unwrap();
+    static ref ENTITY: Regex = Regex::new(r"&(#[0-9]+|#[xX][0-9A-Fa-f]+|[A-Za-z]+);").unwrap();
+    static ref TAG: Regex =
+        Regex::new(r"(?s)<!--.*?-->|<(/?)([A-Za-z][A-Za-z0-9]*)([^>]*)>").unwrap();
+    static ref TITLE: Regex = Regex::new(r"(?is)<title[^>]*>(.*?)</title>").unwrap();
+}
+
+// Telegram's HTML subset, links keep only their `href`
+const KEPT_TAGS: &[&str] = &[
+    "b", "strong", "i", "em", "u", "ins", "s", "strike", "del", "code", "a",
+];
+// their text is dropped
+const SKIPPED_TAGS: &[&str] = &["script", "style", "head", "title"];
+// never have an end tag
+const VOID_TAGS: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "source",
+    "track", "wbr",
+];
+
+// unknown named entities are kept as is
+pub fn decode_entities(s: &str) -> String {
+    ENTITY
+        .replace_all(s, |cap: &::regex::Captures| {
+            let entity = &cap[1];
+            let c = if entity.starts_with("#x") || entity.starts_with("#X") {
+                u32::from_str_radix(&entity[2..], 16).ok().and_then(::std::char::from_u32)
+            } else if entity.starts_with('#') {
+                entity[1..].parse().ok().and_then(::std::char::from_u32)
+            } else {
+                match entity {
+                    "lt" => Some('<'),
+                    "gt" => Some('>'),
+                    "quot" => Some('"'),
+                    "apos" => Some('\''),
+                    "amp" => Some('&'),
+                    "nbsp" => Some(' '),
+                    _ => None,
+                }
+            };
+            c.map_or_else(|| cap[0].to_owned(), |c| c.to_string())
+        })
+        .into_owned()
+}
+
+// the text of an item description with the formatting Telegram supports kept,
+// whitespace collapsed and cut at `max_chars` characters of text
+pub fn excerpt(html: &str, max_chars: usize) -> String {
+    let mut out = Excerpt {
+        out: String::new(),
+        chars: 0,
+        max_chars: max_chars,
+        space: false,
+        truncated: false,
+    };
+    let mut open: Vec<&'static str> = Vec::new();
+    let mut rest = html;
+    while !out.truncated {
+        let cap = match TAG.captures(rest) {
+            Some(cap) => cap,
+            None => {
+                out.push_text(rest);
+                break;
+            }
+        };
+        let (start, end) = {
+            let m = cap.get(0).unwrap();
+            (m.start(), m.end())
+        };
+        out.push_text(&rest[..start]);
+        rest = &rest[end..];
+        let name = match cap.get(2) {
+            Some(name) => name.as_str().to_ascii_lowercase(),
+            None => continue,
+        };
+        let closing = &cap[1] == "/";
+        if SKIPPED_TAGS.contains(&name.as_str()) && !closing {
+            let end_tag = format!("</{}", name);
+            rest = match rest.to_ascii_lowercase().find(&end_tag) {
+                Some(i) => &rest[i..],
+                None => "",
+            };
+            continue;
+        }
+        let tag = match KEPT_TAGS.iter().find(|tag| **tag == name) {
+            Some(tag) => *tag,
+            None => {
+                // block elements still separate words
+                out.space = true;
+                continue;
+            }
+        };
+        if closing {
+            if let Some(i) = open.iter().rposition(|t| *t == tag) {
+                for t in open.drain(i..).rev() {
+                    out.out.push_str(&format!("</{}>", t));
+                }
+            }
+        } else if !open.iter().any(|t| *t == "a" || *t == "code") {
+            if tag == "a" {
+                let href = attributes(&cap[3])
+                    .into_iter()
+                    .find(|&(ref name, _)| name == "href")
+                    .map(|(_, href)| decode_entities(href.trim()))
+                    .filter(|href| href.starts_with("http://") || href.starts_with("https://"));
+                match href {
+                    Some(href) => {
+                        out.out.push_str(&format!("<a href=\"{}\">", EscapeUrl(&href)))
+                    }
+                    None => continue,
+                }
+            } else {
+                out.out.push_str(&format!("<{}>", tag));
+            }
+            open.push(tag);
+        }
+    }
+    if out.truncated {
+        out.out.push('…');
+    }
+    for tag in open.into_iter().rev() {
+        out.out.push_str(&format!("</{}>", tag));
+    }
+    if out.chars == 0 {
+        return String::new();
+    }
+    out.out
+}
+
+struct Excerpt {
+    out: String,
+    // of text, tags aren't counted
+    chars: usize,
+    max_chars: usize,
+    space: bool,
+    truncated: bool,
+}
+
+impl Excerpt {
+    fn push_text(&mut self, html: &str) {
+        for c in decode_entities(html).chars() {
+            if c.is_whitespace() {
+                self.space = true;
+                continue;
+            }
+            let space = self.space && self.chars > 0;
+            if self.chars + space as usize >= self.max_chars {
+                self.truncated = true;
+                return;
+            }
+            if space {
+                self.out.push(' ');
+                self.chars += 1;
+            }
+            self.space = false;
+            self.out.push_str(&Escape(&c.to_string()).to_string());
+            self.chars += 1;
+        }
+    }
+}
+
+// `<meta property="og:image" content="...">` or `<meta name="..." content="...">`
+pub fn extract_meta(html: &str, name: &str) -> Option<String> {
+    for tag in META.find_iter(head(html)) {
+        let mut key = None;
+        let mut content = None;
+        for (name, value) in attributes(tag.as_str()) {
+            match name.as_str() {
+                "property" | "name" => key = Some(value),
+                "content" => content = Some(value),
+                _ => (),
+            }
+        }
+        if key.map_or(false, |key| key.eq_ignore_ascii_case(name)) {
+            if let Some(content) = content {
+                return Some(decode_entities(content.trim()));
+            }
+        }
+    }
+    None
+}
+
+fn head(html: &str) -> &str {
+    html.find("</head>")
+        .or_else(|| html.find("</HEAD>"))
+        .map_or(html, |end| &html[..end])
+}
+
+fn attributes(tag: &str) -> Vec<(String, &str)> {
+    ATTR.captures_iter(tag)
+        .map(|cap| {
+            let value = cap.get(2)
+                .or_else(|| cap.get(3))
+                .or_else(|| cap.get(4))
+                .map_or("", |m| m.as_str());
+            (cap[1].to_ascii_lowercase(), value)
+        })
+        .collect()
+}
+
+pub fn extract_title(html: &str) -> Option<String> {
+    let title = TITLE.captures(head(html))?;
+    let title = decode_entities(&title[1]);
+    let title = title.split_whitespace().collect::<Vec<_>>().join(" ");
+    if title.is_empty() {
+        None
+    } else {
+        Some(title)
+    }
+}
+
+// `tag`, `#id`, `.class` or a combination like `div.post`
+#[derive(Debug, Default)]
+struct Compound {
+    tag: Option<String>,
+    id: Option<String>,
+    classes: Vec<String>,
+}
+
+impl Compound {
+    fn parse(s: &str) -> Option<Compound> {
+        let mut compound = Compound::default();
+        let mut kind = ' ';
+        let mut name = String::new();
+        for c in s.chars().chain(Some('.')) {
+            if c != '#' && c != '.' {
+                if !(c.is_alphanumeric() || c == '-' || c == '_') {
+                    return None;
+                }
+                name.push(c);
+                continue;
+            }
+            match (kind, name.is_empty()) {
+                (' ', true) => (),
+                (' ', false) => compound.tag = Some(name.to_ascii_lowercase()),
+                ('#', false) => compound.id = Some(name.clone()),
+                ('.', false) => compound.classes.push(name.clone()),
+                _ => return None,
+            }
+            kind = c;
+            name.clear();
+        }
+        Some(compound)
+    }
+
+    fn matches(&self, tag: &str, attrs: &str) -> bool {
+        if self.tag.as_ref().map_or(false, |t| t != tag) {
+            return false;
+        }
+        let attrs = attributes(attrs);
+        let attr = |name: &str| {
+            attrs
+                .iter()
+                .find(|&&(ref n, _)| n == name)
+                .map_or("", |&(_, value)| value)
+        };
+        self.id.as_ref().map_or(true, |id| attr("id") == id)
+            && self.classes
+                .iter()
+                .all(|class| attr("class").split_whitespace().any(|c| c == class))
+    }
+}
+
+// the inner HTML of the first element matching a simple CSS selector,
+// compound selectors separated by spaces select descendants, see `Compound`
+pub fn select<'a>(html: &'a str, selector: &str) -> Option<&'a str> {
+    let mut region = html;
+    for compound in selector.split_whitespace() {
+        region = select_one(region, &Compound::parse(compound)?)?;
+    }
+    Some(region)
+}
+
+fn select_one<'a>(html: &'a str, compound: &Compound) -> Option<&'a str> {
+    let mut open_tag = String::new();
+    let mut inner_start = None;
+    let mut depth = 0;
+    for cap in TAG.captures_iter(html) {
+        let name = match cap.get(2) {
+            Some(name) => name.as_str().to_ascii_lowercase(),
+            None => continue,
+        };
+        let m = cap.get(0).unwrap();
+        let closing = &cap[1] == "/";
+        let self_closing = cap[3].ends_with('/') || VOID_TAGS.contains(&name.as_str());
+        match inner_start {
+            None if !closing && compound.matches(&name, &cap[3]) => {
+                if self_closing {
+                    return Some("");
+                }
+                open_tag = name;
+                inner_start = Some(m.end());
+                depth = 1;
+            }
+            Some(start) if name == open_tag => {
+                if !closing {
+                    depth += if self_closing { 0 } else { 1 };
+                } else if depth == 1 {
+                    return Some(&html[start..m.start()]);
+                } else {
+                    depth -= 1;
+                }
+            }
+            _ => (),
+        }
+    }
+    // never closed
+    inner_start.map(|start| &html[start..])
+}
+
+// `<link rel="canonical" href="...">`, or `og:url` if there's none,
+// only absolute http(s) links are returned
+pub fn extract_canonical(html: &str, base: &str) -> Option<String> {
+    let base = Url::parse(base).ok()?;
+    let canonical = LINK.find_iter(head(html))
+        .filter_map(|tag| {
+            let attrs = attributes(tag.as_str());
+            let is_canonical = attrs.iter().any(|&(ref name, value)| {
+                name == "rel"
+                    && value
+                        .split_whitespace()
+                        .any(|rel| rel.eq_ignore_ascii_case("canonical"))
+            });
+            if !is_canonical {
+                return None;
+            }
+            attrs
+                .into_iter()
+                .find(|&(ref name, _)| name == "href")
+                .map(|(_, href)| decode_entities(href.trim()))
+        })
+        .next()
+        .or_else(|| extract_meta(html, "og:url"))?;
+    let canonical = base.join(&canonical).ok()?;
+    match canonical.scheme() {
+        "http" | "https" => Some(canonical.into_string()),
+        _ => None,
+    }
+}
+
+// `<link rel="alternate" type="application/rss+xml" href="..." title="...">`,
+// returns the absolute links and titles of the feeds a page announces
+pub fn extract_feed_links(html: &str, base: &str) -> Vec<(String, String)> {
+    let base = match Url::parse(base) {
+        Ok(base) => base,
+        Err(_) => return Vec::new(),
+    };
+    let mut links: Vec<(String, String)> = Vec::new();
+    for tag in LINK.find_iter(head(html)) {
+        let mut alternate = false;
+        let mut is_feed = false;
+        let mut href = None;
+        let mut title = String::new();
+        for (name, value) in attributes(tag.as_str()) {
+            match name.as_str() {
+                "rel" => {
+                    alternate = value
+                        .split_whitespace()
+                        .any(|rel| rel.eq_ignore_ascii_case("alternate"))
+                }
+                "type" => {
+                    is_feed = match value.to_ascii_lowercase().as_str() {
+                        "application/rss+xml" | "application/atom+xml" | "application/rdf+xml" => {
+                            true
+                        }
+                        _ => false,
+                    }
+                }
+                "href" => href = Some(decode_entities(value.trim())),
+                "title" => title = decode_entities(value.trim()),
+                _ => (),
+            }
+        }
+        if !alternate || !is_feed {
+            continue;
+        }
+        let link = match href.and_then(|href| base.join(&href).ok()) {
+            Some(link) => link.into_string(),
+            None => continue,
+        };
+        if !links.iter().any(|&(ref l, _)| *l == link) {
+            links.push((link, title));
+        }
+    }
+    links
+}
+
+#[test]
+fn test_extract_meta() {
+    let html = r#"<html><head>
+<meta charset="utf-8">
+<meta property="og:title" content="Title">
+<meta content='http://example.com/a.jpg?a=1&amp;b=2' property='og:image' />
+</head><body><meta property="og:image" content="body"></body></html>"#;
+    assert_eq!(
+        extract_meta(html, "og:image"),
+        Some("http://example.com/a.jpg?a=1&b=2".into())
+    );
+    assert_eq!(extract_meta(html, "og:title"), Some("Title".into()));
+    assert_eq!(extract_meta(html, "og:url"), None);
+}
+
+#[test]
+fn test_extract_canonical() {
+    let html = r#"<html><head>
+<meta property="og:url" content="https://example.com/og">
+<link rel="canonical" href="/articles/1?a=1&amp;b=2">
+</head></html>"#;
+    assert_eq!(
+        extract_canonical(html, "https://agg.example.org/x"),
+        Some("https://agg.example.org/articles/1?a=1&b=2".into())
+    );
+    let html = r#"<head><meta property="og:url" content="https://example.com/og"></head>"#;
+    assert_eq!(
+        extract_canonical(html, "https://agg.example.org/x"),
+        Some("https://example.com/og".into())
+    );
+    assert_eq!(extract_canonical("<head></head>", "https://example.com/"), None);
+}
+
+#[test]
+fn test_extract_feed_links() {
+    let html = r#"<html><head>
+<link rel="stylesheet" href="/style.css">
+<link rel="alternate" type="application/rss+xml" title="Posts &amp; News" href="/feed.xml">
+<link href='https://example.com/atom.xml' type='application/atom+xml' rel='alternate'>
+<link rel="alternate" type="application/rss+xml" href="/feed.xml">
+<link rel="alternate" hreflang="en" href="/en/">
+</head></html>"#;
+    assert_eq!(
+        extract_feed_links(html, "https://example.com/blog/"),
+        vec![
+            ("https://example.com/feed.xml".to_owned(), "Posts & News".to_owned()),
+            ("https://example.com/atom.xml".to_owned(), String::new()),
+        ]
+    );
+}
+
+#[test]
+fn test_decode_entities() {
+    assert_eq!(decode_entities("&amp;lt; &#20013;&#x6587; &nbsp;&foo;"), "&lt; 中文  &foo;");
+}
+
+#[test]
+fn test_excerpt() {
+    let html = "<p>Hello <strong>big</strong>\n\n <span>world</span> &amp; \
+                <a href='https://example.com/?a=1&amp;b=2' class=c>friends</a> \
+                <a href='/relative'>again</a></p><script>alert(1)</script><img src=a.jpg>";
+    assert_eq!(
+        excerpt(html, 100),
+        "Hello <strong>big</strong> world &amp; \
+         <a href=\"https://example.com/?a=1&b=2\">friends</a> again"
+    );
+    assert_eq!(excerpt(html, 13), "Hello <strong>big</strong> wor…");
+    assert_eq!(excerpt("<b>a <i>bc", 3), "<b>a <i>b…</i></b>");
+    assert_eq!(excerpt("<p> </p>", 10), "");
+}
+
+#[test]
+fn test_extract_title() {
+    let html = "<html><head><title>\n  A &amp; B\n</title></head><body></body></html>";
+    assert_eq!(extract_title(html), Some("A & B".into()));
+    assert_eq!(extract_title("<title> </title>"), None);
+}
+
+#[test]
+fn test_select() {
+    let html = r#"<body><div class="nav">menu</div>
+<div id="main" class="content wide"><div class="post">a<div>b</div></div><br>
+<span class="price">42</span></div></body>"#;
+    assert_eq!(select(html, "div.post"), Some("a<div>b</div>"));
+    assert_eq!(select(html, "#main .price"), Some("42"));
+    assert_eq!(select(html, ".content.wide span"), Some("42"));
+    assert_eq!(select(html, "BR"), Some(""));
+    assert_eq!(select(html, "div.missing"), None);
+    assert_eq!(select(html, "div > span"), None);
+    assert_eq!(select(html, "div..post"), None);
+}
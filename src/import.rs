@@ -0,0 +1,41 @@
+use chrono_tz::Tz;
+
+use data::{Database, LinkPreview, SubscriptionOptions};
+use errors::*;
+use json::from_json;
+use opml::from_opml;
+
+// restores the settings of a JSON export right away,
+// the subscriptions are left to the caller, e.g. `subscribe_many` of `/import`
+pub fn import_file(
+    db: &Database,
+    subscriber: i64,
+    body: &[u8],
+) -> Result<Vec<(String, LinkPreview, SubscriptionOptions)>> {
+    let is_json = body
+        .iter()
+        .find(|b| !b.is_ascii_whitespace())
+        .map_or(false, |b| *b == b'{');
+    if !is_json {
+        let links = from_opml(body)?
+            .into_iter()
+            .map(|outline| (outline.link, outline.link_preview, outline.options))
+            .collect();
+        return Ok(links);
+    }
+    let document = from_json(body)?;
+    let settings = document.settings;
+    db.update_chat_settings(subscriber, |s| {
+        s.lang = settings.lang;
+        s.utc_offset = settings.utc_offset;
+        s.timezone = settings.timezone.filter(|name| name.parse::<Tz>().is_ok());
+        s.resubscribe_suggestions = settings.resubscribe_suggestions;
+        s.dedupe_hours = settings.dedupe_hours;
+    })?;
+    let links = document
+        .subscriptions
+        .iter()
+        .map(|s| (s.link.clone(), s.link_preview(), s.options()))
+        .collect();
+    Ok(links)
+}
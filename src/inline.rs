@@ -0,0 +1,86 @@
+use std::collections::HashSet;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+use erased_serde::Serialize;
+use futures::prelude::*;
+use telebot;
+use telebot::functions::*;
+use telebot::objects::InlineQuery;
+
+use data::Database;
+use utils::{Escape, EscapeUrl};
+
+// telegram allows 50
+const MAX_RESULTS: usize = 20;
+// in seconds, the cached items only change with the next fetch
+const CACHE_TIME: i64 = 60;
+
+#[derive(Serialize)]
+struct Article {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    id: String,
+    title: String,
+    description: String,
+    url: String,
+    input_message_content: MessageContent,
+}
+
+#[derive(Serialize)]
+struct MessageContent {
+    message_text: String,
+    parse_mode: &'static str,
+}
+
+// the recent items of the feeds the user subscribed to, whose titles contain
+// every word of the query
+pub fn handle_query(bot: &telebot::RcBot, db: &Database, query: InlineQuery) {
+    let words: Vec<String> = query
+        .query
+        .split_whitespace()
+        .map(str::to_lowercase)
+        .collect();
+    let mut ids = HashSet::new();
+    let mut results: Vec<Box<Serialize>> = Vec::new();
+    'feeds: for feed in db.get_subscribed_feeds(query.from.id).unwrap_or_default() {
+        for item in feed.recent_items {
+            if results.len() >= MAX_RESULTS {
+                break 'feeds;
+            }
+            let title = item.title.to_lowercase();
+            if !words.iter().all(|word| title.contains(word.as_str())) {
+                continue;
+            }
+            let mut hasher = DefaultHasher::new();
+            item.link.hash(&mut hasher);
+            let id = format!("{:x}", hasher.finish());
+            if !ids.insert(id.clone()) {
+                continue;
+            }
+            let text = format!(
+                "<a href=\"{}\">{}</a>",
+                EscapeUrl(&item.link),
+                Escape(&item.title)
+            );
+            results.push(Box::new(Article {
+                kind: "article",
+                id: id,
+                title: item.title,
+                description: feed.title.clone(),
+                url: item.link,
+                input_message_content: MessageContent {
+                    message_text: text,
+                    parse_mode: "HTML",
+                },
+            }));
+        }
+    }
+    let r = bot.answer_inline_query(query.id, results)
+        .is_personal(true)
+        .cache_time(CACHE_TIME)
+        .send()
+        .map(|_| ())
+        .map_err(|e| error!("telebot: {:?}", e));
+    bot.inner.handle.spawn(r);
+}
@@ -0,0 +1,192 @@
+use chrono::Utc;
+use serde_json;
+
+use data::{ChatSettings, Feed, LinkPreview, SubscriptionOptions};
+use errors::*;
+use feed::Headers;
+use messages::Lang;
+
+// bump when a field changes its meaning, adding fields is fine
+const VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize)]
+pub struct Document {
+    pub version: u32,
+    pub chat: i64,
+    pub exported_at: String,
+    pub settings: Settings,
+    pub subscriptions: Vec<Subscription>,
+}
+
+// `expires_at` and `premium_until` are granted by owners, imports ignore them
+#[derive(Serialize, Deserialize)]
+pub struct Settings {
+    pub lang: Lang,
+    pub utc_offset: i32,
+    #[serde(default)]
+    pub timezone: Option<String>,
+    #[serde(default)]
+    pub resubscribe_suggestions: bool,
+    #[serde(default)]
+    pub dedupe_hours: u32,
+    pub expires_at: Option<i64>,
+    pub premium_until: Option<i64>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Subscription {
+    pub link: String,
+    pub title: String,
+    pub link_preview: String,
+    #[serde(default)]
+    pub instant_view_rhash: Option<u64>,
+    pub media: bool,
+    pub og_image: bool,
+    #[serde(default)]
+    pub canonical: bool,
+    #[serde(default)]
+    pub podcast: bool,
+    pub template: Option<String>,
+    #[serde(default)]
+    pub headers: Headers,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub note: Option<String>,
+    #[serde(default)]
+    pub display_title: Option<String>,
+    #[serde(default)]
+    pub summary: Option<usize>,
+    #[serde(default)]
+    pub show_author: bool,
+    #[serde(default)]
+    pub show_date: bool,
+    #[serde(default)]
+    pub category_hashtags: bool,
+    #[serde(default)]
+    pub hashtags: Vec<String>,
+    #[serde(default)]
+    pub snooze_items: Option<usize>,
+    #[serde(default)]
+    pub error_notify: bool,
+    pub expires_at: Option<i64>,
+}
+
+impl Subscription {
+    pub fn link_preview(&self) -> LinkPreview {
+        match (self.link_preview.as_str(), self.instant_view_rhash) {
+            ("on", _) => LinkPreview::On,
+            ("instant_view", Some(rhash)) => LinkPreview::InstantView(rhash),
+            _ => LinkPreview::Off,
+        }
+    }
+
+    pub fn options(&self) -> SubscriptionOptions {
+        SubscriptionOptions {
+            media: self.media,
+            og_image: self.og_image,
+            canonical: self.canonical,
+            podcast: self.podcast,
+            template: self.template.clone(),
+            headers: self.headers.clone(),
+            tags: self.tags.clone(),
+            note: self.note.clone(),
+            title: self.display_title.clone(),
+            summary: self.summary,
+            show_author: self.show_author,
+            show_date: self.show_date,
+            category_hashtags: self.category_hashtags,
+            hashtags: self.hashtags.clone(),
+            snooze_items: self.snooze_items,
+            error_notify: self.error_notify,
+            ..SubscriptionOptions::default()
+        }
+    }
+}
+
+fn link_preview_name(link_preview: LinkPreview) -> &'static str {
+    match link_preview {
+        LinkPreview::Off => "off",
+        LinkPreview::On => "on",
+        LinkPreview::InstantView(_) => "instant_view",
+    }
+}
+
+pub fn to_json(
+    chat: i64,
+    settings: &ChatSettings,
+    subscriptions: &[(Feed, LinkPreview, SubscriptionOptions)],
+) -> String {
+    let mut subscriptions: Vec<Subscription> = subscriptions
+        .iter()
+        .map(|&(ref feed, link_preview, ref options)| Subscription {
+            link: feed.link.clone(),
+            title: feed.title.clone(),
+            link_preview: link_preview_name(link_preview).to_owned(),
+            instant_view_rhash: match link_preview {
+                LinkPreview::InstantView(rhash) => Some(rhash),
+                _ => None,
+            },
+            media: options.media,
+            og_image: options.og_image,
+            canonical: options.canonical,
+            podcast: options.podcast,
+            template: options.template.clone(),
+            headers: options.headers.clone(),
+            tags: options.tags.clone(),
+            note: options.note.clone(),
+            display_title: options.title.clone(),
+            summary: options.summary,
+            show_author: options.show_author,
+            show_date: options.show_date,
+            category_hashtags: options.category_hashtags,
+            hashtags: options.hashtags.clone(),
+            snooze_items: options.snooze_items,
+            error_notify: options.error_notify,
+            expires_at: options.expires_at,
+        })
+        .collect();
+    // stable order, so exports can be diffed
+    subscriptions.sort_by(|a, b| a.link.cmp(&b.link));
+    let document = Document {
+        version: VERSION,
+        chat: chat,
+        exported_at: Utc::now().to_rfc3339(),
+        settings: Settings {
+            lang: settings.lang,
+            utc_offset: settings.utc_offset,
+            timezone: settings.timezone.clone(),
+            resubscribe_suggestions: settings.resubscribe_suggestions,
+            dedupe_hours: settings.dedupe_hours,
+            expires_at: settings.expires_at,
+            premium_until: settings.premium_until,
+        },
+        subscriptions: subscriptions,
+    };
+    serde_json::to_string_pretty(&document).unwrap()
+}
+
+pub fn from_json(json: &[u8]) -> Result<Document> {
+    let document: Document =
+        serde_json::from_slice(json).chain_err(|| ErrorKind::InvalidExport)?;
+    if document.version > VERSION {
+        return Err(ErrorKind::InvalidExport.into());
+    }
+    Ok(document)
+}
+
+#[test]
+fn test_json_round_trip() {
+    let mut feed = Feed::default();
+    feed.title = "title".into();
+    feed.link = "link".into();
+    let mut options = SubscriptionOptions::default();
+    options.template = Some("{title}".into());
+    let subscriptions = vec![(feed, LinkPreview::InstantView(42), options.clone())];
+    let json = to_json(1, &ChatSettings::default(), &subscriptions);
+    let document = from_json(json.as_bytes()).unwrap();
+    assert_eq!(document.subscriptions.len(), 1);
+    let subscription = &document.subscriptions[0];
+    assert_eq!(subscription.link_preview(), LinkPreview::InstantView(42));
+    assert_eq!(subscription.options(), options);
+}
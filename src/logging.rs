@@ -0,0 +1,103 @@
+use std::cell::RefCell;
+use std::env;
+
+use chrono::Utc;
+use env_logger::LogBuilder;
+use log::{LogLevelFilter, LogRecord};
+use serde_json::{self, Map, Value};
+
+use errors::*;
+
+thread_local! {
+    // attached to the records logged inside `with_fields`
+    static FIELDS: RefCell<Vec<(&'static str, String)>> = RefCell::new(Vec::new());
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    // `LEVEL:target: message key=value...`
+    Plain,
+    // one object per line, with the fields as keys
+    Json,
+}
+
+impl Format {
+    pub fn from_name(name: &str) -> Option<Format> {
+        match name {
+            "plain" => Some(Format::Plain),
+            "json" => Some(Format::Json),
+            _ => None,
+        }
+    }
+}
+
+// `filters` is like `RUST_LOG`, e.g. `info,rssbot::fetcher=debug`,
+// `RUST_LOG` is used without them
+pub fn init(format: Format, filters: Option<&str>) -> Result<()> {
+    let mut builder = LogBuilder::new();
+    builder.filter(None, LogLevelFilter::Info);
+    if let Some(filters) = filters.map(str::to_owned).or_else(|| env::var("RUST_LOG").ok()) {
+        builder.parse(&filters);
+    }
+    builder.format(move |record: &LogRecord| {
+        FIELDS.with(|fields| render(format, record, &fields.borrow()))
+    });
+    builder
+        .init()
+        .map_err(|e| ErrorKind::InvalidConfig(format!("failed to set up logging: {}", e)).into())
+}
+
+// logs everything `log` does with `fields` attached, e.g.
+// `with_fields(&[("feed", link.as_str())], || warn!("failed to fetch: {}", e))`
+pub fn with_fields<F: FnOnce()>(fields: &[(&'static str, &str)], log: F) {
+    let len = FIELDS.with(|f| {
+        let mut f = f.borrow_mut();
+        let len = f.len();
+        f.extend(fields.iter().map(|&(key, value)| (key, value.to_owned())));
+        len
+    });
+    log();
+    FIELDS.with(|f| f.borrow_mut().truncate(len));
+}
+
+// the name of the variant, without its values
+pub fn error_kind(e: &Error) -> String {
+    let kind = format!("{:?}", e.kind());
+    let end = kind
+        .find(|c: char| !c.is_alphanumeric() && c != '_')
+        .unwrap_or_else(|| kind.len());
+    kind[..end].to_owned()
+}
+
+fn render(format: Format, record: &LogRecord, fields: &[(&'static str, String)]) -> String {
+    match format {
+        Format::Plain => {
+            let mut line = format!("{}:{}: {}", record.level(), record.target(), record.args());
+            for &(key, ref value) in fields {
+                line.push_str(&format!(" {}={}", key, value));
+            }
+            line
+        }
+        Format::Json => {
+            let mut object = Map::new();
+            let entries = vec![
+                ("time", Utc::now().to_rfc3339()),
+                ("level", record.level().to_string()),
+                ("target", record.target().to_owned()),
+                ("message", record.args().to_string()),
+            ];
+            for (key, value) in entries.into_iter().chain(fields.iter().cloned()) {
+                object.insert(key.to_owned(), Value::String(value));
+            }
+            serde_json::to_string(&Value::Object(object)).unwrap()
+        }
+    }
+}
+
+#[test]
+fn test_error_kind() {
+    let e: Error = ErrorKind::Http(404).into();
+    assert_eq!(error_kind(&e), "Http");
+    let e: Error = ErrorKind::NotSubscribed.into();
+    assert_eq!(error_kind(&e), "NotSubscribed");
+}
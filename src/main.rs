@@ -11,78 +11,269 @@ extern crate serde_json;
 extern crate serde_derive;
 extern crate curl;
 extern crate futures_await as futures;
+extern crate hyper;
 extern crate quick_xml;
 extern crate telebot;
 extern crate tokio_core;
 extern crate tokio_curl;
+extern crate tokio_signal;
 #[macro_use]
 extern crate lazy_static;
 extern crate chrono;
+extern crate chrono_tz;
+extern crate encoding_rs;
+extern crate erased_serde;
 extern crate regex;
 extern crate url;
 
-use futures::Stream;
+use std::rc::Rc;
+
+use futures::{Future, Stream};
 use tokio_core::reactor::Core;
 
+mod abtest;
+mod admin;
+mod approval;
+mod backup;
+mod budget;
 mod checker;
+mod cli;
 mod cmdhandles;
+mod config;
+mod csv;
 mod data;
+mod deadfeed;
+mod discovery;
+mod erase;
 mod errors;
 mod feed;
 mod fetcher;
+mod html;
+mod import;
+mod inline;
+mod json;
+mod logging;
+mod messages;
 mod opml;
+mod pipeline;
+mod ratelimit;
+mod sender;
+mod shortcut;
+mod shutdown;
+mod template;
 mod utils;
+mod watch;
+mod youtube;
 
 fn main() {
     let args: Vec<String> = std::env::args().collect();
-    if args.len() < 3 {
-        eprintln!("Usage: {} DATAFILE TELEGRAM-BOT-TOKEN", args[0]);
-        std::process::exit(1);
+    if args.get(1).map(|s| s.as_str()) == Some("db") {
+        if let Err(e) = cli::run_db(&args) {
+            utils::print_error(&e);
+            std::process::exit(1);
+        }
+        return;
     }
-    let datafile = &args[1];
-    let token = &args[2];
-    let period = args.get(3)
-        .map(|s| {
-            s.parse().unwrap_or_else(|_| {
-                eprintln!("period must be unsigned");
-                std::process::exit(1);
-            })
-        })
-        .unwrap_or(300);
+    if args.get(1).map(|s| s.as_str()) == Some("check") {
+        if let Err(e) = cli::run_check(&args) {
+            utils::print_error(&e);
+            std::process::exit(1);
+        }
+        return;
+    }
+    let config = config::Config::from_args(&args).unwrap_or_else(|e| {
+        utils::print_error(&e);
+        eprintln!(
+            "Usage: {} DATAFILE TELEGRAM-BOT-TOKEN [PERIOD] [--config FILE] [--send-workers N] [--admin-api [HOST:]PORT] \
+             [--self-domain DOMAIN]... [--host-budget N] [--max-concurrent-fetches N] \
+             [--backup-keep N] [--backup-interval SECS] [--owner USER-ID]... [--max-item-age DAYS] \
+             [--max-subscriptions N] [--premium-max-subscriptions N] \
+             [--log-format plain|json] [--log-level FILTERS]",
+            args[0]
+        );
+        eprintln!("       {} db DATAFILE ls|grep|rm|subscribers|export|import|vacuum ...", args[0]);
+        eprintln!("       {} check URL [--user-agent UA] [--proxy URL]", args[0]);
+        std::process::exit(1);
+    });
+
+    logging::init(
+        config.log_format().unwrap(),
+        config.log_level.as_ref().map(|s| s.as_str()),
+    ).unwrap_or_else(|e| {
+        utils::print_error(&e);
+        std::process::exit(1);
+    });
 
-    let db = data::Database::open(datafile)
+    let db = data::Database::open(&config.datafile)
         .map_err(|e| {
-            eprintln!("error: {}", e);
-            for e in e.iter().skip(1) {
-                eprintln!("caused by: {}", e);
-            }
-            if let Some(backtrace) = e.backtrace() {
-                eprintln!("backtrace: {:?}", backtrace);
-            }
+            utils::print_error(&e);
             std::process::exit(1);
         })
         .unwrap();
 
-    env_logger::init().unwrap();
+    let budget = Rc::new(budget::HostBudget::new(
+        config.host_budget,
+        config.period as i64,
+    ));
+    apply_config(&config, &db, &budget).unwrap_or_else(|e| {
+        utils::print_error(&e);
+        std::process::exit(1);
+    });
 
     let mut lp = Core::new().unwrap();
     let lphandle = lp.handle();
-    let bot = lp.run(telebot::RcBot::new(lphandle, token))
+    let bot = lp.run(telebot::RcBot::new(lphandle, &config.token))
         .expect("failed to initialize bot")
         .update_interval(200);
 
-    cmdhandles::register_commands(&bot, &db, lp.handle());
+    let sender = sender::Sender::spawn(bot.clone(), db.clone(), config.send_workers);
+
+    cmdhandles::register_commands(&bot, &db, lp.handle(), &config, &sender);
+
+    if let Some(addr) = config.admin_api_addr().unwrap() {
+        admin::spawn_admin_api(addr, bot.clone(), db.clone(), sender.clone())
+            .expect("failed to start admin api");
+    }
+
+    spawn_reloader(args.clone(), db.clone(), budget.clone(), lp.handle());
+    checker::spawn_expiry_checker(db.clone(), sender.clone(), lp.handle());
+    checker::spawn_popularity_checker(db.clone(), sender.clone(), lp.handle());
+
+    fetcher::spawn_fetcher(
+        bot.clone(),
+        db.clone(),
+        config.period,
+        sender.clone(),
+        budget.clone(),
+        config.max_concurrent_fetches,
+    );
 
-    fetcher::spawn_fetcher(bot.clone(), db.clone(), period);
+    if config.backup_keep > 0 {
+        backup::spawn_backup(db.clone(), config.backup_interval, lp.handle());
+    }
 
-    checker::spawn_subscriber_alive_checker(bot.clone(), db, lp.handle());
+    checker::spawn_subscriber_alive_checker(bot.clone(), db.clone(), lp.handle());
 
+    let self_hosts = config.self_hosts();
+    let db2 = db.clone();
     let s = bot.get_stream()
-        .map(|_| ())
+        .map(move |(bot, update)| {
+            // a group upgraded to a supergroup gets a new ID, both of them are told
+            if let Some(ref msg) = update.message {
+                let migration = match (msg.migrate_to_chat_id, msg.migrate_from_chat_id) {
+                    (Some(to), _) => Some((msg.chat.id, to)),
+                    (None, Some(from)) => Some((from, msg.chat.id)),
+                    (None, None) => None,
+                };
+                if let Some((from, to)) = migration {
+                    info!("chat {} migrated to {}", from, to);
+                    db.update_subscriber(from, to);
+                }
+                if db.is_authorized(msg.chat.id, msg.from.as_ref().map(|user| user.id)) {
+                    discovery::handle_message(&bot, &db, msg, &self_hosts);
+                }
+            }
+            if let Some(query) = update.callback_query {
+                let prefix = query
+                    .data
+                    .as_ref()
+                    .and_then(|data| data.split(':').next())
+                    .map(str::to_owned);
+                match prefix.as_ref().map(|s| s.as_str()) {
+                    Some("ab") => abtest::handle_callback(&bot, &db, query),
+                    Some("approve") => approval::handle_callback(&bot, &db, query, &self_hosts),
+                    Some("dead") => deadfeed::handle_callback(&bot, &db, query),
+                    Some("erase") => erase::handle_callback(&bot, &db, query),
+                    Some("sub") => discovery::handle_callback(&bot, &db, query, &self_hosts),
+                    _ => (),
+                }
+            }
+            if let Some(query) = update.inline_query {
+                if db.is_authorized(query.from.id, Some(query.from.id)) {
+                    inline::handle_query(&bot, &db, query);
+                }
+            }
+        })
         .or_else(|e| {
             error!("telebot: {:?}", e);
             Ok::<(), ()>(())
         })
         .for_each(|_| Ok(()));
-    lp.run(s).unwrap();
+    // stops taking updates on a signal, the bot stream never ends by itself
+    let _ = lp.run(s.select2(shutdown::signal(&lp.handle())));
+
+    info!("shutting down");
+    let _ = lp.run(shutdown::drain(sender, lp.handle()));
+    if let Err(e) = db2.flush() {
+        utils::print_error(&e);
+        std::process::exit(1);
+    }
+}
+
+// the settings which can change while running, see `spawn_reloader`
+fn apply_config(
+    config: &config::Config,
+    db: &data::Database,
+    budget: &budget::HostBudget,
+) -> errors::Result<()> {
+    let shortcuts = config.shortcuts()?;
+    let iv_rhashes = config.iv_rhashes()?;
+    let rewrite_rules = config.rewrite_rules()?;
+    feed::set_default_proxy(config.proxy.clone());
+    utils::set_user_agent(config.user_agent.clone());
+    shortcut::set_shortcuts(shortcuts);
+    fetcher::set_period(config.period);
+    budget.set_limit(config.host_budget, config.period as i64);
+    db.set_owners(config.owners.clone());
+    db.set_private(config.allowed_chats.clone(), config.passphrase.clone());
+    db.set_approve_new_domains(config.approve_new_domains);
+    db.set_backup_keep(config.backup_keep);
+    db.set_max_item_age(config.max_item_age);
+    db.set_dead_feed_days(config.dead_feed_days);
+    db.set_group_admin_only(config.group_admin_only);
+    db.set_config_read_only(config.read_only, config.maintenance_message.clone());
+    ratelimit::set_rate(config.commands_per_minute);
+    pipeline::set_tracking(config.strip_tracking, &config.tracking_params);
+    db.set_seen_items(config.seen_items_days, config.seen_items_max);
+    db.set_seen_file(config.seen_file);
+    db.set_quota(config.quota());
+    db.set_config_iv_rhashes(iv_rhashes);
+    db.set_config_rewrite_rules(rewrite_rules);
+    db.set_domain_lists(config.allowed_domains.clone(), config.blocked_domains.clone());
+    Ok(())
+}
+
+// reads the command line and the config file again on SIGHUP, an invalid config
+// is logged and the running one is kept
+#[cfg(unix)]
+fn spawn_reloader(
+    args: Vec<String>,
+    db: data::Database,
+    budget: Rc<budget::HostBudget>,
+    handle: tokio_core::reactor::Handle,
+) {
+    use tokio_signal::unix::{Signal, SIGHUP};
+
+    let reloader = Signal::new(SIGHUP, &handle)
+        .flatten_stream()
+        .for_each(move |_| {
+            let r = config::Config::from_args(&args)
+                .and_then(|config| apply_config(&config, &db, &budget));
+            match r {
+                Ok(()) => info!("config reloaded"),
+                Err(e) => utils::log_error(&e),
+            }
+            Ok(())
+        })
+        .map_err(|e| error!("failed to listen for SIGHUP: {}", e));
+    handle.spawn(reloader);
+}
+
+#[cfg(not(unix))]
+fn spawn_reloader(
+    _args: Vec<String>,
+    _db: data::Database,
+    _budget: Rc<budget::HostBudget>,
+    _handle: tokio_core::reactor::Handle,
+) {
 }
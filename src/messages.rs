@@ -0,0 +1,1554 @@
+use std::fmt::Display;
+
+use chrono::{DateTime, FixedOffset, TimeZone, Utc};
+use chrono_tz::Tz;
+
+use data::{LinkPreview, RewriteRule, Stats};
+use errors;
+use feed::NO_PROXY;
+use utils::{truncate_message, Escape, EscapeUrl};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Lang {
+    #[serde(rename = "en")]
+    En,
+    #[serde(rename = "zh")]
+    Zh,
+}
+
+pub const LANGS: &[Lang] = &[Lang::En, Lang::Zh];
+
+impl Default for Lang {
+    fn default() -> Lang {
+        Lang::En
+    }
+}
+
+// how dates are shown to a chat
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Locale {
+    pub lang: Lang,
+    // seconds east of UTC
+    pub utc_offset: i32,
+    // takes precedence over `utc_offset`, follows daylight saving time
+    pub timezone: Option<Tz>,
+}
+
+impl Locale {
+    pub fn format_date(&self, date: &DateTime<Utc>) -> String {
+        match self.timezone {
+            Some(timezone) => self.format_local(date.with_timezone(&timezone)),
+            None => {
+                let offset =
+                    FixedOffset::east_opt(self.utc_offset).unwrap_or(FixedOffset::east(0));
+                self.format_local(date.with_timezone(&offset))
+            }
+        }
+    }
+
+    // how long ago within a day, the date otherwise
+    pub fn format_age(&self, date: &DateTime<Utc>, now: &DateTime<Utc>) -> String {
+        let secs = now.timestamp() - date.timestamp();
+        if secs < 0 || secs >= 24 * 60 * 60 {
+            return self.format_date(date);
+        }
+        let (minutes, hours) = (secs / 60, secs / (60 * 60));
+        match self.lang {
+            Lang::En if hours > 0 => format!("{}h ago", hours),
+            Lang::En if minutes > 0 => format!("{}m ago", minutes),
+            Lang::En => "just now".to_string(),
+            Lang::Zh if hours > 0 => format!("{} 小时前", hours),
+            Lang::Zh if minutes > 0 => format!("{} 分钟前", minutes),
+            Lang::Zh => "刚刚".to_string(),
+        }
+    }
+
+    fn format_local<T: TimeZone>(&self, date: DateTime<T>) -> String
+    where
+        T::Offset: Display,
+    {
+        match self.lang {
+            Lang::En => date.format("%b %-d, %Y %-I:%M %p").to_string(),
+            Lang::Zh => date.format("%Y年%-m月%-d日 %H:%M").to_string(),
+        }
+    }
+}
+
+pub fn format_utc_offset(offset: i32) -> String {
+    let sign = if offset < 0 { '-' } else { '+' };
+    let offset = offset.abs();
+    format!("UTC{}{:02}:{:02}", sign, offset / 3600, offset % 3600 / 60)
+}
+
+fn feed_label(link: &str, title: &str) -> String {
+    format!("「<a href=\"{}\">{}</a>」", EscapeUrl(link), Escape(title))
+}
+
+impl Lang {
+    pub fn from_code(code: &str) -> Option<Lang> {
+        let code = code.to_lowercase();
+        LANGS.iter().cloned().find(|lang| lang.code() == code)
+    }
+
+    pub fn code(self) -> &'static str {
+        match self {
+            Lang::En => "en",
+            Lang::Zh => "zh",
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Lang::En => "English",
+            Lang::Zh => "中文",
+        }
+    }
+
+    pub fn usage(self, syntax: &str) -> String {
+        match self {
+            Lang::En => format!("Usage: {}", syntax),
+            Lang::Zh => format!("用法: {}", syntax),
+        }
+    }
+
+    pub fn unsubthis_usage(self) -> String {
+        match self {
+            Lang::En => "Usage: \
+                         Use this command as a reply to RSS Feed messages you want to unsubscribe, \
+                         doesn't work on channels"
+                .to_string(),
+            Lang::Zh => "用法: 使用此命令回复想要退订的 RSS 消息即可退订, 不支持 Channel".to_string(),
+        }
+    }
+
+    // the reason of a failed fetch, shown to users
+    pub fn error_reason(self, e: &errors::Error) -> String {
+        use errors::ErrorKind::*;
+        match (self, e.kind()) {
+            (Lang::En, &Curl(ref e)) => format!("Network error ({})", e),
+            (Lang::Zh, &Curl(ref e)) => format!("网络错误 ({})", e),
+            (Lang::En, &Utf8(ref e)) => format!("Encoding error ({})", e),
+            (Lang::Zh, &Utf8(ref e)) => format!("编码错误 ({})", e),
+            (lang, &Xml(ref e)) => {
+                let s = e.to_string();
+                let msg = truncate_message(&s, 500);
+                match lang {
+                    Lang::En => format!("Parsing error ({})", msg),
+                    Lang::Zh => format!("解析错误 ({})", msg),
+                }
+            }
+            (Lang::Zh, &EmptyFeed) => "RSS 为空或无效".to_string(),
+            (Lang::Zh, &InvalidExport) => "不是有效的导出文件".to_string(),
+            (Lang::Zh, &TooManyRedirects) => "重定向次数过多".to_string(),
+            (Lang::Zh, &EOF) => "意外的 EOF".to_string(),
+            (lang, &QuotaExceeded(limit)) => lang.quota_exceeded(limit),
+            (Lang::Zh, &HeadersTaken) => "该 RSS 的请求头已由其他订阅者设置".to_string(),
+            (Lang::Zh, &SelfReferentialFeed) => "该 RSS 指向 Bot 自身".to_string(),
+            (Lang::Zh, &DomainBlocked(ref domain)) => format!("该 Bot 不接受来自 {} 的 RSS", domain),
+            (Lang::Zh, &ApprovalRequired(ref domain)) => {
+                format!("订阅 {} 的 RSS 需要 Bot 所有者批准", domain)
+            }
+            _ => format!("{}", e),
+        }
+    }
+
+    pub fn unexpected_error(self, e: &errors::Error) -> String {
+        match self {
+            Lang::En => format!("error: {}", e),
+            Lang::Zh => format!("错误: {}", e),
+        }
+    }
+
+    pub fn subscription_list(self) -> String {
+        match self {
+            Lang::En => "Subscription list:".to_string(),
+            Lang::Zh => "订阅列表:".to_string(),
+        }
+    }
+
+    pub fn subscription_list_empty(self) -> String {
+        match self {
+            Lang::En => "Subscription list is empty".to_string(),
+            Lang::Zh => "订阅列表为空".to_string(),
+        }
+    }
+
+    pub fn feed_status_head(self) -> String {
+        match self {
+            Lang::En => "Status of the subscriptions:".to_string(),
+            Lang::Zh => "订阅状态:".to_string(),
+        }
+    }
+
+    pub fn feed_status(
+        self,
+        link: &str,
+        title: &str,
+        error_count: u32,
+        last_fetched: Option<String>,
+        last_error: Option<&str>,
+        note: Option<&str>,
+    ) -> String {
+        let icon = if error_count == 0 { "✅" } else { "⚠️" };
+        let last_fetched = last_fetched.unwrap_or_else(|| match self {
+            Lang::En => "never".to_string(),
+            Lang::Zh => "从未".to_string(),
+        });
+        let mut s = match self {
+            Lang::En => format!(
+                "{} {}last fetched: {}, errors: {}",
+                icon,
+                feed_label(link, title),
+                last_fetched,
+                error_count
+            ),
+            Lang::Zh => format!(
+                "{} {}上次抓取: {}, 错误次数: {}",
+                icon,
+                feed_label(link, title),
+                last_fetched,
+                error_count
+            ),
+        };
+        if let Some(error) = last_error {
+            let error = Escape(&truncate_message(error, 200)).to_string();
+            match self {
+                Lang::En => s.push_str(&format!(", last error: {}", error)),
+                Lang::Zh => s.push_str(&format!(", 最近的错误: {}", error)),
+            }
+        }
+        if let Some(note) = note {
+            s.push_str(&format!(" ({})", Escape(note)));
+        }
+        s
+    }
+
+    pub fn snoozed(self, items: usize) -> String {
+        match self {
+            Lang::En => format!("New items will be delivered together once there are {}", items),
+            Lang::Zh => format!("新条目将在累积 {} 条后一起推送", items),
+        }
+    }
+
+    pub fn snooze_removed(self) -> String {
+        match self {
+            Lang::En => "New items will be delivered right away".to_string(),
+            Lang::Zh => "新条目将立即推送".to_string(),
+        }
+    }
+
+    pub fn summary_set(self, chars: usize) -> String {
+        match self {
+            Lang::En => format!("Messages will include up to {} characters of each item", chars),
+            Lang::Zh => format!("消息将附带每个条目最多 {} 字的摘要", chars),
+        }
+    }
+
+    pub fn summary_off(self) -> String {
+        match self {
+            Lang::En => "Messages will only include titles".to_string(),
+            Lang::Zh => "消息将只包含标题".to_string(),
+        }
+    }
+
+    pub fn hashtags_updated(self, categories: bool, hashtags: &[String]) -> String {
+        let hashtags = hashtags.join(" ");
+        match (self, categories, hashtags.is_empty()) {
+            (Lang::En, false, true) => "Hashtags removed".to_string(),
+            (Lang::En, false, false) => format!("Messages will end with {}", hashtags),
+            (Lang::En, true, true) => "Messages will end with the categories of items".to_string(),
+            (Lang::En, true, false) => {
+                format!("Messages will end with {} and the categories of items", hashtags)
+            }
+            (Lang::Zh, false, true) => "已移除话题标签".to_string(),
+            (Lang::Zh, false, false) => format!("消息末尾将附加 {}", hashtags),
+            (Lang::Zh, true, true) => "消息末尾将附加条目的分类标签".to_string(),
+            (Lang::Zh, true, false) => format!("消息末尾将附加 {} 和条目的分类标签", hashtags),
+        }
+    }
+
+    pub fn display_updated(self, author: bool, date: bool) -> String {
+        match (self, author, date) {
+            (Lang::En, true, true) => "Titles will be followed by the author and date".to_string(),
+            (Lang::En, true, false) => "Titles will be followed by the author".to_string(),
+            (Lang::En, false, true) => "Titles will be followed by the date".to_string(),
+            (Lang::En, false, false) => "Only titles will be shown".to_string(),
+            (Lang::Zh, true, true) => "标题后将显示作者和发布时间".to_string(),
+            (Lang::Zh, true, false) => "标题后将显示作者".to_string(),
+            (Lang::Zh, false, true) => "标题后将显示发布时间".to_string(),
+            (Lang::Zh, false, false) => "将只显示标题".to_string(),
+        }
+    }
+
+    pub fn renamed(self, link: &str, title: &str) -> String {
+        match self {
+            Lang::En => format!("{}Renamed successfully", feed_label(link, title)),
+            Lang::Zh => format!("{}重命名成功", feed_label(link, title)),
+        }
+    }
+
+    pub fn prefix_set(self, prefix: &str) -> String {
+        match self {
+            Lang::En => format!("Messages of this subscription will start with {}", prefix),
+            Lang::Zh => format!("该订阅的消息将以 {} 开头", prefix),
+        }
+    }
+
+    pub fn prefix_removed(self) -> String {
+        match self {
+            Lang::En => "Prefix removed".to_string(),
+            Lang::Zh => "前缀已删除".to_string(),
+        }
+    }
+
+    pub fn prefix_too_long(self, max: usize) -> String {
+        match self {
+            Lang::En => format!("The prefix can be at most {} characters", max),
+            Lang::Zh => format!("前缀最多 {} 个字符", max),
+        }
+    }
+
+    pub fn headers_updated(self, link: &str, title: &str, names: &[&str]) -> String {
+        match (self, names.is_empty()) {
+            (Lang::En, true) => format!("{}No extra headers are sent", feed_label(link, title)),
+            (Lang::En, false) => format!(
+                "{}Sent with headers: {}",
+                feed_label(link, title),
+                Escape(&names.join(", "))
+            ),
+            (Lang::Zh, true) => format!("{}不再发送额外的请求头", feed_label(link, title)),
+            (Lang::Zh, false) => format!(
+                "{}抓取时发送的请求头: {}",
+                feed_label(link, title),
+                Escape(&names.join(", "))
+            ),
+        }
+    }
+
+    pub fn pin_rule_set(self, keyword: &str) -> String {
+        match self {
+            Lang::En => format!(
+                "Items whose title contains \"{}\" will be pinned, \
+                 the bot needs the permission to pin messages",
+                keyword
+            ),
+            Lang::Zh => format!("标题包含「{}」的条目将被置顶, Bot 需要有置顶消息的权限", keyword),
+        }
+    }
+
+    pub fn pin_rule_removed(self) -> String {
+        match self {
+            Lang::En => "Pin rule removed".to_string(),
+            Lang::Zh => "置顶规则已删除".to_string(),
+        }
+    }
+
+    pub fn note_set(self) -> String {
+        match self {
+            Lang::En => "Note saved".to_string(),
+            Lang::Zh => "备注已保存".to_string(),
+        }
+    }
+
+    pub fn note_removed(self) -> String {
+        match self {
+            Lang::En => "Note removed".to_string(),
+            Lang::Zh => "备注已删除".to_string(),
+        }
+    }
+
+    pub fn find_results(self, count: usize) -> String {
+        match self {
+            Lang::En => format!("Found {} RSS:", count),
+            Lang::Zh => format!("找到 {} 个 RSS:", count),
+        }
+    }
+
+    pub fn find_nothing(self) -> String {
+        match self {
+            Lang::En => "No subscribed RSS matches".to_string(),
+            Lang::Zh => "没有匹配的已订阅 RSS".to_string(),
+        }
+    }
+
+    pub fn subscription_list_tagged(self, tag: &str) -> String {
+        match self {
+            Lang::En => format!("Subscription list of {}:", Escape(tag)),
+            Lang::Zh => format!("{} 的订阅列表:", Escape(tag)),
+        }
+    }
+
+    pub fn tag_empty(self, tag: &str) -> String {
+        match self {
+            Lang::En => format!("No RSS is tagged {}", tag),
+            Lang::Zh => format!("没有标签为 {} 的 RSS", tag),
+        }
+    }
+
+    pub fn tag_added(self, tag: &str) -> String {
+        match self {
+            Lang::En => format!("Tagged {}", tag),
+            Lang::Zh => format!("已添加标签 {}", tag),
+        }
+    }
+
+    pub fn tag_removed(self, tag: &str) -> String {
+        match self {
+            Lang::En => format!("Removed tag {}", tag),
+            Lang::Zh => format!("已移除标签 {}", tag),
+        }
+    }
+
+    pub fn subscription_list_new(self) -> String {
+        match self {
+            Lang::En => "RSS with new items since the last check:".to_string(),
+            Lang::Zh => "上次查看后有更新的 RSS:".to_string(),
+        }
+    }
+
+    pub fn no_new_items(self) -> String {
+        match self {
+            Lang::En => "No RSS has new items since the last check".to_string(),
+            Lang::Zh => "上次查看后没有 RSS 更新".to_string(),
+        }
+    }
+
+    pub fn processing(self) -> String {
+        match self {
+            Lang::En => "Please wait while processing".to_string(),
+            Lang::Zh => "处理中, 请稍候".to_string(),
+        }
+    }
+
+    pub fn subscription_failed(self, reason: &str) -> String {
+        match self {
+            Lang::En => format!("Subscription failed: {}", reason),
+            Lang::Zh => format!("订阅失败: {}", reason),
+        }
+    }
+
+    pub fn already_subscribed(self) -> String {
+        match self {
+            Lang::En => "Subscribed RSS".to_string(),
+            Lang::Zh => "已订阅过的 RSS".to_string(),
+        }
+    }
+
+    pub fn subscriptions_copied(self, added: usize, existing: usize) -> String {
+        match self {
+            Lang::En => format!(
+                "Subscribed to {} feeds, {} were already subscribed",
+                added, existing
+            ),
+            Lang::Zh => format!("已订阅 {} 个 RSS, {} 个之前已订阅", added, existing),
+        }
+    }
+
+    pub fn mirror_added(self, target: &str) -> String {
+        match self {
+            Lang::En => format!("Updates of this subscription will also be posted to {}", target),
+            Lang::Zh => format!("该订阅的更新将同时推送到 {}", target),
+        }
+    }
+
+    pub fn mirror_removed(self, target: &str) -> String {
+        match self {
+            Lang::En => format!("Stopped posting updates of this subscription to {}", target),
+            Lang::Zh => format!("已停止将该订阅的更新推送到 {}", target),
+        }
+    }
+
+    pub fn already_mirrored(self, target: &str) -> String {
+        match self {
+            Lang::En => format!("Already posted to {}", target),
+            Lang::Zh => format!("已经在推送到 {}", target),
+        }
+    }
+
+    pub fn not_mirrored(self, target: &str) -> String {
+        match self {
+            Lang::En => format!("Not posted to {}", target),
+            Lang::Zh => format!("没有推送到 {}", target),
+        }
+    }
+
+    pub fn mirror_to_itself(self) -> String {
+        match self {
+            Lang::En => "The target channel is the subscriber itself".to_string(),
+            Lang::Zh => "目标频道就是订阅者本身".to_string(),
+        }
+    }
+
+    pub fn not_subscribed(self) -> String {
+        match self {
+            Lang::En => "Unsubscribed RSS".to_string(),
+            Lang::Zh => "未订阅过的 RSS".to_string(),
+        }
+    }
+
+    pub fn import_file_missing(self) -> String {
+        match self {
+            Lang::En => "Reply to an OPML file with this command".to_string(),
+            Lang::Zh => "请使用此命令回复 OPML 文件".to_string(),
+        }
+    }
+
+    pub fn import_empty(self) -> String {
+        match self {
+            Lang::En => "No RSS found in the file".to_string(),
+            Lang::Zh => "文件中没有 RSS".to_string(),
+        }
+    }
+
+    // head of the reply to a command given several RSS
+    pub fn summary(self, succeeded: usize, total: usize) -> String {
+        match self {
+            Lang::En => format!("{} of {} succeeded:", succeeded, total),
+            Lang::Zh => format!("{} 个中 {} 个成功:", total, succeeded),
+        }
+    }
+
+    pub fn summary_succeeded(self, link: &str, title: &str) -> String {
+        format!("✅ {}", feed_label(link, title))
+    }
+
+    pub fn summary_failed(self, link: &str, reason: &str) -> String {
+        format!("❌ {}: {}", Escape(link), Escape(reason))
+    }
+
+    pub fn subscribed(self, link: &str, title: &str, updated: bool, lp: LinkPreview) -> String {
+        let label = feed_label(link, title);
+        let lp_status = self.link_preview_status(lp);
+        match self {
+            Lang::En => {
+                let action = if updated { "Subscription updated" } else { "Subscribed" };
+                format!("{}{} successfully\n{}", label, action, lp_status)
+            }
+            Lang::Zh => {
+                let action = if updated { "订阅更新" } else { "订阅" };
+                format!("{}{}成功\n{}", label, action, lp_status)
+            }
+        }
+    }
+
+    pub fn link_preview_status(self, lp: LinkPreview) -> String {
+        match (self, lp) {
+            (Lang::En, LinkPreview::Off) => "Link Preview deactivated".to_string(),
+            (Lang::En, LinkPreview::On) => "Link Preview activated".to_string(),
+            (Lang::En, LinkPreview::InstantView(rhash)) => {
+                format!("Instant View activated, rhash: {:x}", rhash)
+            }
+            (Lang::Zh, LinkPreview::Off) => "链接预览已关闭".to_string(),
+            (Lang::Zh, LinkPreview::On) => "链接预览已开启".to_string(),
+            (Lang::Zh, LinkPreview::InstantView(rhash)) => {
+                format!("Instant View 已开启, rhash: {:x}", rhash)
+            }
+        }
+    }
+
+    pub fn link_preview_set(self, link: &str, title: &str, lp: LinkPreview) -> String {
+        format!("{}{}", feed_label(link, title), self.link_preview_status(lp))
+    }
+
+    pub fn unsubscribed(self, link: &str, title: &str) -> String {
+        match self {
+            Lang::En => format!("{}Unsubscribed successfully", feed_label(link, title)),
+            Lang::Zh => format!("{}退订成功", feed_label(link, title)),
+        }
+    }
+
+    pub fn message_unrecognized(self) -> String {
+        match self {
+            Lang::En => "Message unrecognized".to_string(),
+            Lang::Zh => "无法识别该消息".to_string(),
+        }
+    }
+
+    pub fn subscription_not_found(self) -> String {
+        match self {
+            Lang::En => "Unable to find this subscription".to_string(),
+            Lang::Zh => "找不到该订阅".to_string(),
+        }
+    }
+
+    pub fn feeds_discovered(self) -> String {
+        match self {
+            Lang::En => "This is a web page, it links to these feeds, pick one to subscribe:"
+                .to_string(),
+            Lang::Zh => "这是一个网页, 其中包含以下 RSS, 请选择要订阅的:".to_string(),
+        }
+    }
+
+    pub fn subscribe_confirm(self, link: &str) -> String {
+        match self {
+            Lang::En => format!("Subscribe to {}?", link),
+            Lang::Zh => format!("订阅 {} ?", link),
+        }
+    }
+
+    pub fn no_feed_found(self) -> String {
+        match self {
+            Lang::En => "No RSS found at the link".to_string(),
+            Lang::Zh => "该链接中没有找到 RSS".to_string(),
+        }
+    }
+
+    pub fn subscribe_button(self) -> String {
+        match self {
+            Lang::En => "Subscribe".to_string(),
+            Lang::Zh => "订阅".to_string(),
+        }
+    }
+
+    pub fn invalid_deep_link(self) -> String {
+        match self {
+            Lang::En => "The link doesn't contain a valid RSS URL".to_string(),
+            Lang::Zh => "该链接中没有有效的 RSS 地址".to_string(),
+        }
+    }
+
+    pub fn choice_expired(self) -> String {
+        match self {
+            Lang::En => "This choice is no longer available".to_string(),
+            Lang::Zh => "该选项已失效".to_string(),
+        }
+    }
+
+    pub fn verifying_channel(self) -> String {
+        match self {
+            Lang::En => "Verifying Channel".to_string(),
+            Lang::Zh => "正在验证 Channel".to_string(),
+        }
+    }
+
+    pub fn channel_not_found(self, reason: &str) -> String {
+        match self {
+            Lang::En => format!("Unable to find Channel: {}", reason),
+            Lang::Zh => format!("无法找到目标 Channel: {}", reason),
+        }
+    }
+
+    pub fn not_a_channel(self) -> String {
+        match self {
+            Lang::En => "Target needs to be a Channel".to_string(),
+            Lang::Zh => "目标需为 Channel".to_string(),
+        }
+    }
+
+    pub fn ownership_challenge(self, reason: &str, code: &str) -> String {
+        match self {
+            Lang::En => format!(
+                "Unable to get the administrators of this channel: {}\n\
+                 Please add the Bot to the target channel and give it administrator permissions, \
+                 or post {} in the channel within an hour, forward that post here \
+                 and reply to it with the same command",
+                reason, code
+            ),
+            Lang::Zh => format!(
+                "无法获取 Channel 管理员列表: {}\n\
+                 请将 Bot 添加到 Channel 并设为管理员, \
+                 或者在 1 小时内于 Channel 中发布 {}, 将该消息转发到这里并回复它重新执行命令",
+                reason, code
+            ),
+        }
+    }
+
+    pub fn anonymous_admin_challenge(self, code: &str) -> String {
+        match self {
+            Lang::En => format!(
+                "Anonymous administrators can't be recognized, \
+                 please post {} in the channel within an hour, forward that post here \
+                 and reply to it with the same command",
+                code
+            ),
+            Lang::Zh => format!(
+                "无法识别匿名管理员, 请在 1 小时内于 Channel 中发布 {}, \
+                 将该消息转发到这里并回复它重新执行命令",
+                code
+            ),
+        }
+    }
+
+    pub fn bot_not_admin(self) -> String {
+        match self {
+            Lang::En => "Please give administrator permissions to the bot".to_string(),
+            Lang::Zh => "请将 Bot 设为 Channel 管理员".to_string(),
+        }
+    }
+
+    pub fn user_not_admin(self) -> String {
+        match self {
+            Lang::En => "This command can only be used by channel administrators".to_string(),
+            Lang::Zh => "该命令只能由 Channel 管理员使用".to_string(),
+        }
+    }
+
+    pub fn media_delivery(self, enabled: bool) -> String {
+        match (self, enabled) {
+            (Lang::En, true) => "Media delivery activated".to_string(),
+            (Lang::En, false) => "Media delivery deactivated".to_string(),
+            (Lang::Zh, true) => "已开启附件推送".to_string(),
+            (Lang::Zh, false) => "已关闭附件推送".to_string(),
+        }
+    }
+
+    pub fn preview_photo(self, enabled: bool) -> String {
+        match (self, enabled) {
+            (Lang::En, true) => "Preview photo activated".to_string(),
+            (Lang::En, false) => "Preview photo deactivated".to_string(),
+            (Lang::Zh, true) => "已开启预览图推送".to_string(),
+            (Lang::Zh, false) => "已关闭预览图推送".to_string(),
+        }
+    }
+
+    pub fn canonical_links(self, enabled: bool) -> String {
+        match (self, enabled) {
+            (Lang::En, true) => "Canonical links activated".to_string(),
+            (Lang::En, false) => "Canonical links deactivated".to_string(),
+            (Lang::Zh, true) => "已开启使用原文链接".to_string(),
+            (Lang::Zh, false) => "已关闭使用原文链接".to_string(),
+        }
+    }
+
+    pub fn clean_links(self, enabled: bool) -> String {
+        match (self, enabled) {
+            (Lang::En, true) => "Tracking parameters are removed from links".to_string(),
+            (Lang::En, false) => "Tracking parameters are kept in links".to_string(),
+            (Lang::Zh, true) => "已开启去除链接中的跟踪参数".to_string(),
+            (Lang::Zh, false) => "已关闭去除链接中的跟踪参数".to_string(),
+        }
+    }
+
+    pub fn history_empty(self) -> String {
+        match self {
+            Lang::En => "Delivery history is empty".to_string(),
+            Lang::Zh => "推送记录为空".to_string(),
+        }
+    }
+
+    pub fn fetch_failed(self, reason: &str) -> String {
+        match self {
+            Lang::En => format!("Failed to fetch: {}", reason),
+            Lang::Zh => format!("抓取失败: {}", reason),
+        }
+    }
+
+    pub fn checknow_too_soon(self, seconds: i64) -> String {
+        match self {
+            Lang::En => format!("Fetched just now, try again in {} seconds", seconds),
+            Lang::Zh => format!("刚刚抓取过, 请 {} 秒后再试", seconds),
+        }
+    }
+
+    pub fn checknow_done(self, count: usize) -> String {
+        match self {
+            Lang::En if count == 0 => "Fetched, no new items".to_string(),
+            Lang::En => format!("Fetched, {} new items delivered", count),
+            Lang::Zh if count == 0 => "抓取完成, 没有新条目".to_string(),
+            Lang::Zh => format!("抓取完成, 已推送 {} 条新条目", count),
+        }
+    }
+
+    pub fn feed_empty(self) -> String {
+        match self {
+            Lang::En => "Feed is empty".to_string(),
+            Lang::Zh => "RSS 为空".to_string(),
+        }
+    }
+
+    pub fn feed_failing(self, link: &str, title: &str, reason: &str) -> String {
+        match self {
+            Lang::En => format!("{}Started failing: {}", feed_label(link, title), Escape(reason)),
+            Lang::Zh => format!("{}开始抓取失败: {}", feed_label(link, title), Escape(reason)),
+        }
+    }
+
+    pub fn feed_recovered(self, link: &str, title: &str) -> String {
+        match self {
+            Lang::En => format!("{}Fetched successfully again", feed_label(link, title)),
+            Lang::Zh => format!("{}已恢复正常抓取", feed_label(link, title)),
+        }
+    }
+
+    pub fn watching(self, link: &str, title: &str, selector: Option<&String>) -> String {
+        match (self, selector) {
+            (Lang::En, Some(selector)) => format!(
+                "{}Watching {} for changes",
+                feed_label(link, title),
+                Escape(selector)
+            ),
+            (Lang::En, None) => format!("{}Watching the page for changes", feed_label(link, title)),
+            (Lang::Zh, Some(selector)) => {
+                format!("{}已开始监视 {} 的变化", feed_label(link, title), Escape(selector))
+            }
+            (Lang::Zh, None) => format!("{}已开始监视页面的变化", feed_label(link, title)),
+        }
+    }
+
+    pub fn already_watching(self) -> String {
+        match self {
+            Lang::En => "Already watching this page".to_string(),
+            Lang::Zh => "已在监视该页面".to_string(),
+        }
+    }
+
+    pub fn selector_not_found(self) -> String {
+        match self {
+            Lang::En => "Nothing on the page matches the selector".to_string(),
+            Lang::Zh => "页面中没有与选择器匹配的内容".to_string(),
+        }
+    }
+
+    pub fn unwatched(self, link: &str, title: &str) -> String {
+        match self {
+            Lang::En => format!("{}Stopped watching", feed_label(link, title)),
+            Lang::Zh => format!("{}已停止监视", feed_label(link, title)),
+        }
+    }
+
+    pub fn not_watching(self) -> String {
+        match self {
+            Lang::En => "Not watching this page".to_string(),
+            Lang::Zh => "未监视该页面".to_string(),
+        }
+    }
+
+    pub fn watch_list(self) -> String {
+        match self {
+            Lang::En => "Watched pages:".to_string(),
+            Lang::Zh => "监视的页面:".to_string(),
+        }
+    }
+
+    pub fn watch_list_empty(self) -> String {
+        match self {
+            Lang::En => "No page is watched".to_string(),
+            Lang::Zh => "没有监视的页面".to_string(),
+        }
+    }
+
+    pub fn watched_page(self, link: &str, title: &str, selector: Option<&String>) -> String {
+        match selector {
+            Some(selector) => format!("{} {}", feed_label(link, title), Escape(selector)),
+            None => feed_label(link, title),
+        }
+    }
+
+    // `excerpt` is already HTML, see `html::excerpt`
+    pub fn page_changed(self, link: &str, title: &str, excerpt: &str) -> String {
+        match self {
+            Lang::En => format!("{}The page has changed:\n{}", feed_label(link, title), excerpt),
+            Lang::Zh => format!("{}页面已更新:\n{}", feed_label(link, title), excerpt),
+        }
+    }
+
+    pub fn silent_delivery(self, enabled: bool) -> String {
+        match (self, enabled) {
+            (Lang::En, true) => "Updates will be delivered without notification".to_string(),
+            (Lang::En, false) => "Updates will be delivered with notification".to_string(),
+            (Lang::Zh, true) => "更新将静默推送".to_string(),
+            (Lang::Zh, false) => "更新将正常推送并提醒".to_string(),
+        }
+    }
+
+    pub fn podcast_mode(self, enabled: bool) -> String {
+        match (self, enabled) {
+            (Lang::En, true) => "Episodes will be sent as playable audio".to_string(),
+            (Lang::En, false) => "Podcast mode deactivated".to_string(),
+            (Lang::Zh, true) => "节目将以可播放的音频推送".to_string(),
+            (Lang::Zh, false) => "已关闭播客模式".to_string(),
+        }
+    }
+
+    pub fn error_notify(self, enabled: bool) -> String {
+        match (self, enabled) {
+            (Lang::En, true) => "Fetch error notifications activated".to_string(),
+            (Lang::En, false) => "Fetch error notifications deactivated".to_string(),
+            (Lang::Zh, true) => "已开启抓取失败通知".to_string(),
+            (Lang::Zh, false) => "已关闭抓取失败通知".to_string(),
+        }
+    }
+
+    pub fn dead_feed_warning(self, link: &str, title: &str, days: i64, reason: &str) -> String {
+        match self {
+            Lang::En => format!(
+                "{}Failed to fetch for {} days: {}\n\
+                 It will be unsubscribed in a day, unless you keep it",
+                feed_label(link, title),
+                days,
+                Escape(reason)
+            ),
+            Lang::Zh => format!(
+                "{}已连续 {} 天抓取失败: {}\n一天后将自动退订, 除非选择保留",
+                feed_label(link, title),
+                days,
+                Escape(reason)
+            ),
+        }
+    }
+
+    pub fn dead_feed_keep(self) -> String {
+        match self {
+            Lang::En => "Keep anyway".to_string(),
+            Lang::Zh => "仍然保留".to_string(),
+        }
+    }
+
+    pub fn dead_feed_kept(self) -> String {
+        match self {
+            Lang::En => "It won't be unsubscribed for failing".to_string(),
+            Lang::Zh => "不会因抓取失败而被退订".to_string(),
+        }
+    }
+
+    pub fn dead_feed_removed(self, link: &str, title: &str) -> String {
+        match self {
+            Lang::En => format!(
+                "{}Kept failing, so it was unsubscribed",
+                feed_label(link, title)
+            ),
+            Lang::Zh => format!("{}持续抓取失败, 已自动退订", feed_label(link, title)),
+        }
+    }
+
+    pub fn feed_moved(self, old_link: &str, new_link: &str, title: &str) -> String {
+        match self {
+            Lang::En => format!(
+                "{}The feed has moved permanently from {} to {}, the subscription is updated",
+                feed_label(new_link, title),
+                Escape(old_link),
+                Escape(new_link)
+            ),
+            Lang::Zh => format!(
+                "{}该 RSS 已永久迁移, 从 {} 迁移到 {}, 订阅已自动更新",
+                feed_label(new_link, title),
+                Escape(old_link),
+                Escape(new_link)
+            ),
+        }
+    }
+
+    pub fn link_preview_disabled(self, title: &str) -> String {
+        match self {
+            Lang::En => format!(
+                "<b>Error</b>: Link Previews for {} have been disabled",
+                Escape(title)
+            ),
+            Lang::Zh => format!("<b>错误</b>: {} 的链接预览已被关闭", Escape(title)),
+        }
+    }
+
+    pub fn slow_down(self) -> String {
+        match self {
+            Lang::En => "Too many commands, please slow down".to_string(),
+            Lang::Zh => "命令发送过于频繁, 请稍后再试".to_string(),
+        }
+    }
+
+    pub fn group_admin_only(self) -> String {
+        match self {
+            Lang::En => "Only group administrators can change the subscriptions".to_string(),
+            Lang::Zh => "只有群组管理员可以修改订阅".to_string(),
+        }
+    }
+
+    pub fn group_admin_only_set(self, enabled: bool) -> String {
+        match (self, enabled) {
+            (Lang::En, true) => {
+                "Only group administrators can change the subscriptions now".to_string()
+            }
+            (Lang::En, false) => "All members can change the subscriptions now".to_string(),
+            (Lang::Zh, true) => "现在只有群组管理员可以修改订阅".to_string(),
+            (Lang::Zh, false) => "现在所有成员都可以修改订阅".to_string(),
+        }
+    }
+
+    pub fn not_a_group(self) -> String {
+        match self {
+            Lang::En => "This command can only be used in groups".to_string(),
+            Lang::Zh => "该命令只能在群组中使用".to_string(),
+        }
+    }
+
+    pub fn resubscribe_suggestions(self, enabled: bool) -> String {
+        match (self, enabled) {
+            (Lang::En, true) => {
+                "You will be told once when a feed you unsubscribed gets popular".to_string()
+            }
+            (Lang::En, false) => "Re-subscribe suggestions deactivated".to_string(),
+            (Lang::Zh, true) => "退订的 RSS 变得热门时会提醒一次".to_string(),
+            (Lang::Zh, false) => "已关闭重新订阅提醒".to_string(),
+        }
+    }
+
+    pub fn dedupe_set(self, hours: u32) -> String {
+        match self {
+            Lang::En => format!(
+                "Items already sent by another feed in the last {} hours will be skipped",
+                hours
+            ),
+            Lang::Zh => format!("{} 小时内其他 RSS 已推送过的内容将不再重复推送", hours),
+        }
+    }
+
+    pub fn dedupe_off(self) -> String {
+        match self {
+            Lang::En => "Duplicate items will be sent again".to_string(),
+            Lang::Zh => "已关闭重复内容过滤".to_string(),
+        }
+    }
+
+    pub fn popular_again(self, link: &str, title: &str) -> String {
+        match self {
+            Lang::En => format!(
+                "{}You unsubscribed this feed before, it has become popular since, \
+                 use /sub {} to subscribe again",
+                feed_label(link, title),
+                Escape(link)
+            ),
+            Lang::Zh => format!(
+                "{}你之前退订的这个 RSS 最近订阅者大增, 可以使用 /sub {} 重新订阅",
+                feed_label(link, title),
+                Escape(link)
+            ),
+        }
+    }
+
+    pub fn subscription_expired(self, link: &str, title: &str) -> String {
+        match self {
+            Lang::En => format!(
+                "{}This subscription has expired, delivery is paused",
+                feed_label(link, title)
+            ),
+            Lang::Zh => format!("{}该订阅已到期, 暂停推送", feed_label(link, title)),
+        }
+    }
+
+    pub fn chat_expired(self) -> String {
+        match self {
+            Lang::En => "The subscriptions of this chat have expired, delivery is paused".to_string(),
+            Lang::Zh => "本会话的订阅已到期, 暂停推送".to_string(),
+        }
+    }
+
+    pub fn expiry_set(self, days: u32) -> String {
+        match self {
+            Lang::En => format!("Delivery will pause in {} days", days),
+            Lang::Zh => format!("将在 {} 天后暂停推送", days),
+        }
+    }
+
+    pub fn expiry_removed(self) -> String {
+        match self {
+            Lang::En => "Expiry removed".to_string(),
+            Lang::Zh => "已取消到期时间".to_string(),
+        }
+    }
+
+    pub fn premium_set(self, days: u32) -> String {
+        match self {
+            Lang::En => format!("Premium quota granted for {} days", days),
+            Lang::Zh => format!("已开通 {} 天高级额度", days),
+        }
+    }
+
+    pub fn premium_removed(self) -> String {
+        match self {
+            Lang::En => "Premium quota revoked".to_string(),
+            Lang::Zh => "已取消高级额度".to_string(),
+        }
+    }
+
+    pub fn quota_exceeded(self, limit: usize) -> String {
+        match self {
+            Lang::En => format!(
+                "Subscription limit reached ({}), please unsubscribe some feeds first",
+                limit
+            ),
+            Lang::Zh => format!("订阅数量已达上限 ({}), 请先退订部分 RSS", limit),
+        }
+    }
+
+    // `found` is the matching domain, it may be a parent of `domain`
+    pub fn iv_rhash(self, domain: &str, found: Option<(String, u64)>) -> String {
+        match (self, found) {
+            (Lang::En, Some((found, rhash))) => {
+                format!("Instant View rhash of {} (from {}): {:x}", domain, found, rhash)
+            }
+            (Lang::En, None) => format!("No Instant View rhash known for {}", domain),
+            (Lang::Zh, Some((found, rhash))) => {
+                format!("{} 的 Instant View rhash (来自 {}): {:x}", domain, found, rhash)
+            }
+            (Lang::Zh, None) => format!("没有 {} 的 Instant View rhash", domain),
+        }
+    }
+
+    pub fn iv_rhash_set(self, domain: &str, rhash: u64) -> String {
+        match self {
+            Lang::En => format!("New subscriptions on {} will use rhash {:x}", domain, rhash),
+            Lang::Zh => format!("{} 的新订阅将使用 rhash {:x}", domain, rhash),
+        }
+    }
+
+    pub fn iv_rhash_removed(self, domain: &str) -> String {
+        match self {
+            Lang::En => format!("Removed the rhash of {}", domain),
+            Lang::Zh => format!("已删除 {} 的 rhash", domain),
+        }
+    }
+
+    pub fn rewrite_set(self, rules: &str) -> String {
+        match self {
+            Lang::En => format!("Item links will be rewritten by {}", rules),
+            Lang::Zh => format!("条目链接将按 {} 改写", rules),
+        }
+    }
+
+    pub fn rewrite_removed(self) -> String {
+        match self {
+            Lang::En => "Item links won't be rewritten".to_string(),
+            Lang::Zh => "已关闭改写条目链接".to_string(),
+        }
+    }
+
+    pub fn unknown_rewrite_rule(self, name: &str) -> String {
+        match self {
+            Lang::En => format!("No rewrite rule named {}, see /rewriterule", name),
+            Lang::Zh => format!("没有名为 {} 的改写规则, 参见 /rewriterule", name),
+        }
+    }
+
+    pub fn rewrite_rules(self, rules: &[(String, RewriteRule)]) -> String {
+        if rules.is_empty() {
+            return match self {
+                Lang::En => "No rewrite rules".to_string(),
+                Lang::Zh => "没有改写规则".to_string(),
+            };
+        }
+        let mut text = match self {
+            Lang::En => "Rewrite rules:".to_string(),
+            Lang::Zh => "改写规则:".to_string(),
+        };
+        for &(ref name, ref rule) in rules {
+            text.push_str(&format!("\n{}: {} → {}", name, rule.domain, rule.to));
+        }
+        text
+    }
+
+    pub fn rewrite_rule_set(self, name: &str) -> String {
+        match self {
+            Lang::En => format!("Rewrite rule {} saved", name),
+            Lang::Zh => format!("已保存改写规则 {}", name),
+        }
+    }
+
+    pub fn rewrite_rule_removed(self, name: &str) -> String {
+        match self {
+            Lang::En => format!("Removed the rewrite rule {}, the one in the config is kept", name),
+            Lang::Zh => format!("已删除改写规则 {}, 配置文件中的同名规则仍然有效", name),
+        }
+    }
+
+    pub fn proxy_status(self, link: &str, title: &str, proxy: Option<&str>) -> String {
+        match (self, proxy) {
+            (Lang::En, Some(NO_PROXY)) => {
+                format!("{} bypasses the default proxy", feed_label(link, title))
+            }
+            (Lang::Zh, Some(NO_PROXY)) => format!("{} 不使用默认代理", feed_label(link, title)),
+            (Lang::En, Some(proxy)) => {
+                format!("{} is fetched through {}", feed_label(link, title), Escape(proxy))
+            }
+            (Lang::En, None) => format!("{} uses the default proxy", feed_label(link, title)),
+            (Lang::Zh, Some(proxy)) => {
+                format!("{} 通过 {} 抓取", feed_label(link, title), Escape(proxy))
+            }
+            (Lang::Zh, None) => format!("{} 使用默认代理", feed_label(link, title)),
+        }
+    }
+
+    pub fn domain_blocked(self, domain: &str) -> String {
+        match self {
+            Lang::En => format!("Feeds on {} will be rejected", domain),
+            Lang::Zh => format!("将拒绝订阅 {} 的 RSS", domain),
+        }
+    }
+
+    pub fn domain_already_blocked(self, domain: &str) -> String {
+        match self {
+            Lang::En => format!("{} is already blocked", domain),
+            Lang::Zh => format!("{} 已在屏蔽列表中", domain),
+        }
+    }
+
+    pub fn domain_unblocked(self, domain: &str) -> String {
+        match self {
+            Lang::En => format!("Feeds on {} are accepted again", domain),
+            Lang::Zh => format!("已恢复接受 {} 的 RSS", domain),
+        }
+    }
+
+    pub fn domain_not_blocked(self, domain: &str) -> String {
+        match self {
+            Lang::En => format!("{} isn't blocked by /blockdomain", domain),
+            Lang::Zh => format!("{} 未被 /blockdomain 屏蔽", domain),
+        }
+    }
+
+    pub fn read_only(self) -> String {
+        match self {
+            Lang::En => {
+                "The bot is under maintenance, subscriptions can't be changed for now".to_string()
+            }
+            Lang::Zh => "Bot 正在维护, 暂时无法修改订阅".to_string(),
+        }
+    }
+
+    pub fn maintenance_on(self) -> String {
+        match self {
+            Lang::En => "Read-only mode on, feeds are still fetched and delivered".to_string(),
+            Lang::Zh => "已进入只读模式, RSS 仍会照常抓取和推送".to_string(),
+        }
+    }
+
+    pub fn maintenance_off(self) -> String {
+        match self {
+            Lang::En => "Read-only mode off".to_string(),
+            Lang::Zh => "已退出只读模式".to_string(),
+        }
+    }
+
+    pub fn approval_pending(self, domain: &str) -> String {
+        match self {
+            Lang::En => format!(
+                "{} is new to this bot, you will be told once the owner approves it",
+                domain
+            ),
+            Lang::Zh => format!("该 Bot 尚无来自 {} 的 RSS, 已提交所有者审核, 结果会另行通知", domain),
+        }
+    }
+
+    pub fn approval_request(self, domain: &str, link: &str, subscriber: i64) -> String {
+        match self {
+            Lang::En => format!(
+                "{} wants to subscribe to {}, approve the new domain {}?",
+                subscriber, link, domain
+            ),
+            Lang::Zh => format!("{} 请求订阅 {}, 是否批准新域名 {}?", subscriber, link, domain),
+        }
+    }
+
+    pub fn approve(self) -> String {
+        match self {
+            Lang::En => "Approve".to_string(),
+            Lang::Zh => "批准".to_string(),
+        }
+    }
+
+    pub fn reject(self) -> String {
+        match self {
+            Lang::En => "Reject".to_string(),
+            Lang::Zh => "拒绝".to_string(),
+        }
+    }
+
+    pub fn approval_decided(self, domain: &str, approved: bool) -> String {
+        match (self, approved) {
+            (Lang::En, true) => format!("{} approved", domain),
+            (Lang::En, false) => format!("{} rejected", domain),
+            (Lang::Zh, true) => format!("已批准 {}", domain),
+            (Lang::Zh, false) => format!("已拒绝 {}", domain),
+        }
+    }
+
+    pub fn approval_rejected(self, link: &str) -> String {
+        match self {
+            Lang::En => format!("The owner rejected the subscription to {}", link),
+            Lang::Zh => format!("所有者拒绝了对 {} 的订阅", link),
+        }
+    }
+
+    pub fn erase_confirm(self) -> String {
+        match self {
+            Lang::En => "This removes all subscriptions and settings of this chat along with \
+                         its delivery history, and can't be undone"
+                .to_string(),
+            Lang::Zh => "将删除此会话的全部订阅、设置和推送记录, 且无法恢复".to_string(),
+        }
+    }
+
+    pub fn erase_button(self) -> String {
+        match self {
+            Lang::En => "Erase everything".to_string(),
+            Lang::Zh => "全部删除".to_string(),
+        }
+    }
+
+    pub fn erased(self, subscriptions: usize, in_backups: bool) -> String {
+        match (self, in_backups) {
+            (Lang::En, false) => format!(
+                "Erased {} subscriptions and everything else the bot kept about this chat",
+                subscriptions
+            ),
+            (Lang::En, true) => format!(
+                "Erased {} subscriptions and everything else the bot kept about this chat, \
+                 copies in the existing database backups are gone once the backups rotate",
+                subscriptions
+            ),
+            (Lang::Zh, false) => {
+                format!("已删除 {} 个订阅以及 Bot 保存的此会话的其他全部数据", subscriptions)
+            }
+            (Lang::Zh, true) => format!(
+                "已删除 {} 个订阅以及 Bot 保存的此会话的其他全部数据, \
+                 已有的数据库备份中的副本会在备份轮换后消失",
+                subscriptions
+            ),
+        }
+    }
+
+    pub fn private_instance(self) -> String {
+        match self {
+            Lang::En => "This is a private instance, use /auth <passphrase> if you have one"
+                .to_string(),
+            Lang::Zh => "这是私有实例, 如有口令请使用 /auth <口令>".to_string(),
+        }
+    }
+
+    pub fn authorized(self) -> String {
+        match self {
+            Lang::En => "Authorized, this chat can use the bot now".to_string(),
+            Lang::Zh => "验证成功, 此会话现在可以使用 Bot".to_string(),
+        }
+    }
+
+    pub fn wrong_passphrase(self) -> String {
+        match self {
+            Lang::En => "Wrong passphrase".to_string(),
+            Lang::Zh => "口令错误".to_string(),
+        }
+    }
+
+    pub fn owner_only(self) -> String {
+        match self {
+            Lang::En => "This command can only be used by the bot owner".to_string(),
+            Lang::Zh => "该命令只能由 Bot 所有者使用".to_string(),
+        }
+    }
+
+    pub fn bot_stats(self, stats: &Stats, queued: usize) -> String {
+        let error_rate = if stats.feeds == 0 {
+            0.0
+        } else {
+            stats.failing_feeds as f64 * 100.0 / stats.feeds as f64
+        };
+        match self {
+            Lang::En => format!(
+                "RSS: {}\nSubscribers: {}\nDelivered in 24h: {}\n\
+                 Failing RSS: {} ({:.1}%)\nQueued jobs: {}\nItem hashes: {}",
+                stats.feeds,
+                stats.subscribers,
+                stats.delivered_last_day,
+                stats.failing_feeds,
+                error_rate,
+                queued,
+                stats.hashes
+            ),
+            Lang::Zh => format!(
+                "RSS: {}\n订阅者: {}\n24 小时内推送: {}\n\
+                 抓取失败的 RSS: {} ({:.1}%)\n待发送: {}\n条目哈希: {}",
+                stats.feeds,
+                stats.subscribers,
+                stats.delivered_last_day,
+                stats.failing_feeds,
+                error_rate,
+                queued,
+                stats.hashes
+            ),
+        }
+    }
+
+    pub fn broadcast_started(self, count: usize) -> String {
+        match self {
+            Lang::En => format!("Broadcasting to {} chats", count),
+            Lang::Zh => format!("正在向 {} 个会话发送", count),
+        }
+    }
+
+    pub fn broadcast_done(self, delivered: usize, failed: usize) -> String {
+        match self {
+            Lang::En => format!("Broadcast finished, {} delivered, {} failed", delivered, failed),
+            Lang::Zh => format!("发送完成, 成功 {} 个, 失败 {} 个", delivered, failed),
+        }
+    }
+
+    pub fn transport_polling(self) -> String {
+        match self {
+            Lang::En => "Updates are received by long polling".to_string(),
+            Lang::Zh => "当前通过长轮询接收消息".to_string(),
+        }
+    }
+
+    pub fn transport_webhook_unsupported(self) -> String {
+        match self {
+            Lang::En => "Webhooks are not supported by the Telegram library in use, \
+                         staying on long polling"
+                .to_string(),
+            Lang::Zh => "所用的 Telegram 库不支持 Webhook, 继续使用长轮询".to_string(),
+        }
+    }
+
+    pub fn unknown_feed(self) -> String {
+        match self {
+            Lang::En => "No one is subscribed to this feed".to_string(),
+            Lang::Zh => "没有人订阅该 RSS".to_string(),
+        }
+    }
+
+    pub fn inject_invalid_item(self, err: &str) -> String {
+        match self {
+            Lang::En => format!("Invalid item: {}", err),
+            Lang::Zh => format!("无效的条目: {}", err),
+        }
+    }
+
+    pub fn inject_duplicate(self) -> String {
+        match self {
+            Lang::En => "The feed has had this item already, it wouldn't be delivered".to_string(),
+            Lang::Zh => "该 RSS 已有此条目, 不会被推送".to_string(),
+        }
+    }
+
+    pub fn inject_queued(self, renderings: usize) -> String {
+        match self {
+            Lang::En => format!("{} distinct renderings will be sent here", renderings),
+            Lang::Zh => format!("将在此发送 {} 种不同的推送结果", renderings),
+        }
+    }
+
+    pub fn template_set(self) -> String {
+        match self {
+            Lang::En => "Message template updated".to_string(),
+            Lang::Zh => "消息模板已更新".to_string(),
+        }
+    }
+
+    pub fn template_removed(self) -> String {
+        match self {
+            Lang::En => "Message template removed, using the default format".to_string(),
+            Lang::Zh => "消息模板已删除, 恢复默认格式".to_string(),
+        }
+    }
+
+    pub fn template_test_started(self) -> String {
+        match self {
+            Lang::En => "Both templates will be previewed to you privately with the next update, \
+                         please make sure you have started a chat with the bot"
+                .to_string(),
+            Lang::Zh => "收到下一次更新时会私聊发送两个模板的预览, 请确保已与 Bot 开始对话".to_string(),
+        }
+    }
+
+    // `a` and `b` are already escaped
+    pub fn template_preview(self, feed_title: &str, a: &str, b: &str) -> String {
+        let feed_title = Escape(feed_title);
+        match self {
+            Lang::En => format!(
+                "Template preview of <b>{}</b>\n\n<b>A:</b>\n{}\n\n<b>B:</b>\n{}\n\nPick one:",
+                feed_title, a, b
+            ),
+            Lang::Zh => format!(
+                "<b>{}</b> 的模板预览\n\n<b>A:</b>\n{}\n\n<b>B:</b>\n{}\n\n请选择:",
+                feed_title, a, b
+            ),
+        }
+    }
+
+    pub fn template_chosen(self, choice: &str) -> String {
+        match self {
+            Lang::En => format!("Template {} is now in use", choice),
+            Lang::Zh => format!("已启用模板 {}", choice),
+        }
+    }
+
+    pub fn template_test_not_found(self) -> String {
+        match self {
+            Lang::En => "This preview is no longer valid".to_string(),
+            Lang::Zh => "该预览已失效".to_string(),
+        }
+    }
+
+    pub fn current_timezone(self, timezone: &str) -> String {
+        match self {
+            Lang::En => format!("Current timezone: {}", timezone),
+            Lang::Zh => format!("当前时区: {}", timezone),
+        }
+    }
+
+    pub fn timezone_updated(self, timezone: &str) -> String {
+        match self {
+            Lang::En => format!("Timezone set to {}", timezone),
+            Lang::Zh => format!("时区已设为 {}", timezone),
+        }
+    }
+
+    pub fn current_lang(self) -> String {
+        let available: Vec<String> = LANGS
+            .iter()
+            .map(|lang| format!("{} ({})", lang.code(), lang.name()))
+            .collect();
+        match self {
+            Lang::En => format!(
+                "Current language: {}\nAvailable: {}",
+                self.name(),
+                available.join(", ")
+            ),
+            Lang::Zh => format!("当前语言: {}\n可选: {}", self.name(), available.join(", ")),
+        }
+    }
+
+    pub fn lang_updated(self) -> String {
+        match self {
+            Lang::En => "Language set to English".to_string(),
+            Lang::Zh => "语言已设为中文".to_string(),
+        }
+    }
+}
+
+#[test]
+fn test_lang_code() {
+    for lang in LANGS {
+        assert_eq!(Lang::from_code(lang.code()), Some(*lang));
+    }
+    assert_eq!(Lang::from_code("ZH"), Some(Lang::Zh));
+    assert_eq!(Lang::from_code("xx"), None);
+}
+
+#[test]
+fn test_format_date() {
+    let date = "2001-01-02T15:04:00Z".parse().unwrap();
+    let locale = Locale {
+        lang: Lang::Zh,
+        utc_offset: 8 * 3600,
+        timezone: None,
+    };
+    assert_eq!(locale.format_date(&date), "2001年1月2日 23:04");
+    assert_eq!(format_utc_offset(-(5 * 3600 + 1800)), "UTC-05:30");
+    let locale = Locale {
+        lang: Lang::En,
+        utc_offset: 8 * 3600,
+        timezone: Some(Tz::America__New_York),
+    };
+    assert_eq!(locale.format_date(&date), "Jan 2, 2001 10:04 AM");
+    let date = "2001-07-02T15:04:00Z".parse().unwrap();
+    assert_eq!(locale.format_date(&date), "Jul 2, 2001 11:04 AM");
+}
+
+#[test]
+fn test_format_age() {
+    let now = "2001-01-02T15:04:00Z".parse().unwrap();
+    let locale = Locale::default();
+    let age = |date: &str| locale.format_age(&date.parse().unwrap(), &now);
+    assert_eq!(age("2001-01-02T15:03:30Z"), "just now");
+    assert_eq!(age("2001-01-02T14:50:00Z"), "14m ago");
+    assert_eq!(age("2001-01-02T12:00:00Z"), "3h ago");
+    assert_eq!(age("2001-01-01T12:00:00Z"), "Jan 1, 2001 12:00 PM");
+}
@@ -1,15 +1,32 @@
+use std::collections::BTreeMap;
 use std::io::Cursor;
 use std::io::Write;
 
 use chrono::Local;
 use quick_xml::events::attributes::Attribute;
 use quick_xml::events::{BytesDecl, BytesEnd, BytesStart, BytesText, Event};
+use quick_xml::reader::Reader;
 use quick_xml::writer::Writer;
+use serde_json;
 
-use data::Feed;
+use data::{Feed, LinkPreview, SubscriptionOptions};
 use errors::*;
 
-pub fn to_opml(feeds: Vec<Feed>) -> String {
+// namespace of our extension attributes
+const NAMESPACE: &str = "https://github.com/iovxw/rssbot";
+
+// an outline with a feed link, as imported, the tags of `options` are the folders it's in
+#[derive(Debug, PartialEq)]
+pub struct Outline {
+    pub link: String,
+    pub title: String,
+    pub link_preview: LinkPreview,
+    pub options: SubscriptionOptions,
+}
+
+// tagged feeds are nested in a folder per tag, the settings of the subscriptions
+// are kept in our attributes
+pub fn to_opml(feeds: Vec<(Feed, LinkPreview, SubscriptionOptions)>) -> String {
     let mut writer = Writer::new(Cursor::new(Vec::new()));
     let decl = BytesDecl::new(b"1.0", Some(b"UTF-8"), None);
     writer.write_event(Event::Decl(decl)).unwrap();
@@ -17,7 +34,10 @@ pub fn to_opml(feeds: Vec<Feed>) -> String {
     with_tag(
         &mut writer,
         b"opml",
-        &mut [Attribute::from(("version", "2.0")).into()],
+        &mut [
+            Attribute::from(("version", "2.0")).into(),
+            Attribute::from(("xmlns:rssbot", NAMESPACE)).into(),
+        ],
         |writer| {
             with_tag(writer, b"head", &mut [], |writer| {
                 with_tag(writer, b"title", &mut [], |writer| {
@@ -39,12 +59,24 @@ pub fn to_opml(feeds: Vec<Feed>) -> String {
                 })
             })?;
             with_tag(writer, b"body", &mut [], move |writer| {
-                for feed in feeds {
-                    let mut outline = BytesStart::borrowed(b"outline", 7);
-                    outline.push_attribute(Attribute::from(("type", "rss")));
-                    outline.push_attribute(Attribute::from(("text", feed.title.as_str())));
-                    outline.push_attribute(Attribute::from(("xmlUrl", feed.link.as_str())));
-                    writer.write_event(Event::Empty(outline))?;
+                let mut folders: BTreeMap<&str, Vec<&(Feed, LinkPreview, SubscriptionOptions)>> =
+                    BTreeMap::new();
+                for feed in &feeds {
+                    if feed.2.tags.is_empty() {
+                        write_outline(writer, &feed.0, feed.1, &feed.2)?;
+                    }
+                    for tag in &feed.2.tags {
+                        folders.entry(tag).or_insert_with(Vec::new).push(feed);
+                    }
+                }
+                for (tag, feeds) in folders {
+                    let attrs = &mut [Attribute::from(("text", tag)).into()];
+                    with_tag(writer, b"outline", attrs, |writer| {
+                        for &&(ref feed, link_preview, ref options) in &feeds {
+                            write_outline(writer, feed, link_preview, options)?;
+                        }
+                        Ok(())
+                    })?;
                 }
                 Ok(())
             })
@@ -54,6 +86,155 @@ pub fn to_opml(feeds: Vec<Feed>) -> String {
     unsafe { String::from_utf8_unchecked(writer.into_inner().into_inner()) }
 }
 
+fn write_outline<W: Write>(
+    writer: &mut Writer<W>,
+    feed: &Feed,
+    link_preview: LinkPreview,
+    options: &SubscriptionOptions,
+) -> Result<()> {
+    let mut outline = BytesStart::borrowed(b"outline", 7);
+    outline.push_attribute(Attribute::from(("type", "rss")));
+    outline.push_attribute(Attribute::from(("text", feed.title.as_str())));
+    outline.push_attribute(Attribute::from(("xmlUrl", feed.link.as_str())));
+    if !options.headers.is_empty() {
+        let headers = serde_json::to_string(&options.headers).unwrap();
+        outline.push_attribute(Attribute::from(("rssbot:headers", &*headers)));
+    }
+    match link_preview {
+        LinkPreview::Off => (),
+        LinkPreview::On => outline.push_attribute(Attribute::from(("rssbot:linkPreview", "on"))),
+        LinkPreview::InstantView(rhash) => {
+            let value = format!("instant_view:{:x}", rhash);
+            outline.push_attribute(Attribute::from(("rssbot:linkPreview", &*value)));
+        }
+    }
+    let options = portable_options(options);
+    if options != SubscriptionOptions::default() {
+        let options = serde_json::to_string(&options).unwrap();
+        outline.push_attribute(Attribute::from(("rssbot:options", &*options)));
+    }
+    writer.write_event(Event::Empty(outline))?;
+    Ok(())
+}
+
+// what another instance can restore, headers and tags have their own attributes
+fn portable_options(options: &SubscriptionOptions) -> SubscriptionOptions {
+    SubscriptionOptions {
+        expires_at: None,
+        expiry_notified: false,
+        template_test: None,
+        headers: Vec::new(),
+        tags: Vec::new(),
+        snoozed: Vec::new(),
+        initial_items: Vec::new(),
+        ..options.clone()
+    }
+}
+
+fn parse_link_preview(value: &str) -> Option<LinkPreview> {
+    if value == "on" {
+        return Some(LinkPreview::On);
+    }
+    if value.starts_with("instant_view:") {
+        let rhash = u64::from_str_radix(&value["instant_view:".len()..], 16).ok()?;
+        return Some(LinkPreview::InstantView(rhash));
+    }
+    None
+}
+
+// every outline with a `xmlUrl`, a feed found in several folders is merged
+pub fn from_opml(opml: &[u8]) -> Result<Vec<Outline>> {
+    let mut reader = Reader::from_reader(opml);
+    reader.trim_text(true);
+    let mut outlines: Vec<Outline> = Vec::new();
+    // one entry per open outline, folders have a name
+    let mut folders: Vec<Option<String>> = Vec::new();
+    let mut buf = Vec::new();
+    loop {
+        buf.clear();
+        let (e, is_start) = match reader.read_event(&mut buf)? {
+            Event::Start(e) => (e, true),
+            Event::Empty(e) => (e, false),
+            Event::End(ref e) if e.name() == b"outline" => {
+                folders.pop();
+                continue;
+            }
+            Event::Eof => break,
+            _ => continue,
+        };
+        if e.name() != b"outline" {
+            continue;
+        }
+        let mut link = None;
+        let mut title = String::new();
+        let mut headers = Vec::new();
+        let mut link_preview = LinkPreview::Off;
+        let mut options = SubscriptionOptions::default();
+        for attribute in e.attributes() {
+            let attribute = attribute?;
+            match attribute.key {
+                b"xmlUrl" => link = Some(attribute.unescape_and_decode_value(&reader)?),
+                b"text" | b"title" if title.is_empty() => {
+                    title = attribute.unescape_and_decode_value(&reader)?
+                }
+                b"rssbot:headers" => {
+                    let value = attribute.unescape_and_decode_value(&reader)?;
+                    headers = serde_json::from_str(&value).unwrap_or_else(|e| {
+                        warn!("invalid headers in OPML: {}", e);
+                        Vec::new()
+                    });
+                }
+                b"rssbot:linkPreview" => {
+                    let value = attribute.unescape_and_decode_value(&reader)?;
+                    link_preview = parse_link_preview(&value).unwrap_or_else(|| {
+                        warn!("invalid link preview in OPML: {}", value);
+                        LinkPreview::Off
+                    });
+                }
+                b"rssbot:options" => {
+                    let value = attribute.unescape_and_decode_value(&reader)?;
+                    options = serde_json::from_str(&value).unwrap_or_else(|e| {
+                        warn!("invalid options in OPML: {}", e);
+                        SubscriptionOptions::default()
+                    });
+                }
+                _ => (),
+            }
+        }
+        let tags: Vec<String> = folders.iter().filter_map(|f| f.clone()).collect();
+        match link {
+            Some(link) => {
+                if is_start {
+                    folders.push(None);
+                }
+                match outlines.iter().position(|o| o.link == link) {
+                    Some(i) => {
+                        let outline = &mut outlines[i];
+                        for tag in tags {
+                            if !outline.options.tags.contains(&tag) {
+                                outline.options.tags.push(tag);
+                            }
+                        }
+                    }
+                    None => outlines.push(Outline {
+                        link: link,
+                        title: title,
+                        link_preview: link_preview,
+                        options: SubscriptionOptions {
+                            headers: headers,
+                            tags: tags,
+                            ..portable_options(&options)
+                        },
+                    }),
+                }
+            }
+            None if is_start => folders.push(Some(title).filter(|t| !t.is_empty())),
+            None => (),
+        }
+    }
+    Ok(outlines)
+}
+
 // type of `attrs` is for zero allocation
 fn with_tag<'a, W, F>(
     writer: &mut Writer<W>,
@@ -84,10 +265,16 @@ fn test_to_opml() {
     let mut feed2 = Feed::default();
     feed2.title = "title2".into();
     feed2.link = "link2".into();
-    let feeds = vec![feed1, feed2];
+    let mut options = SubscriptionOptions::default();
+    options.headers = vec![("X-Api-Key".to_owned(), "a\"b".to_owned())];
+    options.tags = vec!["tag".to_owned()];
+    let feeds = vec![
+        (feed1, LinkPreview::Off, SubscriptionOptions::default()),
+        (feed2, LinkPreview::Off, options),
+    ];
     let r = format!(
         "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\
-         <opml version=\"2.0\">\
+         <opml version=\"2.0\" xmlns:rssbot=\"https://github.com/iovxw/rssbot\">\
          <head>\
          <title>Exported from RSSBot</title>\
          <dateCreated>{}</dateCreated>\
@@ -95,10 +282,36 @@ fn test_to_opml() {
          </head>\
          <body>\
          <outline type=\"rss\" text=\"title1\" xmlUrl=\"link1\"/>\
-         <outline type=\"rss\" text=\"title2\" xmlUrl=\"link2\"/>\
+         <outline text=\"tag\">\
+         <outline type=\"rss\" text=\"title2\" xmlUrl=\"link2\" \
+         rssbot:headers=\"[[&quot;X-Api-Key&quot;,&quot;a\\&quot;b&quot;]]\"/>\
+         </outline>\
          </body>\
          </opml>",
         Local::now().format("%a, %d %b %Y %T %Z")
     );
     assert_eq!(to_opml(feeds), r);
 }
+
+#[test]
+fn test_from_opml() {
+    let mut feed = Feed::default();
+    feed.title = "title".into();
+    feed.link = "link".into();
+    let mut options = SubscriptionOptions::default();
+    options.headers = vec![("User-Agent".to_owned(), "<private>".to_owned())];
+    options.tags = vec!["a".to_owned(), "b".to_owned()];
+    options.template = Some("<b>{title}</b>".to_owned());
+    options.silent = true;
+    let link_preview = LinkPreview::InstantView(0xabc);
+    let opml = to_opml(vec![(feed, link_preview, options.clone())]);
+    assert_eq!(
+        from_opml(opml.as_bytes()).unwrap(),
+        vec![Outline {
+            link: "link".into(),
+            title: "title".into(),
+            link_preview: link_preview,
+            options: options,
+        }]
+    );
+}
@@ -0,0 +1,325 @@
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+
+use url::Url;
+
+use data::{LinkPreview, RewriteRule, SubscriptionOptions};
+use feed;
+use feed::MediaKind;
+use fetcher::{format_caption, format_updates, PageMeta};
+use messages::Locale;
+use utils::Media;
+
+// Telegram doesn't fetch larger files by URL, see `/podcast`
+const MAX_URL_MEDIA_SIZE: u64 = 20 * 1024 * 1024;
+
+// removed by `/cleanlinks` unless configured otherwise, `utm_*` matches a prefix
+pub const DEFAULT_TRACKING_PARAMS: &[&str] = &[
+    "utm_*", "fbclid", "gclid", "dclid", "msclkid", "yclid", "igshid", "mc_cid", "mc_eid",
+    "_hsenc", "_hsmi", "mkt_tok",
+];
+
+thread_local! {
+    // the default of `/cleanlinks`, see `set_tracking`
+    static STRIP_TRACKING: Cell<bool> = Cell::new(false);
+    static TRACKING_PARAMS: RefCell<Vec<String>> = RefCell::new(Vec::new());
+}
+
+// an empty `params` is `DEFAULT_TRACKING_PARAMS`
+pub fn set_tracking(strip: bool, params: &[String]) {
+    let params = if params.is_empty() {
+        DEFAULT_TRACKING_PARAMS.iter().map(|&param| param.to_owned()).collect()
+    } else {
+        params.iter().map(|param| param.to_lowercase()).collect()
+    };
+    STRIP_TRACKING.with(|s| s.set(strip));
+    TRACKING_PARAMS.with(|p| *p.borrow_mut() = params);
+}
+
+// what the processors of a subscription may look at
+pub struct Context<'a> {
+    pub rss_title: &'a str,
+    pub rss_link: &'a str,
+    pub link_preview: LinkPreview,
+    pub options: &'a SubscriptionOptions,
+    pub locale: Locale,
+    // by item link, only fetched for options which need them
+    pub pages: &'a HashMap<String, PageMeta>,
+    // the ones named by the subscription, see `/rewrite`
+    pub rewrite_rules: &'a [RewriteRule],
+}
+
+// a step working on the new items, before they are rendered,
+// may drop, reorder or rewrite them
+pub trait Processor {
+    fn process(&self, items: Vec<feed::Item>, ctx: &Context) -> Vec<feed::Item>;
+}
+
+// what a subscriber receives, ready for a `sender::Job`
+#[derive(Debug, Clone)]
+pub struct Output {
+    pub media_msgs: Vec<Media>,
+    pub msgs: Vec<String>,
+    // as delivered, for the history
+    pub items: Vec<feed::Item>,
+}
+
+// items pass the processors in order, then are split into media and text,
+// rendered, and finally sent by the sender
+pub struct Pipeline {
+    processors: Vec<Box<Processor>>,
+}
+
+impl Pipeline {
+    // the processors used by a subscription, in order
+    pub fn for_subscription(options: &SubscriptionOptions) -> Pipeline {
+        let mut processors: Vec<Box<Processor>> = vec![Box::new(Normalize)];
+        if options.canonical {
+            processors.push(Box::new(CanonicalLinks));
+        }
+        let strip = STRIP_TRACKING.with(|s| s.get());
+        if options.strip_tracking.unwrap_or(strip) {
+            processors.push(Box::new(StripTracking));
+        }
+        if !options.rewrite.is_empty() {
+            processors.push(Box::new(RewriteLinks));
+        }
+        Pipeline {
+            processors: processors,
+        }
+    }
+
+    pub fn run(&self, items: Vec<feed::Item>, ctx: &Context) -> Output {
+        let items = self.processors
+            .iter()
+            .fold(items, |items, processor| processor.process(items, ctx));
+        let (media_items, text_items): (Vec<&feed::Item>, Vec<&feed::Item>) = items
+            .iter()
+            .partition(|item| item_media(item, ctx.options, ctx.pages).is_some());
+        let media_msgs = media_items
+            .into_iter()
+            .filter_map(|item| {
+                item_media(item, ctx.options, ctx.pages).map(|(kind, url)| {
+                    let caption = format_caption(item, ctx.options, ctx.rss_title, ctx.rss_link);
+                    let mut media = Media::new(kind, url, caption);
+                    if ctx.options.podcast && kind == MediaKind::Audio {
+                        media.title = item.title.clone();
+                        media.performer =
+                            Some(item.author.as_ref().map_or(ctx.rss_title, |s| s.as_str()).into());
+                        media.duration = item.duration;
+                    }
+                    media
+                })
+            })
+            .collect();
+        let text_items: Vec<feed::Item> = text_items.into_iter().cloned().collect();
+        let msgs = format_updates(
+            ctx.link_preview,
+            ctx.options,
+            ctx.locale,
+            ctx.rss_title,
+            ctx.rss_link,
+            &text_items,
+        );
+        Output {
+            media_msgs: media_msgs,
+            msgs: msgs,
+            items: items,
+        }
+    }
+}
+
+// trims titles and collapses the whitespace in them, blank titles are dropped
+struct Normalize;
+
+impl Processor for Normalize {
+    fn process(&self, items: Vec<feed::Item>, _ctx: &Context) -> Vec<feed::Item> {
+        items
+            .into_iter()
+            .map(|mut item| {
+                item.title = item
+                    .title
+                    .map(|title| title.split_whitespace().collect::<Vec<_>>().join(" "))
+                    .filter(|title| !title.is_empty());
+                item
+            })
+            .collect()
+    }
+}
+
+// replaces links to aggregator pages with the article they point at
+struct CanonicalLinks;
+
+impl Processor for CanonicalLinks {
+    fn process(&self, items: Vec<feed::Item>, ctx: &Context) -> Vec<feed::Item> {
+        items
+            .into_iter()
+            .map(|mut item| {
+                let canonical = item.link
+                    .as_ref()
+                    .and_then(|link| ctx.pages.get(link))
+                    .and_then(|page| page.canonical.clone());
+                if canonical.is_some() {
+                    item.link = canonical;
+                }
+                item
+            })
+            .collect()
+    }
+}
+
+// removes tracking parameters from the links, see `/cleanlinks`
+struct StripTracking;
+
+impl Processor for StripTracking {
+    fn process(&self, items: Vec<feed::Item>, _ctx: &Context) -> Vec<feed::Item> {
+        TRACKING_PARAMS.with(|params| {
+            let params = params.borrow();
+            items
+                .into_iter()
+                .map(|mut item| {
+                    item.link = item.link.map(|link| strip_params(&link, &params));
+                    item
+                })
+                .collect()
+        })
+    }
+}
+
+// points the links at other front-ends, the first matching rule wins
+struct RewriteLinks;
+
+impl Processor for RewriteLinks {
+    fn process(&self, items: Vec<feed::Item>, ctx: &Context) -> Vec<feed::Item> {
+        items
+            .into_iter()
+            .map(|mut item| {
+                let rewritten = item.link.as_ref().and_then(|link| {
+                    ctx.rewrite_rules.iter().filter_map(|rule| rule.rewrite(link)).next()
+                });
+                if rewritten.is_some() {
+                    item.link = rewritten;
+                }
+                item
+            })
+            .collect()
+    }
+}
+
+// links without such parameters are kept as they are, not re-encoded
+fn strip_params(link: &str, params: &[String]) -> String {
+    let mut url = match Url::parse(link) {
+        Ok(url) => url,
+        Err(_) => return link.to_owned(),
+    };
+    let is_tracking = |key: &str| {
+        let key = key.to_lowercase();
+        params.iter().any(|param| {
+            if param.ends_with('*') {
+                key.starts_with(param.trim_right_matches('*'))
+            } else {
+                key == *param
+            }
+        })
+    };
+    let pairs: Vec<(String, String)> = url.query_pairs().into_owned().collect();
+    let kept: Vec<&(String, String)> = pairs.iter().filter(|pair| !is_tracking(&pair.0)).collect();
+    if kept.len() == pairs.len() {
+        return link.to_owned();
+    }
+    if kept.is_empty() {
+        url.set_query(None);
+    } else {
+        url.query_pairs_mut().clear().extend_pairs(kept);
+    }
+    url.into_string()
+}
+
+fn item_media(
+    item: &feed::Item,
+    options: &SubscriptionOptions,
+    pages: &HashMap<String, PageMeta>,
+) -> Option<(MediaKind, String)> {
+    if options.podcast {
+        if let Some(audio) = item.audio() {
+            // too large ones are sent as links
+            return if audio.length.map_or(true, |length| length <= MAX_URL_MEDIA_SIZE) {
+                Some((MediaKind::Audio, audio.url.clone()))
+            } else {
+                None
+            };
+        }
+    }
+    if options.media {
+        if let Some((kind, enclosure)) = item.media() {
+            return Some((kind, enclosure.url.clone()));
+        }
+    }
+    if options.og_image {
+        let image = item.link
+            .as_ref()
+            .and_then(|link| pages.get(link))
+            .and_then(|page| page.og_image.as_ref());
+        if let Some(image) = image {
+            return Some((MediaKind::Photo, image.clone()));
+        }
+    }
+    None
+}
+
+#[test]
+fn test_normalize() {
+    let item = |title: Option<&str>| feed::Item {
+        title: title.map(str::to_owned),
+        ..feed::Item::default()
+    };
+    let pages = HashMap::new();
+    let options = SubscriptionOptions::default();
+    let ctx = Context {
+        rss_title: "",
+        rss_link: "",
+        link_preview: LinkPreview::Off,
+        options: &options,
+        locale: Locale::default(),
+        pages: &pages,
+        rewrite_rules: &[],
+    };
+    let items = vec![item(Some(" a\n  b ")), item(Some(" ")), item(None)];
+    assert_eq!(
+        Normalize.process(items, &ctx),
+        vec![item(Some("a b")), item(None), item(None)]
+    );
+}
+
+#[test]
+fn test_strip_params() {
+    let params: Vec<String> = DEFAULT_TRACKING_PARAMS.iter().map(|&p| p.to_owned()).collect();
+    assert_eq!(
+        strip_params("https://example.com/a?id=1&utm_source=rss&UTM_medium=feed", &params),
+        "https://example.com/a?id=1"
+    );
+    assert_eq!(
+        strip_params("https://example.com/a?fbclid=x#top", &params),
+        "https://example.com/a#top"
+    );
+    assert_eq!(
+        strip_params("https://example.com/a?q=a+b", &params),
+        "https://example.com/a?q=a+b"
+    );
+}
+
+#[test]
+fn test_rewrite_rule() {
+    let nitter = RewriteRule::parse("twitter.com=nitter.net").unwrap();
+    assert_eq!(
+        nitter.rewrite("https://mobile.twitter.com/a/status/1"),
+        Some("https://nitter.net/a/status/1".to_owned())
+    );
+    assert_eq!(nitter.rewrite("https://example.com/"), None);
+    let archive = RewriteRule::parse("*=https://archive.today/newest/{}").unwrap();
+    assert_eq!(
+        archive.rewrite("https://example.com/a"),
+        Some("https://archive.today/newest/https://example.com/a".to_owned())
+    );
+    assert_eq!(RewriteRule::parse("twitter.com=https://nitter.net"), None);
+}
@@ -0,0 +1,124 @@
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::time::Instant;
+
+use futures::prelude::*;
+use telebot;
+use telebot::functions::*;
+use telebot::objects::Message;
+
+use data::Database;
+
+thread_local! {
+    // commands per minute of a user, 0 is unlimited
+    static RATE: Cell<u32> = Cell::new(0);
+    static BUCKETS: RefCell<HashMap<i64, Bucket>> = RefCell::new(HashMap::new());
+}
+
+// a burst of up to a minute's worth of commands, refilled continuously
+struct Bucket {
+    tokens: f64,
+    updated_at: Instant,
+    // told to slow down since the last allowed command
+    warned: bool,
+}
+
+pub fn set_rate(rate: u32) {
+    RATE.with(|r| r.set(rate));
+}
+
+// `bot.new_cmd`, dropping the commands of users over the rate, owners are exempt;
+// on a private instance only `/auth` is taken from the others
+pub fn new_cmd(
+    bot: &telebot::RcBot,
+    db: &Database,
+    cmd: &str,
+) -> impl Stream<Item = (telebot::RcBot, Message), Error = telebot::Error> {
+    let db = db.clone();
+    let is_auth = cmd == "/auth";
+    bot.new_cmd(cmd).filter(move |&(ref bot, ref msg)| {
+        let user_id = msg.from.as_ref().map(|user| user.id);
+        if let Some(user_id) = user_id {
+            if db.is_owner(user_id) {
+                return true;
+            }
+            match take(user_id) {
+                Taken::Allowed => (),
+                Taken::Warn => {
+                    reply(bot, msg.chat.id, db.get_lang(msg.chat.id).slow_down());
+                    return false;
+                }
+                Taken::Denied => return false,
+            }
+        }
+        if is_auth || db.is_authorized(msg.chat.id, user_id) {
+            return true;
+        }
+        reply(bot, msg.chat.id, db.get_lang(msg.chat.id).private_instance());
+        false
+    })
+}
+
+fn reply(bot: &telebot::RcBot, chat_id: i64, text: String) {
+    let r = bot.message(chat_id, text)
+        .send()
+        .map(|_| ())
+        .map_err(|e| error!("telebot: {:?}", e));
+    bot.inner.handle.spawn(r);
+}
+
+#[derive(Debug, PartialEq)]
+enum Taken {
+    Allowed,
+    // the first denied command, replied to
+    Warn,
+    Denied,
+}
+
+fn take(user_id: i64) -> Taken {
+    let rate = RATE.with(|r| r.get());
+    if rate == 0 {
+        return Taken::Allowed;
+    }
+    let capacity = f64::from(rate);
+    let now = Instant::now();
+    BUCKETS.with(|buckets| {
+        let mut buckets = buckets.borrow_mut();
+        if buckets.len() > 4096 {
+            // a full bucket is the same as none
+            buckets.retain(|_, bucket| {
+                let elapsed = now.duration_since(bucket.updated_at).as_secs();
+                bucket.tokens + elapsed as f64 * capacity / 60.0 < capacity
+            });
+        }
+        let bucket = buckets.entry(user_id).or_insert(Bucket {
+            tokens: capacity,
+            updated_at: now,
+            warned: false,
+        });
+        let elapsed = now.duration_since(bucket.updated_at);
+        let elapsed = elapsed.as_secs() as f64 + f64::from(elapsed.subsec_nanos()) / 1e9;
+        bucket.tokens = (bucket.tokens + elapsed * capacity / 60.0).min(capacity);
+        bucket.updated_at = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            bucket.warned = false;
+            Taken::Allowed
+        } else if !bucket.warned {
+            bucket.warned = true;
+            Taken::Warn
+        } else {
+            Taken::Denied
+        }
+    })
+}
+
+#[test]
+fn test_take() {
+    set_rate(2);
+    assert_eq!(take(1), Taken::Allowed);
+    assert_eq!(take(1), Taken::Allowed);
+    assert_eq!(take(1), Taken::Warn);
+    assert_eq!(take(1), Taken::Denied);
+    assert_eq!(take(2), Taken::Allowed);
+}
@@ -0,0 +1,487 @@
+use std::cell::{Cell, RefCell};
+use std::cmp::max;
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use futures::prelude::*;
+use futures::unsync::mpsc;
+use telebot;
+use telebot::functions::*;
+use telebot::objects::ResponseParameters;
+use tokio_core::reactor::{Interval, Timeout};
+use tokio_curl::Session;
+
+use data::{Database, QueuedJob};
+use feed;
+use logging;
+use utils::{chat_is_unavailable, pin_message, send_media, Media};
+
+// Telegram allows about 30 messages per second in total, 20 per minute
+// in a group or channel and 1 per second in a private chat
+const GLOBAL_INTERVAL: u64 = 35;
+const GROUP_INTERVAL: u64 = 3000;
+const PRIVATE_INTERVAL: u64 = 1000;
+const MAX_RETRIES: u32 = 5;
+// in seconds, doubled on every retry of a transient error
+const RETRY_BASE_DELAY: u64 = 2;
+// a chat is deleted after failing this many jobs in a row because it's unavailable,
+// a single failure may be a hiccup of Telegram
+const MAX_UNAVAILABLE: u32 = 3;
+// in seconds, how often the progress of the queued jobs is saved
+const QUEUE_SAVE_INTERVAL: u64 = 5;
+
+#[derive(Clone)]
+pub struct Job {
+    pub target: i64,
+    pub media_msgs: Vec<Media>,
+    pub msgs: Vec<String>,
+    pub link_preview: bool,
+    // without a notification sound
+    pub silent: bool,
+    // the first message containing it is pinned, see `/pinrule`
+    pub pin_keyword: Option<String>,
+    // recorded into the delivery history once sent
+    pub feed_title: String,
+    pub items: Vec<feed::Item>,
+}
+
+impl Job {
+    pub fn text(target: i64, msg: String) -> Job {
+        Job {
+            target: target,
+            media_msgs: Vec::new(),
+            msgs: vec![msg],
+            link_preview: false,
+            silent: false,
+            pin_keyword: None,
+            feed_title: String::new(),
+            items: Vec::new(),
+        }
+    }
+
+    fn to_queued(&self) -> QueuedJob {
+        QueuedJob {
+            target: self.target,
+            media_msgs: self.media_msgs.clone(),
+            msgs: self.msgs.clone(),
+            link_preview: self.link_preview,
+            silent: self.silent,
+            pin_keyword: self.pin_keyword.clone(),
+            feed_title: self.feed_title.clone(),
+            items: self.items
+                .iter()
+                .map(|item| (item.title.clone(), item.link.clone()))
+                .collect(),
+        }
+    }
+
+    fn from_queued(job: QueuedJob) -> Job {
+        Job {
+            target: job.target,
+            media_msgs: job.media_msgs,
+            msgs: job.msgs,
+            link_preview: job.link_preview,
+            silent: job.silent,
+            pin_keyword: job.pin_keyword,
+            feed_title: job.feed_title,
+            items: job.items
+                .into_iter()
+                .map(|(title, link)| feed::Item {
+                    title: title,
+                    link: link,
+                    ..Default::default()
+                })
+                .collect(),
+        }
+    }
+}
+
+#[derive(Clone)]
+enum Outgoing {
+    Media(Media),
+    Text(String),
+}
+
+impl Outgoing {
+    fn text(&self) -> &str {
+        match *self {
+            Outgoing::Media(ref media) => &media.caption,
+            Outgoing::Text(ref text) => text,
+        }
+    }
+}
+
+// every message waits for a free slot of both the bot and its chat
+struct Pacer {
+    next_global: Instant,
+    next_chat: HashMap<i64, Instant>,
+}
+
+impl Pacer {
+    fn new() -> Pacer {
+        Pacer {
+            next_global: Instant::now(),
+            next_chat: HashMap::new(),
+        }
+    }
+
+    // reserves the next slot for `chat` and returns when it begins
+    fn reserve(&mut self, chat: i64) -> Instant {
+        let now = Instant::now();
+        if self.next_chat.len() > 4096 {
+            self.next_chat.retain(|_, next| *next > now);
+        }
+        let chat_next = self.next_chat.get(&chat).cloned().unwrap_or(now);
+        let at = max(now, max(self.next_global, chat_next));
+        let chat_interval = if chat < 0 {
+            GROUP_INTERVAL
+        } else {
+            PRIVATE_INTERVAL
+        };
+        self.next_global = at + Duration::from_millis(GLOBAL_INTERVAL);
+        self.next_chat.insert(chat, at + Duration::from_millis(chat_interval));
+        at
+    }
+
+    fn delay(&mut self, chat: i64, delay: Duration) {
+        let at = Instant::now() + delay;
+        let next = self.next_chat.entry(chat).or_insert(at);
+        *next = max(*next, at);
+    }
+}
+
+// fetching only enqueues jobs, at most `workers` of them are being sent at the same time;
+// the jobs are kept in the database until they're sent, and resumed by the next start
+#[derive(Clone)]
+pub struct Sender {
+    tx: mpsc::UnboundedSender<(u64, Job)>,
+    queued: Rc<Cell<usize>>,
+    bot: telebot::RcBot,
+    db: Database,
+    pacer: Rc<RefCell<Pacer>>,
+}
+
+impl Sender {
+    pub fn spawn(bot: telebot::RcBot, db: Database, workers: usize) -> Sender {
+        let (tx, rx) = mpsc::unbounded();
+        let queued = Rc::new(Cell::new(0));
+        let pacer = Rc::new(RefCell::new(Pacer::new()));
+        let handle = bot.inner.handle.clone();
+        let queued2 = Rc::clone(&queued);
+        let bot2 = bot.clone();
+        let db2 = db.clone();
+        let pacer2 = Rc::clone(&pacer);
+        // chat -> consecutive jobs failed because it's unavailable
+        let unavailable = Rc::new(RefCell::new(HashMap::new()));
+        let worker = rx
+            .map(move |(id, job)| {
+                deliver(bot2.clone(), db2.clone(), pacer2.clone(), unavailable.clone(), id, job)
+            })
+            .buffer_unordered(workers)
+            .for_each(move |()| {
+                queued2.set(queued2.get() - 1);
+                Ok(())
+            });
+        handle.spawn(worker);
+        let db3 = db.clone();
+        let saver = Interval::new(Duration::from_secs(QUEUE_SAVE_INTERVAL), &handle)
+            .expect("failed to start queue saver loop")
+            .for_each(move |_| {
+                db3.save_queue();
+                Ok(())
+            })
+            .map_err(|e| error!("queue saver loop: {}", e));
+        handle.spawn(saver);
+        let sender = Sender {
+            tx: tx,
+            queued: queued,
+            bot: bot,
+            db: db,
+            pacer: pacer,
+        };
+        let jobs = sender.db.get_queued_jobs();
+        if !jobs.is_empty() {
+            info!("resuming {} jobs queued before the restart", jobs.len());
+        }
+        for (id, job) in jobs {
+            sender.push(id, Job::from_queued(job));
+        }
+        sender
+    }
+
+    pub fn send(&self, job: Job) {
+        let id = self.db.queue_job(job.to_queued());
+        self.push(id, job);
+    }
+
+    fn push(&self, id: u64, job: Job) {
+        self.queued.set(self.queued.get() + 1);
+        if self.tx.unbounded_send((id, job)).is_err() {
+            error!("sender has stopped");
+        }
+    }
+
+    pub fn queued(&self) -> usize {
+        self.queued.get()
+    }
+
+    // sends `text` to every target, sharing the pace with the jobs,
+    // resolves to the number of delivered and failed messages
+    pub fn broadcast(
+        &self,
+        targets: Vec<i64>,
+        text: String,
+    ) -> impl Future<Item = (usize, usize), Error = ()> {
+        broadcast(self.bot.clone(), self.pacer.clone(), targets, text)
+    }
+}
+
+#[async]
+fn deliver(
+    bot: telebot::RcBot,
+    db: Database,
+    pacer: Rc<RefCell<Pacer>>,
+    unavailable: Rc<RefCell<HashMap<i64, u32>>>,
+    id: u64,
+    job: Job,
+) -> Result<(), ()> {
+    let Job {
+        target,
+        media_msgs,
+        msgs,
+        link_preview,
+        silent,
+        pin_keyword,
+        feed_title,
+        items,
+    } = job;
+    let outgoing: Vec<Outgoing> = media_msgs
+        .into_iter()
+        .map(Outgoing::Media)
+        .chain(msgs.into_iter().map(Outgoing::Text))
+        .collect();
+    let r = send_all(
+        bot.clone(),
+        db.clone(),
+        pacer.clone(),
+        id,
+        target,
+        outgoing.clone(),
+        link_preview,
+        silent,
+        pin_keyword.clone(),
+    );
+    let r = await!(r);
+    let chat = target.to_string();
+    if r.is_ok() {
+        unavailable.borrow_mut().remove(&target);
+    }
+    match r {
+        // not when the chat was erased by `/erase` while it was being sent
+        Ok(()) => if !items.is_empty() && db.is_job_queued(id) {
+            db.record_delivery(target, &feed_title, &items);
+        },
+        Err(telebot::Error::Telegram(_, ref s, None)) if chat_is_unavailable(s) => {
+            let failures = {
+                let mut unavailable = unavailable.borrow_mut();
+                let failures = unavailable.entry(target).or_insert(0);
+                *failures += 1;
+                *failures
+            };
+            if failures < MAX_UNAVAILABLE {
+                logging::with_fields(&[("chat", chat.as_str())], || {
+                    info!("chat is unavailable ({}), {} failures", s, failures)
+                });
+            } else {
+                unavailable.borrow_mut().remove(&target);
+                let feeds = db.delete_subscriber(target);
+                let links: Vec<&str> = feeds.iter().map(|feed| feed.link.as_str()).collect();
+                logging::with_fields(&[("chat", chat.as_str())], || {
+                    warn!(
+                        "deleted chat after {} failures ({}), unsubscribed from {} feeds: {}",
+                        failures,
+                        s,
+                        links.len(),
+                        links.join(" ")
+                    )
+                });
+            }
+        }
+        Err(telebot::Error::Telegram(
+            _,
+            _,
+            Some(ResponseParameters {
+                migrate_to_chat_id: Some(new_id),
+                ..
+            }),
+        )) => {
+            info!("chat {} migrated to {}", target, new_id);
+            db.update_subscriber(target, new_id);
+            // the messages sent before the migration aren't sent again
+            let left = db.get_queued_job(id)
+                .map_or(0, |job| job.media_msgs.len() + job.msgs.len());
+            let sent = outgoing.len() - left;
+            let outgoing = outgoing.into_iter().skip(sent).collect();
+            let r = send_all(bot.clone(), db.clone(), pacer, id, new_id, outgoing, link_preview,
+                             silent, pin_keyword);
+            match await!(r) {
+                Ok(()) => if !items.is_empty() && db.is_job_queued(id) {
+                    db.record_delivery(new_id, &feed_title, &items);
+                },
+                Err(e) => warn!("failed to send updates to {}, {:?}", new_id, e),
+            }
+        }
+        Err(telebot::Error::Telegram(_, ref s, _)) => {
+            logging::with_fields(&[("chat", chat.as_str())], || {
+                warn!("failed to send updates, {}", s)
+            })
+        }
+        // e.g. Telegram is unreachable, it's tried again by the next start
+        Err(ref e) if is_transient(e) => {
+            logging::with_fields(&[("chat", chat.as_str())], || {
+                warn!("failed to send updates, kept for the next start, {:?}", e)
+            });
+            return Ok(());
+        }
+        Err(e) => {
+            logging::with_fields(&[("chat", chat.as_str())], || {
+                warn!("failed to send updates, {:?}", e)
+            })
+        }
+    }
+    db.unqueue_job(id);
+    Ok(())
+}
+
+#[async]
+fn broadcast(
+    bot: telebot::RcBot,
+    pacer: Rc<RefCell<Pacer>>,
+    targets: Vec<i64>,
+    text: String,
+) -> Result<(usize, usize), ()> {
+    let mut delivered = 0;
+    let mut failed = 0;
+    for target in targets {
+        let msg = Outgoing::Text(text.clone());
+        match await!(send_paced(bot.clone(), pacer.clone(), target, msg, false, false)) {
+            Ok(_) => delivered += 1,
+            Err(e) => {
+                info!("failed to broadcast to {}, {:?}", target, e);
+                failed += 1;
+            }
+        }
+    }
+    Ok((delivered, failed))
+}
+
+// media which can't be sent (too large, unreachable) falls back to its caption,
+// every sent message is recorded in job `id` so a retry doesn't repeat it
+#[async]
+fn send_all(
+    bot: telebot::RcBot,
+    db: Database,
+    pacer: Rc<RefCell<Pacer>>,
+    id: u64,
+    target: i64,
+    outgoing: Vec<Outgoing>,
+    link_preview: bool,
+    silent: bool,
+    mut pin_keyword: Option<String>,
+) -> Result<(), telebot::Error> {
+    for msg in outgoing {
+        let pin = pin_keyword
+            .as_ref()
+            .map_or(false, |keyword| msg.text().to_lowercase().contains(keyword.as_str()));
+        let r = send_paced(bot.clone(), pacer.clone(), target, msg.clone(), link_preview, silent);
+        let r = await!(r);
+        let msg_id = match (r, msg) {
+            (Err(e), Outgoing::Media(media)) => {
+                logging::with_fields(&[("chat", target.to_string().as_str())], || {
+                    info!("failed to send {:?} {}, {:?}", media.kind, media.url, e)
+                });
+                let caption = Outgoing::Text(media.caption);
+                let r =
+                    send_paced(bot.clone(), pacer.clone(), target, caption, link_preview, silent);
+                await!(r)?
+            }
+            (r, _) => r?,
+        };
+        db.job_message_sent(id, pin);
+        if pin {
+            pin_keyword = None;
+            let session = Session::new(bot.inner.handle.clone());
+            let r = pin_message(&bot, session, target, msg_id).map_err(move |e| {
+                logging::with_fields(&[("chat", target.to_string().as_str())], || {
+                    info!("failed to pin message {}, {}", msg_id, e)
+                })
+            });
+            bot.inner.handle.spawn(r);
+        }
+    }
+    Ok(())
+}
+
+// retries when Telegram asks to slow down or the error is transient,
+// instead of dropping the message
+#[async]
+fn send_paced(
+    bot: telebot::RcBot,
+    pacer: Rc<RefCell<Pacer>>,
+    target: i64,
+    msg: Outgoing,
+    link_preview: bool,
+    silent: bool,
+) -> Result<i64, telebot::Error> {
+    let mut retries = 0;
+    loop {
+        let at = pacer.borrow_mut().reserve(target);
+        let _ = await!(Timeout::new_at(at, &bot.inner.handle).expect("failed to start sleep"));
+        let r: Box<Future<Item = i64, Error = telebot::Error>> = match msg {
+            Outgoing::Media(ref media) => send_media(&bot, target, media, silent),
+            Outgoing::Text(ref text) => Box::new(
+                bot.message(target, text.clone())
+                    .parse_mode("HTML")
+                    .disable_web_page_preview(!link_preview)
+                    .disable_notification(silent)
+                    .send()
+                    .map(|(_, msg)| msg.message_id),
+            ),
+        };
+        match await!(r) {
+            Err(telebot::Error::Telegram(
+                _,
+                _,
+                Some(ResponseParameters {
+                    retry_after: Some(secs),
+                    ..
+                }),
+            )) if retries < MAX_RETRIES =>
+            {
+                retries += 1;
+                // back off further on every retry in case it keeps happening
+                let delay = Duration::from_secs(secs as u64 * u64::from(retries));
+                info!("flood control of {}, retry in {:?}", target, delay);
+                pacer.borrow_mut().delay(target, delay);
+            }
+            Err(ref e) if is_transient(e) && retries < MAX_RETRIES => {
+                let delay = Duration::from_secs(RETRY_BASE_DELAY << retries);
+                retries += 1;
+                info!("failed to send to {}, retry in {:?}, {:?}", target, delay, e);
+                pacer.borrow_mut().delay(target, delay);
+            }
+            r => return r,
+        }
+    }
+}
+
+// network errors and server errors of Telegram, unlike e.g. a blocked bot or a deleted chat;
+// a reply which can't be decoded is from an aborted connection, anything else is permanent
+fn is_transient(e: &telebot::Error) -> bool {
+    match *e {
+        telebot::Error::Telegram(code, _, _) => code >= 500,
+        telebot::Error::TokioCurl | telebot::Error::UTF8Decode => true,
+        _ => false,
+    }
+}
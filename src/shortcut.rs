@@ -0,0 +1,64 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+pub const DEFAULT_RSSHUB: &str = "https://rsshub.app";
+
+thread_local! {
+    // prefix -> feed link with `{}` for the rest, see `set_shortcuts`
+    static SHORTCUTS: RefCell<HashMap<String, String>> =
+        RefCell::new(builtin(DEFAULT_RSSHUB));
+}
+
+// `github:owner/repo`, `reddit:subreddit` and `rsshub:route`
+pub fn builtin(rsshub: &str) -> HashMap<String, String> {
+    let mut shortcuts = HashMap::new();
+    shortcuts.insert("github".to_owned(), "https://github.com/{}/releases.atom".to_owned());
+    shortcuts.insert("reddit".to_owned(), "https://www.reddit.com/r/{}/.rss".to_owned());
+    shortcuts.insert("rsshub".to_owned(), format!("{}/{{}}", rsshub.trim_right_matches('/')));
+    shortcuts
+}
+
+// the builtin ones along with the ones of the config file, see `Config::shortcuts`
+pub fn set_shortcuts(shortcuts: HashMap<String, String>) {
+    SHORTCUTS.with(|s| *s.borrow_mut() = shortcuts);
+}
+
+// `prefix:rest` to a feed link, `None` for links and unknown prefixes
+pub fn expand(s: &str) -> Option<String> {
+    let mut parts = s.splitn(2, ':');
+    let (prefix, rest) = (parts.next()?, parts.next()?);
+    let rest = rest.trim_left_matches('/');
+    if rest.is_empty() || s[prefix.len()..].starts_with("://") {
+        return None;
+    }
+    SHORTCUTS.with(|shortcuts| {
+        shortcuts
+            .borrow()
+            .get(&prefix.to_ascii_lowercase())
+            .map(|link| link.replace("{}", rest))
+    })
+}
+
+#[test]
+fn test_expand() {
+    assert_eq!(
+        expand("github:rust-lang/rust"),
+        Some("https://github.com/rust-lang/rust/releases.atom".to_owned())
+    );
+    assert_eq!(expand("Reddit:rust"), Some("https://www.reddit.com/r/rust/.rss".to_owned()));
+    assert_eq!(
+        expand("rsshub:/telegram/channel/awesome"),
+        Some("https://rsshub.app/telegram/channel/awesome".to_owned())
+    );
+    assert_eq!(expand("https://example.com/feed.xml"), None);
+    assert_eq!(expand("github:"), None);
+    assert_eq!(expand("unknown:x"), None);
+    let mut shortcuts = builtin("http://127.0.0.1:1200/");
+    shortcuts.insert("mastodon".to_owned(), "https://mastodon.social/@{}.rss".to_owned());
+    set_shortcuts(shortcuts);
+    assert_eq!(expand("rsshub:a/b"), Some("http://127.0.0.1:1200/a/b".to_owned()));
+    assert_eq!(
+        expand("mastodon:someone"),
+        Some("https://mastodon.social/@someone.rss".to_owned())
+    );
+}
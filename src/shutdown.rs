@@ -0,0 +1,59 @@
+use std::cell::Cell;
+use std::time::{Duration, Instant};
+
+use futures::prelude::*;
+use tokio_core::reactor::{Handle, Timeout};
+use tokio_signal;
+
+use sender::Sender;
+
+// `docker stop` kills after 10 seconds
+const DRAIN_TIMEOUT: u64 = 8;
+const DRAIN_POLL_INTERVAL: u64 = 100;
+
+thread_local! {
+    static SHUTTING_DOWN: Cell<bool> = Cell::new(false);
+}
+
+// no new fetches are scheduled once it's set
+pub fn is_shutting_down() -> bool {
+    SHUTTING_DOWN.with(|s| s.get())
+}
+
+// resolves on the first SIGINT or SIGTERM
+pub fn signal(handle: &Handle) -> Box<Future<Item = (), Error = ()>> {
+    let ctrl_c = tokio_signal::ctrl_c(handle).flatten_stream();
+    #[cfg(unix)]
+    let signals = {
+        use tokio_signal::unix::{Signal, SIGTERM};
+        let sigterm = Signal::new(SIGTERM, handle)
+            .flatten_stream()
+            .map(|_| ());
+        ctrl_c.select(sigterm)
+    };
+    #[cfg(not(unix))]
+    let signals = ctrl_c;
+    Box::new(
+        signals
+            .into_future()
+            .map(|_| ())
+            .map_err(|(e, _)| error!("failed to listen for signals: {}", e)),
+    )
+}
+
+// waits for the queued and in-flight jobs of `sender`, at most `DRAIN_TIMEOUT` seconds
+#[async]
+pub fn drain(sender: Sender, handle: Handle) -> Result<(), ()> {
+    SHUTTING_DOWN.with(|s| s.set(true));
+    let deadline = Instant::now() + Duration::from_secs(DRAIN_TIMEOUT);
+    while sender.queued() > 0 {
+        if Instant::now() >= deadline {
+            warn!("shutting down with {} jobs unsent, resumed by the next start", sender.queued());
+            return Ok(());
+        }
+        let sleep = Timeout::new(Duration::from_millis(DRAIN_POLL_INTERVAL), &handle)
+            .expect("failed to start sleep");
+        await!(sleep).map_err(|e| error!("drain sleep error: {}", e))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,83 @@
+use feed;
+use messages::Locale;
+use utils::{truncate_message, Escape, TELEGRAM_MAX_MSG_LEN};
+
+// user defined message format of a subscription, placeholders:
+// {title} {link} {feed} {feed_link} {pubdate} {author}, unknown ones are kept as is
+pub fn render(
+    template: &str,
+    item: &feed::Item,
+    rss_title: &str,
+    rss_link: &str,
+    locale: Locale,
+) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let end = match rest.find('}') {
+            Some(end) => end,
+            None => break,
+        };
+        match &rest[1..end] {
+            "title" => out.push_str(item.title.as_ref().map(|s| s.as_str()).unwrap_or(rss_title)),
+            "link" => out.push_str(item.link.as_ref().map(|s| s.as_str()).unwrap_or(rss_link)),
+            "feed" => out.push_str(rss_title),
+            "feed_link" => out.push_str(rss_link),
+            "pubdate" => {
+                if let Some(ref date) = item.pubdate {
+                    out.push_str(&locale.format_date(date));
+                }
+            }
+            "author" => out.push_str(item.author.as_ref().map(|s| s.as_str()).unwrap_or("")),
+            _ => out.push_str(&rest[..end + 1]),
+        }
+        rest = &rest[end + 1..];
+    }
+    out.push_str(rest);
+    out
+}
+
+// templates are plain text, the result is ready to be sent in HTML mode
+pub fn render_html(
+    template: &str,
+    item: &feed::Item,
+    rss_title: &str,
+    rss_link: &str,
+    locale: Locale,
+) -> String {
+    let text = render(template, item, rss_title, rss_link, locale);
+    Escape(&truncate_message(&text, TELEGRAM_MAX_MSG_LEN - 500)).to_string()
+}
+
+#[test]
+fn test_render() {
+    let item = feed::Item {
+        title: Some("Hello".into()),
+        link: Some("https://example.com/1".into()),
+        pubdate: "2001-01-02T15:04:00Z".parse().ok(),
+        ..feed::Item::default()
+    };
+    assert_eq!(
+        render(
+            "{feed}: {title}\n{link} {unknown} {pubdate}",
+            &item,
+            "Blog",
+            "https://example.com",
+            Locale::default()
+        ),
+        "Blog: Hello\nhttps://example.com/1 {unknown} Jan 2, 2001 3:04 PM"
+    );
+    let item = feed::Item::default();
+    assert_eq!(
+        render(
+            "{title} <{feed_link}> {pubdate}{",
+            &item,
+            "Blog",
+            "https://example.com",
+            Locale::default()
+        ),
+        "Blog <https://example.com> {"
+    );
+}
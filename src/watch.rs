@@ -0,0 +1,93 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::rc::Rc;
+
+use futures::prelude::*;
+use telebot;
+use tokio_curl::Session;
+
+use budget::HostBudget;
+use data::{Database, WatchedPage};
+use errors::*;
+use feed;
+use html::{excerpt, select};
+use logging;
+use sender::{Job, Sender};
+use utils::{gen_ua, link_host};
+
+// of the change notifications
+const EXCERPT_CHARS: usize = 300;
+
+// the selected region of a page, `None` if the selector matches nothing
+pub fn region<'a>(html: &'a str, selector: Option<&str>) -> Option<&'a str> {
+    match selector {
+        Some(selector) => select(html, selector),
+        None => Some(select(html, "body").unwrap_or(html)),
+    }
+}
+
+// of the excerpt, so changed scripts, styles or attributes don't notify
+pub fn region_hash(region: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    excerpt(region, usize::max_value()).hash(&mut hasher);
+    hasher.finish()
+}
+
+#[async]
+pub fn check_pages(
+    bot: telebot::RcBot,
+    db: Database,
+    session: Session,
+    sender: Sender,
+    budget: Rc<HostBudget>,
+) -> Result<(), ()> {
+    for page in db.get_all_watched_pages() {
+        let host = link_host(&page.link).unwrap_or_default();
+        if !budget.acquire(&host) {
+            info!("fetch budget of {} used up", host);
+            continue;
+        }
+        if let Err(e) = await!(check_page(bot.clone(), db.clone(), session.clone(),
+                                          page.clone(), sender.clone())) {
+            let count = db.inc_watch_error_count(page.get_id());
+            let kind = logging::error_kind(&e);
+            let fields = [("page", page.link.as_str()), ("error_kind", kind.as_str())];
+            logging::with_fields(&fields, || warn!("failed to check ({} errors): {}", count, e));
+        }
+    }
+    Ok(())
+}
+
+#[async]
+fn check_page(
+    bot: telebot::RcBot,
+    db: Database,
+    session: Session,
+    page: WatchedPage,
+    sender: Sender,
+) -> Result<()> {
+    let body = await!(feed::fetch_page(session, gen_ua(&bot), page.link.clone()))?;
+    let html = String::from_utf8_lossy(&body);
+    // a missing region counts as a change too, e.g. the element was removed
+    let region = region(&html, page.selector.as_ref().map(|s| s.as_str())).unwrap_or("");
+    if !db.update_watched_page(page.get_id(), region_hash(region)) {
+        return Ok(());
+    }
+    let text = excerpt(region, EXCERPT_CHARS);
+    for &subscriber in &page.subscribers {
+        let msg = db.get_lang(subscriber).page_changed(&page.link, &page.title, &text);
+        sender.send(Job::text(subscriber, msg));
+    }
+    Ok(())
+}
+
+#[test]
+fn test_region_hash() {
+    let html = "<html><body><div id=\"price\"><span>42</span> USD</div><p>ad</p></body></html>";
+    let price = region(html, Some("#price")).unwrap();
+    assert_eq!(price, "<span>42</span> USD");
+    assert_eq!(region_hash(price), region_hash("42 <span class=\"new\">USD</span>"));
+    assert_ne!(region_hash(price), region_hash("43 USD"));
+    assert_eq!(region(html, Some("#missing")), None);
+    assert!(region(html, None).unwrap().starts_with("<div"));
+}
@@ -0,0 +1,109 @@
+use futures::prelude::*;
+use regex::Regex;
+use tokio_curl::Session;
+use url::Url;
+
+use errors::*;
+use feed;
+
+const FEED_URL: &str = "https://www.youtube.com/feeds/videos.xml";
+
+lazy_static! {
+    // the canonical link, or the data of the page
+    static ref CHANNEL_ID: Regex = Regex::new(
+        r#"youtube\.com/channel/(UC[\w-]{22})|"(?:channelId|externalId)":"(UC[\w-]{22})""#
+    ).unwrap();
+}
+
+// what a YouTube page given to `/sub` is converted to
+#[derive(Debug, PartialEq)]
+enum Conversion {
+    Feed(String),
+    // `/@handle` and `/c/name` pages have to be fetched for the channel ID
+    Page(String),
+}
+
+fn is_youtube_host(host: &str) -> bool {
+    let host = host.to_ascii_lowercase();
+    host == "youtube.com" || host.ends_with(".youtube.com")
+}
+
+fn convert(link: &str) -> Option<Conversion> {
+    let url = Url::parse(link).ok()?;
+    if !is_youtube_host(url.host_str()?) {
+        return None;
+    }
+    let path: Vec<&str> = url.path_segments()?.filter(|s| !s.is_empty()).collect();
+    match (path.get(0).cloned(), path.get(1)) {
+        (Some("feeds"), _) => return None,
+        (Some("channel"), Some(id)) => {
+            return Some(Conversion::Feed(format!("{}?channel_id={}", FEED_URL, id)));
+        }
+        (Some("user"), Some(name)) => {
+            return Some(Conversion::Feed(format!("{}?user={}", FEED_URL, name)));
+        }
+        _ => (),
+    }
+    // a playlist, or a video of one
+    if let Some((_, list)) = url.query_pairs().find(|&(ref key, _)| key == "list") {
+        return Some(Conversion::Feed(format!("{}?playlist_id={}", FEED_URL, list)));
+    }
+    match (path.get(0).cloned(), path.get(1)) {
+        (Some(handle), _) if handle.starts_with('@') => Some(Conversion::Page(link.to_owned())),
+        (Some("c"), Some(_)) => Some(Conversion::Page(link.to_owned())),
+        _ => None,
+    }
+}
+
+fn extract_channel_id(html: &str) -> Option<String> {
+    let captures = CHANNEL_ID.captures(html)?;
+    captures
+        .get(1)
+        .or_else(|| captures.get(2))
+        .map(|id| id.as_str().to_owned())
+}
+
+// the feed of a YouTube channel, user or playlist link, `None` for other links
+#[async]
+pub fn feed_link(session: Session, ua: String, link: String) -> Result<Option<String>> {
+    match convert(&link) {
+        Some(Conversion::Feed(feed_link)) => Ok(Some(feed_link)),
+        Some(Conversion::Page(page)) => {
+            let body = await!(feed::fetch_page(session, ua, page))?;
+            let channel_id = extract_channel_id(&String::from_utf8_lossy(&body));
+            Ok(channel_id.map(|id| format!("{}?channel_id={}", FEED_URL, id)))
+        }
+        None => Ok(None),
+    }
+}
+
+#[test]
+fn test_convert() {
+    let feed = |query: &str| Some(Conversion::Feed(format!("{}?{}", FEED_URL, query)));
+    assert_eq!(
+        convert("https://www.youtube.com/channel/UCabcdefghijklmnopqrstuv/videos"),
+        feed("channel_id=UCabcdefghijklmnopqrstuv")
+    );
+    assert_eq!(convert("https://youtube.com/user/someone"), feed("user=someone"));
+    assert_eq!(
+        convert("https://m.youtube.com/watch?v=abc&list=PL123"),
+        feed("playlist_id=PL123")
+    );
+    assert_eq!(
+        convert("https://www.youtube.com/@someone"),
+        Some(Conversion::Page("https://www.youtube.com/@someone".into()))
+    );
+    assert_eq!(convert("https://www.youtube.com/feeds/videos.xml?user=someone"), None);
+    assert_eq!(convert("https://www.youtube.com/watch?v=abc"), None);
+    assert_eq!(convert("https://notyoutube.com/channel/UCabcdefghijklmnopqrstuv"), None);
+}
+
+#[test]
+fn test_extract_channel_id() {
+    let html = r#"<link rel="canonical"
+        href="https://www.youtube.com/channel/UCabcdefghijklmnopqrstuv">"#;
+    assert_eq!(extract_channel_id(html), Some("UCabcdefghijklmnopqrstuv".into()));
+    let html = r#"{"externalId":"UCabcdefghijklmnopqrstuv","title":"x"}"#;
+    assert_eq!(extract_channel_id(html), Some("UCabcdefghijklmnopqrstuv".into()));
+    assert_eq!(extract_channel_id("<html></html>"), None);
+}